@@ -0,0 +1,512 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{Builder, JoinHandle};
+
+use alsa::seq::{Addr, Event, PortCap, PortSubscribe};
+use alsa::Seq;
+
+use super::{
+    helpers, EventReader, InputDecoder, MidiInputPort, MidiOutputPort, SharedSeq,
+    INITIAL_CODER_BUFFER_SIZE,
+};
+use crate::errors::*;
+use crate::os::linux::PortFilter;
+use crate::r#virtual::VirtualPortOptions;
+use crate::Ignore;
+
+/// The handler of the events received by one input port,
+/// with the type of its user data erased.
+trait PortHandler: Send {
+    /// Decodes the event, returning whether a message is complete.
+    fn decode(&mut self, ev: &mut Event<'_>) -> bool;
+
+    /// Passes the last complete message to the callback.
+    fn deliver(&mut self);
+
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+/// Handlers are locked individually, so that callbacks run without
+/// the map of handlers being locked.
+type SharedHandler = Arc<Mutex<dyn PortHandler>>;
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct InputHandler<T> {
+    ignore_flags: Ignore,
+    decoder: InputDecoder,
+    callback: Callback<T>,
+    user_data: Option<T>, // taken when the connection is closed
+}
+
+impl<T: Send + 'static> PortHandler for InputHandler<T> {
+    fn decode(&mut self, ev: &mut Event<'_>) -> bool {
+        self.decoder.decode(ev, self.ignore_flags)
+    }
+
+    fn deliver(&mut self) {
+        if let Some(ref mut user_data) = self.user_data {
+            let message = self.decoder.message();
+            (self.callback)(message.timestamp, &message.bytes, user_data);
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The part of the client that is shared with the dispatch thread.
+struct Dispatch {
+    seq: SharedSeq,
+    handlers: Mutex<HashMap<i32, SharedHandler>>,
+    exclusive_ports: Mutex<HashSet<i32>>, // ports that only accept a single subscriber
+}
+
+struct ClientInner {
+    dispatch: Arc<Dispatch>,
+    client_id: i32,
+    queue_id: i32,
+    trigger_send_fd: i32,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Dispatch {
+    fn handler(&self, port: i32) -> Option<SharedHandler> {
+        self.handlers.lock().unwrap().get(&port).cloned()
+    }
+}
+
+impl ClientInner {
+    fn lock_seq(&self) -> MutexGuard<'_, Seq> {
+        self.dispatch.seq.lock()
+    }
+
+    fn client_id(&self) -> i32 {
+        self.client_id
+    }
+
+    /// Sets whether the given port only accepts a single subscriber in each direction.
+    fn set_exclusive(&self, port: i32, exclusive: bool) {
+        let mut exclusive_ports = self.dispatch.exclusive_ports.lock().unwrap();
+        if exclusive {
+            exclusive_ports.insert(port);
+        } else {
+            exclusive_ports.remove(&port);
+        }
+    }
+}
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        // Request the thread to stop.
+        let _res = unsafe {
+            libc::write(
+                self.trigger_send_fd,
+                &false as *const bool as *const _,
+                mem::size_of::<bool>() as libc::size_t,
+            )
+        };
+
+        // The thread closes its end of the pipe itself
+        let _ = self.thread.take().unwrap().join();
+        unsafe {
+            libc::close(self.trigger_send_fd);
+        }
+
+        helpers::stop_queue(&self.lock_seq(), self.queue_id);
+    }
+}
+
+pub struct MidiClient {
+    ignore_flags: Ignore,
+    inner: Arc<ClientInner>,
+}
+
+pub struct ClientInputConnection<T: 'static> {
+    inner: Arc<ClientInner>,
+    port: i32,
+    subscription: Option<PortSubscribe>,
+    closed: bool,
+    _marker: PhantomData<T>,
+}
+
+pub struct ClientOutputConnection {
+    inner: Arc<ClientInner>,
+    port: i32,
+    coder: helpers::EventEncoder,
+    subscription: Option<PortSubscribe>,
+    closed: bool,
+}
+
+impl MidiClient {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        let seq = Seq::open(None, None, true).map_err(|_| InitError)?;
+        let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
+        seq.set_client_name(&c_client_name).map_err(|_| InitError)?;
+        let client_id = seq.client_id().map_err(|_| InitError)?;
+
+        let queue_id = helpers::init_queue(&seq);
+        helpers::start_queue(&seq, queue_id);
+
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            helpers::stop_queue(&seq, queue_id);
+            return Err(InitError);
+        }
+
+        let dispatch = Arc::new(Dispatch {
+            seq: SharedSeq::new(seq),
+            handlers: Mutex::new(HashMap::new()),
+            exclusive_ports: Mutex::new(HashSet::new()),
+        });
+
+        // Start the thread that dispatches incoming events to the handlers of all input ports.
+        let thread_dispatch = dispatch.clone();
+        let trigger_rcv_fd = trigger_fds[0];
+        let threadbuilder = Builder::new().name(format!("midir ALSA client '{}'", client_name));
+        let thread = match threadbuilder.spawn(move || {
+            let dispatch = thread_dispatch;
+            let mut reader = EventReader::new(&dispatch.seq.lock(), trigger_rcv_fd);
+            // Messages are only passed to the callbacks after the client and the map of
+            // handlers have been unlocked again, so callbacks can create and close ports
+            while let Some(handler) = reader.next(&dispatch.seq, |seq, ev| {
+                let port = ev.get_dest().port;
+                if dispatch.exclusive_ports.lock().unwrap().contains(&port) {
+                    let addr = Addr {
+                        client: client_id,
+                        port,
+                    };
+                    helpers::reject_subscription(seq, ev, addr);
+                }
+                let handler = dispatch.handler(port)?;
+                let complete = handler.lock().unwrap().decode(ev);
+                complete.then_some(handler)
+            }) {
+                if let Some(handler) = handler {
+                    handler.lock().unwrap().deliver();
+                }
+            }
+            unsafe {
+                libc::close(trigger_rcv_fd);
+            }
+        }) {
+            Ok(handle) => handle,
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                helpers::stop_queue(&dispatch.seq.lock(), queue_id);
+                return Err(InitError);
+            }
+        };
+
+        Ok(MidiClient {
+            ignore_flags: Ignore::None,
+            inner: Arc::new(ClientInner {
+                dispatch,
+                client_id,
+                queue_id,
+                trigger_send_fd: trigger_fds[1],
+                thread: Some(thread),
+            }),
+        })
+    }
+
+    pub fn with_seq<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Seq) -> R,
+    {
+        f(&self.inner.lock_seq())
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn input_ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        helpers::get_ports(
+            &self.inner.lock_seq(),
+            PortCap::READ | PortCap::SUBS_READ,
+            &PortFilter::new(),
+            |p| crate::common::MidiInputPort {
+                imp: MidiInputPort { addr: p.addr() }.into(),
+            },
+        )
+    }
+
+    pub(crate) fn output_ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        helpers::get_ports(
+            &self.inner.lock_seq(),
+            PortCap::WRITE | PortCap::SUBS_WRITE,
+            &PortFilter::new(),
+            |p| crate::common::MidiOutputPort {
+                imp: MidiOutputPort { addr: p.addr() }.into(),
+            },
+        )
+    }
+
+    pub fn input_port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        helpers::get_port_name(&self.inner.lock_seq(), port.addr)
+    }
+
+    pub fn output_port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        helpers::get_port_name(&self.inner.lock_seq(), port.addr)
+    }
+
+    fn create_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let c_port_name = CString::new(port_name)
+            .map_err(|_| ConnectError::other("port_name must not contain null bytes", ()))?;
+
+        let port = helpers::create_input_port(
+            &self.inner.lock_seq(),
+            &c_port_name,
+            PortCap::WRITE | PortCap::SUBS_WRITE,
+            self.inner.queue_id,
+            options,
+        )
+        .map_err(|_| ConnectError::other("could not create ALSA input port", ()))?;
+
+        let handler = InputHandler {
+            ignore_flags: self.ignore_flags,
+            decoder: InputDecoder::new(),
+            callback: Box::new(callback),
+            user_data: Some(data),
+        };
+        self.inner
+            .dispatch
+            .handlers
+            .lock()
+            .unwrap()
+            .insert(port, Arc::new(Mutex::new(handler)));
+        self.inner.set_exclusive(port, options.exclusive);
+
+        Ok(ClientInputConnection {
+            inner: self.inner.clone(),
+            port,
+            subscription: None,
+            closed: false,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn connect_input<F, T: Send + 'static>(
+        &self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        if self.inner.lock_seq().get_any_port_info(port.addr).is_err() {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, ()));
+        }
+
+        let mut conn = self.create_input(port_name, &VirtualPortOptions::new(), callback, data)?;
+
+        // Make subscription
+        let sub = PortSubscribe::empty().unwrap();
+        sub.set_sender(port.addr);
+        sub.set_dest(Addr {
+            client: self.inner.client_id(),
+            port: conn.port,
+        });
+        if self.inner.lock_seq().subscribe_port(&sub).is_err() {
+            return Err(ConnectError::other(
+                "could not create ALSA input subscription",
+                (),
+            ));
+        }
+        conn.subscription = Some(sub);
+
+        Ok(conn)
+    }
+
+    pub fn create_virtual_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.create_input(port_name, options, callback, data)
+    }
+
+    fn create_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        let c_port_name = CString::new(port_name)
+            .map_err(|_| ConnectError::other("port_name must not contain null bytes", ()))?;
+
+        let mut pinfo =
+            helpers::new_port_info(&c_port_name, PortCap::READ | PortCap::SUBS_READ, options);
+        let port = helpers::create_port(&self.inner.lock_seq(), &mut pinfo, options)
+            .map_err(|_| ConnectError::other("could not create ALSA output port", ()))?;
+        self.inner.set_exclusive(port, options.exclusive);
+
+        Ok(ClientOutputConnection {
+            inner: self.inner.clone(),
+            port,
+            coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
+            subscription: None,
+            closed: false,
+        })
+    }
+
+    pub fn connect_output(
+        &self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        if self.inner.lock_seq().get_any_port_info(port.addr).is_err() {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, ()));
+        }
+
+        let mut conn = self.create_output(port_name, &VirtualPortOptions::new())?;
+
+        // Make subscription
+        let sub = PortSubscribe::empty().unwrap();
+        sub.set_sender(Addr {
+            client: self.inner.client_id(),
+            port: conn.port,
+        });
+        sub.set_dest(port.addr);
+        sub.set_time_update(true);
+        sub.set_time_real(true);
+        if self.inner.lock_seq().subscribe_port(&sub).is_err() {
+            return Err(ConnectError::other(
+                "could not create ALSA output subscription",
+                (),
+            ));
+        }
+        conn.subscription = Some(sub);
+
+        Ok(conn)
+    }
+
+    pub fn create_virtual_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        self.create_output(port_name, options)
+    }
+}
+
+impl<T> ClientInputConnection<T> {
+    pub fn port(&self) -> Addr {
+        Addr {
+            client: self.inner.client_id(),
+            port: self.port,
+        }
+    }
+
+    pub fn close(mut self) -> T {
+        let handler = self.close_internal().unwrap();
+        // Waits for the callback to return if it is currently running
+        let mut handler = handler.lock().unwrap();
+        match handler.as_any().downcast_mut::<InputHandler<T>>() {
+            Some(handler) => handler.user_data.take().unwrap(),
+            None => unreachable!("ALSA client port handler has the wrong type"),
+        }
+    }
+
+    fn close_internal(&mut self) -> Option<SharedHandler> {
+        self.closed = true;
+
+        {
+            let seq = self.inner.lock_seq();
+            if let Some(ref subscription) = self.subscription {
+                let _ = seq.unsubscribe_port(subscription.get_sender(), subscription.get_dest());
+            }
+            // Delete the port first, so that no more events arrive for the handler
+            let _ = seq.delete_port(self.port);
+        }
+        self.inner.set_exclusive(self.port, false);
+
+        self.inner
+            .dispatch
+            .handlers
+            .lock()
+            .unwrap()
+            .remove(&self.port)
+    }
+}
+
+impl<T> Drop for ClientInputConnection<T> {
+    fn drop(&mut self) {
+        if !self.closed {
+            if let Some(handler) = self.close_internal() {
+                // Make sure that the callback is not running anymore
+                drop(handler.lock().unwrap());
+            }
+        }
+    }
+}
+
+impl ClientOutputConnection {
+    pub fn port(&self) -> Addr {
+        Addr {
+            client: self.inner.client_id(),
+            port: self.port,
+        }
+    }
+
+    pub fn with_seq<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Seq) -> R,
+    {
+        f(&self.inner.lock_seq())
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        let seq = self.inner.lock_seq();
+        helpers::send_message(&seq, &mut self.coder, self.port, message)
+    }
+
+    pub fn close(mut self) {
+        self.close_internal();
+    }
+
+    fn close_internal(&mut self) {
+        self.closed = true;
+
+        {
+            let seq = self.inner.lock_seq();
+            if let Some(ref subscription) = self.subscription {
+                let _ = seq.unsubscribe_port(subscription.get_sender(), subscription.get_dest());
+            }
+            let _ = seq.delete_port(self.port);
+        }
+        self.inner.set_exclusive(self.port, false);
+    }
+}
+
+impl Drop for ClientOutputConnection {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.close_internal();
+        }
+    }
+}
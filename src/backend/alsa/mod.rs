@@ -1,49 +1,57 @@
 use std::ffi::{CStr, CString};
 use std::io::{stderr, Write};
 use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread::{Builder, JoinHandle};
 
+use crate::os::linux::PortFilter;
+use crate::r#virtual::{Subscriber, SubscriptionEvent, VirtualPortOptions};
 use crate::{errors, Ignore, MidiMessage};
 
-use alsa::seq::{Addr, EventType, PortCap, PortInfo, PortSubscribe, PortType, QueueTempo};
+use alsa::seq::{Addr, Event, EventType, PortCap, PortSubscribe, PortType};
 use alsa::{Direction, Seq};
 
 use errors::*;
 
-mod helpers {
-    use crate::errors::PortInfoError;
-    use alsa::seq::{Addr, ClientIter, MidiEvent, PortCap, PortInfo, PortIter, PortType, Seq};
+pub(crate) mod helpers {
+    use std::ffi::CStr;
+
+    use crate::errors::{PortInfoError, SendError};
+    use crate::os::linux::PortFilter;
+    use crate::r#virtual::{Subscriber, SubscriptionEvent, VirtualPortKind, VirtualPortOptions};
+    use alsa::seq::{
+        Addr, ClientIter, Connect, Event, EventType, MidiEvent, PortCap, PortInfo, PortIter,
+        PortSubscribeIter, PortType, QuerySubsType, QueueTempo, Seq,
+    };
 
     pub fn poll(fds: &mut [libc::pollfd], timeout: i32) -> i32 {
         unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) }
     }
 
     #[inline]
-    pub fn get_ports<F, T>(s: &Seq, capability: PortCap, f: F) -> Vec<T>
+    fn iter_ports<'a>(
+        s: &'a Seq,
+        capability: PortCap,
+        filter: &'a PortFilter,
+    ) -> impl Iterator<Item = PortInfo> + 'a {
+        let own_client = s.client_id().ok();
+        ClientIter::new(s)
+            .flat_map(move |c| PortIter::new(s, c.get_client()))
+            .filter(move |p| filter.accepts(p, own_client, || get_port_name(s, p.addr()).ok()))
+            .filter(move |p| p.get_capability().contains(capability))
+    }
+
+    #[inline]
+    pub fn get_ports<F, T>(s: &Seq, capability: PortCap, filter: &PortFilter, f: F) -> Vec<T>
     where
         F: Fn(PortInfo) -> T,
     {
-        ClientIter::new(s)
-            .flat_map(|c| PortIter::new(s, c.get_client()))
-            .filter(|p| {
-                p.get_type()
-                    .intersects(PortType::MIDI_GENERIC | PortType::SYNTH | PortType::APPLICATION)
-            })
-            .filter(|p| p.get_capability().contains(capability))
-            .map(f)
-            .collect()
+        iter_ports(s, capability, filter).map(f).collect()
     }
 
     #[inline]
-    pub fn get_port_count(s: &Seq, capability: PortCap) -> usize {
-        ClientIter::new(s)
-            .flat_map(|c| PortIter::new(s, c.get_client()))
-            .filter(|p| {
-                p.get_type()
-                    .intersects(PortType::MIDI_GENERIC | PortType::SYNTH | PortType::APPLICATION)
-            })
-            .filter(|p| p.get_capability().contains(capability))
-            .count()
+    pub fn get_port_count(s: &Seq, capability: PortCap, filter: &PortFilter) -> usize {
+        iter_ports(s, capability, filter).count()
     }
 
     #[inline]
@@ -75,6 +83,223 @@ mod helpers {
         Ok(output)
     }
 
+    fn get_subscriber(s: &Seq, addr: Addr) -> Subscriber {
+        let id = format!("{}:{}", addr.client, addr.port);
+        let name = get_port_name(s, addr).unwrap_or_else(|_| id.clone());
+        Subscriber::new(id, name)
+    }
+
+    /// Returns the ports that are subscribed to the given port, in either direction.
+    pub fn get_subscribers(s: &Seq, addr: Addr) -> Vec<Subscriber> {
+        let readers =
+            PortSubscribeIter::new(s, addr, QuerySubsType::READ).map(|sub| sub.get_dest());
+        let writers =
+            PortSubscribeIter::new(s, addr, QuerySubsType::WRITE).map(|sub| sub.get_sender());
+        readers
+            .chain(writers)
+            .map(|peer| get_subscriber(s, peer))
+            .collect()
+    }
+
+    /// Converts a `PortSubscribed` or `PortUnsubscribed` event that
+    /// concerns the given port into the change of its subscribers.
+    pub fn subscription_event(s: &Seq, ev: &Event<'_>, addr: Addr) -> Option<SubscriptionEvent> {
+        let subscribed = match ev.get_type() {
+            EventType::PortSubscribed => true,
+            EventType::PortUnsubscribed => false,
+            _ => return None,
+        };
+        let connect = ev.get_data::<Connect>()?;
+        let peer = if connect.dest == addr {
+            connect.sender
+        } else if connect.sender == addr {
+            connect.dest
+        } else {
+            return None;
+        };
+
+        let subscriber = get_subscriber(s, peer);
+        Some(if subscribed {
+            SubscriptionEvent::Subscribed(subscriber)
+        } else {
+            SubscriptionEvent::Unsubscribed(subscriber)
+        })
+    }
+
+    /// Creates the information for a new port of our own client, which can
+    /// then be passed to `create_port`.
+    pub fn new_port_info(
+        name: &CStr,
+        capability: PortCap,
+        options: &VirtualPortOptions,
+    ) -> PortInfo {
+        let mut pinfo = PortInfo::empty().unwrap();
+        // the client is set by `create_port`, and the port is zeroed already by `empty()`
+        let capability = if options.no_export {
+            capability | PortCap::NO_EXPORT
+        } else {
+            capability
+        };
+        pinfo.set_capability(capability);
+        pinfo.set_type(match options.kind {
+            VirtualPortKind::Application => PortType::MIDI_GENERIC | PortType::APPLICATION,
+            VirtualPortKind::Synth => {
+                PortType::MIDI_GENERIC | PortType::SYNTHESIZER | PortType::SOFTWARE
+            }
+            VirtualPortKind::Hardware => {
+                PortType::MIDI_GENERIC | PortType::HARDWARE | PortType::PORT
+            }
+        });
+        pinfo.set_midi_channels(options.midi_channels as i32);
+        pinfo.set_name(name);
+        pinfo
+    }
+
+    /// Creates a port of our own client, using the port number requested in
+    /// `options` if there is one. Returns the number of the new port.
+    pub fn create_port(
+        s: &Seq,
+        pinfo: &mut PortInfo,
+        options: &VirtualPortOptions,
+    ) -> Result<i32, ()> {
+        if let Some(port) = options.port_number {
+            let port = port as i32;
+            let client = s.client_id().map_err(|_| ())?;
+            if s.get_any_port_info(Addr { client, port }).is_ok() {
+                return Err(()); // the port number is already taken
+            }
+            // alsa-rs cannot set the port number of a `PortInfo`, but `snd_seq_set_port_info`
+            // stores our address in it before updating the port with that address,
+            // which does nothing because the port does not exist yet.
+            s.set_port_info(port, pinfo).map_err(|_| ())?;
+            pinfo.set_port_specified(true);
+        }
+
+        match s.create_port(pinfo) {
+            Ok(()) => Ok(pinfo.get_port()),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Removes a subscription that has just been made to a port which only accepts
+    /// a single subscriber in each direction, if the port already had one.
+    pub fn reject_subscription(s: &Seq, ev: &Event<'_>, addr: Addr) {
+        if ev.get_type() != EventType::PortSubscribed {
+            return;
+        }
+        let connect = match ev.get_data::<Connect>() {
+            Some(connect) => connect,
+            None => return,
+        };
+        let query_type = if connect.sender == addr {
+            QuerySubsType::READ
+        } else if connect.dest == addr {
+            QuerySubsType::WRITE
+        } else {
+            return;
+        };
+        if PortSubscribeIter::new(s, addr, query_type).count() > 1 {
+            let _ = s.unsubscribe_port(connect.sender, connect.dest);
+        }
+    }
+
+    /// Creates the queue that is used to timestamp incoming events.
+    pub fn init_queue(s: &Seq) -> i32 {
+        let mut queue_id = 0;
+        // Create the input queue
+        if !cfg!(feature = "avoid_timestamping") {
+            queue_id = s
+                .alloc_named_queue(unsafe { CStr::from_bytes_with_nul_unchecked(b"midir queue\0") })
+                .unwrap();
+            // Set arbitrary tempo (mm=100) and resolution (240)
+            let qtempo = QueueTempo::empty().unwrap();
+            qtempo.set_tempo(600_000);
+            qtempo.set_ppq(240);
+            s.set_queue_tempo(queue_id, &qtempo).unwrap();
+            let _ = s.drain_output();
+        }
+
+        queue_id
+    }
+
+    pub fn start_queue(s: &Seq, queue_id: i32) {
+        if !cfg!(feature = "avoid_timestamping") {
+            let _ = s.control_queue(queue_id, EventType::Start, 0, None);
+            let _ = s.drain_output();
+        }
+    }
+
+    pub fn stop_queue(s: &Seq, queue_id: i32) {
+        if !cfg!(feature = "avoid_timestamping") {
+            let _ = s.control_queue(queue_id, EventType::Stop, 0, None);
+            let _ = s.drain_output();
+            let _ = s.free_queue(queue_id);
+        }
+    }
+
+    /// Creates a port of our own client that receives events,
+    /// which are timestamped using the given queue.
+    pub fn create_input_port(
+        s: &Seq,
+        name: &CStr,
+        capability: PortCap,
+        queue_id: i32,
+        options: &VirtualPortOptions,
+    ) -> Result<i32, ()> {
+        let mut pinfo = new_port_info(name, capability, options);
+
+        if !cfg!(feature = "avoid_timestamping") {
+            pinfo.set_timestamping(true);
+            pinfo.set_timestamp_real(true);
+            pinfo.set_timestamp_queue(queue_id);
+        }
+
+        create_port(s, &mut pinfo, options)
+    }
+
+    /// Encodes the message and sends it directly to all subscribers of the given port.
+    pub fn send_message(
+        s: &Seq,
+        coder: &mut EventEncoder,
+        port: i32,
+        message: &[u8],
+    ) -> Result<(), SendError> {
+        let nbytes = message.len();
+        assert!(nbytes <= u32::max_value() as usize);
+
+        if nbytes > coder.get_buffer_size() as usize {
+            if coder.resize_buffer(nbytes as u32).is_err() {
+                return Err(SendError::Other("could not resize ALSA encoding buffer"));
+            }
+        }
+
+        let mut ev = match coder.get_wrapped().encode(message) {
+            Ok((_, Some(ev))) => ev,
+            _ => return Err(SendError::InvalidData("ALSA encoder reported invalid data")),
+        };
+
+        ev.set_source(port);
+        ev.set_subs();
+        ev.set_direct();
+
+        // Send the event.
+        if s.event_output_direct(&mut ev).is_err() {
+            return Err(SendError::Other("could not send encoded ALSA message"));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get_client_name(s: &Seq, client: i32) -> Result<String, PortInfoError> {
+        let cinfo = s
+            .get_any_client_info(client)
+            .map_err(|_| PortInfoError::InvalidPort)?;
+        cinfo
+            .get_name()
+            .map(|name| name.to_string())
+            .map_err(|_| PortInfoError::CannotRetrievePortName)
+    }
+
     pub struct EventDecoder {
         ev: MidiEvent,
     }
@@ -92,6 +317,8 @@ mod helpers {
         }
     }
 
+    unsafe impl Send for EventDecoder {}
+
     pub struct EventEncoder {
         ev: MidiEvent,
         buffer_size: u32,
@@ -131,11 +358,138 @@ mod helpers {
     }
 }
 
+mod client;
+pub(crate) mod ump;
+pub use self::client::{ClientInputConnection, ClientOutputConnection, MidiClient};
+
 const INITIAL_CODER_BUFFER_SIZE: usize = 32;
 
+/// A sequencer client that can be shared between the input handler thread and
+/// other threads, i.e. the sending half of a duplex port or the connections of a `MidiClient`.
+///
+/// A `Seq` must not be used by several threads at once, so every use of it is serialized.
+/// The input handler thread only locks the client while it reads and decodes a single
+/// event (see `EventReader`), so that callbacks can send messages through the same client.
+struct SharedSeq(Mutex<Seq>);
+
+impl SharedSeq {
+    fn new(seq: Seq) -> SharedSeq {
+        SharedSeq(Mutex::new(seq))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Seq> {
+        self.0.lock().unwrap()
+    }
+}
+
+type SubscriptionCallback = Box<dyn FnMut(SubscriptionEvent) + Send>;
+
+/// The subscription callback of an input connection,
+/// which is shared with its input handler thread.
+type SharedSubscriptionCallback = Arc<Mutex<Option<SubscriptionCallback>>>;
+
+/// Watches the subscriptions of a port that has no input handler thread, using a
+/// separate client whose hidden port is subscribed to the system announce port,
+/// which broadcasts all changes of subscriptions. If the port is exclusive,
+/// the watcher also removes subscriptions beyond the first one.
+struct SubscriptionWatcher {
+    thread: Option<JoinHandle<()>>,
+    trigger_send_fd: i32,
+}
+
+impl SubscriptionWatcher {
+    fn start(
+        addr: Addr,
+        exclusive: bool,
+        callback: SharedSubscriptionCallback,
+    ) -> Result<Self, InitError> {
+        let seq = Seq::open(None, Some(Direction::Capture), true).map_err(|_| InitError)?;
+        let c_name = CString::new("midir subscription watcher").unwrap();
+        seq.set_client_name(&c_name).map_err(|_| InitError)?;
+
+        let pinfo = helpers::new_port_info(
+            &c_name,
+            PortCap::WRITE,
+            &VirtualPortOptions::new().no_export(true),
+        );
+        seq.create_port(&pinfo).map_err(|_| InitError)?;
+        let sub = PortSubscribe::empty().unwrap();
+        sub.set_sender(Addr::system_announce());
+        sub.set_dest(pinfo.addr());
+        seq.subscribe_port(&sub).map_err(|_| InitError)?;
+
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            return Err(InitError);
+        }
+
+        let threadbuilder = Builder::new().name("midir ALSA subscription watcher".to_string());
+        let thread = threadbuilder.spawn(move || {
+            let seq = SharedSeq::new(seq);
+            let mut reader = EventReader::new(&seq.lock(), trigger_fds[0]);
+            while let Some(event) = reader.next(&seq, |seq, ev| {
+                if exclusive {
+                    helpers::reject_subscription(seq, ev, addr);
+                }
+                helpers::subscription_event(seq, ev, addr)
+            }) {
+                if let Some(event) = event {
+                    if let Some(ref mut on_change) = *callback.lock().unwrap() {
+                        on_change(event);
+                    }
+                }
+            }
+            unsafe { libc::close(trigger_fds[0]) };
+        });
+
+        match thread {
+            Ok(thread) => Ok(SubscriptionWatcher {
+                thread: Some(thread),
+                trigger_send_fd: trigger_fds[1],
+            }),
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                Err(InitError)
+            }
+        }
+    }
+}
+
+impl Drop for SubscriptionWatcher {
+    fn drop(&mut self) {
+        let _res = unsafe {
+            libc::write(
+                self.trigger_send_fd,
+                &false as *const bool as *const _,
+                mem::size_of::<bool>() as libc::size_t,
+            )
+        };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe { libc::close(self.trigger_send_fd) };
+    }
+}
+
+/// A duplex port, which is deleted once both the receiving
+/// and the sending connection have been closed.
+struct DuplexPort {
+    seq: Arc<SharedSeq>,
+    port: i32,
+}
+
+impl Drop for DuplexPort {
+    fn drop(&mut self) {
+        let _ = self.seq.lock().delete_port(self.port);
+    }
+}
+
 pub struct MidiInput {
     ignore_flags: Ignore,
-    seq: Option<Seq>,
+    seq: Option<Arc<SharedSeq>>,
 }
 
 #[derive(Clone, PartialEq)]
@@ -147,20 +501,39 @@ impl MidiInputPort {
     pub fn id(&self) -> String {
         format!("{}:{}", self.addr.client, self.addr.port)
     }
+
+    pub fn from_addr(addr: Addr) -> Self {
+        MidiInputPort { addr }
+    }
+
+    pub fn addr(&self) -> Addr {
+        self.addr
+    }
 }
 
+/// The receiving and the sending half of a duplex port.
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
 pub struct MidiInputConnection<T: 'static> {
     subscription: Option<PortSubscribe>,
     thread: Option<JoinHandle<(HandlerData<T>, T)>>,
+    client_id: i32,
     vport: i32, // TODO: probably port numbers are only u8, therefore could use Option<u8>
+    duplex: Option<Arc<DuplexPort>>,
     trigger_send_fd: i32,
+    seq: Arc<SharedSeq>, // shared with the input handler thread
+    subscription_callback: SharedSubscriptionCallback,
 }
 
 struct HandlerData<T: 'static> {
     ignore_flags: Ignore,
-    seq: Seq,
+    seq: Arc<SharedSeq>,
+    client_id: i32,
+    vport: i32,
+    exclusive: bool, // whether subscriptions beyond the first one are removed
     trigger_rcv_fd: i32,
     callback: Box<dyn FnMut(u64, &[u8], &mut T) + Send>,
+    subscription_callback: SharedSubscriptionCallback,
     queue_id: i32, // an input queue is needed to get timestamped events
 }
 
@@ -178,52 +551,70 @@ impl MidiInput {
 
         Ok(MidiInput {
             ignore_flags: Ignore::None,
-            seq: Some(seq),
+            seq: Some(Arc::new(SharedSeq::new(seq))),
         })
     }
 
+    pub fn from_seq(seq: Seq) -> Self {
+        MidiInput {
+            ignore_flags: Ignore::None,
+            seq: Some(Arc::new(SharedSeq::new(seq))),
+        }
+    }
+
+    pub fn with_seq<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Seq) -> R,
+    {
+        f(&self.lock_seq())
+    }
+
+    fn lock_seq(&self) -> MutexGuard<'_, Seq> {
+        self.seq.as_ref().unwrap().lock()
+    }
+
     pub fn ignore(&mut self, flags: Ignore) {
         self.ignore_flags = flags;
     }
 
     pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        self.ports_filtered(&PortFilter::new())
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.port_count_filtered(&PortFilter::new())
+    }
+
+    pub(crate) fn ports_filtered(&self, filter: &PortFilter) -> Vec<crate::common::MidiInputPort> {
         helpers::get_ports(
-            self.seq.as_ref().unwrap(),
+            &self.lock_seq(),
             PortCap::READ | PortCap::SUBS_READ,
+            filter,
             |p| crate::common::MidiInputPort {
-                imp: MidiInputPort { addr: p.addr() },
+                imp: MidiInputPort { addr: p.addr() }.into(),
             },
         )
     }
 
-    pub fn port_count(&self) -> usize {
-        helpers::get_port_count(
-            self.seq.as_ref().unwrap(),
-            PortCap::READ | PortCap::SUBS_READ,
-        )
+    pub fn port_count_filtered(&self, filter: &PortFilter) -> usize {
+        helpers::get_port_count(&self.lock_seq(), PortCap::READ | PortCap::SUBS_READ, filter)
     }
 
     pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
-        helpers::get_port_name(self.seq.as_ref().unwrap(), port.addr)
+        helpers::get_port_name(&self.lock_seq(), port.addr)
     }
 
-    fn init_queue(&mut self) -> i32 {
-        let seq = self.seq.as_mut().unwrap();
-        let mut queue_id = 0;
-        // Create the input queue
-        if !cfg!(feature = "avoid_timestamping") {
-            queue_id = seq
-                .alloc_named_queue(unsafe { CStr::from_bytes_with_nul_unchecked(b"midir queue\0") })
-                .unwrap();
-            // Set arbitrary tempo (mm=100) and resolution (240)
-            let qtempo = QueueTempo::empty().unwrap();
-            qtempo.set_tempo(600_000);
-            qtempo.set_ppq(240);
-            seq.set_queue_tempo(queue_id, &qtempo).unwrap();
-            let _ = seq.drain_output();
-        }
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // All ports of a device belong to the same ALSA sequencer client
+        Ok(port.addr.client.to_string())
+    }
 
-        queue_id
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        helpers::get_client_name(&self.lock_seq(), port.addr.client)
+    }
+
+    fn init_queue(&mut self) -> i32 {
+        helpers::init_queue(&self.lock_seq())
     }
 
     fn init_trigger(&mut self) -> Result<[i32; 2], ()> {
@@ -236,34 +627,18 @@ impl MidiInput {
         }
     }
 
-    fn create_port(&mut self, port_name: &CStr, queue_id: i32) -> Result<i32, ()> {
-        let mut pinfo = PortInfo::empty().unwrap();
-        // these functions are private, and the values are zeroed already by `empty()`
-        //pinfo.set_client(0);
-        //pinfo.set_port(0);
-        pinfo.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE);
-        pinfo.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
-        pinfo.set_midi_channels(16);
-
-        if !cfg!(feature = "avoid_timestamping") {
-            pinfo.set_timestamping(true);
-            pinfo.set_timestamp_real(true);
-            pinfo.set_timestamp_queue(queue_id);
-        }
-
-        pinfo.set_name(port_name);
-        match self.seq.as_mut().unwrap().create_port(&mut pinfo) {
-            Ok(_) => Ok(pinfo.get_port()),
-            Err(_) => Err(()),
-        }
+    fn create_port(
+        &mut self,
+        port_name: &CStr,
+        capability: PortCap,
+        queue_id: i32,
+        options: &VirtualPortOptions,
+    ) -> Result<i32, ()> {
+        helpers::create_input_port(&self.lock_seq(), port_name, capability, queue_id, options)
     }
 
     fn start_input_queue(&mut self, queue_id: i32) {
-        if !cfg!(feature = "avoid_timestamping") {
-            let seq = self.seq.as_mut().unwrap();
-            let _ = seq.control_queue(queue_id, EventType::Start, 0, None);
-            let _ = seq.drain_output();
-        }
+        helpers::start_queue(&self.lock_seq(), queue_id);
     }
 
     pub fn connect<F, T: Send>(
@@ -286,9 +661,14 @@ impl MidiInput {
             }
         };
 
+        let client_id = match self.with_seq(|seq| seq.client_id()) {
+            Ok(id) => id,
+            Err(_) => return Err(ConnectError::other("could not get ALSA client id", self)),
+        };
+
         let queue_id = self.init_queue();
 
-        let src_pinfo = match self.seq.as_ref().unwrap().get_any_port_info(port.addr) {
+        let src_pinfo = match self.with_seq(|seq| seq.get_any_port_info(port.addr)) {
             Ok(p) => p,
             Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
         };
@@ -303,7 +683,12 @@ impl MidiInput {
             }
         };
 
-        let vport = match self.create_port(&c_port_name, queue_id) {
+        let vport = match self.create_port(
+            &c_port_name,
+            PortCap::WRITE | PortCap::SUBS_WRITE,
+            queue_id,
+            &VirtualPortOptions::new(),
+        ) {
             Ok(vp) => vp,
             Err(_) => {
                 return Err(ConnectError::other(
@@ -317,10 +702,10 @@ impl MidiInput {
         let sub = PortSubscribe::empty().unwrap();
         sub.set_sender(src_pinfo.addr());
         sub.set_dest(Addr {
-            client: self.seq.as_ref().unwrap().client_id().unwrap(),
+            client: client_id,
             port: vport,
         });
-        if self.seq.as_ref().unwrap().subscribe_port(&sub).is_err() {
+        if self.lock_seq().subscribe_port(&sub).is_err() {
             return Err(ConnectError::other(
                 "could not create ALSA input subscription",
                 self,
@@ -332,11 +717,17 @@ impl MidiInput {
         self.start_input_queue(queue_id);
 
         // Start our MIDI input thread.
+        let seq = self.seq.take().unwrap();
+        let subscription_callback = SharedSubscriptionCallback::default();
         let handler_data = HandlerData {
             ignore_flags: self.ignore_flags,
-            seq: self.seq.take().unwrap(),
+            seq: seq.clone(),
+            client_id,
+            vport,
+            exclusive: false,
             trigger_rcv_fd: trigger_fds[0],
             callback: Box::new(callback),
+            subscription_callback: subscription_callback.clone(),
             queue_id: queue_id,
         };
 
@@ -361,14 +752,81 @@ impl MidiInput {
         Ok(MidiInputConnection {
             subscription: Some(subscription),
             thread: Some(thread),
+            client_id,
             vport: vport,
+            duplex: None,
             trigger_send_fd: trigger_fds[1],
+            seq,
+            subscription_callback,
         })
     }
 
     pub fn create_virtual<F, T: Send>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.create_virtual_port(
+            port_name,
+            PortCap::WRITE | PortCap::SUBS_WRITE,
+            options,
+            callback,
+            data,
+        )
+    }
+
+    pub fn create_virtual_duplex<F, T: Send>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let seq = self.seq.clone().unwrap();
+        let mut conn_in = self.create_virtual_port(
+            port_name,
+            PortCap::READ | PortCap::WRITE | PortCap::SUBS_READ | PortCap::SUBS_WRITE,
+            options,
+            callback,
+            data,
+        )?;
+
+        // Both connections share the port, so that it is only
+        // deleted after both of them have been closed
+        let duplex = Arc::new(DuplexPort {
+            seq: seq.clone(),
+            port: conn_in.vport,
+        });
+        conn_in.duplex = Some(duplex.clone());
+
+        // The sending half uses the client of the receiving half
+        let conn_out = MidiOutputConnection {
+            seq: Some(seq),
+            client_id: conn_in.client_id,
+            vport: conn_in.vport,
+            coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
+            subscription: None,
+            duplex: Some(duplex),
+            watcher: None,
+            subscription_callback: SharedSubscriptionCallback::default(),
+        };
+
+        Ok((conn_in, conn_out))
+    }
+
+    fn create_virtual_port<F, T: Send>(
         mut self,
         port_name: &str,
+        capability: PortCap,
+        options: &VirtualPortOptions,
         callback: F,
         data: T,
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
@@ -385,6 +843,11 @@ impl MidiInput {
             }
         };
 
+        let client_id = match self.with_seq(|seq| seq.client_id()) {
+            Ok(id) => id,
+            Err(_) => return Err(ConnectError::other("could not get ALSA client id", self)),
+        };
+
         let queue_id = self.init_queue();
 
         let c_port_name = match CString::new(port_name) {
@@ -397,7 +860,7 @@ impl MidiInput {
             }
         };
 
-        let vport = match self.create_port(&c_port_name, queue_id) {
+        let vport = match self.create_port(&c_port_name, capability, queue_id, options) {
             Ok(vp) => vp,
             Err(_) => {
                 return Err(ConnectError::other(
@@ -411,11 +874,17 @@ impl MidiInput {
         self.start_input_queue(queue_id);
 
         // Start our MIDI input thread.
+        let seq = self.seq.take().unwrap();
+        let subscription_callback = SharedSubscriptionCallback::default();
         let handler_data = HandlerData {
             ignore_flags: self.ignore_flags,
-            seq: self.seq.take().unwrap(),
+            seq: seq.clone(),
+            client_id,
+            vport,
+            exclusive: options.exclusive,
             trigger_rcv_fd: trigger_fds[0],
             callback: Box::new(callback),
+            subscription_callback: subscription_callback.clone(),
             queue_id: queue_id,
         };
 
@@ -438,13 +907,37 @@ impl MidiInput {
         Ok(MidiInputConnection {
             subscription: None,
             thread: Some(thread),
+            client_id,
             vport: vport,
+            duplex: None,
             trigger_send_fd: trigger_fds[1],
+            seq,
+            subscription_callback,
         })
     }
 }
 
 impl<T> MidiInputConnection<T> {
+    pub fn port(&self) -> Addr {
+        Addr {
+            client: self.client_id,
+            port: self.vport,
+        }
+    }
+
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        helpers::get_subscribers(&self.seq.lock(), self.port())
+    }
+
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        // The events are delivered to our own client, so the input handler thread reports them
+        *self.subscription_callback.lock().unwrap() = Some(Box::new(callback));
+        Ok(())
+    }
+
     pub fn close(mut self) -> (MidiInput, T) {
         let (handler_data, user_data) = self.close_internal();
 
@@ -486,6 +979,7 @@ impl<T> MidiInputConnection<T> {
         if let Some(ref subscription) = self.subscription {
             let _ = handler_data
                 .seq
+                .lock()
                 .unsubscribe_port(subscription.get_sender(), subscription.get_dest());
         }
 
@@ -496,16 +990,12 @@ impl<T> MidiInputConnection<T> {
         }
 
         // Stop and free the input queue
-        if !cfg!(feature = "avoid_timestamping") {
-            let _ = handler_data
-                .seq
-                .control_queue(handler_data.queue_id, EventType::Stop, 0, None);
-            let _ = handler_data.seq.drain_output();
-            let _ = handler_data.seq.free_queue(handler_data.queue_id);
-        }
+        helpers::stop_queue(&handler_data.seq.lock(), handler_data.queue_id);
 
-        // Delete the port
-        let _ = handler_data.seq.delete_port(self.vport);
+        // Delete the port, unless it is a duplex port that is still used for sending
+        if self.duplex.take().is_none() {
+            let _ = handler_data.seq.lock().delete_port(self.vport);
+        }
 
         (handler_data, user_data)
     }
@@ -521,7 +1011,7 @@ impl<T> Drop for MidiInputConnection<T> {
 }
 
 pub struct MidiOutput {
-    seq: Option<Seq>, // TODO: if `Seq` is marked as non-zero, this should just be pointer-sized
+    seq: Option<Arc<SharedSeq>>, // shared with a `MidiInput` if this object has been returned by the sending half of a duplex port
 }
 
 #[derive(Clone, PartialEq)]
@@ -533,13 +1023,25 @@ impl MidiOutputPort {
     pub fn id(&self) -> String {
         format!("{}:{}", self.addr.client, self.addr.port)
     }
+
+    pub fn from_addr(addr: Addr) -> Self {
+        MidiOutputPort { addr }
+    }
+
+    pub fn addr(&self) -> Addr {
+        self.addr
+    }
 }
 
 pub struct MidiOutputConnection {
-    seq: Option<Seq>,
+    seq: Option<Arc<SharedSeq>>,
+    client_id: i32,
     vport: i32,
     coder: helpers::EventEncoder,
     subscription: Option<PortSubscribe>,
+    duplex: Option<Arc<DuplexPort>>, // if set, `vport` is shared with the receiving connection
+    watcher: Option<SubscriptionWatcher>,
+    subscription_callback: SharedSubscriptionCallback,
 }
 
 impl MidiOutput {
@@ -554,28 +1056,66 @@ impl MidiOutput {
         let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
         seq.set_client_name(&c_client_name).map_err(|_| InitError)?;
 
-        Ok(MidiOutput { seq: Some(seq) })
+        Ok(MidiOutput {
+            seq: Some(Arc::new(SharedSeq::new(seq))),
+        })
+    }
+
+    pub fn from_seq(seq: Seq) -> Self {
+        MidiOutput {
+            seq: Some(Arc::new(SharedSeq::new(seq))),
+        }
+    }
+
+    pub fn with_seq<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Seq) -> R,
+    {
+        f(&self.lock_seq())
+    }
+
+    fn lock_seq(&self) -> MutexGuard<'_, Seq> {
+        self.seq.as_ref().unwrap().lock()
     }
 
     pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        self.ports_filtered(&PortFilter::new())
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.port_count_filtered(&PortFilter::new())
+    }
+
+    pub(crate) fn ports_filtered(&self, filter: &PortFilter) -> Vec<crate::common::MidiOutputPort> {
         helpers::get_ports(
-            self.seq.as_ref().unwrap(),
+            &self.lock_seq(),
             PortCap::WRITE | PortCap::SUBS_WRITE,
+            filter,
             |p| crate::common::MidiOutputPort {
-                imp: MidiOutputPort { addr: p.addr() },
+                imp: MidiOutputPort { addr: p.addr() }.into(),
             },
         )
     }
 
-    pub fn port_count(&self) -> usize {
+    pub fn port_count_filtered(&self, filter: &PortFilter) -> usize {
         helpers::get_port_count(
-            self.seq.as_ref().unwrap(),
+            &self.lock_seq(),
             PortCap::WRITE | PortCap::SUBS_WRITE,
+            filter,
         )
     }
 
     pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
-        helpers::get_port_name(self.seq.as_ref().unwrap(), port.addr)
+        helpers::get_port_name(&self.lock_seq(), port.addr)
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // All ports of a device belong to the same ALSA sequencer client
+        Ok(port.addr.client.to_string())
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        helpers::get_client_name(&self.lock_seq(), port.addr.client)
     }
 
     pub fn connect(
@@ -583,7 +1123,12 @@ impl MidiOutput {
         port: &MidiOutputPort,
         port_name: &str,
     ) -> Result<MidiOutputConnection, ConnectError<Self>> {
-        let pinfo = match self.seq.as_ref().unwrap().get_any_port_info(port.addr) {
+        let client_id = match self.with_seq(|seq| seq.client_id()) {
+            Ok(id) => id,
+            Err(_) => return Err(ConnectError::other("could not get ALSA client id", self)),
+        };
+
+        let pinfo = match self.with_seq(|seq| seq.get_any_port_info(port.addr)) {
             Ok(p) => p,
             Err(_) => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
         };
@@ -598,11 +1143,13 @@ impl MidiOutput {
             }
         };
 
-        let vport = match self.seq.as_ref().unwrap().create_simple_port(
-            &c_port_name,
-            PortCap::READ | PortCap::SUBS_READ,
-            PortType::MIDI_GENERIC | PortType::APPLICATION,
-        ) {
+        let vport = match self.with_seq(|seq| {
+            seq.create_simple_port(
+                &c_port_name,
+                PortCap::READ | PortCap::SUBS_READ,
+                PortType::MIDI_GENERIC | PortType::APPLICATION,
+            )
+        }) {
             Ok(vport) => vport,
             Err(_) => {
                 return Err(ConnectError::other(
@@ -615,13 +1162,13 @@ impl MidiOutput {
         // Make subscription
         let sub = PortSubscribe::empty().unwrap();
         sub.set_sender(Addr {
-            client: self.seq.as_ref().unwrap().client_id().unwrap(),
+            client: client_id,
             port: vport,
         });
         sub.set_dest(pinfo.addr());
         sub.set_time_update(true);
         sub.set_time_real(true);
-        if self.seq.as_ref().unwrap().subscribe_port(&sub).is_err() {
+        if self.lock_seq().subscribe_port(&sub).is_err() {
             return Err(ConnectError::other(
                 "could not create ALSA output subscription",
                 self,
@@ -630,16 +1177,33 @@ impl MidiOutput {
 
         Ok(MidiOutputConnection {
             seq: self.seq.take(),
+            client_id,
             vport: vport,
             coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
             subscription: Some(sub),
+            duplex: None,
+            watcher: None,
+            subscription_callback: SharedSubscriptionCallback::default(),
         })
     }
 
     pub fn create_virtual(
         mut self,
         port_name: &str,
+        options: &VirtualPortOptions,
     ) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        if options.exclusive && options.no_export {
+            return Err(ConnectError::other(
+                "exclusive ALSA output ports cannot be hidden with no_export",
+                self,
+            ));
+        }
+
+        let client_id = match self.with_seq(|seq| seq.client_id()) {
+            Ok(id) => id,
+            Err(_) => return Err(ConnectError::other("could not get ALSA client id", self)),
+        };
+
         let c_port_name = match CString::new(port_name) {
             Ok(c_port_name) => c_port_name,
             Err(_) => {
@@ -650,11 +1214,9 @@ impl MidiOutput {
             }
         };
 
-        let vport = match self.seq.as_ref().unwrap().create_simple_port(
-            &c_port_name,
-            PortCap::READ | PortCap::SUBS_READ,
-            PortType::MIDI_GENERIC | PortType::APPLICATION,
-        ) {
+        let mut pinfo =
+            helpers::new_port_info(&c_port_name, PortCap::READ | PortCap::SUBS_READ, options);
+        let vport = match self.with_seq(|seq| helpers::create_port(seq, &mut pinfo, options)) {
             Ok(vport) => vport,
             Err(_) => {
                 return Err(ConnectError::other(
@@ -664,16 +1226,77 @@ impl MidiOutput {
             }
         };
 
+        // Our own client cannot receive the subscription events that are needed to keep
+        // the port exclusive, so another one has to watch the port from the start
+        let subscription_callback = SharedSubscriptionCallback::default();
+        let watcher = if options.exclusive {
+            let addr = Addr {
+                client: client_id,
+                port: vport,
+            };
+            match SubscriptionWatcher::start(addr, true, subscription_callback.clone()) {
+                Ok(watcher) => Some(watcher),
+                Err(_) => {
+                    let _ = self.lock_seq().delete_port(vport);
+                    return Err(ConnectError::other(
+                        "could not watch subscriptions of ALSA output port",
+                        self,
+                    ));
+                }
+            }
+        } else {
+            None
+        };
+
         Ok(MidiOutputConnection {
             seq: self.seq.take(),
+            client_id,
             vport: vport,
             coder: helpers::EventEncoder::new(INITIAL_CODER_BUFFER_SIZE as u32),
             subscription: None,
+            duplex: None,
+            watcher,
+            subscription_callback,
         })
     }
 }
 
 impl MidiOutputConnection {
+    pub fn port(&self) -> Addr {
+        Addr {
+            client: self.client_id,
+            port: self.vport,
+        }
+    }
+
+    pub fn with_seq<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Seq) -> R,
+    {
+        f(&self.lock_seq())
+    }
+
+    fn lock_seq(&self) -> MutexGuard<'_, Seq> {
+        self.seq.as_ref().unwrap().lock()
+    }
+
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        helpers::get_subscribers(&self.lock_seq(), self.port())
+    }
+
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        *self.subscription_callback.lock().unwrap() = Some(Box::new(callback));
+        if self.watcher.is_none() {
+            // Our own client cannot receive events, so another one is needed to watch the port
+            let callback = self.subscription_callback.clone();
+            self.watcher = Some(SubscriptionWatcher::start(self.port(), false, callback)?);
+        }
+        Ok(())
+    }
+
     pub fn close(mut self) -> MidiOutput {
         self.close_internal();
 
@@ -683,41 +1306,22 @@ impl MidiOutputConnection {
     }
 
     pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
-        let nbytes = message.len();
-        assert!(nbytes <= u32::max_value() as usize);
-
-        if nbytes > self.coder.get_buffer_size() as usize {
-            if self.coder.resize_buffer(nbytes as u32).is_err() {
-                return Err(SendError::Other("could not resize ALSA encoding buffer"));
-            }
-        }
-
-        let mut ev = match self.coder.get_wrapped().encode(message) {
-            Ok((_, Some(ev))) => ev,
-            _ => return Err(SendError::InvalidData("ALSA encoder reported invalid data")),
-        };
-
-        ev.set_source(self.vport);
-        ev.set_subs();
-        ev.set_direct();
-
         // Send the event.
-        if self
-            .seq
-            .as_ref()
-            .unwrap()
-            .event_output_direct(&mut ev)
-            .is_err()
-        {
-            return Err(SendError::Other("could not send encoded ALSA message"));
-        }
-
-        let _ = self.seq.as_mut().unwrap().drain_output();
+        let seq = self.seq.as_ref().unwrap().lock();
+        helpers::send_message(&seq, &mut self.coder, self.vport, message)?;
+        let _ = seq.drain_output();
         Ok(())
     }
 
     fn close_internal(&mut self) {
-        let seq = self.seq.as_mut().unwrap();
+        self.watcher = None;
+
+        // A duplex port is deleted once its receiving connection has been closed as well
+        if self.duplex.take().is_some() {
+            return;
+        }
+
+        let seq = self.lock_seq();
         if let Some(ref subscription) = self.subscription {
             let _ = seq.unsubscribe_port(subscription.get_sender(), subscription.get_dest());
         }
@@ -733,182 +1337,241 @@ impl Drop for MidiOutputConnection {
     }
 }
 
+/// What the input handler thread has received with an event.
+enum Received {
+    /// A message is complete and can be taken from the `InputDecoder`.
+    Message,
+    Subscription(SubscriptionEvent),
+}
+
 fn handle_input<T>(mut data: HandlerData<T>, user_data: &mut T) -> HandlerData<T> {
-    use alsa::seq::Connect;
-    use alsa::PollDescriptors;
-    use libc::pollfd;
-
-    const INVALID_POLLFD: pollfd = pollfd {
-        fd: -1,
-        events: 0,
-        revents: 0,
+    let mut decoder = InputDecoder::new();
+    let ignore_flags = data.ignore_flags;
+    let exclusive = data.exclusive;
+    let addr = Addr {
+        client: data.client_id,
+        port: data.vport,
     };
+    let mut reader = EventReader::new(&data.seq.lock(), data.trigger_rcv_fd);
 
-    let mut continue_sysex: bool = false;
+    // The callbacks are only called after the client has been unlocked again
+    while let Some(received) = reader.next(&data.seq, |seq, ev| {
+        if exclusive {
+            helpers::reject_subscription(seq, ev, addr);
+        }
+        match helpers::subscription_event(seq, ev, addr) {
+            Some(event) => Some(Received::Subscription(event)),
+            None => decoder
+                .decode(ev, ignore_flags)
+                .then_some(Received::Message),
+        }
+    }) {
+        match received {
+            Some(Received::Message) => {
+                let message = decoder.message();
+                (data.callback)(message.timestamp, &message.bytes, user_data);
+            }
+            Some(Received::Subscription(event)) => {
+                if let Some(ref mut on_change) = *data.subscription_callback.lock().unwrap() {
+                    on_change(event);
+                }
+            }
+            None => {}
+        }
+    }
 
-    // ALSA documentation says:
-    // The required buffer size for a sequencer event it as most 12 bytes, except for System Exclusive events (which we handle separately)
-    let mut buffer = [0; 12];
+    data // return data back to thread owner
+}
 
-    let mut coder = helpers::EventDecoder::new(false);
+/// Waits for the events of a sequencer client, until a request
+/// to stop is received through the trigger pipe.
+struct EventReader {
+    poll_fds: Vec<libc::pollfd>,
+    stopped: bool,
+}
 
-    let poll_desc_info = (&data.seq, Some(Direction::Capture));
-    let mut poll_fds = vec![INVALID_POLLFD; poll_desc_info.count() + 1];
-    poll_fds[0] = pollfd {
-        fd: data.trigger_rcv_fd,
-        events: libc::POLLIN,
-        revents: 0,
-    };
+impl EventReader {
+    fn new(seq: &Seq, trigger_rcv_fd: i32) -> EventReader {
+        use alsa::PollDescriptors;
+        use libc::pollfd;
+
+        const INVALID_POLLFD: pollfd = pollfd {
+            fd: -1,
+            events: 0,
+            revents: 0,
+        };
 
-    poll_desc_info.fill(&mut poll_fds[1..]).unwrap();
+        let poll_desc_info = (seq, Some(Direction::Capture));
+        let mut poll_fds = vec![INVALID_POLLFD; poll_desc_info.count() + 1];
+        poll_fds[0] = pollfd {
+            fd: trigger_rcv_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
 
-    let mut message = MidiMessage::new();
+        poll_desc_info.fill(&mut poll_fds[1..]).unwrap();
 
+        EventReader {
+            poll_fds,
+            stopped: false,
+        }
+    }
+
+    /// Waits for the next event and passes it to `f` while the client is locked.
+    /// Returns `None` once a request to stop has been received.
+    fn next<F, R>(&mut self, seq: &SharedSeq, f: F) -> Option<R>
+    where
+        F: FnOnce(&Seq, &mut Event<'_>) -> R,
     {
-        // open scope where we can borrow data.seq
-        let mut seq_input = data.seq.input();
-
-        let mut do_input = true;
-        while do_input {
-            if let Ok(0) = seq_input.event_input_pending(true) {
-                // No data pending
-                if helpers::poll(&mut poll_fds, -1) >= 0 {
-                    // Read from our "channel" whether we should stop the thread
-                    if poll_fds[0].revents & libc::POLLIN != 0 {
-                        let _res = unsafe {
-                            libc::read(
-                                poll_fds[0].fd,
-                                mem::transmute(&mut do_input),
-                                mem::size_of::<bool>() as libc::size_t,
-                            )
-                        };
+        while !self.stopped {
+            {
+                let seq = seq.lock();
+                let mut seq_input = seq.input();
+                if !matches!(seq_input.event_input_pending(true), Ok(0)) {
+                    // If here, there should be data.
+                    match seq_input.event_input() {
+                        Ok(mut ev) => return Some(f(&seq, &mut ev)),
+                        Err(ref e) if e.errno() == libc::ENOSPC => {
+                            let _ = writeln!(
+                                stderr(),
+                                "\nError in handle_input: ALSA MIDI input buffer overrun!\n"
+                            );
+                        }
+                        Err(ref e) if e.errno() == libc::EAGAIN => {
+                            let _ = writeln!(
+                                stderr(),
+                                "\nError in handle_input: no input event from ALSA MIDI input buffer!\n"
+                            );
+                        }
+                        Err(ref e) => {
+                            let _ = writeln!(
+                                stderr(),
+                                "\nError in handle_input: unknown ALSA MIDI input error ({})!\n",
+                                e
+                            );
+                        }
                     }
-                }
-                continue;
-            }
-
-            // This is a bit weird, but we now have to decode an ALSA MIDI
-            // event (back) into MIDI bytes. We'll ignore non-MIDI types.
-
-            // The ALSA sequencer has a maximum buffer size for MIDI sysex
-            // events of 256 bytes. If a device sends sysex messages larger
-            // than this, they are segmented into 256 byte chunks.    So,
-            // we'll watch for this and concatenate sysex chunks into a
-            // single sysex message if necessary.
-            //
-            // TODO: Figure out if this is still true (seems to not be the case)
-            //       If not (i.e., each event represents a complete message), we can
-            //       call the user callback with the byte buffer directly, without the
-            //       copying to `message.bytes` first.
-            if !continue_sysex {
-                message.bytes.clear()
-            }
-
-            let ignore_flags = data.ignore_flags;
-
-            // If here, there should be data.
-            let mut ev = match seq_input.event_input() {
-                Ok(ev) => ev,
-                Err(ref e) if e.errno() == libc::ENOSPC => {
-                    let _ = writeln!(
-                        stderr(),
-                        "\nError in handle_input: ALSA MIDI input buffer overrun!\n"
-                    );
                     continue;
                 }
-                Err(ref e) if e.errno() == libc::EAGAIN => {
-                    let _ = writeln!(
-                        stderr(),
-                        "\nError in handle_input: no input event from ALSA MIDI input buffer!\n"
-                    );
-                    continue;
-                }
-                Err(ref e) => {
-                    let _ = writeln!(
-                        stderr(),
-                        "\nError in handle_input: unknown ALSA MIDI input error ({})!\n",
-                        e
-                    );
-                    //perror("System reports");
-                    continue;
-                }
-            };
+            }
 
-            let do_decode = match ev.get_type() {
-                EventType::PortSubscribed => {
-                    if cfg!(debug) {
-                        println!("Notice from handle_input: ALSA port connection made!")
+            // No data pending, so wait for more without locking the client
+            if helpers::poll(&mut self.poll_fds, -1) >= 0 {
+                // Read from our "channel" whether we should stop the thread
+                if self.poll_fds[0].revents & libc::POLLIN != 0 {
+                    let mut do_input = true;
+                    let _res = unsafe {
+                        libc::read(
+                            self.poll_fds[0].fd,
+                            &mut do_input as *mut bool as *mut libc::c_void,
+                            mem::size_of::<bool>() as libc::size_t,
+                        )
                     };
-                    false
+                    self.stopped = !do_input;
                 }
-                EventType::PortUnsubscribed => {
-                    if cfg!(debug) {
-                        let _ = writeln!(
-                            stderr(),
-                            "Notice from handle_input: ALSA port connection has closed!"
-                        );
-                        let connect = ev.get_data::<Connect>().unwrap();
-                        let _ = writeln!(
-                            stderr(),
-                            "sender = {}:{}, dest = {}:{}",
-                            connect.sender.client,
-                            connect.sender.port,
-                            connect.dest.client,
-                            connect.dest.port
-                        );
-                    }
-                    false
-                }
-                EventType::Qframe => {
-                    // MIDI time code
-                    !ignore_flags.contains(Ignore::Time)
-                }
-                EventType::Tick => {
-                    // 0xF9 ... MIDI timing tick
-                    !ignore_flags.contains(Ignore::Time)
-                }
-                EventType::Clock => {
-                    // 0xF8 ... MIDI timing (clock) tick
-                    !ignore_flags.contains(Ignore::Time)
-                }
-                EventType::Sensing => {
-                    // Active sensing
-                    !ignore_flags.contains(Ignore::ActiveSense)
-                }
-                EventType::Sysex => {
-                    if !ignore_flags.contains(Ignore::Sysex) {
-                        // Directly copy the data from the external buffer to our message
-                        message.bytes.extend_from_slice(ev.get_ext().unwrap());
-                        continue_sysex = *message.bytes.last().unwrap() != 0xF7;
-                    }
-                    false // don't ever decode sysex messages (it would unnecessarily copy the message content to another buffer)
-                }
-                _ => true,
-            };
+            }
+        }
+        None
+    }
+}
 
-            // NOTE: SysEx messages have already been "decoded" at this point!
-            if do_decode {
-                if let Ok(nbytes) = coder.get_wrapped().decode(&mut buffer, &mut ev) {
-                    if nbytes > 0 {
-                        message.bytes.extend_from_slice(&buffer[0..nbytes]);
-                    }
+/// Decodes the events received by one port (back) into MIDI messages.
+struct InputDecoder {
+    coder: helpers::EventDecoder,
+    message: MidiMessage,
+    continue_sysex: bool,
+    // ALSA documentation says:
+    // The required buffer size for a sequencer event it as most 12 bytes, except for System Exclusive events (which we handle separately)
+    buffer: [u8; 12],
+}
+
+impl InputDecoder {
+    fn new() -> InputDecoder {
+        InputDecoder {
+            coder: helpers::EventDecoder::new(false),
+            message: MidiMessage::new(),
+            continue_sysex: false,
+            buffer: [0; 12],
+        }
+    }
+
+    /// The last message that has been decoded.
+    fn message(&self) -> &MidiMessage {
+        &self.message
+    }
+
+    /// Decodes the event, returning whether a message is complete.
+    fn decode(&mut self, ev: &mut Event<'_>, ignore_flags: Ignore) -> bool {
+        let message = &mut self.message;
+
+        // This is a bit weird, but we now have to decode an ALSA MIDI
+        // event (back) into MIDI bytes. We'll ignore non-MIDI types.
+
+        // The ALSA sequencer has a maximum buffer size for MIDI sysex
+        // events of 256 bytes. If a device sends sysex messages larger
+        // than this, they are segmented into 256 byte chunks.    So,
+        // we'll watch for this and concatenate sysex chunks into a
+        // single sysex message if necessary.
+        //
+        // TODO: Figure out if this is still true (seems to not be the case)
+        //       If not (i.e., each event represents a complete message), we can
+        //       call the user callback with the byte buffer directly, without the
+        //       copying to `message.bytes` first.
+        if !self.continue_sysex {
+            message.bytes.clear()
+        }
+
+        let do_decode = match ev.get_type() {
+            // Changes of subscriptions are reported by `handle_input`
+            EventType::PortSubscribed | EventType::PortUnsubscribed => false,
+            EventType::Qframe => {
+                // MIDI time code
+                !ignore_flags.contains(Ignore::Time)
+            }
+            EventType::Tick => {
+                // 0xF9 ... MIDI timing tick
+                !ignore_flags.contains(Ignore::Time)
+            }
+            EventType::Clock => {
+                // 0xF8 ... MIDI timing (clock) tick
+                !ignore_flags.contains(Ignore::Time)
+            }
+            EventType::Sensing => {
+                // Active sensing
+                !ignore_flags.contains(Ignore::ActiveSense)
+            }
+            EventType::Sysex => {
+                if !ignore_flags.contains(Ignore::Sysex) {
+                    // Directly copy the data from the external buffer to our message
+                    message.bytes.extend_from_slice(ev.get_ext().unwrap());
+                    self.continue_sysex = *message.bytes.last().unwrap() != 0xF7;
                 }
+                false // don't ever decode sysex messages (it would unnecessarily copy the message content to another buffer)
             }
+            _ => true,
+        };
 
-            if message.bytes.len() == 0 || continue_sysex {
-                continue;
+        // NOTE: SysEx messages have already been "decoded" at this point!
+        if do_decode {
+            if let Ok(nbytes) = self.coder.get_wrapped().decode(&mut self.buffer, ev) {
+                if nbytes > 0 {
+                    message.bytes.extend_from_slice(&self.buffer[0..nbytes]);
+                }
             }
+        }
 
-            // Calculate the time stamp:
-            // Use the ALSA sequencer event time data.
-            // (thanks to Pedro Lopez-Cabanillas!).
-            let alsa_time = ev.get_time().unwrap();
-            let secs = alsa_time.as_secs();
-            let nsecs = alsa_time.subsec_nanos();
-
-            message.timestamp = (secs as u64 * 1_000_000) + (nsecs as u64 / 1_000);
-            (data.callback)(message.timestamp, &message.bytes, user_data);
+        if message.bytes.len() == 0 || self.continue_sysex {
+            return false;
         }
-    } // close scope where data.seq is borrowed
-    data // return data back to thread owner
+
+        // Calculate the time stamp:
+        // Use the ALSA sequencer event time data.
+        // (thanks to Pedro Lopez-Cabanillas!).
+        let alsa_time = ev.get_time().unwrap();
+        let secs = alsa_time.as_secs();
+        let nsecs = alsa_time.subsec_nanos();
+
+        message.timestamp = (secs * 1_000_000) + (nsecs as u64 / 1_000);
+        true
+    }
 }
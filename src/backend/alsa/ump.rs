@@ -0,0 +1,612 @@
+//! Sequencer clients in UMP (MIDI 2.0) mode.
+//!
+//! The UMP functions of alsa-lib only exist since version 1.2.10, so they are looked up
+//! at runtime instead of being linked, which keeps midir working with older versions.
+//! The `alsa` crate does not give access to the handle of a `Seq`, so UMP clients are
+//! opened and used through `alsa-sys` directly.
+
+use std::ffi::{CStr, CString};
+use std::io::{stderr, Write};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use alsa::seq::{Addr, PortCap, PortType};
+use alsa_sys::{
+    snd_seq_addr_t, snd_seq_client_info_t, snd_seq_port_info_t, snd_seq_t, snd_seq_timestamp_t,
+};
+
+use crate::errors::*;
+use crate::ump::packet_len;
+
+/// The `midi_version` of a client whose packets use the MIDI 1.0 protocol.
+pub const CLIENT_UMP_MIDI_1_0: c_int = 1;
+/// The `midi_version` of a client whose packets use the MIDI 2.0 protocol.
+pub const CLIENT_UMP_MIDI_2_0: c_int = 2;
+
+/// The flag of events that carry a Universal MIDI Packet.
+const EVENT_UMP: u8 = 1 << 5;
+
+pub const EP_PROTO_MIDI1: c_uint = 0x0100;
+pub const EP_PROTO_MIDI2: c_uint = 0x0200;
+
+pub const DIR_INPUT: c_uint = 0x01;
+pub const DIR_OUTPUT: c_uint = 0x02;
+pub const DIR_BIDIRECTION: c_uint = 0x03;
+
+/// The port of a UMP client that represents the whole endpoint,
+/// the ports of the groups are numbered from 1.
+pub const ENDPOINT_PORT: i32 = 0;
+
+/// `snd_ump_endpoint_info_t` and `snd_ump_block_info_t` are opaque.
+type Info = c_void;
+
+macro_rules! ump_functions {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        /// The UMP functions of alsa-lib.
+        pub struct UmpLib {
+            $(pub $name: unsafe extern "C" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl UmpLib {
+            fn load() -> Option<UmpLib> {
+                Some(UmpLib {
+                    $($name: unsafe {
+                        mem::transmute::<*mut c_void, unsafe extern "C" fn($($arg),*) $(-> $ret)?>(
+                            symbol(concat!(stringify!($name), "\0"))?,
+                        )
+                    },)*
+                })
+            }
+        }
+    };
+}
+
+ump_functions! {
+    snd_seq_set_client_midi_version: fn(*mut snd_seq_t, c_int) -> c_int;
+    snd_seq_ump_event_input: fn(*mut snd_seq_t, *mut *mut UmpEvent) -> c_int;
+    snd_seq_ump_event_output_direct: fn(*mut snd_seq_t, *mut UmpEvent) -> c_int;
+    snd_seq_get_ump_endpoint_info: fn(*mut snd_seq_t, c_int, *mut Info) -> c_int;
+    snd_seq_get_ump_block_info: fn(*mut snd_seq_t, c_int, c_int, *mut Info) -> c_int;
+    snd_seq_create_ump_endpoint: fn(*mut snd_seq_t, *const Info, c_uint) -> c_int;
+    snd_seq_create_ump_block: fn(*mut snd_seq_t, c_int, *const Info) -> c_int;
+    snd_ump_endpoint_info_malloc: fn(*mut *mut Info) -> c_int;
+    snd_ump_endpoint_info_free: fn(*mut Info);
+    snd_ump_endpoint_info_get_name: fn(*const Info) -> *const c_char;
+    snd_ump_endpoint_info_get_protocol: fn(*const Info) -> c_uint;
+    snd_ump_endpoint_info_get_protocol_caps: fn(*const Info) -> c_uint;
+    snd_ump_endpoint_info_get_num_blocks: fn(*const Info) -> c_uint;
+    snd_ump_endpoint_info_set_name: fn(*mut Info, *const c_char);
+    snd_ump_endpoint_info_set_protocol: fn(*mut Info, c_uint);
+    snd_ump_endpoint_info_set_protocol_caps: fn(*mut Info, c_uint);
+    snd_ump_endpoint_info_set_num_blocks: fn(*mut Info, c_uint);
+    snd_ump_endpoint_info_set_version: fn(*mut Info, c_uint);
+    snd_ump_block_info_malloc: fn(*mut *mut Info) -> c_int;
+    snd_ump_block_info_free: fn(*mut Info);
+    snd_ump_block_info_get_block_id: fn(*const Info) -> c_uint;
+    snd_ump_block_info_get_name: fn(*const Info) -> *const c_char;
+    snd_ump_block_info_get_active: fn(*const Info) -> c_uint;
+    snd_ump_block_info_get_direction: fn(*const Info) -> c_uint;
+    snd_ump_block_info_get_first_group: fn(*const Info) -> c_uint;
+    snd_ump_block_info_get_num_groups: fn(*const Info) -> c_uint;
+    snd_ump_block_info_set_block_id: fn(*mut Info, c_uint);
+    snd_ump_block_info_set_name: fn(*mut Info, *const c_char);
+    snd_ump_block_info_set_active: fn(*mut Info, c_uint);
+    snd_ump_block_info_set_direction: fn(*mut Info, c_uint);
+    snd_ump_block_info_set_first_group: fn(*mut Info, c_uint);
+    snd_ump_block_info_set_num_groups: fn(*mut Info, c_uint);
+}
+
+fn symbol(name: &str) -> Option<*mut c_void> {
+    let p = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr() as *const c_char) };
+    if p.is_null() {
+        None
+    } else {
+        Some(p)
+    }
+}
+
+/// Returns the UMP functions, or `None` if alsa-lib is too old to have them.
+pub fn lib() -> Option<&'static UmpLib> {
+    static LIB: OnceLock<Option<UmpLib>> = OnceLock::new();
+    LIB.get_or_init(UmpLib::load).as_ref()
+}
+
+/// `snd_seq_ump_event_t`, a sequencer event that carries a Universal MIDI Packet.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UmpEvent {
+    type_: u8,
+    flags: u8,
+    tag: u8,
+    queue: u8,
+    time: snd_seq_timestamp_t,
+    source: snd_seq_addr_t,
+    dest: snd_seq_addr_t,
+    ump: [u32; 4],
+}
+
+impl UmpEvent {
+    /// Returns the packet of the event, or `None` if it is not a UMP event
+    /// (e.g. an announcement of the system client).
+    pub fn packet(&self) -> Option<&[u32]> {
+        if self.flags & EVENT_UMP == 0 {
+            return None;
+        }
+        Some(&self.ump[..packet_len(self.ump[0])])
+    }
+
+    /// Returns the port of our own client that the event has been delivered to.
+    pub fn dest_port(&self) -> i32 {
+        self.dest.port as i32
+    }
+}
+
+/// The information about a UMP endpoint.
+pub struct EndpointInfo {
+    pub name: String,
+    pub protocol: c_uint,
+    pub protocol_caps: c_uint,
+    pub num_blocks: c_uint,
+}
+
+/// The information about a function block of a UMP endpoint.
+pub struct BlockInfo {
+    pub id: c_uint,
+    pub name: String,
+    pub active: bool,
+    pub direction: c_uint,
+    pub first_group: c_uint,
+    pub num_groups: c_uint,
+}
+
+/// An owned `snd_ump_endpoint_info_t` or `snd_ump_block_info_t`.
+struct InfoBox {
+    p: *mut Info,
+    free: unsafe extern "C" fn(*mut Info),
+}
+
+impl InfoBox {
+    fn new(
+        malloc: unsafe extern "C" fn(*mut *mut Info) -> c_int,
+        free: unsafe extern "C" fn(*mut Info),
+    ) -> Result<InfoBox, ()> {
+        let mut p = ptr::null_mut();
+        if unsafe { malloc(&mut p) } < 0 {
+            return Err(());
+        }
+        Ok(InfoBox { p, free })
+    }
+}
+
+impl Drop for InfoBox {
+    fn drop(&mut self) {
+        unsafe { (self.free)(self.p) }
+    }
+}
+
+fn string_from(p: *const c_char) -> String {
+    if p.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+}
+
+/// The handle of a sequencer client.
+struct Handle(*mut snd_seq_t);
+
+unsafe impl Send for Handle {}
+
+/// A sequencer client in UMP mode.
+///
+/// Like `SharedSeq`, it can be shared between the input handler thread and other threads
+/// that send events or query the sequencer, so every use of the handle is serialized.
+/// The input handler thread only locks it while it reads a single event.
+pub struct UmpSeq {
+    handle: Mutex<Handle>,
+    lib: &'static UmpLib,
+}
+
+impl UmpSeq {
+    /// Opens a client that exchanges packets with the given `midi_version`.
+    pub fn open(client_name: &str, midi_version: c_int) -> Result<UmpSeq, InitError> {
+        let lib = lib().ok_or(InitError)?;
+        let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
+        let mut p = ptr::null_mut();
+        let rc = unsafe {
+            alsa_sys::snd_seq_open(
+                &mut p,
+                c"default".as_ptr(),
+                alsa_sys::SND_SEQ_OPEN_DUPLEX,
+                alsa_sys::SND_SEQ_NONBLOCK,
+            )
+        };
+        if rc < 0 {
+            return Err(InitError);
+        }
+        let seq = UmpSeq {
+            handle: Mutex::new(Handle(p)),
+            lib,
+        };
+        // This fails if the kernel does not support UMP (before Linux 6.5)
+        if unsafe { (lib.snd_seq_set_client_midi_version)(p, midi_version) } < 0
+            || unsafe { alsa_sys::snd_seq_set_client_name(p, c_client_name.as_ptr()) } < 0
+        {
+            return Err(InitError);
+        }
+        Ok(seq)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Handle> {
+        self.handle.lock().unwrap()
+    }
+
+    pub fn client_id(&self) -> i32 {
+        unsafe { alsa_sys::snd_seq_client_id(self.lock().0) }
+    }
+
+    /// Get the addresses of the ports of other clients that have the given capabilities.
+    pub fn ports(&self, capability: PortCap) -> Vec<Addr> {
+        let own_client = self.client_id();
+        let mut result = Vec::new();
+        self.for_each_client(|client| {
+            if client == own_client || client == 0 {
+                return; // skip our own ports and those of the `System` client
+            }
+            self.for_each_port(client, |pinfo| {
+                let caps = PortCap::from_bits_truncate(unsafe {
+                    alsa_sys::snd_seq_port_info_get_capability(pinfo)
+                });
+                if caps.contains(capability) && !caps.contains(PortCap::NO_EXPORT) {
+                    result.push(Addr {
+                        client,
+                        port: unsafe { alsa_sys::snd_seq_port_info_get_port(pinfo) },
+                    });
+                }
+            });
+        });
+        result
+    }
+
+    /// Get the name of a port, in the same format as `helpers::get_port_name`.
+    pub fn port_name(&self, addr: Addr) -> Result<String, PortInfoError> {
+        let pinfo = PortInfo::new().map_err(|_| PortInfoError::CannotRetrievePortName)?;
+        if unsafe {
+            alsa_sys::snd_seq_get_any_port_info(self.lock().0, addr.client, addr.port, pinfo.0)
+        } < 0
+        {
+            return Err(PortInfoError::InvalidPort);
+        }
+        let cinfo = ClientInfo::new().map_err(|_| PortInfoError::CannotRetrievePortName)?;
+        if unsafe { alsa_sys::snd_seq_get_any_client_info(self.lock().0, addr.client, cinfo.0) } < 0
+        {
+            return Err(PortInfoError::CannotRetrievePortName);
+        }
+        Ok(format!(
+            "{}:{} {}:{}",
+            string_from(unsafe { alsa_sys::snd_seq_client_info_get_name(cinfo.0) }),
+            string_from(unsafe { alsa_sys::snd_seq_port_info_get_name(pinfo.0) }),
+            addr.client,
+            addr.port
+        ))
+    }
+
+    /// Get the clients that are UMP endpoints, with their endpoint information.
+    pub fn endpoints(&self) -> Vec<(i32, EndpointInfo)> {
+        let mut result = Vec::new();
+        self.for_each_client(|client| {
+            if let Some(info) = self.endpoint_info(client) {
+                result.push((client, info));
+            }
+        });
+        result
+    }
+
+    /// Get the endpoint information of a client, or `None` if it is not a UMP endpoint.
+    pub fn endpoint_info(&self, client: i32) -> Option<EndpointInfo> {
+        let lib = self.lib;
+        let info = InfoBox::new(
+            lib.snd_ump_endpoint_info_malloc,
+            lib.snd_ump_endpoint_info_free,
+        )
+        .ok()?;
+        unsafe {
+            if (lib.snd_seq_get_ump_endpoint_info)(self.lock().0, client, info.p) < 0 {
+                return None;
+            }
+            Some(EndpointInfo {
+                name: string_from((lib.snd_ump_endpoint_info_get_name)(info.p)),
+                protocol: (lib.snd_ump_endpoint_info_get_protocol)(info.p),
+                protocol_caps: (lib.snd_ump_endpoint_info_get_protocol_caps)(info.p),
+                num_blocks: (lib.snd_ump_endpoint_info_get_num_blocks)(info.p),
+            })
+        }
+    }
+
+    /// Get the function blocks of a UMP endpoint.
+    pub fn blocks(&self, client: i32) -> Vec<BlockInfo> {
+        let lib = self.lib;
+        let num_blocks = match self.endpoint_info(client) {
+            Some(info) => info.num_blocks,
+            None => return Vec::new(),
+        };
+        let info = match InfoBox::new(lib.snd_ump_block_info_malloc, lib.snd_ump_block_info_free) {
+            Ok(info) => info,
+            Err(()) => return Vec::new(),
+        };
+        (0..num_blocks as c_int)
+            .filter_map(|blk| unsafe {
+                if (lib.snd_seq_get_ump_block_info)(self.lock().0, client, blk, info.p) < 0 {
+                    return None;
+                }
+                Some(BlockInfo {
+                    id: (lib.snd_ump_block_info_get_block_id)(info.p),
+                    name: string_from((lib.snd_ump_block_info_get_name)(info.p)),
+                    active: (lib.snd_ump_block_info_get_active)(info.p) != 0,
+                    direction: (lib.snd_ump_block_info_get_direction)(info.p),
+                    first_group: (lib.snd_ump_block_info_get_first_group)(info.p),
+                    num_groups: (lib.snd_ump_block_info_get_num_groups)(info.p),
+                })
+            })
+            .collect()
+    }
+
+    /// Turns this client into a UMP endpoint with one bidirectional function block that
+    /// spans all groups. This creates the endpoint port and a port for every group.
+    pub fn create_endpoint(
+        &self,
+        name: &CStr,
+        protocol: c_uint,
+        num_groups: u32,
+    ) -> Result<(), ()> {
+        let lib = self.lib;
+        let ep = InfoBox::new(
+            lib.snd_ump_endpoint_info_malloc,
+            lib.snd_ump_endpoint_info_free,
+        )?;
+        let block = InfoBox::new(lib.snd_ump_block_info_malloc, lib.snd_ump_block_info_free)?;
+        let seq = self.lock();
+        unsafe {
+            (lib.snd_ump_endpoint_info_set_name)(ep.p, name.as_ptr());
+            (lib.snd_ump_endpoint_info_set_version)(ep.p, 0x0101);
+            (lib.snd_ump_endpoint_info_set_protocol)(ep.p, protocol);
+            (lib.snd_ump_endpoint_info_set_protocol_caps)(ep.p, EP_PROTO_MIDI1 | EP_PROTO_MIDI2);
+            (lib.snd_ump_endpoint_info_set_num_blocks)(ep.p, 1);
+            if (lib.snd_seq_create_ump_endpoint)(seq.0, ep.p, num_groups) < 0 {
+                return Err(());
+            }
+
+            (lib.snd_ump_block_info_set_block_id)(block.p, 0);
+            (lib.snd_ump_block_info_set_name)(block.p, name.as_ptr());
+            (lib.snd_ump_block_info_set_active)(block.p, 1);
+            (lib.snd_ump_block_info_set_direction)(block.p, DIR_BIDIRECTION);
+            (lib.snd_ump_block_info_set_first_group)(block.p, 0);
+            (lib.snd_ump_block_info_set_num_groups)(block.p, num_groups);
+            if (lib.snd_seq_create_ump_block)(seq.0, 0, block.p) < 0 {
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create_port(&self, name: &CStr, capability: PortCap) -> Result<i32, ()> {
+        let port = unsafe {
+            alsa_sys::snd_seq_create_simple_port(
+                self.lock().0,
+                name.as_ptr(),
+                capability.bits(),
+                (PortType::MIDI_GENERIC | PortType::APPLICATION).bits(),
+            )
+        };
+        if port < 0 {
+            Err(())
+        } else {
+            Ok(port)
+        }
+    }
+
+    pub fn delete_port(&self, port: i32) {
+        unsafe { alsa_sys::snd_seq_delete_port(self.lock().0, port) };
+    }
+
+    /// Subscribes our port to the given sender port.
+    pub fn connect_from(&self, port: i32, sender: Addr) -> Result<(), ()> {
+        let rc = unsafe {
+            alsa_sys::snd_seq_connect_from(self.lock().0, port, sender.client, sender.port)
+        };
+        if rc < 0 {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn disconnect_from(&self, port: i32, sender: Addr) {
+        unsafe {
+            alsa_sys::snd_seq_disconnect_from(self.lock().0, port, sender.client, sender.port)
+        };
+    }
+
+    /// Subscribes the given destination port to our port.
+    pub fn connect_to(&self, port: i32, dest: Addr) -> Result<(), ()> {
+        let rc =
+            unsafe { alsa_sys::snd_seq_connect_to(self.lock().0, port, dest.client, dest.port) };
+        if rc < 0 {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn disconnect_to(&self, port: i32, dest: Addr) {
+        unsafe { alsa_sys::snd_seq_disconnect_to(self.lock().0, port, dest.client, dest.port) };
+    }
+
+    /// Sends one or more complete packets directly to all subscribers of the given port.
+    pub fn send_packets(&self, port: i32, mut packets: &[u32]) -> Result<(), SendError> {
+        if packets.is_empty() {
+            return Err(SendError::InvalidData(
+                "packets to be sent must not be empty",
+            ));
+        }
+        while !packets.is_empty() {
+            let len = packet_len(packets[0]);
+            if packets.len() < len {
+                return Err(SendError::InvalidData("incomplete Universal MIDI Packet"));
+            }
+            let mut ev: UmpEvent = unsafe { mem::zeroed() };
+            ev.flags = EVENT_UMP | alsa_sys::SND_SEQ_EVENT_LENGTH_FIXED;
+            ev.queue = alsa_sys::SND_SEQ_QUEUE_DIRECT;
+            ev.source.port = port as u8;
+            ev.dest.client = alsa_sys::SND_SEQ_ADDRESS_SUBSCRIBERS;
+            ev.dest.port = alsa_sys::SND_SEQ_ADDRESS_UNKNOWN;
+            ev.ump[..len].copy_from_slice(&packets[..len]);
+            if unsafe { (self.lib.snd_seq_ump_event_output_direct)(self.lock().0, &mut ev) } < 0 {
+                return Err(SendError::Other("could not send ALSA UMP event"));
+            }
+            packets = &packets[len..];
+        }
+        Ok(())
+    }
+
+    fn for_each_client<F: FnMut(i32)>(&self, mut f: F) {
+        let cinfo = match ClientInfo::new() {
+            Ok(cinfo) => cinfo,
+            Err(()) => return,
+        };
+        unsafe { alsa_sys::snd_seq_client_info_set_client(cinfo.0, -1) };
+        while unsafe { alsa_sys::snd_seq_query_next_client(self.lock().0, cinfo.0) } >= 0 {
+            f(unsafe { alsa_sys::snd_seq_client_info_get_client(cinfo.0) });
+        }
+    }
+
+    fn for_each_port<F: FnMut(*mut snd_seq_port_info_t)>(&self, client: i32, mut f: F) {
+        let pinfo = match PortInfo::new() {
+            Ok(pinfo) => pinfo,
+            Err(()) => return,
+        };
+        unsafe {
+            alsa_sys::snd_seq_port_info_set_client(pinfo.0, client);
+            alsa_sys::snd_seq_port_info_set_port(pinfo.0, -1);
+        }
+        while unsafe { alsa_sys::snd_seq_query_next_port(self.lock().0, pinfo.0) } >= 0 {
+            f(pinfo.0);
+        }
+    }
+
+    /// Reads events until a request to stop is received through the trigger pipe,
+    /// and passes the packets of UMP events to `handle_event`.
+    pub fn read_events<F>(&self, trigger_rcv_fd: i32, mut handle_event: F)
+    where
+        F: FnMut(&UmpEvent),
+    {
+        use libc::pollfd;
+
+        let count =
+            unsafe { alsa_sys::snd_seq_poll_descriptors_count(self.lock().0, libc::POLLIN) };
+        let mut poll_fds = vec![
+            pollfd {
+                fd: -1,
+                events: 0,
+                revents: 0,
+            };
+            count.max(0) as usize + 1
+        ];
+        poll_fds[0] = pollfd {
+            fd: trigger_rcv_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe {
+            alsa_sys::snd_seq_poll_descriptors(
+                self.lock().0,
+                poll_fds[1..].as_mut_ptr(),
+                count.max(0) as c_uint,
+                libc::POLLIN,
+            )
+        };
+
+        let mut do_input = true;
+        while do_input {
+            if unsafe { alsa_sys::snd_seq_event_input_pending(self.lock().0, 1) } == 0 {
+                // No data pending
+                if super::helpers::poll(&mut poll_fds, -1) >= 0 {
+                    // Read from our "channel" whether we should stop the thread
+                    if poll_fds[0].revents & libc::POLLIN != 0 {
+                        let _res = unsafe {
+                            libc::read(
+                                poll_fds[0].fd,
+                                &mut do_input as *mut bool as *mut c_void,
+                                mem::size_of::<bool>() as libc::size_t,
+                            )
+                        };
+                    }
+                }
+                continue;
+            }
+
+            let event = {
+                let seq = self.lock();
+                let mut ev = ptr::null_mut();
+                let rc = unsafe { (self.lib.snd_seq_ump_event_input)(seq.0, &mut ev) };
+                if rc < 0 {
+                    if rc == -libc::ENOSPC {
+                        let _ = writeln!(
+                            stderr(),
+                            "\nError in handle_input: ALSA MIDI input buffer overrun!\n"
+                        );
+                    }
+                    continue;
+                }
+                // The event lives in the input buffer of the client, so it is copied
+                // before the lock is released and the callback may use the client
+                unsafe { *ev }
+            };
+            handle_event(&event);
+        }
+    }
+}
+
+impl Drop for UmpSeq {
+    fn drop(&mut self) {
+        unsafe { alsa_sys::snd_seq_close(self.handle.get_mut().unwrap().0) };
+    }
+}
+
+struct ClientInfo(*mut snd_seq_client_info_t);
+
+impl ClientInfo {
+    fn new() -> Result<ClientInfo, ()> {
+        let mut p = ptr::null_mut();
+        if unsafe { alsa_sys::snd_seq_client_info_malloc(&mut p) } < 0 {
+            return Err(());
+        }
+        Ok(ClientInfo(p))
+    }
+}
+
+impl Drop for ClientInfo {
+    fn drop(&mut self) {
+        unsafe { alsa_sys::snd_seq_client_info_free(self.0) };
+    }
+}
+
+struct PortInfo(*mut snd_seq_port_info_t);
+
+impl PortInfo {
+    fn new() -> Result<PortInfo, ()> {
+        let mut p = ptr::null_mut();
+        if unsafe { alsa_sys::snd_seq_port_info_malloc(&mut p) } < 0 {
+            return Err(());
+        }
+        Ok(PortInfo(p))
+    }
+}
+
+impl Drop for PortInfo {
+    fn drop(&mut self) {
+        unsafe { alsa_sys::snd_seq_port_info_free(self.0) };
+    }
+}
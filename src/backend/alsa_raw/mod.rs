@@ -0,0 +1,521 @@
+//! A backend for the ALSA rawmidi interface (`/dev/snd/midiC*D*`), which gives direct
+//! access to the byte streams of hardware ports, without going through the sequencer.
+//!
+//! The rawmidi iterator of the `alsa` crate fails for devices that only have an input
+//! or only an output stream, so the devices are enumerated through `alsa-sys` directly.
+
+use std::ffi::{CStr, CString};
+use std::io::{Read, Write};
+use std::mem;
+use std::ptr;
+use std::thread::{Builder, JoinHandle};
+use std::time::Instant;
+
+use alsa::card::Card;
+use alsa::rawmidi::Rawmidi;
+use alsa::{Direction, PollDescriptors};
+use alsa_sys::{snd_ctl_t, snd_rawmidi_info_t};
+
+use super::alsa::helpers;
+use crate::errors::*;
+use crate::parser::{is_ignored, StreamParser};
+use crate::Ignore;
+
+/// The control interface of a sound card.
+struct Ctl(*mut snd_ctl_t);
+
+impl Ctl {
+    fn open(card: &Card) -> Option<Ctl> {
+        let name = CString::new(format!("hw:{}", card.get_index())).unwrap();
+        let mut p = ptr::null_mut();
+        if unsafe { alsa_sys::snd_ctl_open(&mut p, name.as_ptr(), 0) } < 0 {
+            return None;
+        }
+        Some(Ctl(p))
+    }
+
+    /// Returns the next rawmidi device of the card, or `None` if there are no more.
+    fn next_device(&self, device: i32) -> Option<i32> {
+        let mut device = device;
+        if unsafe { alsa_sys::snd_ctl_rawmidi_next_device(self.0, &mut device) } < 0 || device < 0 {
+            return None;
+        }
+        Some(device)
+    }
+}
+
+impl Drop for Ctl {
+    fn drop(&mut self) {
+        unsafe { alsa_sys::snd_ctl_close(self.0) };
+    }
+}
+
+/// The information about one direction of a rawmidi subdevice.
+struct Info(*mut snd_rawmidi_info_t);
+
+impl Info {
+    /// Returns `None` if the subdevice does not exist or does not support the direction.
+    fn query(ctl: &Ctl, device: i32, subdevice: i32, direction: Direction) -> Option<Info> {
+        let mut p = ptr::null_mut();
+        if unsafe { alsa_sys::snd_rawmidi_info_malloc(&mut p) } < 0 {
+            return None;
+        }
+        let info = Info(p);
+        let stream = match direction {
+            Direction::Playback => alsa_sys::SND_RAWMIDI_STREAM_OUTPUT,
+            Direction::Capture => alsa_sys::SND_RAWMIDI_STREAM_INPUT,
+        };
+        unsafe {
+            alsa_sys::snd_rawmidi_info_set_device(info.0, device as u32);
+            alsa_sys::snd_rawmidi_info_set_subdevice(info.0, subdevice as u32);
+            alsa_sys::snd_rawmidi_info_set_stream(info.0, stream);
+        }
+        if unsafe { alsa_sys::snd_ctl_rawmidi_info(ctl.0, info.0) } < 0 {
+            return None;
+        }
+        Some(info)
+    }
+
+    fn device(&self) -> i32 {
+        unsafe { alsa_sys::snd_rawmidi_info_get_device(self.0) as i32 }
+    }
+
+    fn subdevice(&self) -> i32 {
+        unsafe { alsa_sys::snd_rawmidi_info_get_subdevice(self.0) as i32 }
+    }
+
+    fn subdevices_count(&self) -> i32 {
+        unsafe { alsa_sys::snd_rawmidi_info_get_subdevices_count(self.0) as i32 }
+    }
+
+    fn subdevice_name(&self) -> Option<String> {
+        let name = unsafe { alsa_sys::snd_rawmidi_info_get_subdevice_name(self.0) };
+        if name.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(name) }
+            .to_str()
+            .ok()
+            .map(String::from)
+    }
+}
+
+impl Drop for Info {
+    fn drop(&mut self) {
+        unsafe { alsa_sys::snd_rawmidi_info_free(self.0) };
+    }
+}
+
+/// Calls `f` for every rawmidi subdevice of every sound card that supports the given direction.
+fn for_each_port<F>(direction: Direction, mut f: F)
+where
+    F: FnMut(&Card, &Info),
+{
+    for card in alsa::card::Iter::new().filter_map(Result::ok) {
+        let ctl = match Ctl::open(&card) {
+            Some(ctl) => ctl,
+            None => continue,
+        };
+        let mut device = -1;
+        while let Some(next) = ctl.next_device(device) {
+            device = next;
+            // Devices without a stream in this direction fail here and are skipped
+            let count = match Info::query(&ctl, device, 0, direction) {
+                Some(info) => info.subdevices_count(),
+                None => continue,
+            };
+            for subdevice in 0..count {
+                if let Some(info) = Info::query(&ctl, device, subdevice, direction) {
+                    f(&card, &info);
+                }
+            }
+        }
+    }
+}
+
+/// The location of a rawmidi subdevice, from which its `hw:` name is derived.
+#[derive(Clone, Copy, PartialEq)]
+struct Location {
+    card: i32,
+    device: i32,
+    subdevice: i32,
+}
+
+impl Location {
+    fn new(card: &Card, info: &Info) -> Location {
+        Location {
+            card: card.get_index(),
+            device: info.device(),
+            subdevice: info.subdevice(),
+        }
+    }
+
+    fn hw_name(&self) -> String {
+        format!("hw:{},{},{}", self.card, self.device, self.subdevice)
+    }
+
+    fn port_count(direction: Direction) -> usize {
+        let mut count = 0;
+        for_each_port(direction, |_, _| count += 1);
+        count
+    }
+
+    fn port_name(&self, direction: Direction) -> Result<String, PortInfoError> {
+        let mut result = Err(PortInfoError::InvalidPort);
+        for_each_port(direction, |card, info| {
+            if result.is_err() && Location::new(card, info) == *self {
+                result = match (card.get_name(), info.subdevice_name()) {
+                    (Ok(card_name), Some(subdevice_name)) => Ok(format!(
+                        "{}:{} {}",
+                        card_name,
+                        subdevice_name,
+                        self.hw_name()
+                    )),
+                    _ => Err(PortInfoError::CannotRetrievePortName),
+                };
+            }
+        });
+        result
+    }
+
+    fn device_name(&self) -> Result<String, PortInfoError> {
+        Card::new(self.card)
+            .get_name()
+            .map_err(|_| PortInfoError::CannotRetrievePortName)
+    }
+
+    fn open(&self, direction: Direction) -> Option<Rawmidi> {
+        let name = CString::new(self.hw_name()).unwrap();
+        Rawmidi::open(&name, direction, direction == Direction::Capture).ok()
+    }
+}
+
+/// The rawmidi interface can only be used if there is at least one sound card.
+fn check_available() -> Result<(), InitError> {
+    match alsa::card::Iter::new().next() {
+        Some(Ok(_)) => Ok(()),
+        _ => Err(InitError),
+    }
+}
+
+pub struct MidiInput {
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    location: Location,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.location.hw_name()
+    }
+}
+
+pub struct MidiInputConnection<T: 'static> {
+    thread: Option<JoinHandle<(HandlerData<T>, T)>>,
+    trigger_send_fd: i32,
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<T: 'static> {
+    ignore_flags: Ignore,
+    rawmidi: Rawmidi,
+    trigger_rcv_fd: i32,
+    callback: Callback<T>,
+}
+
+impl MidiInput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        check_available()?;
+        Ok(MidiInput {
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        let mut ports = Vec::new();
+        for_each_port(Direction::Capture, |card, info| {
+            ports.push(crate::common::MidiInputPort {
+                imp: MidiInputPort {
+                    location: Location::new(card, info),
+                }
+                .into(),
+            })
+        });
+        ports
+    }
+
+    pub fn port_count(&self) -> usize {
+        Location::port_count(Direction::Capture)
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        port.location.port_name(Direction::Capture)
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(format!("hw:{}", port.location.card))
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        port.location.device_name()
+    }
+
+    pub fn connect<F, T: Send>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let rawmidi = match port.location.open(Direction::Capture) {
+            Some(rawmidi) => rawmidi,
+            None => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
+        };
+
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            return Err(ConnectError::other(
+                "could not create communication pipe for ALSA handler",
+                self,
+            ));
+        }
+
+        let handler_data = HandlerData {
+            ignore_flags: self.ignore_flags,
+            rawmidi,
+            trigger_rcv_fd: trigger_fds[0],
+            callback: Box::new(callback),
+        };
+
+        let threadbuilder = Builder::new();
+        let name = format!("midir ALSA rawmidi input handler (port '{}')", port_name);
+        let threadbuilder = threadbuilder.name(name);
+        let thread = match threadbuilder.spawn(move || {
+            let mut d = data;
+            let h = handle_input(handler_data, &mut d);
+            (h, d) // return both the handler data and the user data
+        }) {
+            Ok(handle) => handle,
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                return Err(ConnectError::other(
+                    "could not start ALSA input handler thread",
+                    self,
+                ));
+            }
+        };
+
+        Ok(MidiInputConnection {
+            thread: Some(thread),
+            trigger_send_fd: trigger_fds[1],
+        })
+    }
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        let (handler_data, user_data) = self.close_internal();
+
+        (
+            MidiInput {
+                ignore_flags: handler_data.ignore_flags,
+            },
+            user_data,
+        )
+    }
+
+    /// This must only be called if the handler thread has not yet been shut down
+    fn close_internal(&mut self) -> (HandlerData<T>, T) {
+        // Request the thread to stop.
+        let _res = unsafe {
+            libc::write(
+                self.trigger_send_fd,
+                &false as *const bool as *const _,
+                mem::size_of::<bool>() as libc::size_t,
+            )
+        };
+
+        let thread = self.thread.take().unwrap();
+        let (handler_data, user_data) = match thread.join() {
+            Ok(data) => data,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<&'static str>() {
+                    panic!("Error when joining ALSA thread: {}", e);
+                } else {
+                    panic!("Unknown error when joining ALSA thread: {:?}", e);
+                }
+            }
+        };
+
+        unsafe {
+            libc::close(handler_data.trigger_rcv_fd);
+            libc::close(self.trigger_send_fd);
+        }
+
+        (handler_data, user_data)
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been dropped
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+/// Reads from the rawmidi device until a request to stop is received through the trigger pipe.
+fn handle_input<T>(mut data: HandlerData<T>, user_data: &mut T) -> HandlerData<T> {
+    use libc::pollfd;
+
+    const INVALID_POLLFD: pollfd = pollfd {
+        fd: -1,
+        events: 0,
+        revents: 0,
+    };
+
+    let mut poll_fds = vec![INVALID_POLLFD; data.rawmidi.count() + 1];
+    poll_fds[0] = pollfd {
+        fd: data.trigger_rcv_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    if data.rawmidi.fill(&mut poll_fds[1..]).is_err() {
+        poll_fds.truncate(1);
+    }
+
+    let start = Instant::now();
+    let mut parser = StreamParser::new();
+    let mut buffer = [0; 256];
+    let ignore_flags = data.ignore_flags;
+    let callback = &mut data.callback;
+
+    loop {
+        if helpers::poll(&mut poll_fds, -1) < 0 {
+            continue;
+        }
+        if poll_fds[0].revents & libc::POLLIN != 0 {
+            break;
+        }
+
+        let timestamp = if cfg!(feature = "avoid_timestamping") {
+            0
+        } else {
+            start.elapsed().as_micros() as u64
+        };
+        match data.rawmidi.io().read(&mut buffer) {
+            Ok(count) => parser.feed(&buffer[..count], |message| {
+                if !is_ignored(message, ignore_flags) {
+                    callback(timestamp, message, user_data);
+                }
+            }),
+            // rawmidi reports errors as negative error codes
+            Err(ref e) if e.raw_os_error() == Some(-libc::EAGAIN) => {}
+            Err(_) => {
+                // The device has been unplugged, only wait for the request to stop
+                poll_fds.truncate(1);
+            }
+        }
+    }
+
+    data
+}
+
+pub struct MidiOutput;
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    location: Location,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.location.hw_name()
+    }
+}
+
+pub struct MidiOutputConnection {
+    rawmidi: Rawmidi,
+}
+
+impl MidiOutput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        check_available()?;
+        Ok(MidiOutput)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        let mut ports = Vec::new();
+        for_each_port(Direction::Playback, |card, info| {
+            ports.push(crate::common::MidiOutputPort {
+                imp: MidiOutputPort {
+                    location: Location::new(card, info),
+                }
+                .into(),
+            })
+        });
+        ports
+    }
+
+    pub fn port_count(&self) -> usize {
+        Location::port_count(Direction::Playback)
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        port.location.port_name(Direction::Playback)
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(format!("hw:{}", port.location.card))
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        port.location.device_name()
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        _port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        match port.location.open(Direction::Playback) {
+            Some(rawmidi) => Ok(MidiOutputConnection { rawmidi }),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
+        }
+    }
+}
+
+impl MidiOutputConnection {
+    pub fn close(self) -> MidiOutput {
+        MidiOutput
+    }
+
+    /// The bytes are written to the device exactly as given,
+    /// so messages may use running status.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if message.is_empty() {
+            return Err(SendError::InvalidData(
+                "message to be sent must not be empty",
+            ));
+        }
+        self.rawmidi
+            .io()
+            .write_all(message)
+            .map_err(|_| SendError::Other("could not write to ALSA rawmidi device"))
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use crate::errors::*;
+use crate::r#virtual::VirtualPortOptions;
 use crate::{Ignore, MidiMessage};
 
 use coremidi::*;
@@ -15,6 +16,7 @@ mod external {
 
 pub struct MidiInput {
     client: Client,
+    client_name: String,
     ignore_flags: Ignore,
 }
 
@@ -49,6 +51,7 @@ impl MidiInput {
         match Client::new(client_name) {
             Ok(cl) => Ok(MidiInput {
                 client: cl,
+                client_name: client_name.to_string(),
                 ignore_flags: Ignore::None,
             }),
             Err(_) => Err(InitError),
@@ -61,7 +64,8 @@ impl MidiInput {
             .map(|s| crate::common::MidiInputPort {
                 imp: MidiInputPort {
                     source: Arc::new(s),
-                },
+                }
+                .into(),
             })
             .collect()
     }
@@ -81,6 +85,15 @@ impl MidiInput {
         }
     }
 
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     fn handle_input<T>(packets: &PacketList, handler_data: &mut HandlerData<T>) {
         let continue_sysex = &mut handler_data.continue_sysex;
         let ignore = handler_data.ignore_flags;
@@ -218,6 +231,7 @@ impl MidiInput {
         }
         Ok(MidiInputConnection {
             client: self.client,
+            client_name: self.client_name,
             details: InputConnectionDetails::Explicit(iport),
             handler_data: handler_data,
         })
@@ -226,6 +240,7 @@ impl MidiInput {
     pub fn create_virtual<F, T: Send + 'static>(
         self,
         port_name: &str,
+        _options: &VirtualPortOptions,
         callback: F,
         data: T,
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
@@ -248,10 +263,53 @@ impl MidiInput {
         };
         Ok(MidiInputConnection {
             client: self.client,
+            client_name: self.client_name,
             details: InputConnectionDetails::Virtual(vrt),
             handler_data: handler_data,
         })
     }
+
+    pub fn create_virtual_duplex<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        // Our client is owned by the input connection and disposed together with it, so
+        // the sending half gets a client of its own, which also owns the virtual source
+        let midi_out = match MidiOutput::new(&self.client_name) {
+            Ok(midi_out) => midi_out,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "error creating MIDI client for output",
+                    self,
+                ))
+            }
+        };
+        // CoreMIDI endpoints are unidirectional, so a source and a destination
+        // of the same name are created instead
+        let vrt = match midi_out.client.virtual_source(port_name) {
+            Ok(p) => p,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "error creating virtual MIDI source",
+                    self,
+                ))
+            }
+        };
+        let conn_in = self.create_virtual(port_name, options, callback, data)?;
+        Ok((
+            conn_in,
+            MidiOutputConnection {
+                client: midi_out.client,
+                details: OutputConnectionDetails::Virtual(vrt),
+            },
+        ))
+    }
 }
 
 enum InputConnectionDetails {
@@ -259,8 +317,12 @@ enum InputConnectionDetails {
     Virtual(VirtualDestination),
 }
 
+/// The receiving and the sending half of a duplex port.
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
 pub struct MidiInputConnection<T> {
     client: Client,
+    client_name: String,
     #[allow(dead_code)]
     details: InputConnectionDetails,
     // TODO: get rid of Arc & Mutex?
@@ -276,6 +338,7 @@ impl<T> MidiInputConnection<T> {
         (
             MidiInput {
                 client: self.client,
+                client_name: self.client_name,
                 ignore_flags: handler_data_locked.ignore_flags,
             },
             handler_data_locked.user_data.take().unwrap(),
@@ -338,7 +401,7 @@ impl MidiOutput {
         Destinations
             .into_iter()
             .map(|d| crate::common::MidiOutputPort {
-                imp: MidiOutputPort { dest: Arc::new(d) },
+                imp: MidiOutputPort { dest: Arc::new(d) }.into(),
             })
             .collect()
     }
@@ -354,6 +417,15 @@ impl MidiOutput {
         }
     }
 
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect(
         self,
         port: &MidiOutputPort,
@@ -372,6 +444,7 @@ impl MidiOutput {
     pub fn create_virtual(
         self,
         port_name: &str,
+        _options: &VirtualPortOptions,
     ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
         let vrt = match self.client.virtual_source(port_name) {
             Ok(p) => p,
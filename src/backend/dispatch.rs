@@ -0,0 +1,860 @@
+//! Backend-independent implementations of the public types, which forward
+//! every call to the backend that the object has been created with.
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+use super::alsa;
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+use super::alsa_raw;
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+use super::coremidi;
+#[cfg(feature = "dummy")]
+use super::dummy;
+#[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+use super::ipmidi;
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+use super::jack;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+use super::pipewire;
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+use super::rtpmidi;
+#[cfg(all(unix, feature = "serial"))]
+use super::serial;
+#[cfg(target_arch = "wasm32")]
+use super::webmidi;
+#[cfg(all(target_os = "windows", not(feature = "winrt")))]
+use super::winmm;
+#[cfg(all(target_os = "windows", feature = "winrt"))]
+use super::winrt;
+use super::Backend;
+use crate::errors::*;
+use crate::r#virtual::VirtualPortOptions;
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows")),
+    feature = "dummy"
+))]
+use crate::r#virtual::{Subscriber, SubscriptionEvent};
+use crate::Ignore;
+
+/// Evaluates `$body` with `$imp` bound to the backend-specific object of `$value`.
+macro_rules! dispatch {
+    ($Enum:ident, $value:expr, $imp:ident => $body:expr) => {
+        match $value {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::Alsa($imp) => $body,
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::AlsaRaw($imp) => $body,
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            $Enum::Jack($imp) => $body,
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            $Enum::PipeWire($imp) => $body,
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            $Enum::CoreMidi($imp) => $body,
+            #[cfg(all(target_os = "windows", not(feature = "winrt")))]
+            $Enum::WinMM($imp) => $body,
+            #[cfg(all(target_os = "windows", feature = "winrt"))]
+            $Enum::WinRT($imp) => $body,
+            #[cfg(target_arch = "wasm32")]
+            $Enum::WebMidi($imp) => $body,
+            #[cfg(feature = "dummy")]
+            $Enum::Dummy($imp) => $body,
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            $Enum::RtpMidi($imp) => $body,
+            #[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+            $Enum::IpMidi($imp) => $body,
+            #[cfg(all(unix, feature = "serial"))]
+            $Enum::Serial($imp) => $body,
+        }
+    };
+}
+
+/// Like `dispatch!`, but only for the backends that support virtual ports.
+/// For all other backends, `$fallback` is evaluated with `$other` bound to `$value`.
+macro_rules! dispatch_virtual {
+    ($Enum:ident, $value:expr, $imp:ident => $body:expr, $other:ident => $fallback:expr) => {
+        match $value {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::Alsa($imp) => $body,
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            $Enum::Jack($imp) => $body,
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            $Enum::PipeWire($imp) => $body,
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            $Enum::CoreMidi($imp) => $body,
+            #[cfg(feature = "dummy")]
+            $Enum::Dummy($imp) => $body,
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            $Enum::RtpMidi($imp) => $body,
+            #[allow(unreachable_patterns)]
+            $other => $fallback,
+        }
+    };
+}
+
+/// Like `dispatch!`, but for the types that only exist for ALSA and JACK.
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+macro_rules! dispatch_client {
+    ($Enum:ident, $value:expr, $imp:ident => $body:expr) => {
+        match $value {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::Alsa($imp) => $body,
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            $Enum::Jack($imp) => $body,
+        }
+    };
+}
+
+/// Evaluates to the `Backend` that `$value` belongs to.
+macro_rules! backend_of {
+    ($Enum:ident, $value:expr) => {
+        match $value {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::Alsa(_) => Backend::Alsa,
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            $Enum::AlsaRaw(_) => Backend::AlsaRaw,
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            $Enum::Jack(_) => Backend::Jack,
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            $Enum::PipeWire(_) => Backend::PipeWire,
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            $Enum::CoreMidi(_) => Backend::CoreMidi,
+            #[cfg(all(target_os = "windows", not(feature = "winrt")))]
+            $Enum::WinMM(_) => Backend::WinMM,
+            #[cfg(all(target_os = "windows", feature = "winrt"))]
+            $Enum::WinRT(_) => Backend::WinRT,
+            #[cfg(target_arch = "wasm32")]
+            $Enum::WebMidi(_) => Backend::WebMidi,
+            #[cfg(feature = "dummy")]
+            $Enum::Dummy(_) => Backend::Dummy,
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            $Enum::RtpMidi(_) => Backend::RtpMidi,
+            #[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+            $Enum::IpMidi(_) => Backend::IpMidi,
+            #[cfg(all(unix, feature = "serial"))]
+            $Enum::Serial(_) => Backend::Serial,
+        }
+    };
+}
+
+/// Declares an enum with one variant per compiled-in backend.
+macro_rules! backend_enum {
+    ($(#[$attr:meta])* $Enum:ident $(<$T:ident: $bound:lifetime>)?) => {
+        $(#[$attr])*
+        pub enum $Enum$(<$T: $bound>)? {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Alsa(alsa::$Enum$(<$T>)?),
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            AlsaRaw(alsa_raw::$Enum$(<$T>)?),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            Jack(jack::$Enum$(<$T>)?),
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            PipeWire(pipewire::$Enum$(<$T>)?),
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            CoreMidi(coremidi::$Enum$(<$T>)?),
+            #[cfg(all(target_os = "windows", not(feature = "winrt")))]
+            WinMM(winmm::$Enum$(<$T>)?),
+            #[cfg(all(target_os = "windows", feature = "winrt"))]
+            WinRT(winrt::$Enum$(<$T>)?),
+            #[cfg(target_arch = "wasm32")]
+            WebMidi(webmidi::$Enum$(<$T>)?),
+            #[cfg(feature = "dummy")]
+            Dummy(dummy::$Enum$(<$T>)?),
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            RtpMidi(rtpmidi::$Enum$(<$T>)?),
+            #[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+            IpMidi(ipmidi::$Enum$(<$T>)?),
+            #[cfg(all(unix, feature = "serial"))]
+            Serial(serial::$Enum$(<$T>)?),
+        }
+    };
+}
+
+/// Conversion between a backend-independent type and the corresponding type of one backend.
+pub(crate) trait AsBackend<B>: From<B> {
+    /// Get the backend-specific object, if it belongs to that backend.
+    fn variant(&self) -> Option<&B>;
+}
+
+macro_rules! impl_as_backend {
+    ($Variant:ident, $module:ident, $Enum:ident $(<$T:ident>)?) => {
+        impl$(<$T>)? From<$module::$Enum$(<$T>)?> for $Enum$(<$T>)? {
+            fn from(imp: $module::$Enum$(<$T>)?) -> Self {
+                $Enum::$Variant(imp)
+            }
+        }
+
+        impl$(<$T>)? AsBackend<$module::$Enum$(<$T>)?> for $Enum$(<$T>)? {
+            #[allow(unreachable_patterns)]
+            fn variant(&self) -> Option<&$module::$Enum$(<$T>)?> {
+                match self {
+                    $Enum::$Variant(imp) => Some(imp),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_backend {
+    ($Variant:ident, $module:ident) => {
+        impl_as_backend!($Variant, $module, MidiInput);
+        impl_as_backend!($Variant, $module, MidiInputPort);
+        impl_as_backend!($Variant, $module, MidiInputConnection<T>);
+        impl_as_backend!($Variant, $module, MidiOutput);
+        impl_as_backend!($Variant, $module, MidiOutputPort);
+        impl_as_backend!($Variant, $module, MidiOutputConnection);
+    };
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl_backend!(Alsa, alsa);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl_backend!(AlsaRaw, alsa_raw);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl_backend!(Jack, jack);
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+impl_backend!(PipeWire, pipewire);
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+impl_backend!(CoreMidi, coremidi);
+#[cfg(all(target_os = "windows", not(feature = "winrt")))]
+impl_backend!(WinMM, winmm);
+#[cfg(all(target_os = "windows", feature = "winrt"))]
+impl_backend!(WinRT, winrt);
+#[cfg(target_arch = "wasm32")]
+impl_backend!(WebMidi, webmidi);
+#[cfg(feature = "dummy")]
+impl_backend!(Dummy, dummy);
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+impl_backend!(RtpMidi, rtpmidi);
+#[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+impl_backend!(IpMidi, ipmidi);
+#[cfg(all(unix, feature = "serial"))]
+impl_backend!(Serial, serial);
+
+/// Defines a method that returns the object of one backend, for the backend-specific
+/// extension traits. It returns `None` if the object uses another backend.
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows")),
+    feature = "dummy",
+    all(feature = "rtpmidi", not(target_arch = "wasm32"))
+))]
+macro_rules! accessor {
+    ($name:ident, $module:ident, $Enum:ident $(<$T:ident>)?) => {
+        impl$(<$T>)? $Enum$(<$T>)? {
+            pub fn $name(&self) -> Option<&$module::$Enum$(<$T>)?> {
+                self.variant()
+            }
+        }
+    };
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiInput);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiInputPort);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiInputConnection<T>);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiOutput);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiOutputPort);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiOutputConnection);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, MidiClient);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, ClientInputConnection<T>);
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+accessor!(alsa, alsa, ClientOutputConnection);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiInput);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiInputPort);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiOutput);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiOutputPort);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiInputConnection<T>);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+accessor!(jack, jack, MidiOutputConnection);
+#[cfg(feature = "dummy")]
+accessor!(dummy, dummy, MidiInputConnection<T>);
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+accessor!(rtpmidi, rtpmidi, MidiInputPort);
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+accessor!(rtpmidi, rtpmidi, MidiInputConnection<T>);
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+accessor!(rtpmidi, rtpmidi, MidiOutputPort);
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+accessor!(rtpmidi, rtpmidi, MidiOutputConnection);
+
+fn convert_error<T, U: From<T>>(err: ConnectError<T>) -> ConnectError<U> {
+    let kind = err.kind();
+    ConnectError::new(kind, err.into_inner().into())
+}
+
+backend_enum!(MidiInput);
+
+impl MidiInput {
+    pub fn new(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        match backend {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Backend::Alsa => alsa::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Backend::AlsaRaw => alsa_raw::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            Backend::Jack => jack::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            Backend::PipeWire => pipewire::MidiInput::new(client_name).map(Into::into),
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            Backend::CoreMidi => coremidi::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "windows", not(feature = "winrt")))]
+            Backend::WinMM => winmm::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "windows", feature = "winrt"))]
+            Backend::WinRT => winrt::MidiInput::new(client_name).map(Into::into),
+            #[cfg(target_arch = "wasm32")]
+            Backend::WebMidi => webmidi::MidiInput::new(client_name).map(Into::into),
+            #[cfg(feature = "dummy")]
+            Backend::Dummy => dummy::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            Backend::RtpMidi => rtpmidi::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+            Backend::IpMidi => ipmidi::MidiInput::new(client_name).map(Into::into),
+            #[cfg(all(unix, feature = "serial"))]
+            Backend::Serial => serial::MidiInput::new(client_name).map(Into::into),
+            _ => Err(InitError),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        backend_of!(MidiInput, self)
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        dispatch!(MidiInput, self, imp => imp.ignore(flags))
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        dispatch!(MidiInput, self, imp => imp.ports_internal())
+    }
+
+    pub fn port_count(&self) -> usize {
+        dispatch!(MidiInput, self, imp => imp.port_count())
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiInput, self, imp => match port.variant() {
+            Some(port) => imp.port_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiInput, self, imp => match port.variant() {
+            Some(port) => imp.device_id(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiInput, self, imp => match port.variant() {
+            Some(port) => imp.device_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn connect<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        dispatch!(MidiInput, self, imp => match port.variant() {
+            Some(port) => imp
+                .connect(port, port_name, callback, data)
+                .map(Into::into)
+                .map_err(convert_error),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, imp.into())),
+        })
+    }
+
+    #[cfg(all(feature = "jack", not(target_os = "windows")))]
+    #[allow(unreachable_patterns)]
+    pub fn connect_with_frame_time<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, crate::os::jack::jack_nframes_t, &[u8], &mut T) + Send + 'static,
+    {
+        match (self, port.jack()) {
+            (MidiInput::Jack(imp), Some(port)) => imp
+                .connect_with_frame_time(port, port_name, callback, data)
+                .map(Into::into)
+                .map_err(convert_error),
+            (MidiInput::Jack(imp), None) => Err(ConnectError::new(
+                ConnectErrorKind::InvalidPort,
+                imp.into(),
+            )),
+            (other, _) => Err(ConnectError::other("the MIDI input does not use JACK", other)),
+        }
+    }
+
+    pub fn create_virtual<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        dispatch_virtual!(MidiInput, self, imp => imp
+            .create_virtual(port_name, options, callback, data)
+            .map(Into::into)
+            .map_err(convert_error),
+            other => {
+                // Only used by the backends that support virtual ports
+                let _ = (port_name, options, callback, data);
+                Err(ConnectError::other(UNSUPPORTED_MSG, other))
+            }
+        )
+    }
+
+    pub fn create_virtual_duplex<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        dispatch_virtual!(MidiInput, self, imp => imp
+            .create_virtual_duplex(port_name, options, callback, data)
+            .map(|(conn_in, conn_out)| (conn_in.into(), conn_out.into()))
+            .map_err(convert_error),
+            other => {
+                let _ = (port_name, options, callback, data);
+                Err(ConnectError::other(UNSUPPORTED_MSG, other))
+            }
+        )
+    }
+}
+
+backend_enum!(
+    #[derive(Clone, PartialEq)]
+    MidiInputPort
+);
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        dispatch!(MidiInputPort, self, imp => imp.id())
+    }
+}
+
+backend_enum!(MidiInputConnection<T: 'static>);
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(self) -> (MidiInput, T) {
+        dispatch!(MidiInputConnection, self, imp => {
+            let (input, data) = imp.close();
+            (input.into(), data)
+        })
+    }
+
+    #[cfg(any(
+        all(target_os = "linux", feature = "alsa"),
+        all(feature = "jack", not(target_os = "windows")),
+        feature = "dummy"
+    ))]
+    #[allow(unreachable_patterns)]
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            MidiInputConnection::Alsa(imp) => imp.subscribers(),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            MidiInputConnection::Jack(imp) => imp.subscribers(),
+            #[cfg(feature = "dummy")]
+            MidiInputConnection::Dummy(imp) => imp.subscribers(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[cfg(any(
+        all(target_os = "linux", feature = "alsa"),
+        all(feature = "jack", not(target_os = "windows")),
+        feature = "dummy"
+    ))]
+    #[allow(unreachable_patterns)]
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            MidiInputConnection::Alsa(imp) => imp.on_subscription_change(callback),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            MidiInputConnection::Jack(imp) => imp.on_subscription_change(callback),
+            #[cfg(feature = "dummy")]
+            MidiInputConnection::Dummy(imp) => imp.on_subscription_change(callback),
+            _ => Err(InitError),
+        }
+    }
+}
+
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
+backend_enum!(MidiOutput);
+
+impl MidiOutput {
+    pub fn new(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        match backend {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Backend::Alsa => alsa::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Backend::AlsaRaw => alsa_raw::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            Backend::Jack => jack::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            Backend::PipeWire => pipewire::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            Backend::CoreMidi => coremidi::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "windows", not(feature = "winrt")))]
+            Backend::WinMM => winmm::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(target_os = "windows", feature = "winrt"))]
+            Backend::WinRT => winrt::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(target_arch = "wasm32")]
+            Backend::WebMidi => webmidi::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(feature = "dummy")]
+            Backend::Dummy => dummy::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+            Backend::RtpMidi => rtpmidi::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+            Backend::IpMidi => ipmidi::MidiOutput::new(client_name).map(Into::into),
+            #[cfg(all(unix, feature = "serial"))]
+            Backend::Serial => serial::MidiOutput::new(client_name).map(Into::into),
+            _ => Err(InitError),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        backend_of!(MidiOutput, self)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        dispatch!(MidiOutput, self, imp => imp.ports_internal())
+    }
+
+    pub fn port_count(&self) -> usize {
+        dispatch!(MidiOutput, self, imp => imp.port_count())
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiOutput, self, imp => match port.variant() {
+            Some(port) => imp.port_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiOutput, self, imp => match port.variant() {
+            Some(port) => imp.device_id(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        dispatch!(MidiOutput, self, imp => match port.variant() {
+            Some(port) => imp.device_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        dispatch!(MidiOutput, self, imp => match port.variant() {
+            Some(port) => imp
+                .connect(port, port_name)
+                .map(Into::into)
+                .map_err(convert_error),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, imp.into())),
+        })
+    }
+
+    pub fn create_virtual(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        dispatch_virtual!(MidiOutput, self, imp => imp
+            .create_virtual(port_name, options)
+            .map(Into::into)
+            .map_err(convert_error),
+            other => {
+                let _ = (port_name, options);
+                Err(ConnectError::other(UNSUPPORTED_MSG, other))
+            }
+        )
+    }
+}
+
+backend_enum!(
+    #[derive(Clone, PartialEq)]
+    MidiOutputPort
+);
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        dispatch!(MidiOutputPort, self, imp => imp.id())
+    }
+}
+
+backend_enum!(MidiOutputConnection);
+
+impl MidiOutputConnection {
+    pub fn close(self) -> MidiOutput {
+        dispatch!(MidiOutputConnection, self, imp => imp.close().into())
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        dispatch!(MidiOutputConnection, self, imp => imp.send(message))
+    }
+
+    #[cfg(any(
+        all(target_os = "linux", feature = "alsa"),
+        all(feature = "jack", not(target_os = "windows")),
+        feature = "dummy"
+    ))]
+    #[allow(unreachable_patterns)]
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            MidiOutputConnection::Alsa(imp) => imp.subscribers(),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            MidiOutputConnection::Jack(imp) => imp.subscribers(),
+            #[cfg(feature = "dummy")]
+            MidiOutputConnection::Dummy(imp) => imp.subscribers(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[cfg(any(
+        all(target_os = "linux", feature = "alsa"),
+        all(feature = "jack", not(target_os = "windows")),
+        feature = "dummy"
+    ))]
+    #[allow(unreachable_patterns)]
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            MidiOutputConnection::Alsa(imp) => imp.on_subscription_change(callback),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            MidiOutputConnection::Jack(imp) => imp.on_subscription_change(callback),
+            #[cfg(feature = "dummy")]
+            MidiOutputConnection::Dummy(imp) => imp.on_subscription_change(callback),
+            _ => Err(InitError),
+        }
+    }
+}
+
+/// Declares an enum with one variant for each of the backends
+/// that support `MidiClient` (ALSA and JACK).
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+macro_rules! client_enum {
+    ($Enum:ident $(<$T:ident: $bound:lifetime>)?) => {
+        pub enum $Enum$(<$T: $bound>)? {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Alsa(alsa::$Enum$(<$T>)?),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            Jack(jack::$Enum$(<$T>)?),
+        }
+    };
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+macro_rules! impl_client_backend {
+    ($Variant:ident, $module:ident) => {
+        impl_as_backend!($Variant, $module, MidiClient);
+        impl_as_backend!($Variant, $module, ClientInputConnection<T>);
+        impl_as_backend!($Variant, $module, ClientOutputConnection);
+    };
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl_client_backend!(Alsa, alsa);
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl_client_backend!(Jack, jack);
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+client_enum!(MidiClient);
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+impl MidiClient {
+    pub fn new(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        match backend {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            Backend::Alsa => alsa::MidiClient::new(client_name).map(Into::into),
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            Backend::Jack => jack::MidiClient::new(client_name).map(Into::into),
+            _ => Err(InitError),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "alsa"))]
+            MidiClient::Alsa(_) => Backend::Alsa,
+            #[cfg(all(feature = "jack", not(target_os = "windows")))]
+            MidiClient::Jack(_) => Backend::Jack,
+        }
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        dispatch_client!(MidiClient, self, imp => imp.ignore(flags))
+    }
+
+    pub(crate) fn input_ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        dispatch_client!(MidiClient, self, imp => imp.input_ports_internal())
+    }
+
+    pub(crate) fn output_ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        dispatch_client!(MidiClient, self, imp => imp.output_ports_internal())
+    }
+
+    pub fn input_port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        dispatch_client!(MidiClient, self, imp => match port.variant() {
+            Some(port) => imp.input_port_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn output_port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        dispatch_client!(MidiClient, self, imp => match port.variant() {
+            Some(port) => imp.output_port_name(port),
+            None => Err(PortInfoError::InvalidPort),
+        })
+    }
+
+    pub fn connect_input<F, T: Send + 'static>(
+        &self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        dispatch_client!(MidiClient, self, imp => match port.variant() {
+            Some(port) => imp
+                .connect_input(port, port_name, callback, data)
+                .map(Into::into),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, ())),
+        })
+    }
+
+    pub fn create_virtual_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        dispatch_client!(MidiClient, self, imp => imp
+            .create_virtual_input(port_name, options, callback, data)
+            .map(Into::into))
+    }
+
+    pub fn connect_output(
+        &self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        dispatch_client!(MidiClient, self, imp => match port.variant() {
+            Some(port) => imp.connect_output(port, port_name).map(Into::into),
+            None => Err(ConnectError::new(ConnectErrorKind::InvalidPort, ())),
+        })
+    }
+
+    pub fn create_virtual_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        dispatch_client!(MidiClient, self, imp => imp
+            .create_virtual_output(port_name, options)
+            .map(Into::into))
+    }
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+client_enum!(ClientInputConnection<T: 'static>);
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+impl<T> ClientInputConnection<T> {
+    pub fn close(self) -> T {
+        dispatch_client!(ClientInputConnection, self, imp => imp.close())
+    }
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+client_enum!(ClientOutputConnection);
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+impl ClientOutputConnection {
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        dispatch_client!(ClientOutputConnection, self, imp => imp.send(message))
+    }
+
+    pub fn close(self) {
+        dispatch_client!(ClientOutputConnection, self, imp => imp.close())
+    }
+}
@@ -0,0 +1,675 @@
+//! An in-process backend without any system dependencies, mainly useful for tests.
+//!
+//! All `MidiInput` and `MidiOutput` objects of the process share one graph of ports.
+//! Messages are delivered synchronously: when `send` returns, the callbacks of all
+//! receiving connections have already been called on the sending thread.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{sync::OnceLock, time::Instant};
+
+use crate::errors::*;
+use crate::parser::is_ignored;
+use crate::r#virtual::{Subscriber, SubscriptionEvent, VirtualPortOptions};
+use crate::Ignore;
+
+/// Something that messages can be delivered to, with the type of its user data erased.
+trait Receiver: Send + Sync {
+    fn receive(&self, timestamp: u64, message: &[u8]);
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<T> {
+    ignore_flags: Ignore,
+    fixed_timestamp: Option<u64>,
+    callback: Callback<T>,
+    user_data: Option<T>,
+}
+
+struct InputHandler<T>(Mutex<HandlerData<T>>);
+
+impl<T: Send> Receiver for InputHandler<T> {
+    fn receive(&self, timestamp: u64, message: &[u8]) {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        if is_ignored(message, data.ignore_flags) {
+            return;
+        }
+        let timestamp = data.fixed_timestamp.unwrap_or(timestamp);
+        // The user data has already been taken if the connection has been closed
+        if let Some(ref mut user_data) = data.user_data {
+            (data.callback)(timestamp, message, user_data);
+        }
+    }
+}
+
+type SubscriptionCallback = Box<dyn FnMut(SubscriptionEvent) + Send>;
+type SharedSubscriptionCallback = Arc<Mutex<Option<SubscriptionCallback>>>;
+
+/// A connection that has been made to a port of the graph. Connections that
+/// read from the port have a receiver, connections that write to it do not.
+struct Connection {
+    id: u64,
+    name: String,
+    receiver: Option<Arc<dyn Receiver>>,
+}
+
+impl Connection {
+    fn subscriber(&self) -> Subscriber {
+        Subscriber::new(self.id.to_string(), self.name.clone())
+    }
+}
+
+/// A port of the graph. Ports that other applications can write to have a receiver,
+/// ports that they can read from are readable (a duplex port is both).
+struct Port {
+    id: u64,
+    client: String,
+    name: String,
+    receiver: Option<Arc<dyn Receiver>>,
+    readable: bool,
+    connections: Vec<Connection>,
+    watchers: Vec<SharedSubscriptionCallback>, // of the connections that own the port
+}
+
+struct Graph {
+    ports: Vec<Port>,
+    next_id: u64,
+}
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph {
+    ports: Vec::new(),
+    next_id: 0,
+});
+
+fn graph() -> MutexGuard<'static, Graph> {
+    GRAPH.lock().unwrap()
+}
+
+impl Graph {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn port(&self, id: u64) -> Option<&Port> {
+        self.ports.iter().find(|p| p.id == id)
+    }
+
+    fn port_mut(&mut self, id: u64) -> Option<&mut Port> {
+        self.ports.iter_mut().find(|p| p.id == id)
+    }
+
+    fn add_port(
+        &mut self,
+        client: &str,
+        name: &str,
+        receiver: Option<Arc<dyn Receiver>>,
+        readable: bool,
+        watcher: &SharedSubscriptionCallback,
+    ) -> u64 {
+        let id = self.next_id();
+        self.ports.push(Port {
+            id,
+            client: client.to_string(),
+            name: name.to_string(),
+            receiver,
+            readable,
+            connections: Vec::new(),
+            watchers: vec![watcher.clone()],
+        });
+        id
+    }
+
+    /// Adds a connection to the port, returning the event that has to be reported
+    /// to the watchers of the port once the graph has been unlocked.
+    fn add_connection(&mut self, id: u64, connection: Connection) -> PendingEvent {
+        let port = self.port_mut(id).unwrap();
+        let event = SubscriptionEvent::Subscribed(connection.subscriber());
+        port.connections.push(connection);
+        PendingEvent(port.watchers.clone(), Some(event))
+    }
+
+    /// Removes a connection from the port, returning the event that has to be reported
+    /// to the watchers of the port once the graph has been unlocked.
+    fn remove_connection(&mut self, id: u64, connection_id: u64) -> PendingEvent {
+        let port = match self.port_mut(id) {
+            Some(port) => port,
+            None => return PendingEvent(Vec::new(), None),
+        };
+        match port.connections.iter().position(|c| c.id == connection_id) {
+            Some(idx) => {
+                let connection = port.connections.remove(idx);
+                let event = SubscriptionEvent::Unsubscribed(connection.subscriber());
+                PendingEvent(port.watchers.clone(), Some(event))
+            }
+            None => PendingEvent(Vec::new(), None),
+        }
+    }
+
+    fn subscribers(&self, id: u64) -> Vec<Subscriber> {
+        self.port(id)
+            .map(|p| p.connections.iter().map(Connection::subscriber).collect())
+            .unwrap_or_default()
+    }
+
+    /// Stops receiving messages through the port, which is removed once it is not used anymore.
+    fn release_receiver(&mut self, id: u64, watcher: &SharedSubscriptionCallback) {
+        if let Some(port) = self.port_mut(id) {
+            port.receiver = None;
+            port.connections.retain(|c| c.receiver.is_some());
+            port.watchers.retain(|w| !Arc::ptr_eq(w, watcher));
+        }
+        self.remove_unused(id);
+    }
+
+    /// Stops sending messages from the port, which is removed once it is not used anymore.
+    fn release_sender(&mut self, id: u64, watcher: &SharedSubscriptionCallback) {
+        if let Some(port) = self.port_mut(id) {
+            port.readable = false;
+            port.connections.retain(|c| c.receiver.is_none());
+            port.watchers.retain(|w| !Arc::ptr_eq(w, watcher));
+        }
+        self.remove_unused(id);
+    }
+
+    fn remove_unused(&mut self, id: u64) {
+        self.ports
+            .retain(|p| p.id != id || p.receiver.is_some() || p.readable);
+    }
+
+    fn port_name(&self, id: u64) -> Result<String, PortInfoError> {
+        self.port(id)
+            .map(|p| format!("{}:{}", p.client, p.name))
+            .ok_or(PortInfoError::InvalidPort)
+    }
+
+    fn client_name(&self, id: u64) -> Result<String, PortInfoError> {
+        self.port(id)
+            .map(|p| p.client.clone())
+            .ok_or(PortInfoError::InvalidPort)
+    }
+}
+
+/// A change of subscriptions that has not been reported to the watchers of a port yet.
+/// The graph must not be locked while the callbacks are running, so that they can use it.
+#[must_use]
+struct PendingEvent(Vec<SharedSubscriptionCallback>, Option<SubscriptionEvent>);
+
+impl PendingEvent {
+    fn report(self) {
+        let PendingEvent(watchers, event) = self;
+        if let Some(event) = event {
+            for watcher in watchers {
+                if let Some(ref mut callback) = *watcher.lock().unwrap() {
+                    callback(event.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn timestamp() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+fn timestamp() -> u64 {
+    // `Instant` is not available on the web
+    0
+}
+
+/// Removes the port with the given id, as if its device had been unplugged.
+pub fn disconnect_port(id: &str) -> bool {
+    let id = match id.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return false,
+    };
+    let mut graph = graph();
+    let count = graph.ports.len();
+    graph.ports.retain(|p| p.id != id);
+    graph.ports.len() != count
+}
+
+pub struct MidiInput {
+    client_name: String,
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    id: u64,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+impl MidiInput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiInput {
+            client_name: client_name.to_string(),
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        graph()
+            .ports
+            .iter()
+            .filter(|p| p.readable)
+            .map(|p| crate::common::MidiInputPort {
+                imp: MidiInputPort { id: p.id }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        graph().ports.iter().filter(|p| p.readable).count()
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        graph().port_name(port.id)
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.device_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        graph().client_name(port.id)
+    }
+
+    fn handler<F, T: Send + 'static>(&self, callback: F, data: T) -> Arc<InputHandler<T>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        Arc::new(InputHandler(Mutex::new(HandlerData {
+            ignore_flags: self.ignore_flags,
+            fixed_timestamp: None,
+            callback: Box::new(callback),
+            user_data: Some(data),
+        })))
+    }
+
+    pub fn connect<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let handler = self.handler(callback, data);
+
+        let mut graph = graph();
+        match graph.port(port.id) {
+            Some(p) if p.readable => {}
+            _ => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
+        }
+        let id = graph.next_id();
+        let connection = Connection {
+            id,
+            name: format!("{}:{}", self.client_name, port_name),
+            receiver: Some(handler.clone()),
+        };
+        let event = graph.add_connection(port.id, connection);
+        drop(graph);
+        event.report();
+
+        Ok(MidiInputConnection {
+            input: Some(self),
+            handler,
+            kind: InputConnectionKind::Subscription { port: port.id, id },
+            subscription_callback: SharedSubscriptionCallback::default(),
+        })
+    }
+
+    pub fn create_virtual<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        _options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let handler = self.handler(callback, data);
+        let subscription_callback = SharedSubscriptionCallback::default();
+        let port = graph().add_port(
+            &self.client_name,
+            port_name,
+            Some(handler.clone()),
+            false,
+            &subscription_callback,
+        );
+
+        Ok(MidiInputConnection {
+            input: Some(self),
+            handler,
+            kind: InputConnectionKind::Virtual(port),
+            subscription_callback,
+        })
+    }
+
+    pub fn create_virtual_duplex<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        _options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        // Like on ALSA, a single port can be both read from and written to,
+        // and is removed once both connections have been closed
+        let handler = self.handler(callback, data);
+        let in_callback = SharedSubscriptionCallback::default();
+        let out_callback = SharedSubscriptionCallback::default();
+        let port = {
+            let mut graph = graph();
+            let port = graph.add_port(
+                &self.client_name,
+                port_name,
+                Some(handler.clone()),
+                true,
+                &in_callback,
+            );
+            graph
+                .port_mut(port)
+                .unwrap()
+                .watchers
+                .push(out_callback.clone());
+            port
+        };
+        let midi_out = MidiOutput {
+            client_name: self.client_name.clone(),
+        };
+
+        Ok((
+            MidiInputConnection {
+                input: Some(self),
+                handler,
+                kind: InputConnectionKind::Virtual(port),
+                subscription_callback: in_callback,
+            },
+            MidiOutputConnection {
+                output: Some(midi_out),
+                target: OutputTarget::Subscribers(port),
+                subscription_callback: out_callback,
+            },
+        ))
+    }
+}
+
+/// The receiving and the sending half of a duplex port.
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
+enum InputConnectionKind {
+    Subscription { port: u64, id: u64 },
+    Virtual(u64),
+}
+
+pub struct MidiInputConnection<T> {
+    input: Option<MidiInput>, // `None` once the connection has been closed
+    handler: Arc<InputHandler<T>>,
+    kind: InputConnectionKind,
+    subscription_callback: SharedSubscriptionCallback,
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        self.close_internal();
+
+        let user_data = self.handler.0.lock().unwrap().user_data.take().unwrap();
+        (self.input.take().unwrap(), user_data)
+    }
+
+    pub fn set_fixed_timestamp(&self, timestamp: Option<u64>) {
+        self.handler.0.lock().unwrap().fixed_timestamp = timestamp;
+    }
+
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        match self.kind {
+            InputConnectionKind::Subscription { .. } => Vec::new(),
+            InputConnectionKind::Virtual(port) => graph().subscribers(port),
+        }
+    }
+
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        *self.subscription_callback.lock().unwrap() = Some(Box::new(callback));
+        Ok(())
+    }
+
+    fn close_internal(&mut self) {
+        let mut graph = graph();
+        match self.kind {
+            InputConnectionKind::Subscription { port, id } => {
+                let event = graph.remove_connection(port, id);
+                drop(graph);
+                event.report();
+            }
+            InputConnectionKind::Virtual(port) => {
+                graph.release_receiver(port, &self.subscription_callback)
+            }
+        }
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        if self.input.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+pub struct MidiOutput {
+    client_name: String,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    id: u64,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+impl MidiOutput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiOutput {
+            client_name: client_name.to_string(),
+        })
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        graph()
+            .ports
+            .iter()
+            .filter(|p| p.receiver.is_some())
+            .map(|p| crate::common::MidiOutputPort {
+                imp: MidiOutputPort { id: p.id }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        graph()
+            .ports
+            .iter()
+            .filter(|p| p.receiver.is_some())
+            .count()
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        graph().port_name(port.id)
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.device_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        graph().client_name(port.id)
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let mut graph = graph();
+        match graph.port(port.id) {
+            Some(p) if p.receiver.is_some() => {}
+            _ => return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self)),
+        }
+        let id = graph.next_id();
+        let connection = Connection {
+            id,
+            name: format!("{}:{}", self.client_name, port_name),
+            receiver: None,
+        };
+        let event = graph.add_connection(port.id, connection);
+        drop(graph);
+        event.report();
+
+        Ok(MidiOutputConnection {
+            output: Some(self),
+            target: OutputTarget::Port { port: port.id, id },
+            subscription_callback: SharedSubscriptionCallback::default(),
+        })
+    }
+
+    pub fn create_virtual(
+        self,
+        port_name: &str,
+        _options: &VirtualPortOptions,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let subscription_callback = SharedSubscriptionCallback::default();
+        let port = graph().add_port(
+            &self.client_name,
+            port_name,
+            None,
+            true,
+            &subscription_callback,
+        );
+
+        Ok(MidiOutputConnection {
+            output: Some(self),
+            target: OutputTarget::Subscribers(port),
+            subscription_callback,
+        })
+    }
+}
+
+enum OutputTarget {
+    Port { port: u64, id: u64 }, // the port that this connection has been connected to
+    Subscribers(u64),            // the subscribers of our own virtual port
+}
+
+pub struct MidiOutputConnection {
+    output: Option<MidiOutput>, // `None` once the connection has been closed
+    target: OutputTarget,
+    subscription_callback: SharedSubscriptionCallback,
+}
+
+impl MidiOutputConnection {
+    pub fn close(mut self) -> MidiOutput {
+        self.close_internal();
+        self.output.take().unwrap()
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        match message.first() {
+            Some(&status) if status >= 0x80 => {}
+            _ => {
+                return Err(SendError::InvalidData(
+                    "message must start with a status byte",
+                ))
+            }
+        }
+
+        // The graph must not be locked while the callbacks are running, so that they can use it
+        let receivers: Vec<Arc<dyn Receiver>> = {
+            let graph = graph();
+            match self.target {
+                OutputTarget::Port { port, .. } => {
+                    match graph.port(port).and_then(|p| p.receiver.clone()) {
+                        Some(receiver) => vec![receiver],
+                        None => return Err(SendError::Other("port has been disconnected")),
+                    }
+                }
+                OutputTarget::Subscribers(id) => match graph.port(id) {
+                    Some(p) => p
+                        .connections
+                        .iter()
+                        .filter_map(|c| c.receiver.clone())
+                        .collect(),
+                    None => return Err(SendError::Other("port has been disconnected")),
+                },
+            }
+        };
+
+        let timestamp = timestamp();
+        for receiver in receivers {
+            receiver.receive(timestamp, message);
+        }
+        Ok(())
+    }
+
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        match self.target {
+            OutputTarget::Port { .. } => Vec::new(),
+            OutputTarget::Subscribers(port) => graph().subscribers(port),
+        }
+    }
+
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        *self.subscription_callback.lock().unwrap() = Some(Box::new(callback));
+        Ok(())
+    }
+
+    fn close_internal(&mut self) {
+        match self.target {
+            OutputTarget::Port { port, id } => {
+                let event = graph().remove_connection(port, id);
+                event.report();
+            }
+            OutputTarget::Subscribers(port) => {
+                graph().release_sender(port, &self.subscription_callback)
+            }
+        }
+    }
+}
+
+impl Drop for MidiOutputConnection {
+    fn drop(&mut self) {
+        if self.output.is_some() {
+            self.close_internal();
+        }
+    }
+}
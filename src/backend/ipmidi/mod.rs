@@ -0,0 +1,377 @@
+//! A network backend for ipMIDI-style raw MIDI over UDP multicast, where every port is a
+//! UDP port number on a multicast group and every datagram carries plain MIDI bytes.
+//!
+//! Ports are configured with `os::ipmidi::add_port`. Sending to a port sends a datagram to
+//! its group, and connecting an input joins the group. As multicast datagrams are looped back,
+//! everything that is sent is also received by the inputs of this host that are connected
+//! to the same port.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{Builder, JoinHandle};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::errors::*;
+use crate::parser::{is_ignored, StreamParser};
+use crate::Ignore;
+
+/// How often the input thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A UDP port number on a multicast group that is used as a MIDI port.
+#[derive(Clone, PartialEq)]
+struct PortConfig {
+    name: String,
+    address: SocketAddrV4,
+}
+
+struct Config {
+    ports: Vec<PortConfig>,
+    interface: Ipv4Addr,
+}
+
+static CONFIG: Mutex<Config> = Mutex::new(Config {
+    ports: Vec::new(),
+    interface: Ipv4Addr::UNSPECIFIED,
+});
+
+fn config() -> MutexGuard<'static, Config> {
+    CONFIG.lock().unwrap()
+}
+
+pub fn add_port(name: &str, group: Ipv4Addr, port: u16) {
+    let address = SocketAddrV4::new(group, port);
+    let mut config = config();
+    config.ports.retain(|p| p.address != address);
+    config.ports.push(PortConfig {
+        name: name.to_string(),
+        address,
+    });
+}
+
+pub fn remove_port(group: Ipv4Addr, port: u16) -> bool {
+    let address = SocketAddrV4::new(group, port);
+    let mut config = config();
+    let count = config.ports.len();
+    config.ports.retain(|p| p.address != address);
+    config.ports.len() != count
+}
+
+pub fn set_interface(interface: Ipv4Addr) {
+    config().interface = interface;
+}
+
+fn new_socket() -> io::Result<Socket> {
+    Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+}
+
+/// Creates a socket that receives the datagrams that are sent to the group, sharing
+/// the port with all other applications (and connections) that listen to it.
+fn join(address: SocketAddrV4, interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = new_socket()?;
+    socket.set_reuse_address(true)?;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    socket.set_reuse_port(true)?;
+    // Binding to the group address filters out datagrams for other groups,
+    // but this is not possible on Windows
+    #[cfg(not(target_os = "windows"))]
+    let bind_address = address;
+    #[cfg(target_os = "windows")]
+    let bind_address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, address.port());
+    socket.bind(&SocketAddr::V4(bind_address).into())?;
+    socket.join_multicast_v4(address.ip(), &interface)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    Ok(socket.into())
+}
+
+/// Creates a socket that sends to multicast groups through the given interface.
+fn sender(interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = new_socket()?;
+    socket.set_multicast_if_v4(&interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).into())?;
+    Ok(socket.into())
+}
+
+pub struct MidiInput {
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    config: PortConfig,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.config.address.to_string()
+    }
+}
+
+pub struct MidiInputConnection<T: 'static> {
+    thread: Option<JoinHandle<(HandlerData<T>, T)>>,
+    stop: Arc<AtomicBool>,
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<T: 'static> {
+    ignore_flags: Ignore,
+    socket: UdpSocket,
+    stop: Arc<AtomicBool>,
+    callback: Callback<T>,
+}
+
+impl MidiInput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiInput {
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        config()
+            .ports
+            .iter()
+            .map(|p| crate::common::MidiInputPort {
+                imp: MidiInputPort { config: p.clone() }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        config().ports.len()
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.address.ip().to_string())
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(format!("ipMIDI {}", port.config.address.ip()))
+    }
+
+    pub fn connect<F, T: Send>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let interface = config().interface;
+        let socket = match join(port.config.address, interface) {
+            Ok(socket) => socket,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "could not join the multicast group",
+                    self,
+                ))
+            }
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handler_data = HandlerData {
+            ignore_flags: self.ignore_flags,
+            socket,
+            stop: stop.clone(),
+            callback: Box::new(callback),
+        };
+
+        let threadbuilder = Builder::new();
+        let name = format!("midir ipMIDI input handler (port '{}')", port_name);
+        let threadbuilder = threadbuilder.name(name);
+        let thread = match threadbuilder.spawn(move || {
+            let mut d = data;
+            let h = handle_input(handler_data, &mut d);
+            (h, d) // return both the handler data and the user data
+        }) {
+            Ok(handle) => handle,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "could not start ipMIDI input handler thread",
+                    self,
+                ));
+            }
+        };
+
+        Ok(MidiInputConnection {
+            thread: Some(thread),
+            stop,
+        })
+    }
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        let (handler_data, user_data) = self.close_internal();
+
+        (
+            MidiInput {
+                ignore_flags: handler_data.ignore_flags,
+            },
+            user_data,
+        )
+    }
+
+    /// This must only be called if the handler thread has not yet been shut down
+    fn close_internal(&mut self) -> (HandlerData<T>, T) {
+        // The thread notices the request when its read times out
+        self.stop.store(true, Ordering::SeqCst);
+
+        let thread = self.thread.take().unwrap();
+        match thread.join() {
+            Ok(data) => data,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<&'static str>() {
+                    panic!("Error when joining ipMIDI thread: {}", e);
+                } else {
+                    panic!("Unknown error when joining ipMIDI thread: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been dropped
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+/// Receives datagrams until a request to stop is received. Every message
+/// gets the arrival time of the datagram that contains it as its timestamp.
+/// Several hosts can send to the same port, so each sender has its own parser.
+fn handle_input<T>(mut data: HandlerData<T>, user_data: &mut T) -> HandlerData<T> {
+    let mut parsers: HashMap<SocketAddr, StreamParser> = HashMap::new();
+    let mut buffer = [0u8; 65536];
+    let start = Instant::now();
+    let ignore_flags = data.ignore_flags;
+    let callback = &mut data.callback;
+
+    while !data.stop.load(Ordering::SeqCst) {
+        // Errors are timeouts, after which the request to stop is checked
+        let (count, from) = match data.socket.recv_from(&mut buffer) {
+            Ok(result) => result,
+            Err(_) => continue,
+        };
+        let timestamp = if cfg!(feature = "avoid_timestamping") {
+            0
+        } else {
+            start.elapsed().as_micros() as u64
+        };
+        let parser = parsers.entry(from).or_insert_with(StreamParser::new);
+        parser.feed(&buffer[..count], |message| {
+            if !is_ignored(message, ignore_flags) {
+                callback(timestamp, message, user_data);
+            }
+        });
+    }
+
+    data
+}
+
+pub struct MidiOutput;
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    config: PortConfig,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.config.address.to_string()
+    }
+}
+
+pub struct MidiOutputConnection {
+    socket: UdpSocket,
+    address: SocketAddrV4,
+}
+
+impl MidiOutput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiOutput)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        config()
+            .ports
+            .iter()
+            .map(|p| crate::common::MidiOutputPort {
+                imp: MidiOutputPort { config: p.clone() }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        config().ports.len()
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.address.ip().to_string())
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(format!("ipMIDI {}", port.config.address.ip()))
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        _port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let interface = config().interface;
+        match sender(interface) {
+            Ok(socket) => Ok(MidiOutputConnection {
+                socket,
+                address: port.config.address,
+            }),
+            Err(_) => Err(ConnectError::other(
+                "could not create the multicast socket",
+                self,
+            )),
+        }
+    }
+}
+
+impl MidiOutputConnection {
+    pub fn close(self) -> MidiOutput {
+        MidiOutput
+    }
+
+    /// Every message is sent in its own datagram, exactly as given.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if message.is_empty() {
+            return Err(SendError::InvalidData(
+                "message to be sent must not be empty",
+            ));
+        }
+        self.socket
+            .send_to(message, self.address)
+            .map(|_| ())
+            .map_err(|_| SendError::Other("could not send the ipMIDI datagram"))
+    }
+}
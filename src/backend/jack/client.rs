@@ -0,0 +1,425 @@
+use std::any::Any;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use jack_sys::jack_nframes_t;
+use libc::c_void;
+
+use super::wrappers::*;
+use super::{
+    open_client, read_input, register_virtual_port, write_output, MidiInputPort, MidiOutputPort,
+    OutputBuffers, OutputHandlerData,
+};
+use crate::errors::*;
+use crate::os::jack::ServerOptions;
+use crate::parser::is_ignored;
+use crate::r#virtual::VirtualPortOptions;
+use crate::Ignore;
+
+/// The handler of the messages received by one input port,
+/// with the type of its user data erased.
+trait PortHandler: Send {
+    fn process(&mut self, times: CycleTimes, nframes: jack_nframes_t);
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct InputHandler<T> {
+    port: Option<MidiPort>,
+    ignore_flags: Ignore,
+    callback: Callback<T>,
+    user_data: T,
+}
+
+impl<T: Send + 'static> PortHandler for InputHandler<T> {
+    fn process(&mut self, times: CycleTimes, nframes: jack_nframes_t) {
+        if let Some(ref port) = self.port {
+            let ignore_flags = self.ignore_flags;
+            let callback = &mut self.callback;
+            let user_data = &mut self.user_data;
+            read_input(port, times, nframes, |message, _| {
+                if !is_ignored(&message.bytes, ignore_flags) {
+                    callback(message.timestamp, &message.bytes, user_data);
+                }
+            });
+        }
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+enum PortEntry {
+    Input(Box<dyn PortHandler>),
+    Output(OutputHandlerData),
+}
+
+/// The entries of all ports of the client. A list is never modified once the
+/// process callback can see it, it is replaced by a new one instead.
+type PortList = Vec<(usize, *mut PortEntry)>;
+
+/// The ports that are processed by the process callback of the client.
+///
+/// The process thread must not block, so it reads the list of ports without locking.
+/// Whoever replaces the list waits for the current cycle to end before the previous
+/// list (and the entries that are no longer in the new one) may be freed.
+struct ProcessState {
+    client: CallbackClient,
+    ports: AtomicPtr<PortList>,
+    processing: AtomicBool, // set while the process callback uses `ports`
+}
+
+impl ProcessState {
+    fn new(client: CallbackClient) -> ProcessState {
+        ProcessState {
+            client,
+            ports: AtomicPtr::new(Box::into_raw(Box::default())),
+            processing: AtomicBool::new(false),
+        }
+    }
+
+    /// Get a copy of the current list of ports (only to be called by the thread that
+    /// replaces it).
+    fn ports(&self) -> PortList {
+        unsafe { (*self.ports.load(Ordering::SeqCst)).clone() }
+    }
+
+    /// Publishes a new list of ports, and frees the previous one once the process
+    /// callback is guaranteed not to use it anymore.
+    fn replace_ports(&self, ports: PortList) {
+        let previous = self
+            .ports
+            .swap(Box::into_raw(Box::new(ports)), Ordering::SeqCst);
+        // A cycle that starts after the swap sees the new list
+        while self.processing.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+        drop(unsafe { Box::from_raw(previous) });
+    }
+}
+
+struct ClientInner {
+    client: Mutex<Client>,
+    state: Box<ProcessState>,
+    update_lock: Mutex<()>, // serializes replacing the list of ports
+    next_id: AtomicUsize,
+}
+
+impl ClientInner {
+    fn add_port(&self, entry: PortEntry) -> usize {
+        let _guard = self.update_lock.lock().unwrap();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut ports = self.state.ports();
+        ports.push((id, Box::into_raw(Box::new(entry))));
+        self.state.replace_ports(ports);
+        id
+    }
+
+    fn remove_port(&self, id: usize) -> Option<PortEntry> {
+        let _guard = self.update_lock.lock().unwrap();
+        let mut ports = self.state.ports();
+        let idx = ports.iter().position(|&(port_id, _)| port_id == id)?;
+        let entry = ports.remove(idx).1;
+        self.state.replace_ports(ports);
+        // The process callback cannot reach the entry anymore
+        Some(*unsafe { Box::from_raw(entry) })
+    }
+
+    fn unregister(&self, port: MidiPort) {
+        self.client.lock().unwrap().unregister_midi_port(port);
+    }
+}
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        // Stop calling the process callback before `state` is freed
+        self.client.lock().unwrap().deactivate();
+        let ports = unsafe { Box::from_raw(self.state.ports.load(Ordering::SeqCst)) };
+        for &(_, entry) in ports.iter() {
+            drop(unsafe { Box::from_raw(entry) });
+        }
+    }
+}
+
+pub struct MidiClient {
+    ignore_flags: Ignore,
+    inner: Arc<ClientInner>,
+}
+
+pub struct ClientInputConnection<T: 'static> {
+    inner: Arc<ClientInner>,
+    id: usize,
+    closed: bool,
+    _marker: PhantomData<T>,
+}
+
+pub struct ClientOutputConnection {
+    inner: Arc<ClientInner>,
+    id: usize,
+    buffers: Arc<OutputBuffers>,
+    closed: bool,
+}
+
+impl MidiClient {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        let mut client = open_client(client_name, &ServerOptions::new())?;
+
+        let state = Box::new(ProcessState::new(client.callback_ref()));
+        let state_ptr = &*state as *const ProcessState as *mut c_void;
+        client.set_process_callback(handle_process, state_ptr);
+        client.activate();
+
+        Ok(MidiClient {
+            ignore_flags: Ignore::None,
+            inner: Arc::new(ClientInner {
+                client: Mutex::new(client),
+                state,
+                update_lock: Mutex::new(()),
+                next_id: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn input_ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        let client = self.inner.client.lock().unwrap();
+        let ports = client.get_midi_ports(PortFlags::PortIsOutput);
+        (0..ports.count())
+            .map(|i| crate::common::MidiInputPort {
+                imp: MidiInputPort::from_name(ports.get_c_name(i)).into(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn output_ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        let client = self.inner.client.lock().unwrap();
+        let ports = client.get_midi_ports(PortFlags::PortIsInput);
+        (0..ports.count())
+            .map(|i| crate::common::MidiOutputPort {
+                imp: MidiOutputPort::from_name(ports.get_c_name(i)).into(),
+            })
+            .collect()
+    }
+
+    pub fn input_port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.name().to_string_lossy().into())
+    }
+
+    pub fn output_port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.name().to_string_lossy().into())
+    }
+
+    fn create_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<(ClientInputConnection<T>, CString), ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let port = {
+            let mut client = self.inner.client.lock().unwrap();
+            register_virtual_port(&mut client, port_name, PortFlags::PortIsInput, options)
+                .map_err(|msg| ConnectError::other(msg, ()))?
+        };
+        let name = port.get_name().into();
+
+        let id = self.inner.add_port(PortEntry::Input(Box::new(InputHandler {
+            port: Some(port),
+            ignore_flags: self.ignore_flags,
+            callback: Box::new(callback),
+            user_data: data,
+        })));
+
+        let conn = ClientInputConnection {
+            inner: self.inner.clone(),
+            id,
+            closed: false,
+            _marker: PhantomData,
+        };
+        Ok((conn, name))
+    }
+
+    pub fn connect_input<F, T: Send + 'static>(
+        &self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let (conn, dest_name) =
+            self.create_input(port_name, &VirtualPortOptions::new(), callback, data)?;
+
+        // ... and connect it to the output (if this fails, dropping `conn` removes the port again)
+        let result = self
+            .inner
+            .client
+            .lock()
+            .unwrap()
+            .connect(port.name(), &dest_name);
+        if result.is_err() {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, ()));
+        }
+
+        Ok(conn)
+    }
+
+    pub fn create_virtual_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.create_input(port_name, options, callback, data)
+            .map(|(conn, _)| conn)
+    }
+
+    fn create_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<(ClientOutputConnection, CString), ConnectError<()>> {
+        let port = {
+            let mut client = self.inner.client.lock().unwrap();
+            register_virtual_port(&mut client, port_name, PortFlags::PortIsOutput, options)
+                .map_err(|msg| ConnectError::other(msg, ()))?
+        };
+        let name = port.get_name().into();
+
+        let buffers = Arc::new(OutputBuffers::new());
+        let id = self.inner.add_port(PortEntry::Output(OutputHandlerData {
+            port: Some(port),
+            buffers: buffers.clone(),
+        }));
+
+        let conn = ClientOutputConnection {
+            inner: self.inner.clone(),
+            id,
+            buffers,
+            closed: false,
+        };
+        Ok((conn, name))
+    }
+
+    pub fn connect_output(
+        &self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        let (conn, source_name) = self.create_output(port_name, &VirtualPortOptions::new())?;
+
+        // ... and connect it to the input (if this fails, dropping `conn` removes the port again)
+        let result = self
+            .inner
+            .client
+            .lock()
+            .unwrap()
+            .connect(&source_name, port.name());
+        if result.is_err() {
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, ()));
+        }
+
+        Ok(conn)
+    }
+
+    pub fn create_virtual_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        self.create_output(port_name, options).map(|(conn, _)| conn)
+    }
+}
+
+impl<T> ClientInputConnection<T> {
+    pub fn close(mut self) -> T {
+        match self.close_internal() {
+            Some(handler) => handler.user_data,
+            None => unreachable!("JACK client port handler has the wrong type"),
+        }
+    }
+
+    fn close_internal(&mut self) -> Option<Box<InputHandler<T>>> {
+        self.closed = true;
+
+        let mut handler = match self.inner.remove_port(self.id) {
+            Some(PortEntry::Input(handler)) => {
+                handler.into_any().downcast::<InputHandler<T>>().ok()?
+            }
+            _ => return None,
+        };
+        self.inner.unregister(handler.port.take().unwrap());
+        Some(handler)
+    }
+}
+
+impl<T> Drop for ClientInputConnection<T> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.close_internal();
+        }
+    }
+}
+
+impl ClientOutputConnection {
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        self.buffers.send(message)
+    }
+
+    pub fn close(mut self) {
+        self.close_internal();
+    }
+
+    fn close_internal(&mut self) {
+        self.closed = true;
+
+        if let Some(PortEntry::Output(mut output)) = self.inner.remove_port(self.id) {
+            self.inner.unregister(output.port.take().unwrap());
+        }
+    }
+}
+
+impl Drop for ClientOutputConnection {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.close_internal();
+        }
+    }
+}
+
+extern "C" fn handle_process(nframes: jack_nframes_t, arg: *mut c_void) -> i32 {
+    let state: &ProcessState = unsafe { &*(arg as *const ProcessState) };
+
+    // Every port is processed in every cycle, so the buffers of all outputs get cleared
+    state.processing.store(true, Ordering::SeqCst);
+    let ports = unsafe { &*state.ports.load(Ordering::SeqCst) };
+    let times = CycleTimes::current(state.client);
+    for &(_, entry) in ports.iter() {
+        // Only this thread accesses the entries while they are in the list
+        match unsafe { &mut *entry } {
+            PortEntry::Input(handler) => handler.process(times, nframes),
+            PortEntry::Output(output) => write_output(output, nframes),
+        }
+    }
+    state.processing.store(false, Ordering::SeqCst);
+
+    0
+}
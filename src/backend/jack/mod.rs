@@ -1,27 +1,41 @@
-use jack_sys::jack_nframes_t;
-use libc::c_void;
+use jack_sys::{jack_client_t, jack_nframes_t, jack_port_id_t};
+use libc::{c_int, c_void};
 
-use std::ffi::CString;
-use std::{mem, slice};
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{mem, ptr, slice};
 
 mod wrappers;
 use self::wrappers::*;
 
+mod client;
+pub use self::client::{ClientInputConnection, ClientOutputConnection, MidiClient};
+
 use crate::errors::*;
+use crate::os::jack::{JackEvent, OpenError, PortOptionError, ServerOptions};
+use crate::r#virtual::{Subscriber, SubscriptionEvent, VirtualPortOptions};
 use crate::{Ignore, MidiMessage};
 
 const OUTPUT_RINGBUFFER_SIZE: usize = 16384;
 
+/// An input callback that also receives the frame time of each message.
+type FrameCallback<T> = Box<dyn FnMut(u64, jack_nframes_t, &[u8], &mut T) + Send>;
+
 struct InputHandlerData<T> {
+    client: CallbackClient,
     port: Option<MidiPort>,
+    output: Option<OutputHandlerData>, // the sending half of a duplex port
     ignore_flags: Ignore,
-    callback: Box<dyn FnMut(u64, &[u8], &mut T) + Send>,
+    callback: FrameCallback<T>,
     user_data: Option<T>,
 }
 
 pub struct MidiInput {
     ignore_flags: Ignore,
     client: Option<Client>,
+    // used to open the client of the sending half of a duplex port
+    server_options: ServerOptions,
 }
 
 #[derive(Clone, PartialEq)]
@@ -33,28 +47,58 @@ impl MidiInputPort {
     pub fn id(&self) -> String {
         self.name.to_string_lossy().to_string()
     }
+
+    pub fn from_name(name: &CStr) -> Self {
+        MidiInputPort { name: name.into() }
+    }
+
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
 }
 
+/// The receiving and the sending half of a duplex port.
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
 pub struct MidiInputConnection<T> {
     handler_data: Box<InputHandlerData<T>>,
+    subscriptions: Arc<SubscriptionState>,
+    events: Arc<EventState>,
     client: Option<Client>,
+    server_options: ServerOptions,
 }
 
 impl MidiInput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        let client = match Client::open(client_name, JackOpenOptions::NoStartServer) {
-            Ok(c) => c,
-            Err(_) => {
-                return Err(InitError);
-            } // TODO: maybe add message that Jack server might not be running
-        };
+        Ok(Self::with_server_options(
+            client_name,
+            &ServerOptions::new(),
+        )?)
+    }
 
+    pub fn with_server_options(
+        client_name: &str,
+        options: &ServerOptions,
+    ) -> Result<Self, OpenError> {
         Ok(MidiInput {
             ignore_flags: Ignore::None,
-            client: Some(client),
+            client: Some(open_client(client_name, options)?),
+            server_options: options.clone(),
         })
     }
 
+    pub unsafe fn from_raw_client(client: *mut jack_client_t) -> Self {
+        MidiInput {
+            ignore_flags: Ignore::None,
+            client: Some(Client::from_raw(client)),
+            server_options: ServerOptions::new(),
+        }
+    }
+
+    pub fn raw_client(&self) -> *mut jack_client_t {
+        self.client.as_ref().unwrap().as_raw()
+    }
+
     pub fn ignore(&mut self, flags: Ignore) {
         self.ignore_flags = flags;
     }
@@ -70,7 +114,8 @@ impl MidiInput {
             result.push(crate::common::MidiInputPort {
                 imp: MidiInputPort {
                     name: ports.get_c_name(i).into(),
-                },
+                }
+                .into(),
             })
         }
         result
@@ -88,14 +133,42 @@ impl MidiInput {
         Ok(port.name.to_string_lossy().into())
     }
 
-    fn activate_callback<F, T: Send>(&mut self, callback: F, data: T) -> Box<InputHandlerData<T>>
-    where
-        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
-    {
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // JACK port names have the form `client:port`, so the client name identifies the device
+        Ok(client_name_of(&port.name))
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(device_name_of(self.client.as_ref().unwrap(), &port.name))
+    }
+
+    pub fn port_aliases(&self, port: &MidiInputPort) -> Vec<String> {
+        self.client.as_ref().unwrap().port_aliases(&port.name)
+    }
+
+    pub fn port_metadata(&self, port: &MidiInputPort, key: &str) -> Option<String> {
+        let key = CString::new(key).ok()?;
+        self.client
+            .as_ref()
+            .unwrap()
+            .port_property(&port.name, &key)
+    }
+
+    fn activate_callback<T: Send>(
+        &mut self,
+        callback: FrameCallback<T>,
+        data: T,
+    ) -> (
+        Box<InputHandlerData<T>>,
+        Arc<SubscriptionState>,
+        Arc<EventState>,
+    ) {
         let handler_data = Box::new(InputHandlerData {
+            client: self.client.as_ref().unwrap().callback_ref(),
             port: None,
+            output: None,
             ignore_flags: self.ignore_flags,
-            callback: Box::new(callback),
+            callback,
             user_data: Some(data),
         });
 
@@ -105,11 +178,31 @@ impl MidiInput {
             .as_mut()
             .unwrap()
             .set_process_callback(handle_input::<T>, data_ptr as *mut c_void);
+        let subscriptions = SubscriptionState::watch(self.client.as_mut().unwrap());
+        let events = EventState::watch(self.client.as_mut().unwrap());
         self.client.as_mut().unwrap().activate();
-        handler_data
+        (handler_data, subscriptions, events)
     }
 
     pub fn connect<F, T: Send>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        mut callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.connect_with_frame_time(
+            port,
+            port_name,
+            move |timestamp, _, message, data| callback(timestamp, message, data),
+            data,
+        )
+    }
+
+    pub fn connect_with_frame_time<F, T: Send>(
         mut self,
         port: &MidiInputPort,
         port_name: &str,
@@ -117,9 +210,10 @@ impl MidiInput {
         data: T,
     ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
     where
-        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+        F: FnMut(u64, jack_nframes_t, &[u8], &mut T) + Send + 'static,
     {
-        let mut handler_data = self.activate_callback(callback, data);
+        let (mut handler_data, subscriptions, events) =
+            self.activate_callback(Box::new(callback), data);
 
         // Create port ...
         let dest_port = match self
@@ -144,24 +238,32 @@ impl MidiInput {
             return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self));
         }
 
+        subscriptions.add_port(&dest_port);
         handler_data.port = Some(dest_port);
 
         Ok(MidiInputConnection {
             handler_data: handler_data,
+            subscriptions,
+            events,
             client: self.client.take(),
+            server_options: self.server_options,
         })
     }
 
     pub fn create_virtual<F, T: Send>(
         mut self,
         port_name: &str,
-        callback: F,
+        options: &VirtualPortOptions,
+        mut callback: F,
         data: T,
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
     where
         F: FnMut(u64, &[u8], &mut T) + Send + 'static,
     {
-        let mut handler_data = self.activate_callback(callback, data);
+        let callback =
+            move |timestamp, _, message: &[u8], data: &mut T| callback(timestamp, message, data);
+        let (mut handler_data, subscriptions, events) =
+            self.activate_callback(Box::new(callback), data);
 
         // Create port
         let port = match self
@@ -176,16 +278,168 @@ impl MidiInput {
             }
         };
 
+        if apply_port_options(self.client.as_ref().unwrap(), &port, options).is_err() {
+            self.client.as_mut().unwrap().unregister_midi_port(port);
+            return Err(ConnectError::other("could not set JACK port options", self));
+        }
+
+        subscriptions.add_port(&port);
         handler_data.port = Some(port);
 
         Ok(MidiInputConnection {
             handler_data: handler_data,
+            subscriptions,
+            events,
             client: self.client.take(),
+            server_options: self.server_options,
         })
     }
+
+    pub fn create_virtual_duplex<F, T: Send>(
+        mut self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        mut callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        // Our client is owned by the input connection, so the `MidiOutput` that is
+        // returned when the sending half is closed gets a client of its own
+        let client_name = self.client.as_ref().unwrap().name();
+        let midi_out = match MidiOutput::with_server_options(&client_name, &self.server_options) {
+            Ok(midi_out) => midi_out,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "could not open JACK client for output",
+                    self,
+                ))
+            }
+        };
+
+        let callback =
+            move |timestamp, _, message: &[u8], data: &mut T| callback(timestamp, message, data);
+        let (mut handler_data, subscriptions, events) =
+            self.activate_callback(Box::new(callback), data);
+
+        // JACK ports are unidirectional, so a pair of ports is registered on our client
+        let client = self.client.as_mut().unwrap();
+        let in_port = match register_virtual_port(
+            client,
+            &format!("{} in", port_name),
+            PortFlags::PortIsInput,
+            &suffixed_options(options, " in"),
+        ) {
+            Ok(p) => p,
+            Err(msg) => return Err(ConnectError::other(msg, self)),
+        };
+        let out_port = match register_virtual_port(
+            client,
+            &format!("{} out", port_name),
+            PortFlags::PortIsOutput,
+            &suffixed_options(options, " out"),
+        ) {
+            Ok(p) => p,
+            Err(msg) => {
+                client.unregister_midi_port(in_port);
+                return Err(ConnectError::other(msg, self));
+            }
+        };
+
+        let buffers = Arc::new(OutputBuffers::new());
+        subscriptions.add_port(&in_port);
+        subscriptions.add_port(&out_port);
+        handler_data.port = Some(in_port);
+        handler_data.output = Some(OutputHandlerData {
+            port: Some(out_port),
+            buffers: buffers.clone(),
+        });
+
+        let conn_out = MidiOutputConnection {
+            // the port is owned by the input connection, which also processes the buffers
+            handler_data: Box::new(OutputHandlerData {
+                port: None,
+                buffers,
+            }),
+            // both halves report the connections of both ports
+            subscriptions: subscriptions.clone(),
+            events: events.clone(),
+            client: midi_out.client,
+        };
+
+        Ok((
+            MidiInputConnection {
+                handler_data,
+                subscriptions,
+                events,
+                client: self.client.take(),
+                server_options: self.server_options,
+            },
+            conn_out,
+        ))
+    }
 }
 
 impl<T> MidiInputConnection<T> {
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        self.subscriptions.subscribers()
+    }
+
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        self.subscriptions.set_callback(Box::new(callback));
+        Ok(())
+    }
+
+    pub fn on_event<F>(&self, callback: F)
+    where
+        F: FnMut(JackEvent) + Send + 'static,
+    {
+        self.events.set_callback(Box::new(callback));
+    }
+
+    pub fn set_port_pretty_name(&self, name: &str) -> Result<(), PortOptionError> {
+        let client = self.client.as_ref().unwrap();
+        let port = self.handler_data.port.as_ref().unwrap();
+        match self.handler_data.output {
+            // The ports of a duplex pair are told apart by suffixes, like in `create_virtual_duplex`
+            Some(ref output) => {
+                let out_port = output.port.as_ref().unwrap();
+                client
+                    .set_port_pretty_name(port, &format!("{} in", name))
+                    .map_err(|()| PortOptionError::Failed)?;
+                client
+                    .set_port_pretty_name(out_port, &format!("{} out", name))
+                    .map_err(|()| PortOptionError::Failed)?;
+            }
+            None => client
+                .set_port_pretty_name(port, name)
+                .map_err(|()| PortOptionError::Failed)?,
+        }
+        Ok(())
+    }
+
+    pub fn add_port_alias(&self, alias: &str) -> Result<(), PortOptionError> {
+        let port = self.handler_data.port.as_ref().unwrap();
+        match self.handler_data.output {
+            Some(ref output) => {
+                let out_port = output.port.as_ref().unwrap();
+                port.set_alias(&format!("{} in", alias))
+                    .map_err(|()| PortOptionError::Failed)?;
+                out_port
+                    .set_alias(&format!("{} out", alias))
+                    .map_err(|()| PortOptionError::Failed)?;
+            }
+            None => port
+                .set_alias(alias)
+                .map_err(|()| PortOptionError::Failed)?,
+        }
+        Ok(())
+    }
+
     pub fn close(mut self) -> (MidiInput, T) {
         self.close_internal();
 
@@ -193,15 +447,24 @@ impl<T> MidiInputConnection<T> {
             MidiInput {
                 client: self.client.take(),
                 ignore_flags: self.handler_data.ignore_flags,
+                server_options: self.server_options.clone(),
             },
             self.handler_data.user_data.take().unwrap(),
         )
     }
 
     fn close_internal(&mut self) {
+        self.subscriptions.clear();
         let port = self.handler_data.port.take().unwrap();
         self.client.as_mut().unwrap().unregister_midi_port(port);
-        self.client.as_mut().unwrap().deactivate();
+        if let Some(mut output) = self.handler_data.output.take() {
+            output.buffers.closed.store(true, Ordering::Release);
+            let port = output.port.take().unwrap();
+            self.client.as_mut().unwrap().unregister_midi_port(port);
+        }
+        let client = self.client.as_mut().unwrap();
+        client.deactivate();
+        client.set_event_callbacks(None, ptr::null_mut());
     }
 }
 
@@ -218,41 +481,331 @@ extern "C" fn handle_input<T>(nframes: jack_nframes_t, arg: *mut c_void) -> i32
 
     // Is port created?
     if let Some(ref port) = data.port {
-        let buff = port.get_midi_buffer(nframes);
+        let callback = &mut data.callback;
+        let user_data = data.user_data.as_mut().unwrap();
+        let times = CycleTimes::current(data.client);
+        read_input(port, times, nframes, |message, frame_time| {
+            callback(message.timestamp, frame_time, &message.bytes, user_data)
+        });
+    }
 
-        let mut message = MidiMessage::new(); // TODO: create MidiMessage once and reuse its buffer for every handle_input call
+    if let Some(ref output) = data.output {
+        write_output(output, nframes);
+    }
 
-        // We have midi events in buffer
-        let evcount = buff.get_event_count();
-        let mut event = mem::MaybeUninit::uninit();
+    return 0;
+}
 
-        for j in 0..evcount {
-            message.bytes.clear();
-            unsafe { buff.get_event(event.as_mut_ptr(), j) };
-            let event = unsafe { event.assume_init() };
+/// Passes all messages that the port received in the current cycle to `f`, with the time
+/// of the frame at which they were received as their timestamp, together with that frame.
+fn read_input<F: FnMut(&MidiMessage, jack_nframes_t)>(
+    port: &MidiPort,
+    times: CycleTimes,
+    nframes: jack_nframes_t,
+    mut f: F,
+) {
+    let buff = port.get_midi_buffer(nframes);
+
+    let mut message = MidiMessage::new(); // TODO: create MidiMessage once and reuse its buffer for every handle_input call
+
+    // We have midi events in buffer
+    let evcount = buff.get_event_count();
+    let mut event = mem::MaybeUninit::uninit();
+
+    for j in 0..evcount {
+        message.bytes.clear();
+        unsafe { buff.get_event(event.as_mut_ptr(), j) };
+        let event = unsafe { event.assume_init() };
+
+        for i in 0..event.size {
+            message
+                .bytes
+                .push(unsafe { *event.buffer.offset(i as isize) });
+        }
 
-            for i in 0..event.size {
-                message
-                    .bytes
-                    .push(unsafe { *event.buffer.offset(i as isize) });
-            }
+        message.timestamp = times.time_of(event.time); // this is in microseconds
+        f(&message, times.frame_time.wrapping_add(event.time));
+    }
+}
 
-            message.timestamp = Client::get_time(); // this is in microseconds
-            (data.callback)(
-                message.timestamp,
-                &message.bytes,
-                data.user_data.as_mut().unwrap(),
-            );
+/// Opens a client on the server that is described by the options.
+fn open_client(client_name: &str, options: &ServerOptions) -> Result<Client, OpenError> {
+    let mut flags = JackOpenOptions::empty();
+    if !options.start_server {
+        flags |= JackOpenOptions::NoStartServer;
+    }
+    let server_name = match options.server_name {
+        Some(ref name) => Some(CString::new(name.as_str()).map_err(|_| OpenError::Failed)?),
+        None => None,
+    };
+    if server_name.is_some() {
+        flags |= JackOpenOptions::ServerName;
+    }
+    Client::open(client_name, flags, server_name.as_deref())
+}
+
+fn apply_port_options(
+    client: &Client,
+    port: &MidiPort,
+    options: &VirtualPortOptions,
+) -> Result<(), ()> {
+    if let Some(ref name) = options.pretty_name {
+        client.set_port_pretty_name(port, name)?;
+    }
+    for alias in &options.aliases {
+        port.set_alias(alias)?;
+    }
+    Ok(())
+}
+
+fn register_virtual_port(
+    client: &mut Client,
+    port_name: &str,
+    flags: PortFlags,
+    options: &VirtualPortOptions,
+) -> Result<MidiPort, &'static str> {
+    let port = client
+        .register_midi_port(port_name, flags)
+        .map_err(|()| "could not register JACK port")?;
+    if apply_port_options(client, &port, options).is_err() {
+        client.unregister_midi_port(port);
+        return Err("could not set JACK port options");
+    }
+    Ok(port)
+}
+
+/// Returns a copy of the options where the pretty name and all aliases have the given suffix,
+/// so that they stay unique for the ports of a duplex pair.
+fn suffixed_options(options: &VirtualPortOptions, suffix: &str) -> VirtualPortOptions {
+    VirtualPortOptions {
+        pretty_name: options
+            .pretty_name
+            .as_ref()
+            .map(|name| name.clone() + suffix),
+        aliases: options
+            .aliases
+            .iter()
+            .map(|alias| alias.clone() + suffix)
+            .collect(),
+        ..options.clone()
+    }
+}
+
+/// Returns the pretty name of the client that owns the port from its metadata,
+/// or the name of the client if it has none.
+fn device_name_of(client: &Client, port_name: &CStr) -> String {
+    let key = CStr::from_bytes_with_nul(JACK_METADATA_PRETTY_NAME).unwrap();
+    let client_name = client_name_of(port_name);
+    CString::new(client_name.as_str())
+        .ok()
+        .and_then(|name| client.client_property(&name, key))
+        .unwrap_or(client_name)
+}
+
+fn client_name_of(port_name: &CStr) -> String {
+    let name = port_name.to_string_lossy();
+    match name.find(':') {
+        Some(idx) => name[..idx].to_string(),
+        None => name.into_owned(),
+    }
+}
+
+type SubscriptionCallback = Box<dyn FnMut(SubscriptionEvent) + Send>;
+
+/// The ports of a connection whose connections are reported to the subscription callback.
+/// It is shared between both halves of a duplex port, and the port connect callback of the
+/// client, which is set before the client is activated and therefore must not be moved.
+struct SubscriptionState {
+    client: *mut jack_client_t,
+    ports: Mutex<Vec<CString>>, // cleared once the client is deactivated
+    callback: Mutex<Option<SubscriptionCallback>>,
+}
+
+// The JACK API is thread-safe, and the client is only used while `ports` is not empty.
+unsafe impl Send for SubscriptionState {}
+unsafe impl Sync for SubscriptionState {}
+
+impl SubscriptionState {
+    /// Creates the state and registers it with the client, which must not be active yet.
+    fn watch(client: &mut Client) -> Arc<SubscriptionState> {
+        let state = Arc::new(SubscriptionState {
+            client: client.as_raw(),
+            ports: Mutex::new(Vec::new()),
+            callback: Mutex::new(None),
+        });
+        client.set_port_connect_callback(handle_port_connect, Arc::as_ptr(&state) as *mut c_void);
+        state
+    }
+
+    fn add_port(&self, port: &MidiPort) {
+        self.ports.lock().unwrap().push(port.get_name().into());
+    }
+
+    fn clear(&self) {
+        self.ports.lock().unwrap().clear();
+    }
+
+    fn set_callback(&self, callback: SubscriptionCallback) {
+        *self.callback.lock().unwrap() = Some(callback);
+    }
+
+    fn subscribers(&self) -> Vec<Subscriber> {
+        let ports = self.ports.lock().unwrap();
+        ports
+            .iter()
+            .flat_map(|port| unsafe { port_connections(self.client, port) })
+            .map(|name| subscriber_of(&name))
+            .collect()
+    }
+}
+
+type EventCallback = Box<dyn FnMut(JackEvent) + Send>;
+
+/// The callback for the server events of a client, which is registered with the client.
+struct EventState {
+    callback: Mutex<Option<EventCallback>>,
+}
+
+impl EventState {
+    /// Creates the state and registers it with the client, which must not be active yet.
+    fn watch(client: &mut Client) -> Arc<EventState> {
+        let state = Arc::new(EventState {
+            callback: Mutex::new(None),
+        });
+        let callbacks = EventCallbacks {
+            shutdown: handle_shutdown,
+            xrun: handle_xrun,
+            sample_rate: handle_sample_rate,
+            buffer_size: handle_buffer_size,
+        };
+        client.set_event_callbacks(Some(callbacks), Arc::as_ptr(&state) as *mut c_void);
+        state
+    }
+
+    fn set_callback(&self, callback: EventCallback) {
+        *self.callback.lock().unwrap() = Some(callback);
+    }
+
+    fn emit(&self, event: JackEvent) {
+        if let Some(ref mut callback) = *self.callback.lock().unwrap() {
+            callback(event);
         }
     }
+}
 
-    return 0;
+unsafe extern "C" fn handle_shutdown(arg: *mut c_void) {
+    let state: &EventState = &*(arg as *const EventState);
+    state.emit(JackEvent::Shutdown);
+}
+
+unsafe extern "C" fn handle_xrun(arg: *mut c_void) -> c_int {
+    let state: &EventState = &*(arg as *const EventState);
+    state.emit(JackEvent::Xrun);
+    0
+}
+
+unsafe extern "C" fn handle_sample_rate(nframes: jack_nframes_t, arg: *mut c_void) -> c_int {
+    let state: &EventState = &*(arg as *const EventState);
+    state.emit(JackEvent::SampleRateChanged(nframes));
+    0
+}
+
+unsafe extern "C" fn handle_buffer_size(nframes: jack_nframes_t, arg: *mut c_void) -> c_int {
+    let state: &EventState = &*(arg as *const EventState);
+    state.emit(JackEvent::BufferSizeChanged(nframes));
+    0
+}
+
+fn subscriber_of(port_name: &CStr) -> Subscriber {
+    let name = port_name.to_string_lossy().into_owned();
+    Subscriber::new(name.clone(), name)
+}
+
+unsafe extern "C" fn handle_port_connect(
+    a: jack_port_id_t,
+    b: jack_port_id_t,
+    connect: c_int,
+    arg: *mut c_void,
+) {
+    let state: &SubscriptionState = &*(arg as *const SubscriptionState);
+
+    let (a, b) = match (
+        port_name_by_id(state.client, a),
+        port_name_by_id(state.client, b),
+    ) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return,
+    };
+    let peer = {
+        let ports = state.ports.lock().unwrap();
+        if ports.contains(&a) {
+            b
+        } else if ports.contains(&b) {
+            a
+        } else {
+            return;
+        }
+    };
+
+    if let Some(ref mut on_change) = *state.callback.lock().unwrap() {
+        let subscriber = subscriber_of(&peer);
+        on_change(if connect != 0 {
+            SubscriptionEvent::Subscribed(subscriber)
+        } else {
+            SubscriptionEvent::Unsubscribed(subscriber)
+        });
+    }
 }
 
 struct OutputHandlerData {
     port: Option<MidiPort>,
+    buffers: Arc<OutputBuffers>,
+}
+
+struct OutputBuffers {
     buff_size: Ringbuffer,
     buff_message: Ringbuffer,
+    closed: AtomicBool, // set when the input connection owning a duplex port is closed
+}
+
+// JACK ringbuffers are lock-free for a single reader and a single writer, and the
+// buffers are only ever written by the connection and read by the process callback.
+unsafe impl Sync for OutputBuffers {}
+
+impl OutputBuffers {
+    fn new() -> OutputBuffers {
+        OutputBuffers {
+            buff_size: Ringbuffer::new(OUTPUT_RINGBUFFER_SIZE),
+            buff_message: Ringbuffer::new(OUTPUT_RINGBUFFER_SIZE),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn send(&self, message: &[u8]) -> Result<(), SendError> {
+        let nbytes = message.len();
+        if self.closed.load(Ordering::Acquire) {
+            return Err(SendError::Other("duplex port has been closed"));
+        }
+
+        // Write full message to buffer
+        let written = self.buff_message.write(message);
+        debug_assert!(
+            written == nbytes,
+            "not enough bytes written to ALSA ringbuffer `message`"
+        );
+        let nbytes_slice = unsafe {
+            slice::from_raw_parts(
+                &nbytes as *const usize as *const u8,
+                mem::size_of_val(&nbytes),
+            )
+        };
+        let written = self.buff_size.write(nbytes_slice);
+        debug_assert!(
+            written == mem::size_of_val(&nbytes),
+            "not enough bytes written to ALSA ringbuffer `size`"
+        );
+        Ok(())
+    }
 }
 
 pub struct MidiOutput {
@@ -268,27 +821,50 @@ impl MidiOutputPort {
     pub fn id(&self) -> String {
         self.name.to_string_lossy().to_string()
     }
+
+    pub fn from_name(name: &CStr) -> Self {
+        MidiOutputPort { name: name.into() }
+    }
+
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
 }
 
 pub struct MidiOutputConnection {
     handler_data: Box<OutputHandlerData>,
+    subscriptions: Arc<SubscriptionState>,
+    events: Arc<EventState>,
     client: Option<Client>,
 }
 
 impl MidiOutput {
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        let client = match Client::open(client_name, JackOpenOptions::NoStartServer) {
-            Ok(c) => c,
-            Err(_) => {
-                return Err(InitError);
-            } // TODO: maybe add message that Jack server might not be running
-        };
+        Ok(Self::with_server_options(
+            client_name,
+            &ServerOptions::new(),
+        )?)
+    }
 
+    pub fn with_server_options(
+        client_name: &str,
+        options: &ServerOptions,
+    ) -> Result<Self, OpenError> {
         Ok(MidiOutput {
-            client: Some(client),
+            client: Some(open_client(client_name, options)?),
         })
     }
 
+    pub unsafe fn from_raw_client(client: *mut jack_client_t) -> Self {
+        MidiOutput {
+            client: Some(Client::from_raw(client)),
+        }
+    }
+
+    pub fn raw_client(&self) -> *mut jack_client_t {
+        self.client.as_ref().unwrap().as_raw()
+    }
+
     pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
         let ports = self
             .client
@@ -300,7 +876,8 @@ impl MidiOutput {
             result.push(crate::common::MidiOutputPort {
                 imp: MidiOutputPort {
                     name: ports.get_c_name(i).into(),
-                },
+                }
+                .into(),
             })
         }
         result
@@ -318,11 +895,37 @@ impl MidiOutput {
         Ok(port.name.to_string_lossy().into())
     }
 
-    fn activate_callback(&mut self) -> Box<OutputHandlerData> {
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // JACK port names have the form `client:port`, so the client name identifies the device
+        Ok(client_name_of(&port.name))
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(device_name_of(self.client.as_ref().unwrap(), &port.name))
+    }
+
+    pub fn port_aliases(&self, port: &MidiOutputPort) -> Vec<String> {
+        self.client.as_ref().unwrap().port_aliases(&port.name)
+    }
+
+    pub fn port_metadata(&self, port: &MidiOutputPort, key: &str) -> Option<String> {
+        let key = CString::new(key).ok()?;
+        self.client
+            .as_ref()
+            .unwrap()
+            .port_property(&port.name, &key)
+    }
+
+    fn activate_callback(
+        &mut self,
+    ) -> (
+        Box<OutputHandlerData>,
+        Arc<SubscriptionState>,
+        Arc<EventState>,
+    ) {
         let handler_data = Box::new(OutputHandlerData {
             port: None,
-            buff_size: Ringbuffer::new(OUTPUT_RINGBUFFER_SIZE),
-            buff_message: Ringbuffer::new(OUTPUT_RINGBUFFER_SIZE),
+            buffers: Arc::new(OutputBuffers::new()),
         });
 
         let data_ptr = unsafe { mem::transmute_copy::<_, *mut OutputHandlerData>(&handler_data) };
@@ -331,8 +934,10 @@ impl MidiOutput {
             .as_mut()
             .unwrap()
             .set_process_callback(handle_output, data_ptr as *mut c_void);
+        let subscriptions = SubscriptionState::watch(self.client.as_mut().unwrap());
+        let events = EventState::watch(self.client.as_mut().unwrap());
         self.client.as_mut().unwrap().activate();
-        handler_data
+        (handler_data, subscriptions, events)
     }
 
     pub fn connect(
@@ -340,7 +945,7 @@ impl MidiOutput {
         port: &MidiOutputPort,
         port_name: &str,
     ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
-        let mut handler_data = self.activate_callback();
+        let (mut handler_data, subscriptions, events) = self.activate_callback();
 
         // Create port ...
         let source_port = match self
@@ -365,10 +970,13 @@ impl MidiOutput {
             return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self));
         }
 
+        subscriptions.add_port(&source_port);
         handler_data.port = Some(source_port);
 
         Ok(MidiOutputConnection {
             handler_data: handler_data,
+            subscriptions,
+            events,
             client: self.client.take(),
         })
     }
@@ -376,8 +984,9 @@ impl MidiOutput {
     pub fn create_virtual(
         mut self,
         port_name: &str,
+        options: &VirtualPortOptions,
     ) -> Result<MidiOutputConnection, ConnectError<Self>> {
-        let mut handler_data = self.activate_callback();
+        let (mut handler_data, subscriptions, events) = self.activate_callback();
 
         // Create port
         let port = match self
@@ -392,39 +1001,73 @@ impl MidiOutput {
             }
         };
 
+        if apply_port_options(self.client.as_ref().unwrap(), &port, options).is_err() {
+            self.client.as_mut().unwrap().unregister_midi_port(port);
+            return Err(ConnectError::other("could not set JACK port options", self));
+        }
+
+        subscriptions.add_port(&port);
         handler_data.port = Some(port);
 
         Ok(MidiOutputConnection {
             handler_data: handler_data,
+            subscriptions,
+            events,
             client: self.client.take(),
         })
     }
 }
 
 impl MidiOutputConnection {
-    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
-        let nbytes = message.len();
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        self.subscriptions.subscribers()
+    }
 
-        // Write full message to buffer
-        let written = self.handler_data.buff_message.write(message);
-        debug_assert!(
-            written == nbytes,
-            "not enough bytes written to ALSA ringbuffer `message`"
-        );
-        let nbytes_slice = unsafe {
-            slice::from_raw_parts(
-                &nbytes as *const usize as *const u8,
-                mem::size_of_val(&nbytes),
-            )
-        };
-        let written = self.handler_data.buff_size.write(nbytes_slice);
-        debug_assert!(
-            written == mem::size_of_val(&nbytes),
-            "not enough bytes written to ALSA ringbuffer `size`"
-        );
+    pub fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static,
+    {
+        self.subscriptions.set_callback(Box::new(callback));
+        Ok(())
+    }
+
+    pub fn on_event<F>(&self, callback: F)
+    where
+        F: FnMut(JackEvent) + Send + 'static,
+    {
+        self.events.set_callback(Box::new(callback));
+    }
+
+    pub fn set_port_pretty_name(&self, name: &str) -> Result<(), PortOptionError> {
+        // The sending half of a duplex port does not own its port
+        let port = self
+            .handler_data
+            .port
+            .as_ref()
+            .ok_or(PortOptionError::NoPort)?;
+        self.client
+            .as_ref()
+            .unwrap()
+            .set_port_pretty_name(port, name)
+            .map_err(|()| PortOptionError::Failed)?;
+        Ok(())
+    }
+
+    pub fn add_port_alias(&self, alias: &str) -> Result<(), PortOptionError> {
+        let port = self
+            .handler_data
+            .port
+            .as_ref()
+            .ok_or(PortOptionError::NoPort)?;
+        port.set_alias(alias)
+            .map_err(|()| PortOptionError::Failed)?;
         Ok(())
     }
 
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        self.handler_data.buffers.send(message)
+    }
+
     pub fn close(mut self) -> MidiOutput {
         self.close_internal();
 
@@ -434,9 +1077,14 @@ impl MidiOutputConnection {
     }
 
     fn close_internal(&mut self) {
-        let port = self.handler_data.port.take().unwrap();
-        self.client.as_mut().unwrap().unregister_midi_port(port);
-        self.client.as_mut().unwrap().deactivate();
+        // The sending half of a duplex port does not own its port
+        if let Some(port) = self.handler_data.port.take() {
+            self.subscriptions.clear();
+            let client = self.client.as_mut().unwrap();
+            client.unregister_midi_port(port);
+            client.deactivate();
+            client.set_event_callbacks(None, ptr::null_mut());
+        }
     }
 }
 
@@ -450,7 +1098,11 @@ impl Drop for MidiOutputConnection {
 
 extern "C" fn handle_output(nframes: jack_nframes_t, arg: *mut c_void) -> i32 {
     let data: &mut OutputHandlerData = unsafe { mem::transmute(arg) };
+    write_output(data, nframes);
+    return 0;
+}
 
+fn write_output(data: &OutputHandlerData, nframes: jack_nframes_t) {
     // Is port created?
     if let Some(ref port) = data.port {
         let mut space: usize = 0;
@@ -458,8 +1110,9 @@ extern "C" fn handle_output(nframes: jack_nframes_t, arg: *mut c_void) -> i32 {
         let mut buff = port.get_midi_buffer(nframes);
         buff.clear();
 
-        while data.buff_size.get_read_space() > 0 {
-            let read = data
+        let buffers = &data.buffers;
+        while buffers.buff_size.get_read_space() > 0 {
+            let read = buffers
                 .buff_size
                 .read(&mut space as *mut usize as *mut u8, mem::size_of::<usize>());
             debug_assert!(
@@ -467,13 +1120,11 @@ extern "C" fn handle_output(nframes: jack_nframes_t, arg: *mut c_void) -> i32 {
                 "not enough bytes read from `size` ringbuffer"
             );
             let midi_data = buff.event_reserve(0, space);
-            let read = data.buff_message.read(midi_data, space);
+            let read = buffers.buff_message.read(midi_data, space);
             debug_assert!(
                 read == space,
                 "not enough bytes read from `message` ringbuffer"
             );
         }
     }
-
-    return 0;
 }
@@ -4,19 +4,38 @@ use std::ffi::{CStr, CString};
 use std::ops::Index;
 use std::{ptr, slice, str};
 
-use libc::{c_void, size_t};
+use libc::{c_char, c_int, c_void, size_t};
 
 use jack_sys::{
-    jack_activate, jack_client_close, jack_client_open, jack_client_t, jack_connect,
-    jack_deactivate, jack_free, jack_get_ports, jack_get_time, jack_midi_clear_buffer,
-    jack_midi_data_t, jack_midi_event_get, jack_midi_event_reserve, jack_midi_event_t,
-    jack_midi_get_event_count, jack_nframes_t, jack_port_get_buffer, jack_port_name,
-    jack_port_register, jack_port_t, jack_port_unregister, jack_ringbuffer_create,
+    jack_activate, jack_client_close, jack_client_t, jack_connect, jack_deactivate,
+    jack_frames_to_time, jack_free, jack_get_client_name, jack_get_ports, jack_get_property,
+    jack_get_sample_rate, jack_get_time, jack_get_uuid_for_client_name, jack_last_frame_time,
+    jack_midi_clear_buffer, jack_midi_data_t, jack_midi_event_get, jack_midi_event_reserve,
+    jack_midi_event_t, jack_midi_get_event_count, jack_nframes_t, jack_on_shutdown, jack_options_t,
+    jack_port_by_id, jack_port_by_name, jack_port_get_aliases, jack_port_get_all_connections,
+    jack_port_get_buffer, jack_port_id_t, jack_port_name, jack_port_name_size, jack_port_register,
+    jack_port_set_alias, jack_port_t, jack_port_unregister, jack_port_uuid, jack_ringbuffer_create,
     jack_ringbuffer_free, jack_ringbuffer_read, jack_ringbuffer_read_space, jack_ringbuffer_t,
-    jack_ringbuffer_write, jack_set_process_callback,
+    jack_ringbuffer_write, jack_set_buffer_size_callback, jack_set_port_connect_callback,
+    jack_set_process_callback, jack_set_property, jack_set_sample_rate_callback,
+    jack_set_xrun_callback, jack_status_t, jack_uuid_parse, jack_uuid_t, JackServerFailed,
 };
 
+use crate::os::jack::OpenError;
+
+extern "C" {
+    // jack-sys declares this without the variadic arguments, which carry the server name
+    fn jack_client_open(
+        client_name: *const c_char,
+        options: jack_options_t,
+        status: *mut jack_status_t,
+        ...
+    ) -> *mut jack_client_t;
+}
+
 pub const JACK_DEFAULT_MIDI_TYPE: &[u8] = b"8 bit raw midi\0";
+pub const JACK_METADATA_PRETTY_NAME: &[u8] = b"http://jackaudio.org/metadata/pretty-name\0";
+pub const JACK_METADATA_HARDWARE: &[u8] = b"http://jackaudio.org/metadata/hardware\0";
 
 bitflags! {
     pub struct JackOpenOptions: u32 {
@@ -40,6 +59,19 @@ bitflags! {
 // TODO: hide this type
 pub type ProcessCallback = extern "C" fn(nframes: jack_nframes_t, arg: *mut c_void) -> i32;
 
+pub type PortConnectCallback =
+    unsafe extern "C" fn(a: jack_port_id_t, b: jack_port_id_t, connect: c_int, arg: *mut c_void);
+
+/// Functions that are called when the server shuts down, an xrun occurs
+/// or the sample rate or buffer size of the server changes.
+#[derive(Clone, Copy)]
+pub struct EventCallbacks {
+    pub shutdown: unsafe extern "C" fn(arg: *mut c_void),
+    pub xrun: unsafe extern "C" fn(arg: *mut c_void) -> c_int,
+    pub sample_rate: unsafe extern "C" fn(nframes: jack_nframes_t, arg: *mut c_void) -> c_int,
+    pub buffer_size: unsafe extern "C" fn(nframes: jack_nframes_t, arg: *mut c_void) -> c_int,
+}
+
 pub struct Client {
     p: *mut jack_client_t,
 }
@@ -51,18 +83,61 @@ impl Client {
         unsafe { jack_get_time() }
     }
 
-    pub fn open(name: &str, options: JackOpenOptions) -> Result<Client, ()> {
+    /// Opens a client on the named server (`options` must then contain `ServerName`)
+    /// or on the default server.
+    pub fn open(
+        name: &str,
+        options: JackOpenOptions,
+        server_name: Option<&CStr>,
+    ) -> Result<Client, OpenError> {
         let c_name = CString::new(name)
             .ok()
             .expect("client name must not contain null bytes");
-        let result = unsafe { jack_client_open(c_name.as_ptr(), options.bits(), ptr::null_mut()) };
+        let mut status: jack_status_t = 0;
+        let result = unsafe {
+            match server_name {
+                Some(server_name) => jack_client_open(
+                    c_name.as_ptr(),
+                    options.bits(),
+                    &mut status,
+                    server_name.as_ptr(),
+                ),
+                None => jack_client_open(c_name.as_ptr(), options.bits(), &mut status),
+            }
+        };
         if result.is_null() {
-            Err(())
+            if status & JackServerFailed != 0 {
+                Err(OpenError::ServerNotRunning)
+            } else {
+                Err(OpenError::Failed)
+            }
         } else {
             Ok(Client { p: result })
         }
     }
 
+    /// Takes ownership of a client that has been opened elsewhere.
+    /// The client will be closed when the returned object is dropped.
+    pub unsafe fn from_raw(p: *mut jack_client_t) -> Client {
+        Client { p }
+    }
+
+    pub fn as_raw(&self) -> *mut jack_client_t {
+        self.p
+    }
+
+    /// Get the name that the server assigned to this client.
+    pub fn name(&self) -> String {
+        unsafe { CStr::from_ptr(jack_get_client_name(self.p)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Get a reference to the client that can be passed to its process callback.
+    pub fn callback_ref(&self) -> CallbackClient {
+        CallbackClient { p: self.p }
+    }
+
     pub fn get_midi_ports(&self, flags: PortFlags) -> PortInfos {
         let ports_ptr = unsafe {
             jack_get_ports(
@@ -72,17 +147,7 @@ impl Client {
                 flags.bits() as _,
             )
         };
-        let slice = if ports_ptr.is_null() {
-            &[]
-        } else {
-            unsafe {
-                let count = (0isize..)
-                    .find(|i| (*ports_ptr.offset(*i)).is_null())
-                    .unwrap() as usize;
-                slice::from_raw_parts(ports_ptr, count)
-            }
-        };
-        PortInfos { p: slice }
+        unsafe { PortInfos::from_raw(ports_ptr) }
     }
 
     pub fn register_midi_port(&mut self, name: &str, flags: PortFlags) -> Result<MidiPort, ()> {
@@ -128,6 +193,88 @@ impl Client {
         unsafe { jack_set_process_callback(self.p, Some(callback), data) };
     }
 
+    /// The callback is called from a notification thread of the client,
+    /// whenever any two ports are connected or disconnected.
+    pub fn set_port_connect_callback(&mut self, callback: PortConnectCallback, data: *mut c_void) {
+        unsafe { jack_set_port_connect_callback(self.p, Some(callback), data) };
+    }
+
+    /// Must be called while the client is not active. `None` removes the callbacks.
+    /// JACK calls them from its notification threads (or from the process thread
+    /// for some events of some JACK versions), and the sample rate callback also
+    /// once on registration.
+    pub fn set_event_callbacks(&mut self, callbacks: Option<EventCallbacks>, arg: *mut c_void) {
+        unsafe {
+            jack_on_shutdown(self.p, callbacks.map(|c| c.shutdown), arg);
+            jack_set_xrun_callback(self.p, callbacks.map(|c| c.xrun), arg);
+            jack_set_sample_rate_callback(self.p, callbacks.map(|c| c.sample_rate), arg);
+            jack_set_buffer_size_callback(self.p, callbacks.map(|c| c.buffer_size), arg);
+        }
+    }
+
+    /// Get the aliases of the port with the given full name.
+    pub fn port_aliases(&self, port_name: &CStr) -> Vec<String> {
+        let port = unsafe { jack_port_by_name(self.p, port_name.as_ptr()) };
+        if port.is_null() {
+            return Vec::new();
+        }
+        // A port has at most two aliases, which are at most as long as port names
+        let size = unsafe { jack_port_name_size() } as usize;
+        let mut buffers = [vec![0 as c_char; size], vec![0 as c_char; size]];
+        let mut pointers = [buffers[0].as_mut_ptr(), buffers[1].as_mut_ptr()];
+        let count = unsafe { jack_port_get_aliases(port, pointers.as_mut_ptr()) };
+        (0..count.clamp(0, 2) as usize)
+            .map(|i| {
+                unsafe { CStr::from_ptr(pointers[i]) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    /// Get a metadata property of the port with the given full name.
+    pub fn port_property(&self, port_name: &CStr, key: &CStr) -> Option<String> {
+        let port = unsafe { jack_port_by_name(self.p, port_name.as_ptr()) };
+        if port.is_null() {
+            return None;
+        }
+        get_property(unsafe { jack_port_uuid(port) }, key)
+    }
+
+    /// Get a metadata property of the client with the given name.
+    pub fn client_property(&self, client_name: &CStr, key: &CStr) -> Option<String> {
+        let uuid_string = unsafe { jack_get_uuid_for_client_name(self.p, client_name.as_ptr()) };
+        if uuid_string.is_null() {
+            return None;
+        }
+        let mut uuid: jack_uuid_t = 0;
+        let rc = unsafe { jack_uuid_parse(uuid_string, &mut uuid) };
+        unsafe { jack_free(uuid_string as *mut c_void) };
+        if rc == 0 {
+            get_property(uuid, key)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_port_pretty_name(&self, port: &MidiPort, name: &str) -> Result<(), ()> {
+        let c_name = CString::new(name).map_err(|_| ())?;
+        let rc = unsafe {
+            jack_set_property(
+                self.p,
+                jack_port_uuid(port.p),
+                JACK_METADATA_PRETTY_NAME.as_ptr() as *const _,
+                c_name.as_ptr(),
+                ptr::null(),
+            )
+        };
+        if rc == 0 {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
     pub fn connect(&mut self, source_port: &CStr, destination_port: &CStr) -> Result<(), ()> {
         let rc = unsafe { jack_connect(self.p, source_port.as_ptr(), destination_port.as_ptr()) };
         if rc == 0 {
@@ -144,6 +291,87 @@ impl Drop for Client {
     }
 }
 
+/// Returns the value of a metadata property of the subject with the given UUID.
+fn get_property(subject: jack_uuid_t, key: &CStr) -> Option<String> {
+    let mut value: *mut c_char = ptr::null_mut();
+    let mut value_type: *mut c_char = ptr::null_mut();
+    let rc = unsafe { jack_get_property(subject, key.as_ptr(), &mut value, &mut value_type) };
+    if rc != 0 {
+        return None;
+    }
+    let result = unsafe { CStr::from_ptr(value) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe {
+        jack_free(value as *mut c_void);
+        if !value_type.is_null() {
+            jack_free(value_type as *mut c_void);
+        }
+    }
+    Some(result)
+}
+
+/// Returns the full name of the port with the given id.
+/// This only needs the raw client, so that it can be used in callbacks.
+pub unsafe fn port_name_by_id(client: *mut jack_client_t, id: jack_port_id_t) -> Option<CString> {
+    let port = jack_port_by_id(client, id);
+    if port.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(jack_port_name(port)).into())
+    }
+}
+
+/// A reference to a client that does not own it, for use in the process callback
+/// of the client (which only runs while the client is open).
+#[derive(Clone, Copy)]
+pub struct CallbackClient {
+    p: *mut jack_client_t,
+}
+
+unsafe impl Send for CallbackClient {}
+unsafe impl Sync for CallbackClient {}
+
+/// The timing of the current process cycle of a client, which converts the frame offsets
+/// of MIDI events into times.
+#[derive(Clone, Copy)]
+pub struct CycleTimes {
+    /// The frame time at the start of the cycle.
+    pub frame_time: jack_nframes_t,
+    /// The time at the start of the cycle, in microseconds.
+    usecs: u64,
+    sample_rate: u64,
+}
+
+impl CycleTimes {
+    /// Must only be called from the process callback of the client.
+    pub fn current(client: CallbackClient) -> CycleTimes {
+        let frame_time = unsafe { jack_last_frame_time(client.p) };
+        CycleTimes {
+            frame_time,
+            usecs: unsafe { jack_frames_to_time(client.p, frame_time) },
+            sample_rate: unsafe { jack_get_sample_rate(client.p) } as u64,
+        }
+    }
+
+    /// Get the time in microseconds of the event at the given frame offset in the cycle.
+    pub fn time_of(&self, offset: jack_nframes_t) -> u64 {
+        self.usecs + offset as u64 * 1_000_000 / self.sample_rate.max(1)
+    }
+}
+
+/// Returns the full names of all ports that are connected to the port with the given name.
+pub unsafe fn port_connections(client: *mut jack_client_t, port_name: &CStr) -> Vec<CString> {
+    let port = jack_port_by_name(client, port_name.as_ptr());
+    if port.is_null() {
+        return Vec::new();
+    }
+    let connections = PortInfos::from_raw(jack_port_get_all_connections(client, port));
+    (0..connections.count())
+        .map(|i| connections.get_c_name(i).into())
+        .collect()
+}
+
 #[cfg(not(any(target_arch = "aarch64", target_arch = "arm")))]
 type PortInfo = i8;
 
@@ -157,6 +385,19 @@ pub struct PortInfos<'a> {
 unsafe impl<'a> Send for PortInfos<'a> {}
 
 impl<'a> PortInfos<'a> {
+    /// Takes ownership of a null-terminated list of port names allocated by JACK.
+    unsafe fn from_raw(ports_ptr: *mut *const PortInfo) -> PortInfos<'a> {
+        let slice = if ports_ptr.is_null() {
+            &[]
+        } else {
+            let count = (0isize..)
+                .find(|i| (*ports_ptr.offset(*i)).is_null())
+                .unwrap() as usize;
+            slice::from_raw_parts(ports_ptr as *const *const PortInfo, count)
+        };
+        PortInfos { p: slice }
+    }
+
     pub fn count(&self) -> usize {
         self.p.len()
     }
@@ -197,6 +438,16 @@ impl MidiPort {
         unsafe { CStr::from_ptr(jack_port_name(self.p)) }
     }
 
+    pub fn set_alias(&self, alias: &str) -> Result<(), ()> {
+        let c_alias = CString::new(alias).map_err(|_| ())?;
+        let rc = unsafe { jack_port_set_alias(self.p, c_alias.as_ptr()) };
+        if rc == 0 {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
     pub fn get_midi_buffer(&self, nframes: jack_nframes_t) -> MidiBuffer {
         let buf = unsafe { jack_port_get_buffer(self.p, nframes) };
         MidiBuffer { p: buf }
@@ -245,13 +496,13 @@ impl Ringbuffer {
         unsafe { jack_ringbuffer_read_space(self.p) as usize }
     }
 
-    pub fn read(&mut self, destination: *mut u8, count: usize) -> usize {
+    pub fn read(&self, destination: *mut u8, count: usize) -> usize {
         let bytes_read =
             unsafe { jack_ringbuffer_read(self.p, destination as *mut _, count as size_t) };
         bytes_read as usize
     }
 
-    pub fn write(&mut self, source: &[u8]) -> usize {
+    pub fn write(&self, source: &[u8]) -> usize {
         unsafe {
             jack_ringbuffer_write(self.p, source.as_ptr() as *const _, source.len() as size_t)
                 as usize
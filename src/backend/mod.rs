@@ -1,39 +1,203 @@
-// This module is not public
+// This module is not public, apart from the items that are re-exported from the crate root
 
-// TODO: improve feature selection (make sure that there is always exactly one implementation, or enable dynamic backend selection)
-// TODO: allow to disable build dependency on ALSA
+#[cfg(not(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows")),
+    all(target_os = "linux", feature = "pipewire"),
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "windows",
+    target_arch = "wasm32",
+    feature = "dummy",
+    feature = "rtpmidi",
+    feature = "ipmidi",
+    all(unix, feature = "serial")
+)))]
+compile_error!(
+    "no MIDI backend is enabled for this target, enable at least one of the `alsa` (Linux), `pipewire` (Linux), `jack`, `rtpmidi`, `ipmidi`, `serial` (Unix) or `dummy` features"
+);
+
+use std::fmt;
+
+use crate::errors::InitError;
 
 #[cfg(all(target_os = "windows", not(feature = "winrt")))]
-mod winmm;
-#[cfg(all(target_os = "windows", not(feature = "winrt")))]
-pub use self::winmm::*;
+pub(crate) mod winmm;
 
 #[cfg(all(target_os = "windows", feature = "winrt"))]
-mod winrt;
-#[cfg(all(target_os = "windows", feature = "winrt"))]
-pub use self::winrt::*;
+pub(crate) mod winrt;
 
-#[cfg(all(target_os = "macos", not(feature = "jack")))]
-mod coremidi;
-#[cfg(all(target_os = "macos", not(feature = "jack")))]
-pub use self::coremidi::*;
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) mod coremidi;
 
-#[cfg(all(target_os = "ios", not(feature = "jack")))]
-mod coremidi;
-#[cfg(all(target_os = "ios", not(feature = "jack")))]
-pub use self::coremidi::*;
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+pub(crate) mod alsa;
 
-#[cfg(all(target_os = "linux", not(feature = "jack")))]
-mod alsa;
-#[cfg(all(target_os = "linux", not(feature = "jack")))]
-pub use self::alsa::*;
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+pub(crate) mod alsa_raw;
 
 #[cfg(all(feature = "jack", not(target_os = "windows")))]
-mod jack;
-#[cfg(all(feature = "jack", not(target_os = "windows")))]
-pub use self::jack::*;
+pub(crate) mod jack;
+
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+pub(crate) mod pipewire;
 
 #[cfg(target_arch = "wasm32")]
-mod webmidi;
-#[cfg(target_arch = "wasm32")]
-pub use self::webmidi::*;
+pub(crate) mod webmidi;
+
+#[cfg(feature = "dummy")]
+pub(crate) mod dummy;
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+pub(crate) mod rtpmidi;
+
+#[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+pub(crate) mod ipmidi;
+
+#[cfg(all(unix, feature = "serial"))]
+pub(crate) mod serial;
+
+with_any_backend! {
+    mod dispatch;
+    pub use self::dispatch::*;
+}
+
+/// The environment variable that can be used to choose the backend
+/// that `MidiInput::new` and `MidiOutput::new` use.
+const BACKEND_ENV_VAR: &str = "MIDIR_BACKEND";
+
+/// A MIDI API that *midir* can use to talk to the MIDI system.
+///
+/// Which backends are available depends on the platform and on the enabled
+/// cargo features, see `available_backends`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The ALSA sequencer (Linux).
+    Alsa,
+    /// The ALSA rawmidi interface, which talks to hardware ports directly (Linux).
+    AlsaRaw,
+    /// The JACK Audio Connection Kit (requires the `jack` feature).
+    Jack,
+    /// PipeWire (Linux, requires the `pipewire` feature).
+    PipeWire,
+    /// CoreMIDI (macOS and iOS).
+    CoreMidi,
+    /// The Windows Multimedia API (Windows, unless the `winrt` feature is enabled).
+    WinMM,
+    /// The Windows Runtime MIDI API (Windows, requires the `winrt` feature).
+    WinRT,
+    /// The Web MIDI API (WebAssembly in a browser).
+    WebMidi,
+    /// An in-process backend without any real MIDI devices, meant for
+    /// tests (requires the `dummy` feature, see `os::dummy`).
+    Dummy,
+    /// RTP-MIDI network sessions, as used by Apple's Network MIDI (requires
+    /// the `rtpmidi` feature, see `os::rtpmidi`).
+    RtpMidi,
+    /// ipMIDI-style raw MIDI over UDP multicast (requires the `ipmidi` feature, see `os::ipmidi`).
+    IpMidi,
+    /// MIDI over serial lines, such as the USB serial ports of microcontroller boards
+    /// (Unix, requires the `serial` feature, see `os::serial`).
+    Serial,
+}
+
+impl Backend {
+    /// Get the name of the backend, as it can be given in the
+    /// `MIDIR_BACKEND` environment variable (in any case).
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Alsa => "alsa",
+            Backend::AlsaRaw => "alsa-raw",
+            Backend::Jack => "jack",
+            Backend::PipeWire => "pipewire",
+            Backend::CoreMidi => "coremidi",
+            Backend::WinMM => "winmm",
+            Backend::WinRT => "winrt",
+            Backend::WebMidi => "webmidi",
+            Backend::Dummy => "dummy",
+            Backend::RtpMidi => "rtpmidi",
+            Backend::IpMidi => "ipmidi",
+            Backend::Serial => "serial",
+        }
+    }
+
+    /// Get the backend with the given name (ignoring case), if there is one.
+    pub fn from_name(name: &str) -> Option<Backend> {
+        ALL_BACKENDS
+            .iter()
+            .copied()
+            .find(|backend| backend.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Check whether this backend has been compiled in.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Alsa | Backend::AlsaRaw => cfg!(all(target_os = "linux", feature = "alsa")),
+            Backend::Jack => cfg!(all(feature = "jack", not(target_os = "windows"))),
+            Backend::PipeWire => cfg!(all(target_os = "linux", feature = "pipewire")),
+            Backend::CoreMidi => cfg!(any(target_os = "macos", target_os = "ios")),
+            Backend::WinMM => cfg!(all(target_os = "windows", not(feature = "winrt"))),
+            Backend::WinRT => cfg!(all(target_os = "windows", feature = "winrt")),
+            Backend::WebMidi => cfg!(target_arch = "wasm32"),
+            Backend::Dummy => cfg!(feature = "dummy"),
+            Backend::RtpMidi => cfg!(all(feature = "rtpmidi", not(target_arch = "wasm32"))),
+            Backend::IpMidi => cfg!(all(feature = "ipmidi", not(target_arch = "wasm32"))),
+            Backend::Serial => cfg!(all(unix, feature = "serial")),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+/// All backends, in the order in which they are preferred.
+const ALL_BACKENDS: [Backend; 12] = [
+    Backend::PipeWire,
+    Backend::Jack,
+    Backend::Alsa,
+    Backend::AlsaRaw,
+    Backend::CoreMidi,
+    Backend::WinMM,
+    Backend::WinRT,
+    Backend::WebMidi,
+    Backend::RtpMidi,
+    Backend::IpMidi,
+    Backend::Serial,
+    Backend::Dummy,
+];
+
+/// Get all backends that have been compiled in, in the order in which they are tried
+/// by `MidiInput::new` and `MidiOutput::new`: PipeWire and JACK come first (they are only
+/// used if their daemon is running), then the native API of the platform, then the network
+/// backends RTP-MIDI and ipMIDI and the serial backend (which do not talk to the local MIDI
+/// system), and finally the `dummy` backend (so that enabling the `dummy` feature does not
+/// replace the real MIDI system).
+pub fn available_backends() -> Vec<Backend> {
+    ALL_BACKENDS
+        .iter()
+        .copied()
+        .filter(|backend| backend.is_available())
+        .collect()
+}
+
+/// Calls `new` for the backend named by the `MIDIR_BACKEND` environment variable or,
+/// if it is not set, for each available backend until one of them succeeds.
+pub(crate) fn with_default_backend<T, F>(mut new: F) -> Result<T, InitError>
+where
+    F: FnMut(Backend) -> Result<T, InitError>,
+{
+    match std::env::var(BACKEND_ENV_VAR) {
+        Ok(name) if !name.is_empty() => match Backend::from_name(&name) {
+            Some(backend) => new(backend),
+            None => Err(InitError),
+        },
+        _ => available_backends()
+            .into_iter()
+            .find_map(|backend| new(backend).ok())
+            .ok_or(InitError),
+    }
+}
@@ -0,0 +1,938 @@
+//! A backend for PipeWire, which talks to the PipeWire daemon directly instead of going
+//! through its ALSA sequencer bridge or its JACK compatibility layer.
+//!
+//! MIDI ports are the control ports of PipeWire nodes (with the DSP format `8 bit raw midi`).
+//! Every connection and every virtual port is a stream node of its own, which is linked to the
+//! port that it connects to. PipeWire objects must only be used on the thread that created
+//! them, so each of these nodes runs its own main loop on a separate thread.
+
+mod sequence;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
+
+use pipewire as pw;
+use pw::link::Link;
+use pw::main_loop::MainLoop;
+use pw::properties::properties;
+use pw::spa::pod::serialize::PodSerializer;
+use pw::spa::pod::{Object, Pod, Property, Value};
+use pw::spa::utils::{Direction, Id};
+use pw::stream::{Stream, StreamFlags, StreamListener, StreamRef, StreamState};
+use pw::types::ObjectType;
+
+use crate::errors::*;
+use crate::parser::{is_ignored, StreamParser};
+use crate::r#virtual::VirtualPortOptions;
+use crate::Ignore;
+
+const MIDI_FORMAT: &str = "8 bit raw midi";
+
+/// The maximum number of messages that are queued for an output node, e.g.
+/// while a virtual output port is not connected and its node is not running.
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// How long to wait for the daemon to answer a request before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the PipeWire daemon, with a main loop that runs on the current thread.
+struct Graph {
+    // The listener must be dropped before the core
+    _listener: pw::core::Listener,
+    main_loop: MainLoop,
+    core: pw::core::Core,
+    failed: Rc<Cell<bool>>,
+}
+
+impl Graph {
+    fn new(client_name: &str) -> Result<Graph, pw::Error> {
+        pw::init();
+        let main_loop = MainLoop::new(None)?;
+        let context = pw::context::Context::new(&main_loop)?;
+        let core = context.connect(Some(properties! {
+            *pw::keys::APP_NAME => client_name,
+        }))?;
+        // Errors of the core or of any object created through it abort the current wait
+        let failed = Rc::new(Cell::new(false));
+        let listener = core
+            .add_listener_local()
+            .error({
+                let failed = failed.clone();
+                let main_loop = main_loop.clone();
+                move |_, _, _, _| {
+                    failed.set(true);
+                    main_loop.quit();
+                }
+            })
+            .register();
+        Ok(Graph {
+            _listener: listener,
+            main_loop,
+            core,
+            failed,
+        })
+    }
+
+    /// Runs the main loop until `done` returns true. Fails if the daemon reports
+    /// an error in the meantime or if it takes longer than `TIMEOUT`.
+    fn wait_until<F>(&self, done: F) -> Result<(), pw::Error>
+    where
+        F: Fn() -> bool,
+    {
+        self.failed.set(false);
+        let timed_out = Rc::new(Cell::new(false));
+        let timer = self.main_loop.loop_().add_timer({
+            let timed_out = timed_out.clone();
+            let main_loop = self.main_loop.clone();
+            move |_| {
+                timed_out.set(true);
+                main_loop.quit();
+            }
+        });
+        timer.update_timer(Some(TIMEOUT), None).into_sync_result()?;
+        while !done() {
+            if self.failed.get() || timed_out.get() {
+                return Err(pw::Error::CreationFailed);
+            }
+            self.main_loop.run();
+        }
+        Ok(())
+    }
+
+    /// Runs the main loop until the daemon has processed all previous requests.
+    fn roundtrip(&self) -> Result<(), pw::Error> {
+        let done = Rc::new(Cell::new(false));
+        let pending = self.core.sync(0)?;
+        let _listener = self
+            .core
+            .add_listener_local()
+            .done({
+                let done = done.clone();
+                let main_loop = self.main_loop.clone();
+                move |id, seq| {
+                    if id == pw::core::PW_ID_CORE && seq == pending {
+                        done.set(true);
+                        main_loop.quit();
+                    }
+                }
+            })
+            .register();
+        self.wait_until(|| done.get())
+    }
+
+    /// Gets all MIDI ports with the given direction (`"in"` or `"out"`).
+    fn midi_ports(&self, direction: &str) -> Result<Vec<PortInfo>, pw::Error> {
+        struct NodeInfo {
+            name: String,
+            description: String,
+            is_midi: bool,
+        }
+
+        struct RawPort {
+            id: u32,
+            node_id: Option<u32>,
+            name: String,
+            alias: Option<String>,
+            direction: String,
+            format: Option<String>,
+        }
+
+        let nodes = Rc::new(RefCell::new(HashMap::new()));
+        let ports = Rc::new(RefCell::new(Vec::new()));
+        let registry = self.core.get_registry()?;
+        let _listener = registry
+            .add_listener_local()
+            .global({
+                let nodes = nodes.clone();
+                let ports = ports.clone();
+                move |global| {
+                    let props = match global.props {
+                        Some(props) => props,
+                        None => return,
+                    };
+                    let get = |key: &str| props.get(key).map(str::to_string);
+                    match global.type_ {
+                        ObjectType::Node => {
+                            let name = get("node.name").unwrap_or_default();
+                            let description = get("node.description")
+                                .or_else(|| get("node.nick"))
+                                .unwrap_or_else(|| name.clone());
+                            nodes.borrow_mut().insert(
+                                global.id,
+                                NodeInfo {
+                                    name,
+                                    description,
+                                    is_midi: props.get("media.type") == Some("Midi"),
+                                },
+                            );
+                        }
+                        ObjectType::Port => ports.borrow_mut().push(RawPort {
+                            id: global.id,
+                            node_id: props.get("node.id").and_then(|id| id.parse().ok()),
+                            name: get("port.name").unwrap_or_default(),
+                            alias: get("port.alias"),
+                            direction: get("port.direction").unwrap_or_default(),
+                            format: get("format.dsp"),
+                        }),
+                        _ => {}
+                    }
+                }
+            })
+            .register();
+        self.roundtrip()?;
+
+        let nodes = nodes.borrow();
+        let result = ports
+            .borrow()
+            .iter()
+            .filter(|port| port.direction == direction)
+            .filter_map(|port| {
+                let node_id = port.node_id?;
+                let node = nodes.get(&node_id)?;
+                if port.format.as_deref() != Some(MIDI_FORMAT) && !node.is_midi {
+                    return None;
+                }
+                Some(PortInfo {
+                    id: port.id,
+                    node_id,
+                    path: format!("{}:{}", node.name, port.name),
+                    name: match port.alias {
+                        Some(ref alias) => alias.clone(),
+                        None => format!("{}:{}", node.description, port.name),
+                    },
+                    node_name: node.name.clone(),
+                    node_description: node.description.clone(),
+                })
+            })
+            .collect();
+        Ok(result)
+    }
+}
+
+/// The identity of a MIDI port, as it was seen when the ports were enumerated.
+#[derive(Clone, PartialEq)]
+struct PortInfo {
+    id: u32,
+    node_id: u32,
+    path: String,
+    name: String,
+    node_name: String,
+    node_description: String,
+}
+
+impl PortInfo {
+    /// Whether both refer to the same port. Names may change while a port exists.
+    fn is_same(&self, other: &PortInfo) -> bool {
+        self.id == other.id && self.path == other.path
+    }
+
+    /// Finds the port again, to check that it still exists.
+    fn lookup(&self, client_name: &str, direction: &str) -> Result<PortInfo, PortInfoError> {
+        Graph::new(client_name)
+            .and_then(|graph| graph.midi_ports(direction))
+            .map_err(|_| PortInfoError::CannotRetrievePortName)?
+            .into_iter()
+            .find(|port| port.is_same(self))
+            .ok_or(PortInfoError::InvalidPort)
+    }
+}
+
+/// The graph time at the start of the current cycle, from which the time of
+/// every event within the cycle is derived.
+struct CycleTime {
+    now: i64,
+    rate_num: u32,
+    rate_denom: u32,
+}
+
+impl CycleTime {
+    fn new(stream: &StreamRef) -> Option<CycleTime> {
+        let mut time: pw::sys::pw_time = unsafe { mem::zeroed() };
+        let res = unsafe {
+            pw::sys::pw_stream_get_time_n(
+                stream.as_raw_ptr(),
+                &mut time,
+                mem::size_of::<pw::sys::pw_time>(),
+            )
+        };
+        if res < 0 || time.rate.denom == 0 {
+            return None;
+        }
+        Some(CycleTime {
+            now: time.now,
+            rate_num: time.rate.num,
+            rate_denom: time.rate.denom,
+        })
+    }
+
+    /// Get the time in microseconds (of `CLOCK_MONOTONIC`) of the given sample offset.
+    fn timestamp(&self, offset: u32) -> u64 {
+        let offset_nsecs =
+            offset as i64 * self.rate_num as i64 * 1_000_000_000 / self.rate_denom as i64;
+        ((self.now + offset_nsecs) / 1000) as u64
+    }
+}
+
+/// The format that all MIDI streams use: a sequence of control events.
+fn midi_format() -> Vec<u8> {
+    PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: pw::spa::sys::SPA_TYPE_OBJECT_Format,
+            id: pw::spa::sys::SPA_PARAM_EnumFormat,
+            properties: vec![
+                Property::new(
+                    pw::spa::sys::SPA_FORMAT_mediaType,
+                    Value::Id(Id(pw::spa::sys::SPA_MEDIA_TYPE_application)),
+                ),
+                Property::new(
+                    pw::spa::sys::SPA_FORMAT_mediaSubtype,
+                    Value::Id(Id(pw::spa::sys::SPA_MEDIA_SUBTYPE_control)),
+                ),
+            ],
+        }),
+    )
+    .expect("could not serialize MIDI format")
+    .0
+    .into_inner()
+}
+
+/// A stream node and the objects that have to live as long as it.
+struct Node {
+    // The listener and the link must be dropped before the stream
+    _listener: StreamListener<()>,
+    _link: Option<Link>,
+    stream: Stream,
+}
+
+impl Node {
+    /// Creates a stream node with a single MIDI port and links it to `target`, if given.
+    fn new<F>(
+        graph: &Graph,
+        port_name: &str,
+        direction: Direction,
+        options: &VirtualPortOptions,
+        target: Option<&PortInfo>,
+        mut process: F,
+    ) -> Result<Node, ConnectErrorKind>
+    where
+        F: FnMut(&StreamRef) + 'static,
+    {
+        let (category, port_direction, target_direction) = match direction {
+            Direction::Input => ("Capture", "in", "out"),
+            _ => ("Playback", "out", "in"),
+        };
+        let stream = Stream::new(
+            &graph.core,
+            port_name,
+            properties! {
+                *pw::keys::MEDIA_TYPE => "Midi",
+                *pw::keys::MEDIA_CATEGORY => category,
+                *pw::keys::MEDIA_ROLE => "DSP",
+                *pw::keys::NODE_NAME => port_name,
+                *pw::keys::NODE_DESCRIPTION => options.pretty_name.as_deref().unwrap_or(port_name),
+            },
+        )
+        .map_err(|_| ConnectErrorKind::Other("could not create PipeWire stream"))?;
+
+        // Wait until the node has been created, which is the case once it is paused
+        let state = Rc::new(Cell::new(None));
+        let listener = stream
+            .add_local_listener()
+            .state_changed({
+                let state = state.clone();
+                let main_loop = graph.main_loop.clone();
+                move |_, _, _, new| match new {
+                    StreamState::Paused | StreamState::Streaming => {
+                        state.set(Some(true));
+                        main_loop.quit();
+                    }
+                    StreamState::Error(_) => {
+                        state.set(Some(false));
+                        main_loop.quit();
+                    }
+                    _ => {}
+                }
+            })
+            .process(move |stream, _| process(stream))
+            .register()
+            .map_err(|_| ConnectErrorKind::Other("could not create PipeWire stream"))?;
+
+        let format = midi_format();
+        let mut params = [Pod::from_bytes(&format).unwrap()];
+        stream
+            .connect(direction, None, StreamFlags::MAP_BUFFERS, &mut params)
+            .map_err(|_| ConnectErrorKind::Other("could not connect PipeWire stream"))?;
+        graph
+            .wait_until(|| state.get().is_some())
+            .map_err(|_| ConnectErrorKind::Other("could not connect PipeWire stream"))?;
+        if state.get() == Some(false) {
+            return Err(ConnectErrorKind::Other("could not connect PipeWire stream"));
+        }
+
+        let link = match target {
+            Some(target) => Some(Self::link(
+                graph,
+                &stream,
+                port_direction,
+                target_direction,
+                target,
+            )?),
+            None => None,
+        };
+
+        Ok(Node {
+            _listener: listener,
+            _link: link,
+            stream,
+        })
+    }
+
+    fn link(
+        graph: &Graph,
+        stream: &Stream,
+        port_direction: &str,
+        target_direction: &str,
+        target: &PortInfo,
+    ) -> Result<Link, ConnectErrorKind> {
+        const LINK_ERROR: ConnectErrorKind =
+            ConnectErrorKind::Other("could not link PipeWire ports");
+
+        if !graph
+            .midi_ports(target_direction)
+            .map_err(|_| LINK_ERROR)?
+            .iter()
+            .any(|port| port.is_same(target))
+        {
+            return Err(ConnectErrorKind::InvalidPort);
+        }
+        let node_id = stream.node_id();
+        let own_port = graph
+            .midi_ports(port_direction)
+            .map_err(|_| LINK_ERROR)?
+            .into_iter()
+            .find(|port| port.node_id == node_id)
+            .ok_or(LINK_ERROR)?;
+
+        let (output, input) = if port_direction == "in" {
+            (target, &own_port)
+        } else {
+            (&own_port, target)
+        };
+        let link = graph
+            .core
+            .create_object::<Link>(
+                "link-factory",
+                &properties! {
+                    "link.output.node" => output.node_id.to_string(),
+                    "link.output.port" => output.id.to_string(),
+                    "link.input.node" => input.node_id.to_string(),
+                    "link.input.port" => input.id.to_string(),
+                    "object.linger" => "false",
+                },
+            )
+            .map_err(|_| LINK_ERROR)?;
+        graph.roundtrip().map_err(|_| LINK_ERROR)?;
+        Ok(link)
+    }
+}
+
+type ReadySender = mpsc::Sender<Result<(), ConnectErrorKind>>;
+
+/// Creates a node on the current thread and runs its main loop until a request
+/// to stop is received. The result of the setup is reported through `ready`.
+#[allow(clippy::too_many_arguments)]
+fn run_node<F>(
+    client_name: &str,
+    port_name: &str,
+    direction: Direction,
+    options: &VirtualPortOptions,
+    target: Option<&PortInfo>,
+    process: F,
+    ready: ReadySender,
+    stop: pw::channel::Receiver<()>,
+) where
+    F: FnMut(&StreamRef) + 'static,
+{
+    let graph = match Graph::new(client_name) {
+        Ok(graph) => graph,
+        Err(_) => {
+            let _ = ready.send(Err(ConnectErrorKind::Other(
+                "could not connect to PipeWire",
+            )));
+            return;
+        }
+    };
+    let node = match Node::new(&graph, port_name, direction, options, target, process) {
+        Ok(node) => node,
+        Err(kind) => {
+            let _ = ready.send(Err(kind));
+            return;
+        }
+    };
+
+    let stopped = Rc::new(Cell::new(false));
+    let _receiver = stop.attach(graph.main_loop.loop_(), {
+        let stopped = stopped.clone();
+        let main_loop = graph.main_loop.clone();
+        move |()| {
+            stopped.set(true);
+            main_loop.quit();
+        }
+    });
+    let _ = ready.send(Ok(()));
+    // The loop might also be quit by the state listener of the node, so check why it stopped
+    while !stopped.get() {
+        graph.main_loop.run();
+    }
+    let _ = node.stream.disconnect();
+}
+
+/// Starts a thread with a node and waits until the node has been set up.
+fn spawn_node<R, S>(
+    thread_name: String,
+    run: S,
+) -> Result<(JoinHandle<R>, pw::channel::Sender<()>), ConnectErrorKind>
+where
+    S: FnOnce(ReadySender, pw::channel::Receiver<()>) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (ready_send, ready_rcv) = mpsc::channel();
+    let (stop_send, stop_rcv) = pw::channel::channel();
+    let thread = Builder::new()
+        .name(thread_name)
+        .spawn(move || run(ready_send, stop_rcv))
+        .map_err(|_| ConnectErrorKind::Other("could not start PipeWire handler thread"))?;
+    match ready_rcv.recv() {
+        Ok(Ok(())) => Ok((thread, stop_send)),
+        Ok(Err(kind)) => {
+            let _ = thread.join();
+            Err(kind)
+        }
+        Err(_) => {
+            let _ = thread.join();
+            Err(ConnectErrorKind::Other(
+                "PipeWire handler thread has stopped",
+            ))
+        }
+    }
+}
+
+/// Tells the thread of a node to stop and waits for its result.
+fn stop_node<R>(thread: JoinHandle<R>, stop: &pw::channel::Sender<()>) -> R {
+    let _ = stop.send(());
+    match thread.join() {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(e) = e.downcast_ref::<&'static str>() {
+                panic!("Error when joining PipeWire thread: {}", e);
+            } else {
+                panic!("Unknown error when joining PipeWire thread: {:?}", e);
+            }
+        }
+    }
+}
+
+pub struct MidiInput {
+    client_name: String,
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    info: PortInfo,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.info.path.clone()
+    }
+}
+
+pub struct MidiInputConnection<T: 'static> {
+    client_name: String,
+    ignore_flags: Ignore,
+    thread: Option<JoinHandle<T>>,
+    stop: pw::channel::Sender<()>,
+}
+
+impl MidiInput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        // Make sure that the daemon is running
+        Graph::new(client_name).map_err(|_| InitError)?;
+        Ok(MidiInput {
+            client_name: client_name.to_string(),
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        Graph::new(&self.client_name)
+            .and_then(|graph| graph.midi_ports("out"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| crate::common::MidiInputPort {
+                imp: MidiInputPort { info }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.ports_internal().len()
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "out")?.name)
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "out")?.node_name)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "out")?.node_description)
+    }
+
+    pub fn connect<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let options = VirtualPortOptions::new();
+        self.start(port_name, &options, Some(port.info.clone()), callback, data)
+    }
+
+    pub fn create_virtual<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.start(port_name, options, None, callback, data)
+    }
+
+    pub fn create_virtual_duplex<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        // Stream nodes have ports in only one direction, so a pair of nodes is created
+        let midi_out = MidiOutput {
+            client_name: self.client_name.clone(),
+        };
+        let conn_in = self.create_virtual(port_name, options, callback, data)?;
+        match midi_out.create_virtual(port_name, options) {
+            Ok(conn_out) => Ok((conn_in, conn_out)),
+            Err(err) => {
+                let kind = err.kind();
+                Err(ConnectError::new(kind, conn_in.close().0))
+            }
+        }
+    }
+
+    fn start<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        target: Option<PortInfo>,
+        mut callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let client_name = self.client_name.clone();
+        let node_name = port_name.to_string();
+        let options = options.clone();
+        let ignore_flags = self.ignore_flags;
+
+        let result = spawn_node(
+            format!("midir PipeWire input handler (port '{}')", port_name),
+            move |ready, stop| {
+                let user_data = Rc::new(RefCell::new(data));
+                let mut parser = StreamParser::new();
+                let process = {
+                    let user_data = user_data.clone();
+                    move |stream: &StreamRef| {
+                        let mut buffer = match stream.dequeue_buffer() {
+                            Some(buffer) => buffer,
+                            None => return,
+                        };
+                        let cycle_time = if cfg!(feature = "avoid_timestamping") {
+                            None
+                        } else {
+                            CycleTime::new(stream)
+                        };
+                        let data = match buffer.datas_mut().first_mut() {
+                            Some(data) => data,
+                            None => return,
+                        };
+                        let start = data.chunk().offset() as usize;
+                        let end = start + data.chunk().size() as usize;
+                        let bytes = match data.data() {
+                            Some(bytes) => bytes,
+                            None => return,
+                        };
+                        let bytes = match bytes.get(start..end) {
+                            Some(bytes) => bytes,
+                            None => return,
+                        };
+                        let user_data = &mut *user_data.borrow_mut();
+                        sequence::read_midi(bytes, |offset, event| {
+                            let timestamp =
+                                cycle_time.as_ref().map_or(0, |time| time.timestamp(offset));
+                            parser.feed(event, |message| {
+                                if !is_ignored(message, ignore_flags) {
+                                    callback(timestamp, message, user_data);
+                                }
+                            });
+                        });
+                    }
+                };
+                run_node(
+                    &client_name,
+                    &node_name,
+                    Direction::Input,
+                    &options,
+                    target.as_ref(),
+                    process,
+                    ready,
+                    stop,
+                );
+                match Rc::try_unwrap(user_data) {
+                    Ok(user_data) => user_data.into_inner(),
+                    Err(_) => unreachable!("the node has been dropped"),
+                }
+            },
+        );
+
+        match result {
+            Ok((thread, stop)) => Ok(MidiInputConnection {
+                client_name: self.client_name,
+                ignore_flags: self.ignore_flags,
+                thread: Some(thread),
+                stop,
+            }),
+            Err(kind) => Err(ConnectError::new(kind, self)),
+        }
+    }
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        let user_data = stop_node(self.thread.take().unwrap(), &self.stop);
+        (
+            MidiInput {
+                client_name: mem::take(&mut self.client_name),
+                ignore_flags: self.ignore_flags,
+            },
+            user_data,
+        )
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been closed
+        if let Some(thread) = self.thread.take() {
+            stop_node(thread, &self.stop);
+        }
+    }
+}
+
+pub struct MidiOutput {
+    client_name: String,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    info: PortInfo,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.info.path.clone()
+    }
+}
+
+type MessageQueue = Arc<Mutex<VecDeque<Vec<u8>>>>;
+
+pub struct MidiOutputConnection {
+    client_name: String,
+    queue: MessageQueue,
+    thread: Option<JoinHandle<()>>,
+    stop: pw::channel::Sender<()>,
+}
+
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
+impl MidiOutput {
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        // Make sure that the daemon is running
+        Graph::new(client_name).map_err(|_| InitError)?;
+        Ok(MidiOutput {
+            client_name: client_name.to_string(),
+        })
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        Graph::new(&self.client_name)
+            .and_then(|graph| graph.midi_ports("in"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| crate::common::MidiOutputPort {
+                imp: MidiOutputPort { info }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.ports_internal().len()
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "in")?.name)
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "in")?.node_name)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.info.lookup(&self.client_name, "in")?.node_description)
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let options = VirtualPortOptions::new();
+        self.start(port_name, &options, Some(port.info.clone()))
+    }
+
+    pub fn create_virtual(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        self.start(port_name, options, None)
+    }
+
+    fn start(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        target: Option<PortInfo>,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        let client_name = self.client_name.clone();
+        let node_name = port_name.to_string();
+        let options = options.clone();
+        let queue = MessageQueue::default();
+
+        let result = spawn_node(
+            format!("midir PipeWire output handler (port '{}')", port_name),
+            {
+                let queue = queue.clone();
+                move |ready, stop| {
+                    let process = move |stream: &StreamRef| {
+                        let mut buffer = match stream.dequeue_buffer() {
+                            Some(buffer) => buffer,
+                            None => return,
+                        };
+                        let data = match buffer.datas_mut().first_mut() {
+                            Some(data) => data,
+                            None => return,
+                        };
+                        // All messages are sent at the start of the cycle
+                        let size = match data.data() {
+                            Some(bytes) => sequence::write_midi(bytes, &mut queue.lock().unwrap()),
+                            None => 0,
+                        };
+                        let chunk = data.chunk_mut();
+                        *chunk.offset_mut() = 0;
+                        *chunk.stride_mut() = 1;
+                        *chunk.size_mut() = size as u32;
+                    };
+                    run_node(
+                        &client_name,
+                        &node_name,
+                        Direction::Output,
+                        &options,
+                        target.as_ref(),
+                        process,
+                        ready,
+                        stop,
+                    );
+                }
+            },
+        );
+
+        match result {
+            Ok((thread, stop)) => Ok(MidiOutputConnection {
+                client_name: self.client_name,
+                queue,
+                thread: Some(thread),
+                stop,
+            }),
+            Err(kind) => Err(ConnectError::new(kind, self)),
+        }
+    }
+}
+
+impl MidiOutputConnection {
+    pub fn close(mut self) -> MidiOutput {
+        stop_node(self.thread.take().unwrap(), &self.stop);
+        MidiOutput {
+            client_name: mem::take(&mut self.client_name),
+        }
+    }
+
+    /// Messages are queued and sent with the next cycle of the PipeWire graph.
+    /// A message that does not fit into a buffer of the graph is dropped.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if message.is_empty() {
+            return Err(SendError::InvalidData(
+                "message to be sent must not be empty",
+            ));
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= MAX_QUEUED_MESSAGES {
+            return Err(SendError::Other("too many messages are waiting to be sent"));
+        }
+        queue.push_back(message.to_vec());
+        Ok(())
+    }
+}
+
+impl Drop for MidiOutputConnection {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been closed
+        if let Some(thread) = self.thread.take() {
+            stop_node(thread, &self.stop);
+        }
+    }
+}
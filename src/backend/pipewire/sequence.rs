@@ -0,0 +1,149 @@
+//! Reading and writing of the SPA control sequences that PipeWire uses to carry MIDI
+//! in the buffers of control ports. A sequence is a POD with an 8 byte header and an
+//! 8 byte body header, followed by the controls, each of which consists of the sample
+//! offset within the cycle, the control type and a POD with the value, padded to 8 bytes.
+
+use std::collections::VecDeque;
+
+use pipewire::spa::sys::{SPA_CONTROL_Midi, SPA_TYPE_Bytes, SPA_TYPE_Sequence};
+
+const HEADER_SIZE: usize = 16;
+const CONTROL_HEADER_SIZE: usize = 16;
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    let bytes = bytes.get(pos..pos + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn write_u32(bytes: &mut [u8], pos: usize, value: u32) {
+    bytes[pos..pos + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn padded(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+/// Calls `callback` with the sample offset and the bytes of every MIDI control in the sequence.
+/// Controls of other types are skipped, and parsing stops at the first malformed control.
+pub fn read_midi<F>(bytes: &[u8], mut callback: F)
+where
+    F: FnMut(u32, &[u8]),
+{
+    let (size, type_) = match (read_u32(bytes, 0), read_u32(bytes, 4)) {
+        (Some(size), Some(type_)) => (size as usize, type_),
+        _ => return,
+    };
+    if type_ != SPA_TYPE_Sequence {
+        return;
+    }
+    let end = (8 + size).min(bytes.len());
+    let mut pos = HEADER_SIZE;
+    while pos + CONTROL_HEADER_SIZE <= end {
+        let offset = read_u32(bytes, pos).unwrap();
+        let control_type = read_u32(bytes, pos + 4).unwrap();
+        let value_size = read_u32(bytes, pos + 8).unwrap() as usize;
+        let value_type = read_u32(bytes, pos + 12).unwrap();
+        let value_start = pos + CONTROL_HEADER_SIZE;
+        if value_start + value_size > end {
+            return;
+        }
+        if control_type == SPA_CONTROL_Midi && value_type == SPA_TYPE_Bytes {
+            callback(offset, &bytes[value_start..value_start + value_size]);
+        }
+        pos = value_start + padded(value_size);
+    }
+}
+
+/// Writes a sequence with the queued messages into `bytes`, all at offset 0, and returns
+/// the number of bytes written. Messages that do not fit stay in the queue for the next cycle,
+/// except for a message that would not even fit into an empty buffer, which is dropped so
+/// that it does not block the queue.
+pub fn write_midi(bytes: &mut [u8], queue: &mut VecDeque<Vec<u8>>) -> usize {
+    if bytes.len() < HEADER_SIZE {
+        return 0;
+    }
+    let mut pos = HEADER_SIZE;
+    while let Some(message) = queue.front() {
+        let control_size = CONTROL_HEADER_SIZE + padded(message.len());
+        if HEADER_SIZE + control_size > bytes.len() {
+            queue.pop_front();
+            continue;
+        }
+        if pos + control_size > bytes.len() {
+            break;
+        }
+        write_u32(bytes, pos, 0);
+        write_u32(bytes, pos + 4, SPA_CONTROL_Midi);
+        write_u32(bytes, pos + 8, message.len() as u32);
+        write_u32(bytes, pos + 12, SPA_TYPE_Bytes);
+        let value_start = pos + CONTROL_HEADER_SIZE;
+        bytes[value_start..value_start + message.len()].copy_from_slice(message);
+        for byte in &mut bytes[value_start + message.len()..pos + control_size] {
+            *byte = 0;
+        }
+        pos += control_size;
+        queue.pop_front();
+    }
+    write_u32(bytes, 0, (pos - 8) as u32);
+    write_u32(bytes, 4, SPA_TYPE_Sequence);
+    write_u32(bytes, 8, 0); // unit
+    write_u32(bytes, 12, 0); // padding
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut queue: VecDeque<Vec<u8>> = vec![
+            vec![0x90, 60, 100],
+            vec![0xF0, 1, 2, 3, 4, 5, 6, 7, 0xF7],
+            vec![0xC0, 5],
+        ]
+        .into();
+        let mut buffer = [0xAA; 128];
+        // Only the first message fits, the second one would need another 32 bytes
+        assert_eq!(write_midi(&mut buffer[..60], &mut queue), 16 + 24);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(write_midi(&mut buffer, &mut queue), 16 + 32 + 24);
+        assert!(queue.is_empty());
+
+        let mut messages = Vec::new();
+        read_midi(&buffer, |offset, message| {
+            messages.push((offset, message.to_vec()))
+        });
+        assert_eq!(
+            messages,
+            vec![
+                (0, vec![0xF0, 1, 2, 3, 4, 5, 6, 7, 0xF7]),
+                (0, vec![0xC0, 5])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oversized() {
+        let mut queue: VecDeque<Vec<u8>> = vec![vec![0xF0; 64], vec![0xC0, 5]].into();
+        let mut buffer = [0; 64];
+        // The SysEx message can never fit, so it is dropped instead of blocking the queue
+        assert_eq!(write_midi(&mut buffer, &mut queue), 16 + 24);
+        assert!(queue.is_empty());
+        let mut messages = Vec::new();
+        read_midi(&buffer, |_, message| messages.push(message.to_vec()));
+        assert_eq!(messages, vec![vec![0xC0, 5]]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut queue: VecDeque<Vec<u8>> = vec![vec![0x90, 60, 100]].into();
+        let mut buffer = [0; 40];
+        assert_eq!(write_midi(&mut buffer, &mut queue), 40);
+        let mut count = 0;
+        read_midi(&buffer[..34], |_, _| count += 1);
+        assert_eq!(count, 0);
+        read_midi(&buffer[..8], |_, _| count += 1);
+        assert_eq!(count, 0);
+    }
+}
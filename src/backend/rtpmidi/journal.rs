@@ -0,0 +1,486 @@
+//! The recovery journal of RFC 6295, which lets a receiver repair the state of notes,
+//! controllers and programs after packets have been lost.
+//!
+//! Only the channel journal chapters for program changes (P), control changes (C) and
+//! notes (N) are written. When reading a journal, all other chapters and the system
+//! journal are skipped using their length fields.
+
+use std::collections::BTreeMap;
+
+const CHAPTER_P: u8 = 0x80;
+const CHAPTER_C: u8 = 0x40;
+const CHAPTER_M: u8 = 0x20;
+const CHAPTER_W: u8 = 0x10;
+const CHAPTER_N: u8 = 0x08;
+
+/// Returns whether sequence number `a` comes before or is equal to `b`, taking wrap-around into account.
+pub fn seq_le(a: u16, b: u16) -> bool {
+    b.wrapping_sub(a) < 0x8000
+}
+
+/// The messages of one channel that have been sent since the checkpoint,
+/// each with the sequence number of the packet that contained it.
+#[derive(Default, Clone)]
+struct ChannelHistory {
+    program: Option<(u16, u8, Option<u8>, Option<u8>)>,
+    controllers: BTreeMap<u8, (u16, u8)>,
+    /// The velocity of a note that is on, or `None` if it has been turned off.
+    notes: BTreeMap<u8, (u16, Option<u8>)>,
+    bank_msb: Option<u8>,
+    bank_lsb: Option<u8>,
+}
+
+impl ChannelHistory {
+    fn is_empty(&self) -> bool {
+        self.program.is_none() && self.controllers.is_empty() && self.notes.is_empty()
+    }
+
+    fn trim(&mut self, checkpoint: u16) {
+        if matches!(self.program, Some((seq, ..)) if seq_le(seq, checkpoint)) {
+            self.program = None;
+        }
+        self.controllers
+            .retain(|_, &mut (seq, _)| !seq_le(seq, checkpoint));
+        self.notes
+            .retain(|_, &mut (seq, _)| !seq_le(seq, checkpoint));
+    }
+
+    fn encode(&self, channel: u8, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        bytes.extend_from_slice(&[0, 0, 0]);
+        let mut toc = 0;
+
+        if let Some((_, program, msb, lsb)) = self.program {
+            toc |= CHAPTER_P;
+            let bank_flag = if msb.is_some() || lsb.is_some() {
+                0x80
+            } else {
+                0
+            };
+            bytes.push(program & 0x7F);
+            bytes.push(bank_flag | msb.unwrap_or(0) & 0x7F);
+            bytes.push(lsb.unwrap_or(0) & 0x7F);
+        }
+
+        if !self.controllers.is_empty() {
+            toc |= CHAPTER_C;
+            bytes.push((self.controllers.len() - 1) as u8);
+            for (&number, &(_, value)) in &self.controllers {
+                bytes.push(number & 0x7F);
+                bytes.push(value & 0x7F);
+            }
+        }
+
+        if !self.notes.is_empty() {
+            toc |= CHAPTER_N;
+            let on: Vec<(u8, u8)> = self
+                .notes
+                .iter()
+                .filter_map(|(&note, &(_, velocity))| velocity.map(|v| (note, v)))
+                .collect();
+            let off: Vec<u8> = self
+                .notes
+                .iter()
+                .filter(|(_, &(_, velocity))| velocity.is_none())
+                .map(|(&note, _)| note)
+                .collect();
+            // LEN is 7 bits: a length of 127 together with the empty range of off bits
+            // (15, 0) stands for 128 logs, so 127 logs need a non-empty range
+            let (len, low, high) = match (off.first(), off.last()) {
+                (Some(&first), Some(&last)) => (on.len() as u8, first / 8, last / 8),
+                _ if on.len() == 127 => (127, 0, 0),
+                // All notes are on, which is the only way to have 128 logs
+                _ if on.len() == 128 => (127, 15, 0),
+                _ => (on.len() as u8, 15, 0),
+            };
+            bytes.push(len);
+            bytes.push(low << 4 | high);
+            for &(note, velocity) in &on {
+                // The Y flag recommends playing the note
+                bytes.extend_from_slice(&[note & 0x7F, 0x80 | velocity & 0x7F]);
+            }
+            if low <= high {
+                let mut offbits = vec![0u8; (high - low + 1) as usize];
+                for note in off {
+                    offbits[(note / 8 - low) as usize] |= 0x80 >> (note % 8);
+                }
+                bytes.extend_from_slice(&offbits);
+            }
+        }
+
+        let length = (bytes.len() - start) as u16;
+        let header = (channel as u16) << 11 | length & 0x03FF;
+        bytes[start..start + 2].copy_from_slice(&header.to_be_bytes());
+        bytes[start + 2] = toc;
+    }
+}
+
+/// Keeps track of the sent messages that must be included in the journal
+/// of the next packet, until all receivers have acknowledged them.
+pub struct SenderJournal {
+    channels: [ChannelHistory; 16],
+    checkpoint: u16,
+}
+
+impl SenderJournal {
+    pub fn new(checkpoint: u16) -> SenderJournal {
+        SenderJournal {
+            channels: Default::default(),
+            checkpoint,
+        }
+    }
+
+    /// Records a message that has been sent in the packet with the given sequence number.
+    pub fn record(&mut self, seq: u16, message: &[u8]) {
+        let status = match message.first() {
+            Some(&status @ 0x80..=0xEF) => status,
+            _ => return,
+        };
+        let channel = &mut self.channels[(status & 0x0F) as usize];
+        match (status & 0xF0, message.get(1), message.get(2)) {
+            (0x80, Some(&note), _) | (0x90, Some(&note), Some(0)) => {
+                channel.notes.insert(note, (seq, None));
+            }
+            (0x90, Some(&note), Some(&velocity)) => {
+                channel.notes.insert(note, (seq, Some(velocity)));
+            }
+            (0xB0, Some(&number), Some(&value)) => {
+                match number {
+                    0 => channel.bank_msb = Some(value),
+                    32 => channel.bank_lsb = Some(value),
+                    _ => {}
+                }
+                channel.controllers.insert(number, (seq, value));
+            }
+            (0xC0, Some(&program), _) => {
+                channel.program = Some((seq, program, channel.bank_msb, channel.bank_lsb));
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets everything that has been sent up to and including the packet with the
+    /// given sequence number, after every receiver has acknowledged that packet.
+    pub fn acknowledge(&mut self, seq: u16) {
+        if !seq_le(seq, self.checkpoint) {
+            self.checkpoint = seq;
+        }
+        for channel in &mut self.channels {
+            channel.trim(seq);
+        }
+    }
+
+    /// Creates the journal that is appended to the next packet,
+    /// or `None` if there is nothing to recover.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let channels: Vec<(usize, &ChannelHistory)> = self
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_empty())
+            .collect();
+        if channels.is_empty() {
+            return None;
+        }
+        // The A flag announces the channel journals
+        let mut bytes = vec![0x20 | (channels.len() - 1) as u8];
+        bytes.extend_from_slice(&self.checkpoint.to_be_bytes());
+        for (channel, history) in channels {
+            history.encode(channel as u8, &mut bytes);
+        }
+        Some(bytes)
+    }
+}
+
+#[derive(Clone)]
+struct ChannelState {
+    notes: [bool; 128],
+    controllers: [Option<u8>; 128],
+    program: Option<(u8, Option<u8>, Option<u8>)>,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        ChannelState {
+            notes: [false; 128],
+            controllers: [None; 128],
+            program: None,
+        }
+    }
+}
+
+/// The state of the MIDI stream that has been received from one sender,
+/// which is compared to the journal when packets have been lost.
+#[derive(Default)]
+pub struct ReceiverState {
+    channels: [ChannelState; 16],
+}
+
+impl ReceiverState {
+    /// Updates the state with a received message.
+    pub fn track(&mut self, message: &[u8]) {
+        let status = match message.first() {
+            Some(&status @ 0x80..=0xEF) => status,
+            _ => return,
+        };
+        let channel = &mut self.channels[(status & 0x0F) as usize];
+        match (status & 0xF0, message.get(1), message.get(2)) {
+            (0x80, Some(&note), _) => channel.notes[(note & 0x7F) as usize] = false,
+            (0x90, Some(&note), Some(&velocity)) => {
+                channel.notes[(note & 0x7F) as usize] = velocity != 0
+            }
+            (0xB0, Some(&number), Some(&value)) => {
+                channel.controllers[(number & 0x7F) as usize] = Some(value)
+            }
+            (0xC0, Some(&program), _) => {
+                channel.program = Some((program, channel.controllers[0], channel.controllers[32]))
+            }
+            _ => {}
+        }
+    }
+
+    /// Calls `callback` with the messages that bring the state in line with the journal
+    /// of a packet that has been received after one or more packets were lost.
+    /// Parsing stops at the first malformed part of the journal.
+    pub fn recover<F>(&mut self, journal: &[u8], mut callback: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut emit = |state: &mut ReceiverState, message: &[u8]| {
+            state.track(message);
+            callback(message);
+        };
+
+        let flags = match journal.first() {
+            Some(&flags) if journal.len() >= 3 => flags,
+            _ => return,
+        };
+        let mut pos = 3;
+        if flags & 0x40 != 0 {
+            // Skip the system journal
+            let header = match journal.get(pos..pos + 2) {
+                Some(header) => u16::from_be_bytes([header[0], header[1]]),
+                None => return,
+            };
+            pos += (header & 0x03FF) as usize;
+        }
+        if flags & 0x20 == 0 {
+            return;
+        }
+
+        for _ in 0..=(flags & 0x0F) {
+            let (header, toc) = match journal.get(pos..pos + 3) {
+                Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]), bytes[2]),
+                None => return,
+            };
+            let channel = (header >> 11 & 0x0F) as u8;
+            let end = pos + (header & 0x03FF) as usize;
+            let chapters = match journal.get(pos + 3..end) {
+                Some(chapters) => chapters,
+                None => return,
+            };
+            if self
+                .recover_channel(channel, toc, chapters, &mut emit)
+                .is_none()
+            {
+                return;
+            }
+            pos = end;
+        }
+    }
+
+    fn recover_channel<F>(
+        &mut self,
+        channel: u8,
+        toc: u8,
+        chapters: &[u8],
+        emit: &mut F,
+    ) -> Option<()>
+    where
+        F: FnMut(&mut ReceiverState, &[u8]),
+    {
+        let mut pos = 0;
+        let index = channel as usize;
+
+        if toc & CHAPTER_P != 0 {
+            let bytes = chapters.get(pos..pos + 3)?;
+            pos += 3;
+            let program = bytes[0] & 0x7F;
+            let (msb, lsb) = if bytes[1] & 0x80 != 0 {
+                (Some(bytes[1] & 0x7F), Some(bytes[2] & 0x7F))
+            } else {
+                (None, None)
+            };
+            let is_current = match self.channels[index].program {
+                Some(current) => {
+                    current.0 == program && (msb.is_none() || current == (program, msb, lsb))
+                }
+                None => false,
+            };
+            if !is_current {
+                if let (Some(msb), Some(lsb)) = (msb, lsb) {
+                    emit(self, &[0xB0 | channel, 0, msb]);
+                    emit(self, &[0xB0 | channel, 32, lsb]);
+                }
+                emit(self, &[0xC0 | channel, program]);
+            }
+        }
+
+        if toc & CHAPTER_C != 0 {
+            let count = (chapters.get(pos)? & 0x7F) as usize + 1;
+            let logs = chapters.get(pos + 1..pos + 1 + 2 * count)?;
+            pos += 1 + 2 * count;
+            for log in logs.chunks(2) {
+                // Logs with the A flag use the alternative tool formats, which are not supported
+                if log[1] & 0x80 != 0 {
+                    continue;
+                }
+                let (number, value) = (log[0] & 0x7F, log[1]);
+                if self.channels[index].controllers[number as usize] != Some(value) {
+                    emit(self, &[0xB0 | channel, number, value]);
+                }
+            }
+        }
+
+        if toc & CHAPTER_M != 0 {
+            let header = chapters.get(pos..pos + 2)?;
+            pos += (u16::from_be_bytes([header[0], header[1]]) & 0x03FF) as usize;
+        }
+
+        if toc & CHAPTER_W != 0 {
+            pos += 2;
+        }
+
+        if toc & CHAPTER_N != 0 {
+            let header = chapters.get(pos..pos + 2)?;
+            pos += 2;
+            let (low, high) = (header[1] >> 4, header[1] & 0x0F);
+            let mut count = (header[0] & 0x7F) as usize;
+            if count == 127 && low == 15 && high == 0 {
+                count = 128;
+            }
+            let logs = chapters.get(pos..pos + 2 * count)?;
+            pos += 2 * count;
+            for log in logs.chunks(2) {
+                let (note, velocity) = (log[0] & 0x7F, log[1] & 0x7F);
+                // Notes without the Y flag are too old to be played
+                if log[1] & 0x80 != 0 && !self.channels[index].notes[note as usize] {
+                    emit(self, &[0x90 | channel, note, velocity]);
+                }
+            }
+            if low <= high {
+                let offbits = chapters.get(pos..pos + (high - low + 1) as usize)?;
+                for (i, &bits) in offbits.iter().enumerate() {
+                    for bit in 0..8 {
+                        let note = (low as usize + i) * 8 + bit;
+                        if bits & (0x80 >> bit) != 0
+                            && note < 128
+                            && self.channels[index].notes[note]
+                        {
+                            emit(self, &[0x80 | channel, note as u8, 0]);
+                        }
+                    }
+                }
+            }
+        }
+
+        // The remaining chapters (E, T and A) only concern note-off velocities and aftertouch
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recover(state: &mut ReceiverState, journal: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        state.recover(journal, |message| messages.push(message.to_vec()));
+        messages
+    }
+
+    #[test]
+    fn test_seq_le() {
+        assert!(seq_le(1, 1));
+        assert!(seq_le(1, 2));
+        assert!(!seq_le(2, 1));
+        assert!(seq_le(0xFFFF, 0));
+        assert!(!seq_le(0, 0xFFFF));
+    }
+
+    #[test]
+    fn test_recovery() {
+        let mut journal = SenderJournal::new(0);
+        assert_eq!(journal.encode(), None);
+
+        let sent: [&[u8]; 7] = [
+            &[0x90, 60, 100],
+            &[0x90, 62, 90],
+            &[0xB0, 0, 1],
+            &[0xB0, 7, 80],
+            &[0xC0, 5],
+            &[0x80, 62, 0],
+            &[0x91, 64, 70],
+        ];
+        for (i, message) in sent.iter().enumerate() {
+            journal.record(i as u16 + 1, message);
+        }
+
+        // The receiver has only seen the first two notes
+        let mut state = ReceiverState::default();
+        state.track(sent[0]);
+        state.track(sent[1]);
+        let bytes = journal.encode().unwrap();
+        assert_eq!(bytes[0], 0x21);
+        assert_eq!(
+            recover(&mut state, &bytes),
+            vec![
+                vec![0xB0, 0, 1],
+                vec![0xB0, 32, 0],
+                vec![0xC0, 5],
+                vec![0xB0, 7, 80],
+                vec![0x80, 62, 0],
+                vec![0x91, 64, 70],
+            ]
+        );
+        // Recovering a second time does not change anything
+        assert!(recover(&mut state, &bytes).is_empty());
+
+        // Acknowledged messages are removed from the journal
+        journal.acknowledge(5);
+        let bytes = journal.encode().unwrap();
+        assert_eq!(&bytes[..3], &[0x21, 0, 5]);
+        journal.acknowledge(7);
+        assert_eq!(journal.encode(), None);
+    }
+
+    #[test]
+    fn test_all_notes_on() {
+        for count in [127, 128] {
+            let mut journal = SenderJournal::new(0);
+            for note in 0..count {
+                journal.record(1, &[0x90, note as u8, 100]);
+            }
+            let mut state = ReceiverState::default();
+            let messages = recover(&mut state, &journal.encode().unwrap());
+            assert_eq!(messages.len(), count);
+            assert_eq!(messages.last().unwrap(), &vec![0x90, count as u8 - 1, 100]);
+        }
+    }
+
+    #[test]
+    fn test_skip_unknown_chapters() {
+        // A system journal of 3 bytes, followed by a journal for channel 2 that
+        // contains chapter W (2 bytes) and chapter N with one note log
+        let journal = [
+            0x60, 0x00, 0x01, 0x00, 0x03, 0x00, 0x10, 0x09, 0x18, 0x40, 0x00, 0x01, 0xF0, 0x3C,
+            0xE4,
+        ];
+        let mut state = ReceiverState::default();
+        assert_eq!(recover(&mut state, &journal), vec![vec![0x92, 0x3C, 0x64]]);
+
+        // Truncated journals are ignored
+        let mut state = ReceiverState::default();
+        assert!(recover(&mut state, &journal[..14]).is_empty());
+    }
+}
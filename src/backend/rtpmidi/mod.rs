@@ -0,0 +1,424 @@
+//! A network backend that implements RTP-MIDI (RFC 6295) with the AppleMIDI session
+//! protocol, which is used by macOS and iOS ("Network MIDI") and by network MIDI interfaces.
+//!
+//! Ports are sessions: those that have been published by this process and those that have
+//! been registered with `os::rtpmidi::add_remote_session`. Connecting to a port creates a
+//! new local session that invites the remote one, while creating a virtual port publishes a
+//! session that others can join. Every session is bidirectional, and all of its
+//! participants receive what is sent through it.
+
+mod journal;
+mod protocol;
+mod session;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use self::session::{Receiver, Session, SessionHandle};
+use crate::errors::*;
+use crate::parser::is_ignored;
+use crate::r#virtual::VirtualPortOptions;
+use crate::Ignore;
+
+const BIND_ERROR_MSG: &str = "could not bind the UDP ports of the session";
+const START_ERROR_MSG: &str = "could not start the threads of the session";
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<T> {
+    ignore_flags: Ignore,
+    callback: Callback<T>,
+    user_data: Option<T>,
+}
+
+type Handler<T> = Arc<Mutex<HandlerData<T>>>;
+
+fn receiver<T: Send + 'static>(handler: &Handler<T>) -> Receiver {
+    let handler = handler.clone();
+    Box::new(move |timestamp, message| {
+        let mut data = handler.lock().unwrap();
+        let data = &mut *data;
+        if is_ignored(message, data.ignore_flags) {
+            return;
+        }
+        if let Some(ref mut user_data) = data.user_data {
+            (data.callback)(timestamp, message, user_data);
+        }
+    })
+}
+
+/// A session that can be connected to, either one that has been published
+/// by this process or a remote one that has been registered by the user.
+struct KnownSession {
+    name: String,
+    address: SocketAddr,
+    published: bool,
+}
+
+static SESSIONS: Mutex<Vec<KnownSession>> = Mutex::new(Vec::new());
+
+fn sessions() -> MutexGuard<'static, Vec<KnownSession>> {
+    SESSIONS.lock().unwrap()
+}
+
+pub fn add_remote_session(name: &str, address: SocketAddr) {
+    let mut sessions = sessions();
+    sessions.retain(|s| s.address != address);
+    sessions.push(KnownSession {
+        name: name.to_string(),
+        address,
+        published: false,
+    });
+}
+
+pub fn remove_remote_session(address: SocketAddr) -> bool {
+    let mut sessions = sessions();
+    let count = sessions.len();
+    sessions.retain(|s| s.published || s.address != address);
+    sessions.len() != count
+}
+
+/// A running session of this process, which is listed as a port while it is published.
+struct RunningSession {
+    handle: SessionHandle,
+    address: SocketAddr,
+    published: bool,
+}
+
+impl RunningSession {
+    fn start(session: Session, published: bool) -> Result<Arc<RunningSession>, &'static str> {
+        let name = session.name().to_string();
+        let address = session.address().map_err(|_| BIND_ERROR_MSG)?;
+        let handle = SessionHandle::start(session).map_err(|_| START_ERROR_MSG)?;
+        if published {
+            sessions().push(KnownSession {
+                name,
+                address,
+                published,
+            });
+        }
+        Ok(Arc::new(RunningSession {
+            handle,
+            address,
+            published,
+        }))
+    }
+
+    fn session(&self) -> &Session {
+        self.handle.session()
+    }
+}
+
+impl Drop for RunningSession {
+    fn drop(&mut self) {
+        if self.published {
+            sessions().retain(|s| !s.published || s.address != self.address);
+        }
+    }
+}
+
+/// Creates a session that is not published and invites the session at `address`.
+fn invite(port_name: &str, address: SocketAddr) -> Result<Session, &'static str> {
+    let session = Session::bind(port_name, 0, false).map_err(|_| BIND_ERROR_MSG)?;
+    session.invite(address)?;
+    Ok(session)
+}
+
+fn publish(port_name: &str, options: &VirtualPortOptions) -> Result<Session, &'static str> {
+    Session::bind(port_name, options.udp_port.unwrap_or(0), true).map_err(|_| BIND_ERROR_MSG)
+}
+
+pub struct MidiInput {
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    name: String,
+    address: SocketAddr,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.address.to_string()
+    }
+
+    pub fn from_address(name: &str, address: SocketAddr) -> MidiInputPort {
+        MidiInputPort {
+            name: name.to_string(),
+            address,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl MidiInput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiInput {
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        sessions()
+            .iter()
+            .map(|s| crate::common::MidiInputPort {
+                imp: MidiInputPort::from_address(&s.name, s.address).into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        sessions().len()
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.id())
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.name.clone())
+    }
+
+    fn handler<F, T: Send + 'static>(&self, callback: F, data: T) -> Handler<T>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        Arc::new(Mutex::new(HandlerData {
+            ignore_flags: self.ignore_flags,
+            callback: Box::new(callback),
+            user_data: Some(data),
+        }))
+    }
+
+    fn start<F, T: Send + 'static>(
+        self,
+        session: Result<Session, &'static str>,
+        published: bool,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let handler = self.handler(callback, data);
+        let session = session.and_then(|session| {
+            session.set_receiver(Some(receiver(&handler)));
+            RunningSession::start(session, published)
+        });
+        match session {
+            Ok(session) => Ok(MidiInputConnection {
+                input: Some(self),
+                handler,
+                session,
+            }),
+            Err(msg) => Err(ConnectError::other(msg, self)),
+        }
+    }
+
+    pub fn connect<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.start(invite(port_name, port.address), false, callback, data)
+    }
+
+    pub fn create_virtual<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.start(publish(port_name, options), true, callback, data)
+    }
+
+    pub fn create_virtual_duplex<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<DuplexConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        // Both connections share one published session, which ends once both have been closed
+        let conn_in = self.start(publish(port_name, options), true, callback, data)?;
+        let conn_out = MidiOutputConnection {
+            output: Some(MidiOutput),
+            session: conn_in.session.clone(),
+        };
+        Ok((conn_in, conn_out))
+    }
+}
+
+/// The receiving and the sending half of a duplex port.
+pub type DuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
+pub struct MidiInputConnection<T> {
+    input: Option<MidiInput>, // `None` once the connection has been closed
+    handler: Handler<T>,
+    session: Arc<RunningSession>,
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        // The session keeps running if it is shared with an output connection
+        self.session.session().set_receiver(None);
+        let user_data = self.handler.lock().unwrap().user_data.take().unwrap();
+        (self.input.take().unwrap(), user_data)
+    }
+
+    pub fn session_address(&self) -> SocketAddr {
+        self.session.address
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        if self.input.is_some() {
+            self.session.session().set_receiver(None);
+        }
+    }
+}
+
+pub struct MidiOutput;
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    name: String,
+    address: SocketAddr,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.address.to_string()
+    }
+
+    pub fn from_address(name: &str, address: SocketAddr) -> MidiOutputPort {
+        MidiOutputPort {
+            name: name.to_string(),
+            address,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl MidiOutput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiOutput)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        sessions()
+            .iter()
+            .map(|s| crate::common::MidiOutputPort {
+                imp: MidiOutputPort::from_address(&s.name, s.address).into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        sessions().len()
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.id())
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.name.clone())
+    }
+
+    fn start(
+        self,
+        session: Result<Session, &'static str>,
+        published: bool,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        match session.and_then(|session| RunningSession::start(session, published)) {
+            Ok(session) => Ok(MidiOutputConnection {
+                output: Some(self),
+                session,
+            }),
+            Err(msg) => Err(ConnectError::other(msg, self)),
+        }
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        self.start(invite(port_name, port.address), false)
+    }
+
+    pub fn create_virtual(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        self.start(publish(port_name, options), true)
+    }
+}
+
+pub struct MidiOutputConnection {
+    output: Option<MidiOutput>,
+    session: Arc<RunningSession>,
+}
+
+impl MidiOutputConnection {
+    pub fn close(mut self) -> MidiOutput {
+        self.output.take().unwrap()
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        match message.first() {
+            Some(&status) if status >= 0x80 => {}
+            _ => {
+                return Err(SendError::InvalidData(
+                    "message must start with a status byte",
+                ))
+            }
+        }
+        if message[0] == 0xF0 && message.last() != Some(&0xF7) {
+            return Err(SendError::InvalidData("SysEx message must end with 0xF7"));
+        }
+
+        self.session
+            .session()
+            .send(message)
+            .map_err(|_| SendError::Other("could not send the message to all participants"))
+    }
+
+    pub fn session_address(&self) -> SocketAddr {
+        self.session.address
+    }
+}
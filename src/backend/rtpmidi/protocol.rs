@@ -0,0 +1,513 @@
+//! Encoding and decoding of the packets that are exchanged in an RTP-MIDI session:
+//! the AppleMIDI session commands (invitation, clock synchronization, receiver feedback
+//! and ending the session) and the RTP packets that carry MIDI commands (RFC 6295).
+
+/// The version of the AppleMIDI session protocol.
+const PROTOCOL_VERSION: u32 = 2;
+
+/// The RTP payload type of RTP-MIDI packets.
+const PAYLOAD_TYPE: u8 = 0x61;
+
+/// The size of an RTP header without any contributing sources.
+const RTP_HEADER_SIZE: usize = 12;
+
+/// The maximum length of a command list, which is limited by the 12 bit length field.
+pub const MAX_COMMAND_LIST_LEN: usize = 0x0FFF;
+
+/// A session command. Every command starts with `0xFFFF` and a two-letter name, which
+/// cannot be confused with an RTP packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `IN`: invites the receiver to a session.
+    Invitation { token: u32, ssrc: u32, name: String },
+    /// `OK`: accepts an invitation.
+    Accept { token: u32, ssrc: u32, name: String },
+    /// `NO`: rejects an invitation.
+    Reject { token: u32, ssrc: u32 },
+    /// `BY`: ends a session.
+    End { token: u32, ssrc: u32 },
+    /// `CK`: one step of the clock synchronization, with the timestamps of all
+    /// previous steps (in units of 100 microseconds).
+    Sync {
+        ssrc: u32,
+        count: u8,
+        timestamps: [u64; 3],
+    },
+    /// `RS`: tells the sender the sequence number of the last received packet,
+    /// so that it can shorten its recovery journal.
+    Feedback { ssrc: u32, seq: u16 },
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    let bytes = bytes.get(pos..pos + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    let bytes = bytes.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> Option<u64> {
+    Some((read_u32(bytes, pos)? as u64) << 32 | read_u32(bytes, pos + 4)? as u64)
+}
+
+/// Returns whether the datagram is a session command rather than an RTP packet.
+pub fn is_command(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xFF, 0xFF])
+}
+
+impl Command {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xFF];
+        match *self {
+            Command::Invitation {
+                token,
+                ssrc,
+                ref name,
+            }
+            | Command::Accept {
+                token,
+                ssrc,
+                ref name,
+            } => {
+                let is_invitation = matches!(*self, Command::Invitation { .. });
+                bytes.extend_from_slice(if is_invitation { b"IN" } else { b"OK" });
+                bytes.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                bytes.extend_from_slice(&token.to_be_bytes());
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+                bytes.extend(name.bytes().filter(|&b| b != 0));
+                bytes.push(0);
+            }
+            Command::Reject { token, ssrc } | Command::End { token, ssrc } => {
+                let is_reject = matches!(*self, Command::Reject { .. });
+                bytes.extend_from_slice(if is_reject { b"NO" } else { b"BY" });
+                bytes.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                bytes.extend_from_slice(&token.to_be_bytes());
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+            }
+            Command::Sync {
+                ssrc,
+                count,
+                timestamps,
+            } => {
+                bytes.extend_from_slice(b"CK");
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+                bytes.extend_from_slice(&[count, 0, 0, 0]);
+                for timestamp in &timestamps {
+                    bytes.extend_from_slice(&timestamp.to_be_bytes());
+                }
+            }
+            Command::Feedback { ssrc, seq } => {
+                bytes.extend_from_slice(b"RS");
+                bytes.extend_from_slice(&ssrc.to_be_bytes());
+                bytes.extend_from_slice(&seq.to_be_bytes());
+                bytes.extend_from_slice(&[0, 0]);
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a session command, returning `None` for unknown or malformed commands.
+    pub fn decode(bytes: &[u8]) -> Option<Command> {
+        if !is_command(bytes) {
+            return None;
+        }
+        let name = bytes.get(2..4)?;
+        match name {
+            b"IN" | b"OK" | b"NO" | b"BY" => {
+                if read_u32(bytes, 4)? != PROTOCOL_VERSION {
+                    return None;
+                }
+                let token = read_u32(bytes, 8)?;
+                let ssrc = read_u32(bytes, 12)?;
+                let name_bytes = &bytes[16..];
+                let name_bytes = match name_bytes.iter().position(|&b| b == 0) {
+                    Some(end) => &name_bytes[..end],
+                    None => name_bytes,
+                };
+                let name = String::from_utf8_lossy(name_bytes).into_owned();
+                Some(match &bytes[2..4] {
+                    b"IN" => Command::Invitation { token, ssrc, name },
+                    b"OK" => Command::Accept { token, ssrc, name },
+                    b"NO" => Command::Reject { token, ssrc },
+                    _ => Command::End { token, ssrc },
+                })
+            }
+            b"CK" => Some(Command::Sync {
+                ssrc: read_u32(bytes, 4)?,
+                count: *bytes.get(8)?,
+                timestamps: [
+                    read_u64(bytes, 12)?,
+                    read_u64(bytes, 20)?,
+                    read_u64(bytes, 28)?,
+                ],
+            }),
+            b"RS" => Some(Command::Feedback {
+                ssrc: read_u32(bytes, 4)?,
+                seq: read_u16(bytes, 8)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of a received RTP-MIDI packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiPacket<'a> {
+    pub seq: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    /// Whether the first command of the list is preceded by a delta time (the Z flag).
+    pub first_has_delta: bool,
+    pub commands: &'a [u8],
+    pub journal: Option<&'a [u8]>,
+}
+
+impl<'a> MidiPacket<'a> {
+    /// Decodes an RTP-MIDI packet, returning `None` if it is not one or is truncated.
+    pub fn decode(bytes: &'a [u8]) -> Option<MidiPacket<'a>> {
+        let first = *bytes.first()?;
+        if first >> 6 != 2 || bytes.get(1)? & 0x7F != PAYLOAD_TYPE {
+            return None;
+        }
+        let csrc_count = (first & 0x0F) as usize;
+        let mut pos = RTP_HEADER_SIZE + 4 * csrc_count;
+        if first & 0x10 != 0 {
+            // Skip the header extension
+            let words = read_u16(bytes, pos + 2)? as usize;
+            pos += 4 + 4 * words;
+        }
+        let flags = *bytes.get(pos)?;
+        let (len, header_len) = if flags & 0x80 != 0 {
+            let len = ((flags & 0x0F) as usize) << 8 | *bytes.get(pos + 1)? as usize;
+            (len, 2)
+        } else {
+            ((flags & 0x0F) as usize, 1)
+        };
+        let commands = bytes.get(pos + header_len..pos + header_len + len)?;
+        let journal = if flags & 0x40 != 0 {
+            Some(&bytes[pos + header_len + len..])
+        } else {
+            None
+        };
+        Some(MidiPacket {
+            seq: read_u16(bytes, 2)?,
+            timestamp: read_u32(bytes, 4)?,
+            ssrc: read_u32(bytes, 8)?,
+            first_has_delta: flags & 0x20 != 0,
+            commands,
+            journal,
+        })
+    }
+
+    /// Encodes an RTP-MIDI packet with a command list that consists of a single
+    /// command (without a delta time) and the given recovery journal, if any.
+    /// The command must not be longer than `MAX_COMMAND_LIST_LEN`.
+    pub fn encode(
+        seq: u16,
+        timestamp: u32,
+        ssrc: u32,
+        command: &[u8],
+        journal: Option<&[u8]>,
+    ) -> Vec<u8> {
+        debug_assert!(command.len() <= MAX_COMMAND_LIST_LEN);
+        let mut bytes = Vec::with_capacity(RTP_HEADER_SIZE + 2 + command.len());
+        bytes.push(0x80);
+        // The marker bit is set for every packet that contains MIDI commands
+        bytes.push(0x80 | PAYLOAD_TYPE);
+        bytes.extend_from_slice(&seq.to_be_bytes());
+        bytes.extend_from_slice(&timestamp.to_be_bytes());
+        bytes.extend_from_slice(&ssrc.to_be_bytes());
+        let journal_flag = if journal.is_some() { 0x40 } else { 0 };
+        if command.len() < 0x10 {
+            bytes.push(journal_flag | command.len() as u8);
+        } else {
+            bytes.push(0x80 | journal_flag | (command.len() >> 8) as u8);
+            bytes.push(command.len() as u8);
+        }
+        bytes.extend_from_slice(command);
+        if let Some(journal) = journal {
+            bytes.extend_from_slice(journal);
+        }
+        bytes
+    }
+}
+
+/// Splits a SysEx message into segments that fit into a command list, as described in
+/// section 3.2 of RFC 6295: the first segment ends with `0xF0` instead of `0xF7`, and
+/// every following segment starts with `0xF7`.
+pub fn sysex_segments(message: &[u8], max_len: usize) -> Vec<Vec<u8>> {
+    if message.len() <= max_len {
+        return vec![message.to_vec()];
+    }
+    let data = &message[1..message.len() - 1];
+    let chunk_len = max_len - 2;
+    let chunks: Vec<&[u8]> = data.chunks(chunk_len).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut segment = Vec::with_capacity(chunk.len() + 2);
+            segment.push(if i == 0 { 0xF0 } else { 0xF7 });
+            segment.extend_from_slice(chunk);
+            segment.push(if i == chunks.len() - 1 { 0xF7 } else { 0xF0 });
+            segment
+        })
+        .collect()
+}
+
+/// The number of data bytes that follow the given status byte.
+fn data_len(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xBF | 0xE0..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+/// Turns the command lists of the packets of one sender back into MIDI messages.
+/// Running status and SysEx messages that are split over several packets are
+/// tracked across packets.
+#[derive(Default)]
+pub struct CommandListParser {
+    running_status: Option<u8>,
+    sysex: Option<Vec<u8>>,
+}
+
+impl CommandListParser {
+    /// Calls `callback` with the accumulated delta time (in RTP timestamp units)
+    /// and the bytes of every complete message in the command list.
+    /// Parsing stops at the first malformed command.
+    pub fn parse<F>(&mut self, list: &[u8], first_has_delta: bool, mut callback: F)
+    where
+        F: FnMut(u32, &[u8]),
+    {
+        let mut pos = 0;
+        let mut time = 0u32;
+        let mut first = true;
+        while pos < list.len() {
+            if !first || first_has_delta {
+                // Delta times are relative to the previous command
+                let mut delta = 0u32;
+                for _ in 0..4 {
+                    let byte = match list.get(pos) {
+                        Some(&byte) => byte,
+                        None => return,
+                    };
+                    pos += 1;
+                    delta = delta << 7 | (byte & 0x7F) as u32;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                time = time.wrapping_add(delta);
+            }
+            first = false;
+            let status = match list.get(pos) {
+                Some(&status) => status,
+                None => return,
+            };
+            match status {
+                0xF0 | 0xF7 => {
+                    let end = match list[pos + 1..]
+                        .iter()
+                        .position(|&b| b == 0xF0 || b == 0xF4 || b == 0xF7)
+                    {
+                        Some(end) => pos + 1 + end,
+                        None => return,
+                    };
+                    self.parse_sysex_segment(&list[pos..=end], time, &mut callback);
+                    self.running_status = None;
+                    pos = end + 1;
+                }
+                0xF4 => {
+                    self.sysex = None;
+                    pos += 1;
+                }
+                0xF8..=0xFF => {
+                    callback(time, &list[pos..pos + 1]);
+                    pos += 1;
+                }
+                0x80..=0xEF | 0xF1..=0xF6 => {
+                    let end = pos + 1 + data_len(status);
+                    if end > list.len() {
+                        return;
+                    }
+                    self.running_status = if status < 0xF0 { Some(status) } else { None };
+                    callback(time, &list[pos..end]);
+                    pos = end;
+                }
+                _ => {
+                    let status = match self.running_status {
+                        Some(status) => status,
+                        None => return,
+                    };
+                    let end = pos + data_len(status);
+                    if end > list.len() {
+                        return;
+                    }
+                    let mut message = [status, 0, 0];
+                    message[1..=end - pos].copy_from_slice(&list[pos..end]);
+                    callback(time, &message[..=end - pos]);
+                    pos = end;
+                }
+            }
+        }
+    }
+
+    fn parse_sysex_segment<F>(&mut self, segment: &[u8], time: u32, callback: &mut F)
+    where
+        F: FnMut(u32, &[u8]),
+    {
+        let last = segment[segment.len() - 1];
+        if last == 0xF4 {
+            // The sender has cancelled the message
+            self.sysex = None;
+            return;
+        }
+        let data = &segment[1..segment.len() - 1];
+        match (segment[0], last) {
+            (0xF0, 0xF7) => {
+                self.sysex = None;
+                callback(time, segment);
+            }
+            (0xF0, _) => {
+                let mut sysex = vec![0xF0];
+                sysex.extend_from_slice(data);
+                self.sysex = Some(sysex);
+            }
+            (_, 0xF7) => {
+                if let Some(mut sysex) = self.sysex.take() {
+                    sysex.extend_from_slice(data);
+                    sysex.push(0xF7);
+                    callback(time, &sysex);
+                }
+            }
+            _ => {
+                if let Some(ref mut sysex) = self.sysex {
+                    sysex.extend_from_slice(data);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let commands = [
+            Command::Invitation {
+                token: 0x12345678,
+                ssrc: 42,
+                name: "Session".to_string(),
+            },
+            Command::Accept {
+                token: 1,
+                ssrc: 2,
+                name: String::new(),
+            },
+            Command::Reject { token: 3, ssrc: 4 },
+            Command::End { token: 5, ssrc: 6 },
+            Command::Sync {
+                ssrc: 7,
+                count: 1,
+                timestamps: [1, 0x1_0000_0002, 0],
+            },
+            Command::Feedback {
+                ssrc: 8,
+                seq: 0xABCD,
+            },
+        ];
+        for command in &commands {
+            assert_eq!(Command::decode(&command.encode()).as_ref(), Some(command));
+        }
+        assert_eq!(
+            &commands[0].encode()[..8],
+            &[0xFF, 0xFF, b'I', b'N', 0, 0, 0, 2]
+        );
+        assert_eq!(commands[4].encode().len(), 36);
+        assert_eq!(Command::decode(&[0xFF, 0xFF, b'X', b'X']), None);
+        assert_eq!(Command::decode(&[0xFF, 0xFF, b'C', b'K', 0]), None);
+    }
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let sysex: Vec<u8> = std::iter::once(0xF0)
+            .chain((0..20).map(|i| i as u8))
+            .chain(std::iter::once(0xF7))
+            .collect();
+        for (command, journal) in [
+            (&[0x90, 60, 100][..], None),
+            (&sysex[..], Some(&[0x20, 0, 1][..])),
+        ] {
+            let bytes = MidiPacket::encode(7, 1000, 99, command, journal);
+            let packet = MidiPacket::decode(&bytes).unwrap();
+            assert_eq!(packet.seq, 7);
+            assert_eq!(packet.timestamp, 1000);
+            assert_eq!(packet.ssrc, 99);
+            assert!(!packet.first_has_delta);
+            assert_eq!(packet.commands, command);
+            assert_eq!(packet.journal, journal);
+        }
+        assert_eq!(
+            MidiPacket::decode(&Command::End { token: 0, ssrc: 0 }.encode()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_command_list() {
+        // Two note-ons with running status and a delta time, an interleaved clock
+        // and the first segment of a SysEx message that is continued in the next list
+        let list = [
+            0x90, 60, 100, 0x81, 0x00, 62, 100, 0x00, 0xF8, 0x05, 0xF0, 1, 2, 0xF0,
+        ];
+        let mut parser = CommandListParser::default();
+        let mut messages = Vec::new();
+        parser.parse(&list, false, |time, message| {
+            messages.push((time, message.to_vec()))
+        });
+        parser.parse(
+            &[0x02, 0xF7, 3, 0xF7, 0x00, 0xC0, 5],
+            true,
+            |time, message| messages.push((time, message.to_vec())),
+        );
+        assert_eq!(
+            messages,
+            vec![
+                (0, vec![0x90, 60, 100]),
+                (128, vec![0x90, 62, 100]),
+                (128, vec![0xF8]),
+                (2, vec![0xF0, 1, 2, 3, 0xF7]),
+                (2, vec![0xC0, 5]),
+            ]
+        );
+
+        // A cancelled SysEx message is dropped
+        let mut messages = Vec::new();
+        parser.parse(&[0xF0, 1, 0xF0], false, |_, m| messages.push(m.to_vec()));
+        parser.parse(&[0xF7, 2, 0xF4], false, |_, m| messages.push(m.to_vec()));
+        parser.parse(&[0xF7, 3, 0xF7], false, |_, m| messages.push(m.to_vec()));
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn test_sysex_segments() {
+        let message = [0xF0, 1, 2, 3, 4, 5, 0xF7];
+        assert_eq!(sysex_segments(&message, 7), vec![message.to_vec()]);
+        assert_eq!(
+            sysex_segments(&message, 5),
+            vec![vec![0xF0, 1, 2, 3, 0xF0], vec![0xF7, 4, 5, 0xF7]]
+        );
+        let mut parser = CommandListParser::default();
+        let mut messages = Vec::new();
+        for segment in sysex_segments(&message, 4) {
+            parser.parse(&segment, false, |_, m| messages.push(m.to_vec()));
+        }
+        assert_eq!(messages, vec![message.to_vec()]);
+    }
+}
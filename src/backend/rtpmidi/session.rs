@@ -0,0 +1,620 @@
+//! An RTP-MIDI session: a pair of UDP sockets (the control port and the data port, whose
+//! number is one higher) together with the participants that have joined it.
+//!
+//! Every session runs two threads. The control thread answers invitations on the control
+//! port, handles receiver feedback and periodically starts the clock synchronization and
+//! sends feedback itself; the data thread receives MIDI packets and synchronization
+//! requests on the data port.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::journal::{seq_le, ReceiverState, SenderJournal};
+use super::protocol::{self, Command, CommandListParser, MidiPacket};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const INVITATION_TIMEOUT: Duration = Duration::from_millis(500);
+const INVITATION_ATTEMPTS: usize = 4;
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+const FEEDBACK_INTERVAL: Duration = Duration::from_secs(1);
+/// The longest command list that is sent in one packet, so that
+/// packets fit into an Ethernet frame together with their journal.
+const MAX_SEGMENT_LEN: usize = 1000;
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// Receives the messages of all participants, with their timestamps.
+pub type Receiver = Box<dyn FnMut(u64, &[u8]) + Send>;
+
+/// The time since the backend was first used, in microseconds.
+pub fn timestamp() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+/// The current time in the units of RTP and clock synchronization timestamps (100 microseconds).
+fn clock() -> u64 {
+    timestamp() / 100
+}
+
+fn random_u32() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    hasher.finish() as u32
+}
+
+/// Binds the control and the data socket. If `port` is 0, the system chooses the
+/// control port, and another one is tried if the port after it is not free.
+fn bind_pair(port: u16) -> io::Result<(UdpSocket, UdpSocket)> {
+    let any = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    if port != 0 {
+        let data_port = port
+            .checked_add(1)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid port"))?;
+        return Ok((
+            UdpSocket::bind((any, port))?,
+            UdpSocket::bind((any, data_port))?,
+        ));
+    }
+    for _ in 0..16 {
+        let control = UdpSocket::bind((any, 0))?;
+        let port = control.local_addr()?.port();
+        if port == u16::MAX {
+            continue;
+        }
+        if let Ok(data) = UdpSocket::bind((any, port + 1)) {
+            return Ok((control, data));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "no free pair of UDP ports",
+    ))
+}
+
+/// The address under which a socket can be reached. Sockets are bound to all interfaces,
+/// so this uses the address of the interface for outgoing traffic, which other hosts can
+/// reach as well, and falls back to the loopback address if there is no network.
+fn local_address(socket: &UdpSocket) -> io::Result<SocketAddr> {
+    let mut address = socket.local_addr()?;
+    if address.ip().is_unspecified() {
+        address.set_ip(outgoing_ip().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+    Ok(address)
+}
+
+fn outgoing_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    // Connecting a UDP socket only chooses the route, nothing is sent
+    // (192.0.2.1 is reserved for documentation, so it is never a real host)
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+struct Participant {
+    ssrc: u32,
+    control_addr: SocketAddr,
+    /// The address of the data port, once the participant has joined through it.
+    data_addr: Option<SocketAddr>,
+    /// Whether this session has invited the participant, which makes
+    /// it responsible for starting the clock synchronization.
+    invited: bool,
+    last_sync: Option<Instant>,
+    parser: CommandListParser,
+    state: ReceiverState,
+    last_seq: Option<u16>,
+    /// The sequence number that has last been reported to the participant, and when.
+    feedback: Option<(u16, Instant)>,
+    /// The sequence number of the last of our packets that the participant has received.
+    acknowledged: Option<u16>,
+    /// The difference between our clock and the clock of the participant, in units of
+    /// 100 microseconds, once the clock synchronization has been completed.
+    clock_offset: Option<i64>,
+}
+
+impl Participant {
+    fn new(ssrc: u32, control_addr: SocketAddr, invited: bool) -> Participant {
+        Participant {
+            ssrc,
+            control_addr,
+            data_addr: None,
+            invited,
+            last_sync: None,
+            parser: CommandListParser::default(),
+            state: ReceiverState::default(),
+            last_seq: None,
+            feedback: None,
+            acknowledged: None,
+            clock_offset: None,
+        }
+    }
+
+    /// Converts the RTP timestamp of a packet from the participant into our time in
+    /// microseconds, or returns `now` if the clocks have not been synchronized yet.
+    fn local_time(&self, rtp_timestamp: u32, now: u64) -> u64 {
+        let offset = match self.clock_offset {
+            Some(offset) => offset,
+            None => return now,
+        };
+        // The RTP timestamp only has the lower 32 bits of the clock of the participant
+        let now_clock = (now / 100) as i64;
+        let remote_now = now_clock.wrapping_sub(offset) as u32;
+        let delta = rtp_timestamp.wrapping_sub(remote_now) as i32 as i64;
+        (now_clock + delta).max(0) as u64 * 100
+    }
+}
+
+struct State {
+    participants: Vec<Participant>,
+    seq: u16,
+    journal: SenderJournal,
+}
+
+pub struct Session {
+    name: String,
+    ssrc: u32,
+    control: UdpSocket,
+    data: UdpSocket,
+    /// Whether invitations from other sessions are accepted, which is only the
+    /// case for published sessions.
+    published: bool,
+    state: Mutex<State>,
+    receiver: Mutex<Option<Receiver>>,
+    stopped: AtomicBool,
+}
+
+impl Session {
+    /// Creates a session on the given control port (or any free port if it is 0).
+    /// Its threads are only started by `SessionHandle::start`.
+    pub fn bind(name: &str, port: u16, published: bool) -> io::Result<Session> {
+        let (control, data) = bind_pair(port)?;
+        control.set_read_timeout(Some(POLL_INTERVAL))?;
+        data.set_read_timeout(Some(POLL_INTERVAL))?;
+        let seq = random_u32() as u16;
+        Ok(Session {
+            name: name.to_string(),
+            ssrc: random_u32(),
+            control,
+            data,
+            published,
+            state: Mutex::new(State {
+                participants: Vec::new(),
+                seq,
+                journal: SenderJournal::new(seq.wrapping_sub(1)),
+            }),
+            receiver: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address of the control port, under which the session can be reached from this host.
+    pub fn address(&self) -> io::Result<SocketAddr> {
+        local_address(&self.control)
+    }
+
+    pub fn set_receiver(&self, receiver: Option<Receiver>) {
+        *self.receiver.lock().unwrap() = receiver;
+    }
+
+    /// Invites the session at the given control address, first on its control port
+    /// and then on its data port. This must be called before the threads are started.
+    pub fn invite(&self, control_addr: SocketAddr) -> Result<(), &'static str> {
+        let token = random_u32();
+        let ssrc = self.invite_on(&self.control, control_addr, token)?;
+        let data_addr = SocketAddr::new(control_addr.ip(), control_addr.port().wrapping_add(1));
+        self.invite_on(&self.data, data_addr, token)?;
+
+        let mut participant = Participant::new(ssrc, control_addr, true);
+        participant.data_addr = Some(data_addr);
+        self.state.lock().unwrap().participants.push(participant);
+        Ok(())
+    }
+
+    fn invite_on(
+        &self,
+        socket: &UdpSocket,
+        addr: SocketAddr,
+        token: u32,
+    ) -> Result<u32, &'static str> {
+        let invitation = Command::Invitation {
+            token,
+            ssrc: self.ssrc,
+            name: self.name.clone(),
+        }
+        .encode();
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        for _ in 0..INVITATION_ATTEMPTS {
+            socket
+                .send_to(&invitation, addr)
+                .map_err(|_| "could not send the invitation")?;
+            let deadline = Instant::now() + INVITATION_TIMEOUT;
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                let _ = socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))));
+                let len = match socket.recv_from(&mut buffer) {
+                    Ok((len, _)) => len,
+                    Err(_) => break,
+                };
+                match Command::decode(&buffer[..len]) {
+                    Some(Command::Accept { token: t, ssrc, .. }) if t == token => {
+                        let _ = socket.set_read_timeout(Some(POLL_INTERVAL));
+                        return Ok(ssrc);
+                    }
+                    Some(Command::Reject { token: t, .. }) if t == token => {
+                        return Err("the invitation has been rejected")
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Err("the session did not answer the invitation")
+    }
+
+    /// Sends a message to all participants. SysEx messages that do
+    /// not fit into one packet are split into several packets.
+    pub fn send(&self, message: &[u8]) -> io::Result<()> {
+        let segments = if message[0] == 0xF0 {
+            protocol::sysex_segments(message, MAX_SEGMENT_LEN)
+        } else {
+            vec![message.to_vec()]
+        };
+        let timestamp = clock() as u32;
+        let mut state = self.state.lock().unwrap();
+        let mut result = Ok(());
+        for segment in segments {
+            let seq = state.seq;
+            state.seq = seq.wrapping_add(1);
+            let journal = state.journal.encode();
+            let packet =
+                MidiPacket::encode(seq, timestamp, self.ssrc, &segment, journal.as_deref());
+            for participant in &state.participants {
+                if let Some(addr) = participant.data_addr {
+                    if let Err(err) = self.data.send_to(&packet, addr) {
+                        result = Err(err);
+                    }
+                }
+            }
+            state.journal.record(seq, &segment);
+        }
+        result
+    }
+
+    fn run_control(&self) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        while !self.stopped.load(Ordering::SeqCst) {
+            // Errors are timeouts, or caused by participants that have gone away
+            if let Ok((len, from)) = self.control.recv_from(&mut buffer) {
+                if let Some(command) = Command::decode(&buffer[..len]) {
+                    self.handle_control(command, from);
+                }
+            }
+            self.maintain();
+        }
+    }
+
+    fn handle_control(&self, command: Command, from: SocketAddr) {
+        let mut state = self.state.lock().unwrap();
+        match command {
+            Command::Invitation { token, ssrc, .. } => {
+                let reply = if self.published {
+                    match state.participants.iter_mut().find(|p| p.ssrc == ssrc) {
+                        Some(participant) => participant.control_addr = from,
+                        None => state.participants.push(Participant::new(ssrc, from, false)),
+                    }
+                    Command::Accept {
+                        token,
+                        ssrc: self.ssrc,
+                        name: self.name.clone(),
+                    }
+                } else {
+                    Command::Reject {
+                        token,
+                        ssrc: self.ssrc,
+                    }
+                };
+                let _ = self.control.send_to(&reply.encode(), from);
+            }
+            Command::End { ssrc, .. } => state.participants.retain(|p| p.ssrc != ssrc),
+            Command::Feedback { ssrc, seq } => {
+                if let Some(participant) = state.participants.iter_mut().find(|p| p.ssrc == ssrc) {
+                    participant.acknowledged = Some(seq);
+                }
+                // The journal can only be shortened once every participant has received a packet
+                let mut acknowledged = state
+                    .participants
+                    .iter()
+                    .filter(|p| p.data_addr.is_some())
+                    .map(|p| p.acknowledged);
+                if let Some(Some(first)) = acknowledged.next() {
+                    let oldest = acknowledged.try_fold(first, |oldest, seq| {
+                        seq.map(|seq| if seq_le(seq, oldest) { seq } else { oldest })
+                    });
+                    if let Some(oldest) = oldest {
+                        state.journal.acknowledge(oldest);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts the clock synchronization with invited participants and reports the
+    /// last received packet to the senders, both in regular intervals.
+    fn maintain(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        for participant in &mut state.participants {
+            if let (true, Some(data_addr)) = (participant.invited, participant.data_addr) {
+                let due = match participant.last_sync {
+                    Some(time) => now - time >= SYNC_INTERVAL,
+                    None => true,
+                };
+                if due {
+                    let sync = Command::Sync {
+                        ssrc: self.ssrc,
+                        count: 0,
+                        timestamps: [clock(), 0, 0],
+                    };
+                    let _ = self.data.send_to(&sync.encode(), data_addr);
+                    participant.last_sync = Some(now);
+                }
+            }
+            if let Some(seq) = participant.last_seq {
+                let due = match participant.feedback {
+                    Some((reported, time)) => reported != seq && now - time >= FEEDBACK_INTERVAL,
+                    None => true,
+                };
+                if due {
+                    let feedback = Command::Feedback {
+                        ssrc: self.ssrc,
+                        seq,
+                    };
+                    let _ = self
+                        .control
+                        .send_to(&feedback.encode(), participant.control_addr);
+                    participant.feedback = Some((seq, now));
+                }
+            }
+        }
+    }
+
+    fn run_data(&self) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        let mut messages = Vec::new();
+        while !self.stopped.load(Ordering::SeqCst) {
+            let (len, from) = match self.data.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(_) => continue,
+            };
+            let bytes = &buffer[..len];
+            if protocol::is_command(bytes) {
+                if let Some(command) = Command::decode(bytes) {
+                    self.handle_data_command(command, from);
+                }
+            } else if let Some(packet) = MidiPacket::decode(bytes) {
+                self.receive(&packet, &mut messages);
+                // The state must not be locked while the callback is running, so that it can send messages
+                if let Some(ref mut receiver) = *self.receiver.lock().unwrap() {
+                    for (timestamp, message) in &messages {
+                        receiver(*timestamp, message);
+                    }
+                }
+                messages.clear();
+            }
+        }
+    }
+
+    fn handle_data_command(&self, command: Command, from: SocketAddr) {
+        let reply = match command {
+            Command::Invitation { token, ssrc, .. } => {
+                let mut state = self.state.lock().unwrap();
+                match state.participants.iter_mut().find(|p| p.ssrc == ssrc) {
+                    // Only participants that have joined through the control port may join
+                    Some(participant) if self.published => {
+                        participant.data_addr = Some(from);
+                        Command::Accept {
+                            token,
+                            ssrc: self.ssrc,
+                            name: self.name.clone(),
+                        }
+                    }
+                    _ => Command::Reject {
+                        token,
+                        ssrc: self.ssrc,
+                    },
+                }
+            }
+            Command::Sync {
+                count: 0,
+                timestamps,
+                ..
+            } => Command::Sync {
+                ssrc: self.ssrc,
+                count: 1,
+                timestamps: [timestamps[0], clock(), 0],
+            },
+            Command::Sync {
+                ssrc,
+                count: 1,
+                timestamps,
+            } => {
+                // We started the synchronization: the participant read its clock
+                // halfway between the first and the third timestamp
+                let now = clock();
+                let offset =
+                    ((timestamps[0] as i128 + now as i128) / 2 - timestamps[1] as i128) as i64;
+                self.set_clock_offset(ssrc, offset);
+                Command::Sync {
+                    ssrc: self.ssrc,
+                    count: 2,
+                    timestamps: [timestamps[0], timestamps[1], now],
+                }
+            }
+            Command::Sync {
+                ssrc,
+                count: 2,
+                timestamps,
+            } => {
+                let offset = (timestamps[1] as i128
+                    - (timestamps[0] as i128 + timestamps[2] as i128) / 2)
+                    as i64;
+                self.set_clock_offset(ssrc, offset);
+                return;
+            }
+            Command::End { ssrc, .. } => {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .participants
+                    .retain(|p| p.ssrc != ssrc);
+                return;
+            }
+            _ => return,
+        };
+        let _ = self.data.send_to(&reply.encode(), from);
+    }
+
+    fn set_clock_offset(&self, ssrc: u32, offset: i64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(participant) = state.participants.iter_mut().find(|p| p.ssrc == ssrc) {
+            participant.clock_offset = Some(offset);
+        }
+    }
+
+    /// Collects the messages of a received packet, after recovering from lost packets.
+    /// The messages of the packet get the time at which the participant sent it, if the
+    /// clocks have been synchronized, while recovered messages get the time of arrival.
+    fn receive(&self, packet: &MidiPacket<'_>, messages: &mut Vec<(u64, Vec<u8>)>) {
+        let mut state = self.state.lock().unwrap();
+        let participant = match state
+            .participants
+            .iter_mut()
+            .find(|p| p.ssrc == packet.ssrc && p.data_addr.is_some())
+        {
+            Some(participant) => participant,
+            None => return,
+        };
+        let now = timestamp();
+        if let Some(last_seq) = participant.last_seq {
+            if seq_le(packet.seq, last_seq) {
+                // A duplicate or a packet that arrived too late
+                return;
+            }
+            if packet.seq != last_seq.wrapping_add(1) {
+                if let Some(journal) = packet.journal {
+                    participant
+                        .state
+                        .recover(journal, |message| messages.push((now, message.to_vec())));
+                }
+            }
+        }
+        participant.last_seq = Some(packet.seq);
+
+        let sent = participant.local_time(packet.timestamp, now);
+        let receiver_state = &mut participant.state;
+        participant
+            .parser
+            .parse(packet.commands, packet.first_has_delta, |delta, message| {
+                receiver_state.track(message);
+                messages.push((sent + delta as u64 * 100, message.to_vec()));
+            });
+    }
+
+    /// Says goodbye to all participants and stops the threads.
+    fn stop(&self) {
+        let state = self.state.lock().unwrap();
+        for participant in &state.participants {
+            let end = Command::End {
+                token: 0,
+                ssrc: self.ssrc,
+            };
+            let _ = self
+                .control
+                .send_to(&end.encode(), participant.control_addr);
+        }
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake up the threads, so that they do not have to wait for the next timeout
+        for socket in [&self.control, &self.data] {
+            if let Ok(mut address) = socket.local_addr() {
+                address.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+                let _ = socket.send_to(&[], address);
+            }
+        }
+    }
+}
+
+/// Keeps a session running. The session ends when the handle is dropped.
+pub struct SessionHandle {
+    session: Arc<Session>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl SessionHandle {
+    pub fn start(session: Session) -> io::Result<SessionHandle> {
+        // If a thread cannot be spawned, dropping the handle stops the other one
+        let mut handle = SessionHandle {
+            session: Arc::new(session),
+            threads: Vec::new(),
+        };
+        let control = handle.session.clone();
+        handle.threads.push(
+            thread::Builder::new()
+                .name("midir RTP-MIDI control".to_string())
+                .spawn(move || control.run_control())?,
+        );
+        let data = handle.session.clone();
+        handle.threads.push(
+            thread::Builder::new()
+                .name("midir RTP-MIDI data".to_string())
+                .spawn(move || data.run_data())?,
+        );
+        Ok(handle)
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.session.stop();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_time() {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5004);
+        let mut participant = Participant::new(1, addr, false);
+        assert_eq!(participant.local_time(12345, 1_000_000), 1_000_000);
+
+        // The clock of the participant is 5000 units (0.5 s) behind ours
+        participant.clock_offset = Some(5000);
+        // A packet that has been sent 20 ms before it arrived
+        assert_eq!(
+            participant.local_time(10000 - 5000 - 200, 1_000_000),
+            980_000
+        );
+        // The lower 32 bits of the clock of the participant have wrapped around
+        participant.clock_offset = Some(10000 - (1 << 32) - 10);
+        assert_eq!(participant.local_time(0xFFFF_FFF0, 1_000_000), 997_400);
+    }
+}
@@ -0,0 +1,427 @@
+//! A backend for MIDI over serial lines (UARTs and USB CDC devices, e.g. Arduino boards), which
+//! carry the plain MIDI byte stream at the MIDI baud rate or at the higher rate of "Hairless
+//! MIDI" style bridges.
+//!
+//! Ports are configured with `os::serial::add_port`, each one being a TTY device that can
+//! be used both for input and for output.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{Builder, JoinHandle};
+use std::time::Instant;
+
+use crate::errors::*;
+use crate::parser::{is_ignored, StreamParser};
+use crate::Ignore;
+
+/// A TTY device that is used as a MIDI port.
+#[derive(Clone, PartialEq)]
+struct PortConfig {
+    name: String,
+    path: PathBuf,
+    baud_rate: u32,
+}
+
+static PORTS: Mutex<Vec<PortConfig>> = Mutex::new(Vec::new());
+
+fn ports() -> MutexGuard<'static, Vec<PortConfig>> {
+    PORTS.lock().unwrap()
+}
+
+pub fn add_port(name: &str, path: PathBuf, baud_rate: u32) {
+    let mut ports = ports();
+    ports.retain(|p| p.path != path);
+    ports.push(PortConfig {
+        name: name.to_string(),
+        path,
+        baud_rate,
+    });
+}
+
+pub fn remove_port(path: PathBuf) -> bool {
+    let mut ports = ports();
+    let count = ports.len();
+    ports.retain(|p| p.path != path);
+    ports.len() != count
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Linux only accepts arbitrary rates (such as 31250 baud) through the `termios2` interface.
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "powerpc", target_arch = "powerpc64"))
+))]
+fn set_baud_rate(fd: RawFd, baud_rate: u32) -> io::Result<()> {
+    let mut tio: libc::termios2 = unsafe { mem::zeroed() };
+    check(unsafe { libc::ioctl(fd, libc::TCGETS2, &mut tio) })?;
+    tio.c_cflag &= !libc::CBAUD;
+    tio.c_cflag |= libc::BOTHER;
+    tio.c_ispeed = baud_rate;
+    tio.c_ospeed = baud_rate;
+    check(unsafe { libc::ioctl(fd, libc::TCSETS2, &tio) })
+}
+
+/// On the BSDs and macOS, the speed values are the baud rates themselves.
+#[cfg(not(all(
+    target_os = "linux",
+    not(any(target_arch = "powerpc", target_arch = "powerpc64"))
+)))]
+fn set_baud_rate(fd: RawFd, baud_rate: u32) -> io::Result<()> {
+    let mut tio: libc::termios = unsafe { mem::zeroed() };
+    check(unsafe { libc::tcgetattr(fd, &mut tio) })?;
+    check(unsafe { libc::cfsetspeed(&mut tio, baud_rate as libc::speed_t) })?;
+    check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &tio) })
+}
+
+impl PortConfig {
+    /// Opens the device and switches it to raw 8N1 mode at the configured baud rate.
+    fn open(&self, nonblocking: bool) -> io::Result<File> {
+        let mut flags = libc::O_NOCTTY;
+        if nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(flags)
+            .open(&self.path)?;
+
+        let fd = file.as_raw_fd();
+        let mut tio: libc::termios = unsafe { mem::zeroed() };
+        check(unsafe { libc::tcgetattr(fd, &mut tio) })?;
+        unsafe { libc::cfmakeraw(&mut tio) };
+        tio.c_cflag |= libc::CLOCAL | libc::CREAD;
+        tio.c_cflag &= !(libc::CSTOPB | libc::PARENB);
+        tio.c_cc[libc::VMIN] = 1;
+        tio.c_cc[libc::VTIME] = 0;
+        check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &tio) })?;
+        set_baud_rate(fd, self.baud_rate)?;
+        Ok(file)
+    }
+}
+
+pub struct MidiInput {
+    ignore_flags: Ignore,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MidiInputPort {
+    config: PortConfig,
+}
+
+impl MidiInputPort {
+    pub fn id(&self) -> String {
+        self.config.path.display().to_string()
+    }
+}
+
+pub struct MidiInputConnection<T: 'static> {
+    thread: Option<JoinHandle<(HandlerData<T>, T)>>,
+    trigger_send_fd: i32,
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<T: 'static> {
+    ignore_flags: Ignore,
+    file: File,
+    trigger_rcv_fd: i32,
+    callback: Callback<T>,
+}
+
+impl MidiInput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiInput {
+            ignore_flags: Ignore::None,
+        })
+    }
+
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiInputPort> {
+        ports()
+            .iter()
+            .map(|p| crate::common::MidiInputPort {
+                imp: MidiInputPort { config: p.clone() }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        ports().len()
+    }
+
+    pub fn port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.path.display().to_string())
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.path.display().to_string())
+    }
+
+    pub fn connect<F, T: Send>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        let file = match port.config.open(true) {
+            Ok(file) => file,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "could not open the serial device",
+                    self,
+                ))
+            }
+        };
+
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            return Err(ConnectError::other(
+                "could not create communication pipe for serial handler",
+                self,
+            ));
+        }
+
+        let handler_data = HandlerData {
+            ignore_flags: self.ignore_flags,
+            file,
+            trigger_rcv_fd: trigger_fds[0],
+            callback: Box::new(callback),
+        };
+
+        let threadbuilder = Builder::new();
+        let name = format!("midir serial input handler (port '{}')", port_name);
+        let threadbuilder = threadbuilder.name(name);
+        let thread = match threadbuilder.spawn(move || {
+            let mut d = data;
+            let h = handle_input(handler_data, &mut d);
+            (h, d) // return both the handler data and the user data
+        }) {
+            Ok(handle) => handle,
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                return Err(ConnectError::other(
+                    "could not start serial input handler thread",
+                    self,
+                ));
+            }
+        };
+
+        Ok(MidiInputConnection {
+            thread: Some(thread),
+            trigger_send_fd: trigger_fds[1],
+        })
+    }
+}
+
+impl<T> MidiInputConnection<T> {
+    pub fn close(mut self) -> (MidiInput, T) {
+        let (handler_data, user_data) = self.close_internal();
+
+        (
+            MidiInput {
+                ignore_flags: handler_data.ignore_flags,
+            },
+            user_data,
+        )
+    }
+
+    /// This must only be called if the handler thread has not yet been shut down
+    fn close_internal(&mut self) -> (HandlerData<T>, T) {
+        // Request the thread to stop.
+        let _res = unsafe {
+            libc::write(
+                self.trigger_send_fd,
+                &false as *const bool as *const _,
+                mem::size_of::<bool>() as libc::size_t,
+            )
+        };
+
+        let thread = self.thread.take().unwrap();
+        let (handler_data, user_data) = match thread.join() {
+            Ok(data) => data,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<&'static str>() {
+                    panic!("Error when joining serial thread: {}", e);
+                } else {
+                    panic!("Unknown error when joining serial thread: {:?}", e);
+                }
+            }
+        };
+
+        unsafe {
+            libc::close(handler_data.trigger_rcv_fd);
+            libc::close(self.trigger_send_fd);
+        }
+
+        (handler_data, user_data)
+    }
+}
+
+impl<T> Drop for MidiInputConnection<T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been dropped
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+/// Reads from the device until a request to stop is received through the trigger pipe.
+fn handle_input<T>(mut data: HandlerData<T>, user_data: &mut T) -> HandlerData<T> {
+    let mut poll_fds = vec![
+        libc::pollfd {
+            fd: data.trigger_rcv_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: data.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    let start = Instant::now();
+    let mut parser = StreamParser::new();
+    let mut buffer = [0; 256];
+    let ignore_flags = data.ignore_flags;
+    let callback = &mut data.callback;
+
+    loop {
+        if unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as libc::nfds_t, -1) } < 0 {
+            continue;
+        }
+        if poll_fds[0].revents & libc::POLLIN != 0 {
+            break;
+        }
+
+        let timestamp = if cfg!(feature = "avoid_timestamping") {
+            0
+        } else {
+            start.elapsed().as_micros() as u64
+        };
+        match data.file.read(&mut buffer) {
+            Ok(count) if count > 0 => parser.feed(&buffer[..count], |message| {
+                if !is_ignored(message, ignore_flags) {
+                    callback(timestamp, message, user_data);
+                }
+            }),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            _ => {
+                // The device has been unplugged or hung up, only wait for the request to stop
+                poll_fds.truncate(1);
+            }
+        }
+    }
+
+    data
+}
+
+pub struct MidiOutput;
+
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort {
+    config: PortConfig,
+}
+
+impl MidiOutputPort {
+    pub fn id(&self) -> String {
+        self.config.path.display().to_string()
+    }
+}
+
+pub struct MidiOutputConnection {
+    file: File,
+}
+
+impl MidiOutput {
+    pub fn new(_client_name: &str) -> Result<Self, InitError> {
+        Ok(MidiOutput)
+    }
+
+    pub(crate) fn ports_internal(&self) -> Vec<crate::common::MidiOutputPort> {
+        ports()
+            .iter()
+            .map(|p| crate::common::MidiOutputPort {
+                imp: MidiOutputPort { config: p.clone() }.into(),
+            })
+            .collect()
+    }
+
+    pub fn port_count(&self) -> usize {
+        ports().len()
+    }
+
+    pub fn port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.name.clone())
+    }
+
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.path.display().to_string())
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        Ok(port.config.path.display().to_string())
+    }
+
+    pub fn connect(
+        self,
+        port: &MidiOutputPort,
+        _port_name: &str,
+    ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
+        match port.config.open(false) {
+            Ok(file) => Ok(MidiOutputConnection { file }),
+            Err(_) => Err(ConnectError::other(
+                "could not open the serial device",
+                self,
+            )),
+        }
+    }
+}
+
+impl MidiOutputConnection {
+    pub fn close(self) -> MidiOutput {
+        MidiOutput
+    }
+
+    /// The bytes are written to the device exactly as given,
+    /// so messages may use running status.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if message.is_empty() {
+            return Err(SendError::InvalidData(
+                "message to be sent must not be empty",
+            ));
+        }
+        self.file
+            .write_all(message)
+            .map_err(|_| SendError::Other("could not write to the serial device"))
+    }
+}
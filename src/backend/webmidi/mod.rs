@@ -123,7 +123,8 @@ impl MidiInput {
                     v.push(crate::common::MidiInputPort {
                         imp: MidiInputPort {
                             input: value.dyn_into().unwrap(),
-                        },
+                        }
+                        .into(),
                     });
                 });
             }
@@ -149,6 +150,15 @@ impl MidiInput {
         Ok(port.input.name().unwrap_or_else(|| port.input.id()))
     }
 
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect<F, T: Send + 'static>(
         self,
         port: &MidiInputPort,
@@ -255,7 +265,8 @@ impl MidiOutput {
                         v.push(crate::common::MidiOutputPort {
                             imp: MidiOutputPort {
                                 output: value.dyn_into().unwrap(),
-                            },
+                            }
+                            .into(),
                         });
                     });
             }
@@ -277,6 +288,15 @@ impl MidiOutput {
         Ok(port.output.name().unwrap_or_else(|| port.output.id()))
     }
 
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect(
         self,
         port: &MidiOutputPort,
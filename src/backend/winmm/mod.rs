@@ -205,7 +205,7 @@ impl MidiInput {
                 Ok(p) => p,
                 Err(_) => continue,
             };
-            result.push(crate::common::MidiInputPort { imp: port });
+            result.push(crate::common::MidiInputPort { imp: port.into() });
         }
         result
     }
@@ -218,6 +218,15 @@ impl MidiInput {
         Ok(port.name.clone())
     }
 
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect<F, T: Send>(
         self,
         port: &MidiInputPort,
@@ -518,7 +527,7 @@ impl MidiOutput {
                 Ok(p) => p,
                 Err(_) => continue,
             };
-            result.push(crate::common::MidiOutputPort { imp: port });
+            result.push(crate::common::MidiOutputPort { imp: port.into() });
         }
         result
     }
@@ -531,6 +540,15 @@ impl MidiOutput {
         Ok(port.name.clone())
     }
 
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect(
         self,
         port: &MidiOutputPort,
@@ -51,7 +51,7 @@ impl MidiInput {
         for device_info in device_collection.into_iter() {
             let device_id = device_info.Id().expect("Id failed");
             result.push(crate::common::MidiInputPort {
-                imp: MidiInputPort { id: device_id },
+                imp: MidiInputPort { id: device_id }.into(),
             });
         }
         result
@@ -77,6 +77,15 @@ impl MidiInput {
         Ok(device_name.to_string())
     }
 
+    pub fn device_id(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     fn handle_input<T>(args: &MidiMessageReceivedEventArgs, handler_data: &mut HandlerData<T>) {
         let ignore = handler_data.ignore_flags;
         let data = &mut handler_data.user_data.as_mut().unwrap();
@@ -223,7 +232,7 @@ impl MidiOutput {
         for device_info in device_collection.into_iter() {
             let device_id = device_info.Id().expect("Id failed");
             result.push(crate::common::MidiOutputPort {
-                imp: MidiOutputPort { id: device_id },
+                imp: MidiOutputPort { id: device_id }.into(),
             });
         }
         result
@@ -249,6 +258,15 @@ impl MidiOutput {
         Ok(device_name.to_string())
     }
 
+    pub fn device_id(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.port_name(port)
+    }
+
+    pub fn device_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        // This backend has no notion of devices, so ports are grouped by name
+        self.port_name(port)
+    }
+
     pub fn connect(
         self,
         port: &MidiOutputPort,
@@ -0,0 +1,209 @@
+#![deny(missing_docs)]
+
+use crate::backend::{
+    ClientInputConnection as ClientInputConnectionImpl,
+    ClientOutputConnection as ClientOutputConnectionImpl, MidiClient as MidiClientImpl,
+};
+use crate::errors::*;
+use crate::r#virtual::VirtualPortOptions;
+use crate::{Backend, Ignore, MidiInputPort, MidiOutputPort};
+
+/// A single client of the MIDI system that can own any number of input,
+/// output and virtual ports (only supported by the ALSA and JACK backends).
+///
+/// In contrast to `MidiInput` and `MidiOutput`, which open a new ALSA sequencer
+/// client or JACK client for every connection, all ports created through a
+/// `MidiClient` belong to the same client, so they show up together in tools
+/// like `aconnect -l` or a JACK patchbay. Incoming messages of all input ports
+/// are dispatched by one shared thread (on JACK, the process thread of the client),
+/// so callbacks of different ports are never called concurrently.
+///
+/// On ALSA, callbacks may create and close connections of the same client.
+/// On JACK, they must not do so, as this would deadlock.
+///
+/// Connections keep the underlying client alive, so the `MidiClient`
+/// can be dropped while some of its connections are still open.
+pub struct MidiClient {
+    imp: MidiClientImpl,
+}
+
+impl MidiClient {
+    /// Creates a new client with the given name.
+    ///
+    /// The backend is chosen in the same way as for `MidiInput::new`,
+    /// skipping backends that do not support clients.
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        crate::backend::with_default_backend(|backend| Self::with_backend(backend, client_name))
+    }
+
+    /// Creates a new client with the given name that uses the given backend.
+    /// An error is returned if the backend does not support clients.
+    pub fn with_backend(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        MidiClientImpl::new(backend, client_name).map(|imp| MidiClient { imp })
+    }
+
+    /// Get the backend that this client uses.
+    pub fn backend(&self) -> Backend {
+        self.imp.backend()
+    }
+
+    /// Set flags to decide what kind of messages should be ignored (i.e., filtered out)
+    /// by input ports that are created afterwards. By default, no messages are ignored.
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.imp.ignore(flags);
+    }
+
+    /// Get a collection of all MIDI ports that input ports of this client can connect to.
+    pub fn input_ports(&self) -> Vec<MidiInputPort> {
+        self.imp.input_ports_internal()
+    }
+
+    /// Get a collection of all MIDI ports that output ports of this client can connect to.
+    pub fn output_ports(&self) -> Vec<MidiOutputPort> {
+        self.imp.output_ports_internal()
+    }
+
+    /// Get the name of a port returned by `input_ports`.
+    pub fn input_port_name(&self, port: &MidiInputPort) -> Result<String, PortInfoError> {
+        self.imp.input_port_name(&port.imp)
+    }
+
+    /// Get the name of a port returned by `output_ports`.
+    pub fn output_port_name(&self, port: &MidiOutputPort) -> Result<String, PortInfoError> {
+        self.imp.output_port_name(&port.imp)
+    }
+
+    /// Creates an input port named `port_name` and connects it to the given port.
+    /// The `callback` and `data` parameters have the same meaning as for `MidiInput::connect`.
+    pub fn connect_input<F, T: Send + 'static>(
+        &self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.imp
+            .connect_input(&port.imp, port_name, callback, data)
+            .map(|imp| ClientInputConnection { imp })
+    }
+
+    /// Creates a virtual input port that other applications can connect to.
+    /// The `callback` and `data` parameters have the same meaning as for `MidiInput::connect`.
+    pub fn create_virtual_input<F, T: Send + 'static>(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<ClientInputConnection<T>, ConnectError<()>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.imp
+            .create_virtual_input(port_name, options, callback, data)
+            .map(|imp| ClientInputConnection { imp })
+    }
+
+    /// Creates an output port named `port_name` and connects it to the given port.
+    pub fn connect_output(
+        &self,
+        port: &MidiOutputPort,
+        port_name: &str,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        self.imp
+            .connect_output(&port.imp, port_name)
+            .map(|imp| ClientOutputConnection { imp })
+    }
+
+    /// Creates a virtual output port that other applications can connect to.
+    pub fn create_virtual_output(
+        &self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<ClientOutputConnection, ConnectError<()>> {
+        self.imp
+            .create_virtual_output(port_name, options)
+            .map(|imp| ClientOutputConnection { imp })
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiClientExt for MidiClient {
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&alsa::Seq) -> R,
+    {
+        self.imp.alsa().map(|imp| imp.with_seq(f))
+    }
+}
+
+/// An input port of a `MidiClient`. The port is removed when the connection is closed or dropped.
+pub struct ClientInputConnection<T: 'static> {
+    imp: ClientInputConnectionImpl<T>,
+}
+
+impl<T> ClientInputConnection<T> {
+    /// Closes the connection and removes its port. The returned value allows you
+    /// to inspect the additional data passed to the callback (the `data` parameter
+    /// of `connect_input` or `create_virtual_input`), but it can be safely ignored.
+    pub fn close(self) -> T {
+        self.imp.close()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl<T> crate::os::linux::MidiInputConnectionExt for ClientInputConnection<T> {
+    fn alsa_port(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.port())
+    }
+}
+
+/// An output port of a `MidiClient`. The port is removed when the connection is closed or dropped.
+pub struct ClientOutputConnection {
+    imp: ClientOutputConnectionImpl,
+}
+
+impl ClientOutputConnection {
+    /// Send a message to the port that this output connection is connected to,
+    /// or to all subscribers of a virtual port.
+    /// The message must be a valid MIDI message (see https://www.midi.org/specifications-old/item/table-1-summary-of-midi-message).
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        self.imp.send(message)
+    }
+
+    /// Closes the connection and removes its port.
+    pub fn close(self) {
+        self.imp.close()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiOutputConnectionExt for ClientOutputConnection {
+    fn alsa_port(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.port())
+    }
+
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&alsa::Seq) -> R,
+    {
+        self.imp.alsa().map(|imp| imp.with_seq(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trait_impls() {
+        // make sure that all the structs implement `Send`
+        fn is_send<T: Send>() {}
+        is_send::<MidiClient>();
+        is_send::<ClientInputConnection<()>>();
+        is_send::<ClientOutputConnection>();
+    }
+}
@@ -7,7 +7,14 @@ use backend::{
 };
 use errors::*;
 
-use crate::{backend, errors, Ignore, InitError};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use crate::{backend, errors, Backend, Ignore, InitError, PortMatcher};
+
+/// How often `MidiIO::wait_for_port` checks whether a matching port has appeared.
+#[cfg(not(target_arch = "wasm32"))]
+const WAIT_FOR_PORT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// Trait that abstracts over input and output ports.
 pub trait MidiIO {
@@ -27,6 +34,38 @@ pub trait MidiIO {
     /// An error will be returned when the port is no longer valid
     /// (e.g. the respective device has been disconnected).
     fn port_name(&self, port: &Self::Port) -> Result<String, PortInfoError>;
+
+    /// Get all MIDI input or output ports whose name matches the given pattern.
+    ///
+    /// Ports whose name cannot be retrieved are skipped.
+    fn find_ports_by_name(&self, matcher: &PortMatcher) -> Vec<Self::Port> {
+        self.ports()
+            .into_iter()
+            .filter(|port| match self.port_name(port) {
+                Ok(name) => matcher.matches(&name),
+                Err(_) => false,
+            })
+            .collect()
+    }
+
+    /// Block until a port whose name matches the given pattern is available,
+    /// and return it. If there already is such a port, it is returned immediately.
+    ///
+    /// `None` is returned if no matching port has appeared before `timeout` elapsed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn wait_for_port(&self, matcher: &PortMatcher, timeout: Duration) -> Option<Self::Port> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(port) = self.find_ports_by_name(matcher).into_iter().next() {
+                return Some(port);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            std::thread::sleep(WAIT_FOR_PORT_POLL_INTERVAL.min(deadline - now));
+        }
+    }
 }
 
 /// An object representing a single input port.
@@ -49,6 +88,45 @@ impl MidiInputPort {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiInputPortExt for MidiInputPort {
+    fn from_alsa_addr(addr: alsa::seq::Addr) -> Self {
+        MidiInputPort {
+            imp: backend::alsa::MidiInputPort::from_addr(addr).into(),
+        }
+    }
+
+    fn alsa_addr(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.addr())
+    }
+}
+
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl crate::os::jack::MidiInputPortExt for MidiInputPort {
+    fn from_jack_name(name: &std::ffi::CStr) -> Self {
+        MidiInputPort {
+            imp: backend::jack::MidiInputPort::from_name(name).into(),
+        }
+    }
+
+    fn jack_name(&self) -> Option<&std::ffi::CStr> {
+        self.imp.jack().map(|imp| imp.name())
+    }
+}
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+impl crate::os::rtpmidi::MidiInputPortExt for MidiInputPort {
+    fn from_session_address(name: &str, address: std::net::SocketAddr) -> Self {
+        MidiInputPort {
+            imp: backend::rtpmidi::MidiInputPort::from_address(name, address).into(),
+        }
+    }
+
+    fn session_address(&self) -> Option<std::net::SocketAddr> {
+        self.imp.rtpmidi().map(|imp| imp.address())
+    }
+}
+
 /// A collection of input ports.
 pub type MidiInputPorts = Vec<MidiInputPort>;
 
@@ -61,8 +139,28 @@ pub struct MidiInput {
 
 impl MidiInput {
     /// Creates a new `MidiInput` object that is required for any MIDI input functionality.
+    ///
+    /// The backend is chosen at runtime: if the `MIDIR_BACKEND` environment variable is set
+    /// to the name of a backend (see `Backend::name`), that backend is used. Otherwise, the
+    /// backends returned by `available_backends` are tried in order until one of them can be
+    /// initialized. The reason why a backend could not be initialized is not reported
+    /// (see `with_backend`).
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        MidiInputImpl::new(client_name).map(|imp| MidiInput { imp })
+        backend::with_default_backend(|backend| Self::with_backend(backend, client_name))
+    }
+
+    /// Creates a new `MidiInput` object that uses the given backend.
+    /// An error is returned if the backend has not been compiled in or cannot be initialized
+    /// (e.g. because no JACK server is running). `InitError` does not tell these cases apart;
+    /// use `os::jack::MidiInputExt::with_server_options` to find out why a JACK client
+    /// could not be opened.
+    pub fn with_backend(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        MidiInputImpl::new(backend, client_name).map(|imp| MidiInput { imp })
+    }
+
+    /// Get the backend that this object uses.
+    pub fn backend(&self) -> Backend {
+        self.imp.backend()
     }
 
     /// Set flags to decide what kind of messages should be ignored (i.e., filtered out)
@@ -71,6 +169,12 @@ impl MidiInput {
         self.imp.ignore(flags);
     }
 
+    /// Check whether the backend supports virtual ports (see the `virtual` module).
+    /// If it does not, creating a virtual port always fails.
+    pub fn supports_virtual_ports(&self) -> Result<(), Unsupported> {
+        crate::r#virtual::supports_virtual_ports(self.backend())
+    }
+
     /// Get a collection of all MIDI input ports that *midir* can connect to.
     /// The resulting vector contains one object per port, which you can use to
     /// query metadata about the port or connect to it in order to receive
@@ -97,6 +201,17 @@ impl MidiInput {
         self.ports().into_iter().find(|port| port.id() == id)
     }
 
+    /// Get the identifier and name of the device that a MIDI input port belongs to.
+    pub(crate) fn device_info(
+        &self,
+        port: &MidiInputPort,
+    ) -> Result<(String, String), PortInfoError> {
+        Ok((
+            self.imp.device_id(&port.imp)?,
+            self.imp.device_name(&port.imp)?,
+        ))
+    }
+
     /// Connect to a specified MIDI input port in order to receive messages.
     /// For each incoming MIDI message, the provided `callback` function will
     /// be called. The first parameter of the callback function is a timestamp
@@ -142,6 +257,68 @@ impl MidiInput {
     }
 }
 
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl crate::os::jack::MidiInputExt for MidiInput {
+    unsafe fn from_jack_client(client: *mut crate::os::jack::jack_client_t) -> Self {
+        MidiInput {
+            imp: backend::jack::MidiInput::from_raw_client(client).into(),
+        }
+    }
+
+    fn with_server_options(
+        client_name: &str,
+        options: &crate::os::jack::ServerOptions,
+    ) -> Result<Self, crate::os::jack::OpenError> {
+        backend::jack::MidiInput::with_server_options(client_name, options)
+            .map(|imp| MidiInput { imp: imp.into() })
+    }
+
+    fn jack_client(&self) -> Option<*mut crate::os::jack::jack_client_t> {
+        self.imp.jack().map(|imp| imp.raw_client())
+    }
+
+    fn connect_with_frame_time<F, T: Send + 'static>(
+        self,
+        port: &MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<MidiInput>>
+    where
+        F: FnMut(u64, crate::os::jack::jack_nframes_t, &[u8], &mut T) + Send + 'static,
+    {
+        match self
+            .imp
+            .connect_with_frame_time(&port.imp, port_name, callback, data)
+        {
+            Ok(imp) => Ok(MidiInputConnection { imp }),
+            Err(imp) => {
+                let kind = imp.kind();
+                Err(ConnectError::new(
+                    kind,
+                    MidiInput {
+                        imp: imp.into_inner(),
+                    },
+                ))
+            }
+        }
+    }
+
+    fn port_aliases(&self, port: &MidiInputPort) -> Vec<String> {
+        match (self.imp.jack(), port.imp.jack()) {
+            (Some(imp), Some(port)) => imp.port_aliases(port),
+            _ => Vec::new(),
+        }
+    }
+
+    fn port_metadata(&self, port: &MidiInputPort, key: &str) -> Option<String> {
+        match (self.imp.jack(), port.imp.jack()) {
+            (Some(imp), Some(port)) => imp.port_metadata(port, key),
+            _ => None,
+        }
+    }
+}
+
 impl MidiIO for MidiInput {
     type Port = MidiInputPort;
 
@@ -158,18 +335,78 @@ impl MidiIO for MidiInput {
     }
 }
 
-#[cfg(unix)]
-impl<T: Send> crate::os::unix::VirtualInput<T> for MidiInput {
-    fn create_virtual<F>(
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiInputExt for MidiInput {
+    fn from_seq(seq: alsa::Seq) -> Self {
+        MidiInput {
+            imp: backend::alsa::MidiInput::from_seq(seq).into(),
+        }
+    }
+
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&alsa::Seq) -> R,
+    {
+        self.imp.alsa().map(|imp| imp.with_seq(f))
+    }
+}
+
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl crate::os::jack::MidiOutputConnectionExt for MidiOutputConnection {
+    fn on_jack_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(crate::os::jack::JackEvent) + Send + 'static,
+    {
+        if let Some(imp) = self.imp.jack() {
+            imp.on_event(callback)
+        }
+    }
+
+    fn set_port_pretty_name(&mut self, name: &str) -> Result<(), crate::os::jack::PortOptionError> {
+        self.imp
+            .jack()
+            .ok_or(crate::os::jack::PortOptionError::OtherBackend)?
+            .set_port_pretty_name(name)
+    }
+
+    fn add_port_alias(&mut self, alias: &str) -> Result<(), crate::os::jack::PortOptionError> {
+        self.imp
+            .jack()
+            .ok_or(crate::os::jack::PortOptionError::OtherBackend)?
+            .add_port_alias(alias)
+    }
+}
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+impl crate::os::rtpmidi::MidiOutputConnectionExt for MidiOutputConnection {
+    fn session_address(&self) -> Option<std::net::SocketAddr> {
+        self.imp.rtpmidi().map(|imp| imp.session_address())
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiIOExt for MidiInput {
+    fn ports_filtered(&self, filter: &crate::os::linux::PortFilter) -> Option<MidiInputPorts> {
+        self.imp.alsa().map(|imp| imp.ports_filtered(filter))
+    }
+
+    fn port_count_filtered(&self, filter: &crate::os::linux::PortFilter) -> Option<usize> {
+        self.imp.alsa().map(|imp| imp.port_count_filtered(filter))
+    }
+}
+
+impl<T: Send> crate::r#virtual::VirtualInput<T> for MidiInput {
+    fn create_virtual_with_options<F>(
         self,
         port_name: &str,
+        options: &crate::r#virtual::VirtualPortOptions,
         callback: F,
         data: T,
     ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
     where
         F: FnMut(u64, &[u8], &mut T) + Send + 'static,
     {
-        match self.imp.create_virtual(port_name, callback, data) {
+        match self.imp.create_virtual(port_name, options, callback, data) {
             Ok(imp) => Ok(MidiInputConnection { imp }),
             Err(imp) => {
                 let kind = imp.kind();
@@ -184,6 +421,38 @@ impl<T: Send> crate::os::unix::VirtualInput<T> for MidiInput {
     }
 }
 
+impl<T: Send> crate::r#virtual::VirtualDuplex<T> for MidiInput {
+    fn create_virtual_duplex<F>(
+        self,
+        port_name: &str,
+        options: &crate::r#virtual::VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<crate::MidiDuplexConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        match self
+            .imp
+            .create_virtual_duplex(port_name, options, callback, data)
+        {
+            Ok((conn_in, conn_out)) => Ok((
+                MidiInputConnection { imp: conn_in },
+                MidiOutputConnection { imp: conn_out },
+            )),
+            Err(err) => {
+                let kind = err.kind();
+                Err(ConnectError::new(
+                    kind,
+                    MidiInput {
+                        imp: err.into_inner(),
+                    },
+                ))
+            }
+        }
+    }
+}
+
 /// Represents an open connection to a MIDI input port.
 pub struct MidiInputConnection<T: 'static> {
     imp: MidiInputConnectionImpl<T>,
@@ -200,6 +469,73 @@ impl<T> MidiInputConnection<T> {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl<T> crate::os::linux::MidiInputConnectionExt for MidiInputConnection<T> {
+    fn alsa_port(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.port())
+    }
+}
+
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl<T> crate::os::jack::MidiInputConnectionExt for MidiInputConnection<T> {
+    fn on_jack_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(crate::os::jack::JackEvent) + Send + 'static,
+    {
+        if let Some(imp) = self.imp.jack() {
+            imp.on_event(callback)
+        }
+    }
+
+    fn set_port_pretty_name(&mut self, name: &str) -> Result<(), crate::os::jack::PortOptionError> {
+        self.imp
+            .jack()
+            .ok_or(crate::os::jack::PortOptionError::OtherBackend)?
+            .set_port_pretty_name(name)
+    }
+
+    fn add_port_alias(&mut self, alias: &str) -> Result<(), crate::os::jack::PortOptionError> {
+        self.imp
+            .jack()
+            .ok_or(crate::os::jack::PortOptionError::OtherBackend)?
+            .add_port_alias(alias)
+    }
+}
+
+#[cfg(feature = "dummy")]
+impl<T> crate::os::dummy::MidiInputConnectionExt for MidiInputConnection<T> {
+    fn set_fixed_timestamp(&self, timestamp: Option<u64>) {
+        if let Some(imp) = self.imp.dummy() {
+            imp.set_fixed_timestamp(timestamp)
+        }
+    }
+}
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+impl<T> crate::os::rtpmidi::MidiInputConnectionExt for MidiInputConnection<T> {
+    fn session_address(&self) -> Option<std::net::SocketAddr> {
+        self.imp.rtpmidi().map(|imp| imp.session_address())
+    }
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows")),
+    feature = "dummy"
+))]
+impl<T> crate::r#virtual::VirtualSubscribers for MidiInputConnection<T> {
+    fn subscribers(&self) -> Vec<crate::r#virtual::Subscriber> {
+        self.imp.subscribers()
+    }
+
+    fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(crate::r#virtual::SubscriptionEvent) + Send + 'static,
+    {
+        self.imp.on_subscription_change(callback)
+    }
+}
+
 /// An object representing a single output port.
 /// How the port is identified internally is backend-dependent.
 /// If the backend allows it, port objects remain valid when
@@ -220,6 +556,45 @@ impl MidiOutputPort {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiOutputPortExt for MidiOutputPort {
+    fn from_alsa_addr(addr: alsa::seq::Addr) -> Self {
+        MidiOutputPort {
+            imp: backend::alsa::MidiOutputPort::from_addr(addr).into(),
+        }
+    }
+
+    fn alsa_addr(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.addr())
+    }
+}
+
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl crate::os::jack::MidiOutputPortExt for MidiOutputPort {
+    fn from_jack_name(name: &std::ffi::CStr) -> Self {
+        MidiOutputPort {
+            imp: backend::jack::MidiOutputPort::from_name(name).into(),
+        }
+    }
+
+    fn jack_name(&self) -> Option<&std::ffi::CStr> {
+        self.imp.jack().map(|imp| imp.name())
+    }
+}
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+impl crate::os::rtpmidi::MidiOutputPortExt for MidiOutputPort {
+    fn from_session_address(name: &str, address: std::net::SocketAddr) -> Self {
+        MidiOutputPort {
+            imp: backend::rtpmidi::MidiOutputPort::from_address(name, address).into(),
+        }
+    }
+
+    fn session_address(&self) -> Option<std::net::SocketAddr> {
+        self.imp.rtpmidi().map(|imp| imp.address())
+    }
+}
+
 /// A collection of output ports.
 pub type MidiOutputPorts = Vec<MidiOutputPort>;
 
@@ -231,8 +606,34 @@ pub struct MidiOutput {
 
 impl MidiOutput {
     /// Creates a new `MidiOutput` object that is required for any MIDI output functionality.
+    ///
+    /// The backend is chosen at runtime: if the `MIDIR_BACKEND` environment variable is set
+    /// to the name of a backend (see `Backend::name`), that backend is used. Otherwise, the
+    /// backends returned by `available_backends` are tried in order until one of them can be
+    /// initialized. The reason why a backend could not be initialized is not reported
+    /// (see `with_backend`).
     pub fn new(client_name: &str) -> Result<Self, InitError> {
-        MidiOutputImpl::new(client_name).map(|imp| MidiOutput { imp })
+        backend::with_default_backend(|backend| Self::with_backend(backend, client_name))
+    }
+
+    /// Creates a new `MidiOutput` object that uses the given backend.
+    /// An error is returned if the backend has not been compiled in or cannot be initialized
+    /// (e.g. because no JACK server is running). `InitError` does not tell these cases apart;
+    /// use `os::jack::MidiOutputExt::with_server_options` to find out why a JACK client
+    /// could not be opened.
+    pub fn with_backend(backend: Backend, client_name: &str) -> Result<Self, InitError> {
+        MidiOutputImpl::new(backend, client_name).map(|imp| MidiOutput { imp })
+    }
+
+    /// Get the backend that this object uses.
+    pub fn backend(&self) -> Backend {
+        self.imp.backend()
+    }
+
+    /// Check whether the backend supports virtual ports (see the `virtual` module).
+    /// If it does not, creating a virtual port always fails.
+    pub fn supports_virtual_ports(&self) -> Result<(), Unsupported> {
+        crate::r#virtual::supports_virtual_ports(self.backend())
     }
 
     /// Get a collection of all MIDI output ports that *midir* can connect to.
@@ -261,6 +662,17 @@ impl MidiOutput {
         self.ports().into_iter().find(|port| port.id() == id)
     }
 
+    /// Get the identifier and name of the device that a MIDI output port belongs to.
+    pub(crate) fn device_info(
+        &self,
+        port: &MidiOutputPort,
+    ) -> Result<(String, String), PortInfoError> {
+        Ok((
+            self.imp.device_id(&port.imp)?,
+            self.imp.device_name(&port.imp)?,
+        ))
+    }
+
     /// Connect to a specified MIDI output port in order to send messages.
     /// The connection will be kept open as long as the returned
     /// `MidiOutputConnection` is kept alive.
@@ -290,6 +702,41 @@ impl MidiOutput {
     }
 }
 
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+impl crate::os::jack::MidiOutputExt for MidiOutput {
+    unsafe fn from_jack_client(client: *mut crate::os::jack::jack_client_t) -> Self {
+        MidiOutput {
+            imp: backend::jack::MidiOutput::from_raw_client(client).into(),
+        }
+    }
+
+    fn with_server_options(
+        client_name: &str,
+        options: &crate::os::jack::ServerOptions,
+    ) -> Result<Self, crate::os::jack::OpenError> {
+        backend::jack::MidiOutput::with_server_options(client_name, options)
+            .map(|imp| MidiOutput { imp: imp.into() })
+    }
+
+    fn jack_client(&self) -> Option<*mut crate::os::jack::jack_client_t> {
+        self.imp.jack().map(|imp| imp.raw_client())
+    }
+
+    fn port_aliases(&self, port: &MidiOutputPort) -> Vec<String> {
+        match (self.imp.jack(), port.imp.jack()) {
+            (Some(imp), Some(port)) => imp.port_aliases(port),
+            _ => Vec::new(),
+        }
+    }
+
+    fn port_metadata(&self, port: &MidiOutputPort, key: &str) -> Option<String> {
+        match (self.imp.jack(), port.imp.jack()) {
+            (Some(imp), Some(port)) => imp.port_metadata(port, key),
+            _ => None,
+        }
+    }
+}
+
 impl MidiIO for MidiOutput {
     type Port = MidiOutputPort;
 
@@ -306,13 +753,40 @@ impl MidiIO for MidiOutput {
     }
 }
 
-#[cfg(unix)]
-impl crate::os::unix::VirtualOutput for MidiOutput {
-    fn create_virtual(
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiOutputExt for MidiOutput {
+    fn from_seq(seq: alsa::Seq) -> Self {
+        MidiOutput {
+            imp: backend::alsa::MidiOutput::from_seq(seq).into(),
+        }
+    }
+
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&alsa::Seq) -> R,
+    {
+        self.imp.alsa().map(|imp| imp.with_seq(f))
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiIOExt for MidiOutput {
+    fn ports_filtered(&self, filter: &crate::os::linux::PortFilter) -> Option<MidiOutputPorts> {
+        self.imp.alsa().map(|imp| imp.ports_filtered(filter))
+    }
+
+    fn port_count_filtered(&self, filter: &crate::os::linux::PortFilter) -> Option<usize> {
+        self.imp.alsa().map(|imp| imp.port_count_filtered(filter))
+    }
+}
+
+impl crate::r#virtual::VirtualOutput for MidiOutput {
+    fn create_virtual_with_options(
         self,
         port_name: &str,
+        options: &crate::r#virtual::VirtualPortOptions,
     ) -> Result<MidiOutputConnection, ConnectError<MidiOutput>> {
-        match self.imp.create_virtual(port_name) {
+        match self.imp.create_virtual(port_name, options) {
             Ok(imp) => Ok(MidiOutputConnection { imp }),
             Err(imp) => {
                 let kind = imp.kind();
@@ -348,6 +822,38 @@ impl MidiOutputConnection {
     }
 }
 
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+impl crate::os::linux::MidiOutputConnectionExt for MidiOutputConnection {
+    fn alsa_port(&self) -> Option<alsa::seq::Addr> {
+        self.imp.alsa().map(|imp| imp.port())
+    }
+
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&alsa::Seq) -> R,
+    {
+        self.imp.alsa().map(|imp| imp.with_seq(f))
+    }
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows")),
+    feature = "dummy"
+))]
+impl crate::r#virtual::VirtualSubscribers for MidiOutputConnection {
+    fn subscribers(&self) -> Vec<crate::r#virtual::Subscriber> {
+        self.imp.subscribers()
+    }
+
+    fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(crate::r#virtual::SubscriptionEvent) + Send + 'static,
+    {
+        self.imp.on_subscription_change(callback)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
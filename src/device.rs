@@ -0,0 +1,233 @@
+#![deny(missing_docs)]
+
+use std::fmt;
+
+use crate::errors::*;
+use crate::{
+    MidiInput, MidiInputConnection, MidiInputPort, MidiOutput, MidiOutputConnection, MidiOutputPort,
+};
+
+/// The pair of connections returned by `MidiDevice::open_duplex`
+/// and `VirtualDuplex::create_virtual_duplex`.
+pub type MidiDuplexConnection<T> = (MidiInputConnection<T>, MidiOutputConnection);
+
+/// A device groups the input and output ports that belong to the
+/// same piece of hardware or software client.
+///
+/// How ports are grouped is backend-dependent: on ALSA, all ports of a
+/// sequencer client form a device, on JACK all ports that share the same
+/// client prefix. Backends without a notion of devices group ports by name,
+/// which pairs up input and output ports that are named identically.
+///
+/// Use `MidiDevice::list` to obtain the available devices.
+#[derive(Clone, PartialEq)]
+pub struct MidiDevice {
+    id: String,
+    name: String,
+    inputs: Vec<MidiInputPort>,
+    outputs: Vec<MidiOutputPort>,
+}
+
+impl fmt::Debug for MidiDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inputs: Vec<_> = self.inputs.iter().map(|port| port.id()).collect();
+        let outputs: Vec<_> = self.outputs.iter().map(|port| port.id()).collect();
+        f.debug_struct("MidiDevice")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("inputs", &inputs)
+            .field("outputs", &outputs)
+            .finish()
+    }
+}
+
+/// The ports of a device, independent of the port types so the grouping can be tested.
+struct PortGroup<I, O> {
+    id: String,
+    name: String,
+    inputs: Vec<I>,
+    outputs: Vec<O>,
+}
+
+/// Groups ports by their device id, keeping the order in which devices are first seen.
+/// The name of a device is taken from its first port.
+fn group_ports<I, O>(
+    inputs: impl IntoIterator<Item = (I, (String, String))>,
+    outputs: impl IntoIterator<Item = (O, (String, String))>,
+) -> Vec<PortGroup<I, O>> {
+    fn entry<I, O>(
+        groups: &mut Vec<PortGroup<I, O>>,
+        id: String,
+        name: String,
+    ) -> &mut PortGroup<I, O> {
+        match groups.iter().position(|d| d.id == id) {
+            Some(idx) => &mut groups[idx],
+            None => {
+                groups.push(PortGroup {
+                    id,
+                    name,
+                    inputs: Vec::new(),
+                    outputs: Vec::new(),
+                });
+                groups.last_mut().unwrap()
+            }
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (port, (id, name)) in inputs {
+        entry(&mut groups, id, name).inputs.push(port);
+    }
+    for (port, (id, name)) in outputs {
+        entry(&mut groups, id, name).outputs.push(port);
+    }
+    groups
+}
+
+impl MidiDevice {
+    /// Get a collection of all devices that have at least one input or
+    /// output port that *midir* can connect to.
+    ///
+    /// Ports whose device cannot be determined (e.g. because the device
+    /// has been disconnected in the meantime) are skipped.
+    pub fn list(midi_in: &MidiInput, midi_out: &MidiOutput) -> Vec<MidiDevice> {
+        let inputs = midi_in
+            .ports()
+            .into_iter()
+            .filter_map(|port| midi_in.device_info(&port).ok().map(|info| (port, info)));
+        let outputs = midi_out
+            .ports()
+            .into_iter()
+            .filter_map(|port| midi_out.device_info(&port).ok().map(|info| (port, info)));
+
+        group_ports(inputs, outputs)
+            .into_iter()
+            .map(|device| MidiDevice {
+                id: device.id,
+                name: device.name,
+                inputs: device.inputs,
+                outputs: device.outputs,
+            })
+            .collect()
+    }
+
+    /// Get a unique identifier for this device.
+    /// This identifier must be treated as an opaque string.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the name of this device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the input ports of this device.
+    pub fn inputs(&self) -> &[MidiInputPort] {
+        &self.inputs
+    }
+
+    /// Get the output ports of this device.
+    pub fn outputs(&self) -> &[MidiOutputPort] {
+        &self.outputs
+    }
+
+    /// Returns whether this device has both input and output ports.
+    pub fn is_duplex(&self) -> bool {
+        !self.inputs.is_empty() && !self.outputs.is_empty()
+    }
+
+    /// Connect to an input port and an output port of this device at once,
+    /// e.g. in order to send requests to the device and receive its replies.
+    /// The `callback` and `data` parameters have the same meaning as for `MidiInput::connect`.
+    ///
+    /// Which input port belongs to which output port cannot be determined reliably
+    /// across backends, so both ports must be chosen explicitly from `inputs()` and
+    /// `outputs()`. An error is returned if any of them does not belong to this device.
+    ///
+    /// If any of the two connections cannot be made, no connection is kept open and
+    /// the `MidiInput` and `MidiOutput` objects are returned as part of the error.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_duplex<F, T: Send>(
+        &self,
+        midi_in: MidiInput,
+        midi_out: MidiOutput,
+        in_port: &MidiInputPort,
+        out_port: &MidiOutputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiDuplexConnection<T>, ConnectError<(MidiInput, MidiOutput)>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        if !self.inputs.contains(in_port) {
+            return Err(ConnectError::other(
+                "input port does not belong to this device",
+                (midi_in, midi_out),
+            ));
+        }
+        if !self.outputs.contains(out_port) {
+            return Err(ConnectError::other(
+                "output port does not belong to this device",
+                (midi_in, midi_out),
+            ));
+        }
+
+        let conn_in = match midi_in.connect(in_port, port_name, callback, data) {
+            Ok(conn) => conn,
+            Err(err) => return Err(ConnectError::new(err.kind(), (err.into_inner(), midi_out))),
+        };
+
+        match midi_out.connect(out_port, port_name) {
+            Ok(conn_out) => Ok((conn_in, conn_out)),
+            Err(err) => {
+                let (midi_in, _) = conn_in.close();
+                Err(ConnectError::new(err.kind(), (midi_in, err.into_inner())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: &str, name: &str) -> (String, String) {
+        (id.to_string(), name.to_string())
+    }
+
+    #[test]
+    fn test_group_ports() {
+        let inputs = vec![
+            (1, info("20", "Synth")),
+            (2, info("14", "Midi Through")),
+            (3, info("20", "Synth (second port)")),
+        ];
+        let outputs = vec![
+            ("a", info("14", "Midi Through")),
+            ("b", info("24", "Keyboard")),
+        ];
+        let groups = group_ports(inputs, outputs);
+
+        let summary: Vec<_> = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.id.as_str(),
+                    g.name.as_str(),
+                    &g.inputs[..],
+                    &g.outputs[..],
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("20", "Synth", &[1, 3][..], &[][..]),
+                ("14", "Midi Through", &[2][..], &["a"][..]),
+                ("24", "Keyboard", &[][..], &["b"][..]),
+            ]
+        );
+    }
+}
@@ -4,6 +4,7 @@ use std::fmt;
 const INVALID_PORT_MSG: &str = "invalid port";
 const PORT_OUT_OF_RANGE_MSG: &str = "provided port number was out of range";
 const CANNOT_RETRIEVE_PORT_NAME_MSG: &str = "unknown error when trying to retrieve the port name";
+pub(crate) const UNSUPPORTED_MSG: &str = "not supported by the MIDI backend";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An error that can occur during initialization (i.e., while
@@ -18,6 +19,18 @@ impl fmt::Display for InitError {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An error that indicates that a feature is not supported by the MIDI backend.
+pub struct Unsupported;
+
+impl Error for Unsupported {}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        UNSUPPORTED_MSG.fmt(f)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An error that can occur when retrieving information about
 /// available ports.
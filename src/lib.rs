@@ -6,6 +6,30 @@
 #[macro_use]
 extern crate bitflags;
 
+/// Declares items that only exist if at least one backend is enabled for the target.
+/// Without a backend, `backend` fails with a `compile_error!`, and leaving these out
+/// keeps it from being buried under errors about the missing backend objects.
+macro_rules! with_any_backend {
+    ($($item:item)*) => {
+        $(
+            #[cfg(any(
+                all(target_os = "linux", feature = "alsa"),
+                all(feature = "jack", not(target_os = "windows")),
+                all(target_os = "linux", feature = "pipewire"),
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "windows",
+                target_arch = "wasm32",
+                feature = "dummy",
+                feature = "rtpmidi",
+                feature = "ipmidi",
+                all(unix, feature = "serial")
+            ))]
+            $item
+        )*
+    };
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An enum that is used to specify what kind of MIDI messages should
@@ -43,11 +67,30 @@ impl Ignore {
 /// a point in time that is arbitrary, but does not change for the
 /// lifetime of a given MidiInputConnection.
 #[derive(Debug, Clone)]
+// the dummy, network and serial backends pass messages through as they are
+#[cfg_attr(
+    any(
+        feature = "dummy",
+        feature = "rtpmidi",
+        feature = "ipmidi",
+        feature = "serial"
+    ),
+    allow(dead_code)
+)]
 struct MidiMessage {
     bytes: Vec<u8>,
     timestamp: u64,
 }
 
+#[cfg_attr(
+    any(
+        feature = "dummy",
+        feature = "rtpmidi",
+        feature = "ipmidi",
+        feature = "serial"
+    ),
+    allow(dead_code)
+)]
 impl MidiMessage {
     fn new() -> MidiMessage {
         MidiMessage {
@@ -57,12 +100,48 @@ impl MidiMessage {
     }
 }
 
-pub mod os; // include platform-specific behaviour
+with_any_backend! {
+    pub mod os; // include platform-specific behaviour
+
+    pub mod r#virtual;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stream;
+
+pub mod ump;
+
+pub mod smf;
 
 mod errors;
 pub use errors::*;
 
-mod common;
-pub use common::*;
+with_any_backend! {
+    mod common;
+    pub use common::*;
+}
+
+mod matcher;
+pub use matcher::*;
+
+with_any_backend! {
+    mod device;
+    pub use device::*;
+}
+
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+mod client;
+#[cfg(any(
+    all(target_os = "linux", feature = "alsa"),
+    all(feature = "jack", not(target_os = "windows"))
+))]
+pub use client::*;
+
+#[cfg(any(not(target_arch = "wasm32"), feature = "dummy"))]
+mod parser;
 
 mod backend;
+pub use backend::{available_backends, Backend};
@@ -0,0 +1,112 @@
+#![deny(missing_docs)]
+
+/// A pattern that is matched against port names when looking up ports
+/// with `MidiIO::find_ports_by_name` or `MidiIO::wait_for_port`.
+///
+/// Port names are matched as returned by `port_name`, which on most
+/// backends contains both the client (device) name and the port name.
+#[derive(Debug, Clone)]
+pub enum PortMatcher {
+    /// The port name must be exactly equal to the given string.
+    Exact(String),
+    /// The port name must contain the given string.
+    Substring(String),
+    /// The port name must match the given glob pattern, where `*` matches
+    /// any sequence of characters and `?` matches any single character.
+    Glob(String),
+    /// The port name must match the given regular expression
+    /// (requires the `regex` feature).
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl PortMatcher {
+    /// Checks whether the given port name matches this pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            PortMatcher::Exact(ref s) => name == s,
+            PortMatcher::Substring(ref s) => name.contains(s.as_str()),
+            PortMatcher::Glob(ref pattern) => glob_match(pattern, name),
+            #[cfg(feature = "regex")]
+            PortMatcher::Regex(ref re) => re.is_match(name),
+        }
+    }
+}
+
+impl From<&str> for PortMatcher {
+    /// Creates a `PortMatcher::Substring` from a string slice.
+    fn from(s: &str) -> Self {
+        PortMatcher::Substring(s.to_string())
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<regex::Regex> for PortMatcher {
+    fn from(re: regex::Regex) -> Self {
+        PortMatcher::Regex(re)
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` swallow one more character and try again
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "Midi Through:Midi Through Port-0 14:0"));
+        assert!(glob_match(
+            "Midi Through:*",
+            "Midi Through:Midi Through Port-0 14:0"
+        ));
+        assert!(glob_match(
+            "*Port-? 14:0",
+            "Midi Through:Midi Through Port-0 14:0"
+        ));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(!glob_match("Port-?", "Port-10"));
+        assert!(!glob_match("", "x"));
+    }
+
+    #[test]
+    fn test_port_matcher() {
+        let name = "FLUID Synth (1234):Synth input port (1234:0) 128:0";
+        assert!(PortMatcher::from("FLUID").matches(name));
+        assert!(!PortMatcher::Exact("FLUID".to_string()).matches(name));
+        assert!(PortMatcher::Glob("FLUID Synth*:Synth input*".to_string()).matches(name));
+    }
+}
@@ -0,0 +1,30 @@
+//! Functionality that is specific to the in-process `dummy` backend, which is
+//! enabled by the `dummy` feature. It is only used by `MidiInput::new` and
+//! `MidiOutput::new` if no other backend is available, so tests should select it with
+//! `MidiInput::with_backend(Backend::Dummy, ...)` or `MIDIR_BACKEND=dummy`.
+//!
+//! All `MidiInput` and `MidiOutput` objects of the process share one graph of ports,
+//! so that tests can create virtual ports and connect to them without any MIDI
+//! system being available. Messages are delivered synchronously: when `send` returns,
+//! the callbacks of all receiving connections have been called on the sending thread.
+//! Because of this, a callback must not send messages that are routed back to itself.
+//!
+//! As tests usually run in parallel, each test should use its own port names.
+
+use crate::backend;
+
+/// Removes the port with the given id (as returned by `MidiInputPort::id` or
+/// `MidiOutputPort::id`), as if its device had been unplugged. Connections to the port
+/// stop receiving messages, and sending to it fails. Returns whether the port existed.
+pub fn disconnect_port(id: &str) -> bool {
+    backend::dummy::disconnect_port(id)
+}
+
+/// Trait that is implemented by `MidiInputConnection` when using the `dummy` backend.
+pub trait MidiInputConnectionExt {
+    /// Use the given value (in microseconds) as the timestamp of all messages that this
+    /// connection receives afterwards, or the elapsed time since the backend was first
+    /// used if it is `None`. Other connections are not affected, and neither are
+    /// connections that do not use the `dummy` backend.
+    fn set_fixed_timestamp(&self, timestamp: Option<u64>);
+}
@@ -0,0 +1,39 @@
+//! Functionality that is specific to the ipMIDI backend, which sends raw MIDI bytes
+//! over UDP multicast, one port per UDP port number of a multicast group.
+//!
+//! The backend has no ports until they are configured with `add_port`. The configuration
+//! is shared by all `MidiInput` and `MidiOutput` objects of the process. ipMIDI itself
+//! uses the group `DEFAULT_GROUP`, with port 1 on `DEFAULT_PORT`, port 2 on the UDP port
+//! after it and so on.
+//!
+//! Multicast datagrams are looped back, so inputs also receive what is sent by
+//! outputs of the same host (including this process) that use the same port.
+
+use std::net::Ipv4Addr;
+
+use crate::backend;
+
+/// The multicast group that is used by ipMIDI.
+pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(225, 0, 0, 37);
+
+/// The UDP port number of the first ipMIDI port.
+pub const DEFAULT_PORT: u16 = 21928;
+
+/// Adds a port for the given UDP port number on the multicast group, which is listed
+/// under `name` by `ports`. An existing port with the same group and number is replaced.
+pub fn add_port(name: &str, group: Ipv4Addr, port: u16) {
+    backend::ipmidi::add_port(name, group, port)
+}
+
+/// Removes a port that has been added with `add_port`. Connections to the
+/// port are not affected. Returns whether the port had been added.
+pub fn remove_port(group: Ipv4Addr, port: u16) -> bool {
+    backend::ipmidi::remove_port(group, port)
+}
+
+/// Set the address of the network interface that connections created afterwards use to
+/// join groups and to send datagrams. By default (`Ipv4Addr::UNSPECIFIED`), the system
+/// chooses the interface; use `Ipv4Addr::LOCALHOST` to stay on this host.
+pub fn set_interface(interface: Ipv4Addr) {
+    backend::ipmidi::set_interface(interface)
+}
@@ -0,0 +1,298 @@
+//! Functionality that is specific to the JACK backend.
+//!
+//! The traits of this module are implemented whenever the JACK backend is compiled in.
+//! Methods that access the JACK objects behind a `MidiInput`, `MidiOutput`, port or
+//! connection return `None` (or do nothing) if that object uses another backend
+//! (see `MidiInput::backend`).
+//!
+//! `port_name` returns the full port name (`client:port`), which also serves as the port `id`.
+//! The pretty names and aliases that ports may have (as set by PipeWire and `a2jmidid`, for
+//! example) can be read with `port_pretty_name` and `port_aliases`. Devices are named after
+//! the pretty name of their client if it has one, falling back to the client name.
+
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+
+pub use jack_sys::{jack_client_t, jack_nframes_t};
+
+use crate::errors::{ConnectError, InitError};
+
+/// Options for opening the JACK client of a `MidiInput` or `MidiOutput`,
+/// see `MidiInputExt::with_server_options`.
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    pub(crate) server_name: Option<String>,
+    pub(crate) start_server: bool,
+}
+
+impl ServerOptions {
+    /// Creates the options that are used by `MidiInput::new` and `MidiOutput::new`:
+    /// connect to the default server, without starting it.
+    pub fn new() -> ServerOptions {
+        ServerOptions::default()
+    }
+
+    /// Set the name of the server to connect to, instead of the default server
+    /// (which can also be chosen with the `JACK_DEFAULT_SERVER` environment variable).
+    pub fn server_name(mut self, name: &str) -> ServerOptions {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    /// Whether the server should be started if it is not running yet.
+    pub fn start_server(mut self, start_server: bool) -> ServerOptions {
+        self.start_server = start_server;
+        self
+    }
+}
+
+/// An error that can occur when opening a JACK client with `with_server_options`.
+///
+/// `MidiInput::new`, `MidiOutput::new` and their `with_backend` variants only return an
+/// `InitError` without the reason, so `with_server_options` is the only way to find out
+/// whether the server is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OpenError {
+    /// The server is not running (and has not been started).
+    ServerNotRunning,
+    /// The client could not be opened for another reason.
+    Failed,
+}
+
+impl Error for OpenError {}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            OpenError::ServerNotRunning => "the JACK server is not running".fmt(f),
+            OpenError::Failed => "the JACK client could not be opened".fmt(f),
+        }
+    }
+}
+
+impl From<OpenError> for InitError {
+    fn from(_: OpenError) -> InitError {
+        InitError
+    }
+}
+
+/// The metadata key of the pretty name of a port or client.
+pub const METADATA_PRETTY_NAME: &str = "http://jackaudio.org/metadata/pretty-name";
+
+/// The metadata key of the hardware device that a port belongs to.
+pub const METADATA_HARDWARE: &str = "http://jackaudio.org/metadata/hardware";
+
+/// An error that can occur when changing the pretty name or the aliases of a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PortOptionError {
+    /// The connection does not own a port, which is the case for the sending half of a
+    /// duplex connection (use the receiving half instead).
+    NoPort,
+    /// The connection does not use the JACK backend.
+    OtherBackend,
+    /// The JACK server refused the change, e.g. because the port already has two aliases.
+    Failed,
+}
+
+impl Error for PortOptionError {}
+
+impl fmt::Display for PortOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PortOptionError::NoPort => "the connection does not own a JACK port".fmt(f),
+            PortOptionError::OtherBackend => "the connection does not use JACK".fmt(f),
+            PortOptionError::Failed => "the JACK port could not be changed".fmt(f),
+        }
+    }
+}
+
+/// A change of the state of the JACK server that a connection is using.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JackEvent {
+    /// The server has shut down or has disconnected the client. The connection does not
+    /// work anymore and should be closed (a new one can be created once the server is back).
+    Shutdown,
+    /// The server could not process a cycle in time, so MIDI events may have been lost.
+    Xrun,
+    /// The sample rate of the server has changed to the given value.
+    SampleRateChanged(u32),
+    /// The buffer size of the server has changed to the given number of frames.
+    BufferSizeChanged(u32),
+}
+
+/// Trait that is implemented by `MidiInput` when using the JACK backend,
+/// giving access to the underlying JACK client.
+pub trait MidiInputExt {
+    /// Creates a `MidiInput` object from an existing JACK client.
+    ///
+    /// # Safety
+    /// `client` must be a valid client that has been opened with `jack_client_open`
+    /// and that has not been activated. The returned object takes ownership of
+    /// the client and closes it when it is dropped.
+    unsafe fn from_jack_client(client: *mut jack_client_t) -> Self
+    where
+        Self: Sized;
+
+    /// Creates a `MidiInput` object with a JACK client that is opened with the given
+    /// options. In contrast to `MidiInput::new`, the reason of a failure is reported,
+    /// e.g. `OpenError::ServerNotRunning`; use `ServerOptions::new()` for the
+    /// options that `MidiInput::new` uses.
+    fn with_server_options(client_name: &str, options: &ServerOptions) -> Result<Self, OpenError>
+    where
+        Self: Sized;
+
+    /// Get the JACK client that is used by this `MidiInput` object.
+    /// The client is owned by this object and must not be closed.
+    fn jack_client(&self) -> Option<*mut jack_client_t>;
+
+    /// Connect to a port like `MidiInput::connect`, additionally passing the JACK frame
+    /// time at which each message has been received to the callback (as its second
+    /// parameter), for rendering it sample-accurately. The timestamp is the same point
+    /// in time in microseconds, in the time base of `jack_get_time`.
+    ///
+    /// An error is returned if this object does not use JACK.
+    fn connect_with_frame_time<F, T: Send + 'static>(
+        self,
+        port: &crate::MidiInputPort,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<crate::MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, jack_nframes_t, &[u8], &mut T) + Send + 'static,
+        Self: Sized;
+
+    /// Get the aliases of a port (at most two), which often carry the name of the
+    /// hardware device, e.g. for the ports that bridge ALSA sequencer ports.
+    /// The list is empty if this object or the port does not use JACK.
+    fn port_aliases(&self, port: &crate::MidiInputPort) -> Vec<String>;
+
+    /// Get the value of a metadata property of a port, e.g. `METADATA_HARDWARE`.
+    /// Returns `None` if the port does not have the property (or does not use JACK).
+    fn port_metadata(&self, port: &crate::MidiInputPort, key: &str) -> Option<String>;
+
+    /// Get the pretty name of a port from its metadata, which patchbays show instead
+    /// of the port name. Returns `None` if the port does not have one.
+    fn port_pretty_name(&self, port: &crate::MidiInputPort) -> Option<String> {
+        self.port_metadata(port, METADATA_PRETTY_NAME)
+    }
+}
+
+/// Trait that is implemented by `MidiOutput` when using the JACK backend,
+/// giving access to the underlying JACK client.
+pub trait MidiOutputExt {
+    /// Creates a `MidiOutput` object from an existing JACK client.
+    ///
+    /// # Safety
+    /// `client` must be a valid client that has been opened with `jack_client_open`
+    /// and that has not been activated. The returned object takes ownership of
+    /// the client and closes it when it is dropped.
+    unsafe fn from_jack_client(client: *mut jack_client_t) -> Self
+    where
+        Self: Sized;
+
+    /// Creates a `MidiOutput` object with a JACK client that is opened with the given
+    /// options. In contrast to `MidiOutput::new`, the reason of a failure is reported,
+    /// e.g. `OpenError::ServerNotRunning`; use `ServerOptions::new()` for the
+    /// options that `MidiOutput::new` uses.
+    fn with_server_options(client_name: &str, options: &ServerOptions) -> Result<Self, OpenError>
+    where
+        Self: Sized;
+
+    /// Get the JACK client that is used by this `MidiOutput` object.
+    /// The client is owned by this object and must not be closed.
+    fn jack_client(&self) -> Option<*mut jack_client_t>;
+
+    /// Get the aliases of a port (at most two), which often carry the name of the
+    /// hardware device, e.g. for the ports that bridge ALSA sequencer ports.
+    /// The list is empty if this object or the port does not use JACK.
+    fn port_aliases(&self, port: &crate::MidiOutputPort) -> Vec<String>;
+
+    /// Get the value of a metadata property of a port, e.g. `METADATA_HARDWARE`.
+    /// Returns `None` if the port does not have the property (or does not use JACK).
+    fn port_metadata(&self, port: &crate::MidiOutputPort, key: &str) -> Option<String>;
+
+    /// Get the pretty name of a port from its metadata, which patchbays show instead
+    /// of the port name. Returns `None` if the port does not have one.
+    fn port_pretty_name(&self, port: &crate::MidiOutputPort) -> Option<String> {
+        self.port_metadata(port, METADATA_PRETTY_NAME)
+    }
+}
+
+/// Trait that is implemented by `MidiInputConnection` when using the JACK backend.
+pub trait MidiInputConnectionExt {
+    /// Set a callback that is called with the changes of the state of the JACK server,
+    /// replacing any previous one. It is called from a notification thread of JACK
+    /// and must not create or close connections.
+    ///
+    /// The two halves of a duplex connection share their callback. Nothing happens
+    /// if the connection does not use JACK.
+    fn on_jack_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(JackEvent) + Send + 'static;
+
+    /// Set the pretty name of the port that midir has registered for this connection,
+    /// which is shown by patchbays instead of the port name.
+    ///
+    /// For the receiving half of a duplex connection, the name is set on both ports
+    /// with the suffixes ` in` and ` out`, like the options of `create_virtual_duplex`.
+    fn set_port_pretty_name(&mut self, name: &str) -> Result<(), PortOptionError>;
+
+    /// Add an alias to the port that midir has registered for this connection.
+    /// A JACK port can have at most two aliases.
+    ///
+    /// For the receiving half of a duplex connection, the alias is added to both ports
+    /// with the suffixes ` in` and ` out`.
+    fn add_port_alias(&mut self, alias: &str) -> Result<(), PortOptionError>;
+}
+
+/// Trait that is implemented by `MidiOutputConnection` when using the JACK backend.
+pub trait MidiOutputConnectionExt {
+    /// Set a callback that is called with the changes of the state of the JACK server,
+    /// replacing any previous one. It is called from a notification thread of JACK
+    /// and must not create or close connections.
+    ///
+    /// The two halves of a duplex connection share their callback. Nothing happens
+    /// if the connection does not use JACK.
+    fn on_jack_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(JackEvent) + Send + 'static;
+
+    /// Set the pretty name of the port that midir has registered for this connection,
+    /// which is shown by patchbays instead of the port name.
+    fn set_port_pretty_name(&mut self, name: &str) -> Result<(), PortOptionError>;
+
+    /// Add an alias to the port that midir has registered for this connection.
+    /// A JACK port can have at most two aliases.
+    fn add_port_alias(&mut self, alias: &str) -> Result<(), PortOptionError>;
+}
+
+/// Trait that is implemented by `MidiInputPort` when using the JACK backend.
+pub trait MidiInputPortExt {
+    /// Creates a port object that refers to the JACK port with the given full
+    /// name (`client:port`). Whether the port actually exists is only checked
+    /// when connecting to it.
+    fn from_jack_name(name: &CStr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the full name (`client:port`) of the JACK port.
+    fn jack_name(&self) -> Option<&CStr>;
+}
+
+/// Trait that is implemented by `MidiOutputPort` when using the JACK backend.
+pub trait MidiOutputPortExt {
+    /// Creates a port object that refers to the JACK port with the given full
+    /// name (`client:port`). Whether the port actually exists is only checked
+    /// when connecting to it.
+    fn from_jack_name(name: &CStr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the full name (`client:port`) of the JACK port.
+    fn jack_name(&self) -> Option<&CStr>;
+}
@@ -0,0 +1,368 @@
+//! Functionality that is specific to the ALSA backend on Linux.
+//!
+//! The traits of this module are implemented whenever the ALSA backend is compiled in.
+//! Methods that access the ALSA objects behind a `MidiInput`, `MidiOutput`, port or
+//! connection return `None` if that object uses another backend (see `MidiInput::backend`).
+
+pub use alsa::seq::{Addr, PortCap, PortType};
+pub use alsa::Seq;
+
+use alsa::seq::PortInfo;
+
+use crate::{MidiIO, PortMatcher};
+
+pub mod patchbay;
+pub mod ump;
+
+/// The client number of the ALSA `System` client, which owns the
+/// timer and announcement ports.
+const SYSTEM_CLIENT: i32 = 0;
+
+/// A filter that decides which ALSA sequencer ports are listed by
+/// `MidiIOExt::ports_filtered`.
+///
+/// `PortFilter::new()` selects the same ports as `MidiInput::ports` and
+/// `MidiOutput::ports`, i.e. ports of type `MIDI_GENERIC`, `SYNTH` or
+/// `APPLICATION`. Use `PortFilter::all()` to get every port that the
+/// sequencer knows about, like `aconnect -l` does.
+#[derive(Debug, Clone)]
+pub struct PortFilter {
+    port_type: Option<PortType>,
+    capability: PortCap,
+    system: bool,
+    no_export: bool,
+    own_ports: bool,
+    hardware_only: bool,
+    name: Option<PortMatcher>,
+}
+
+impl PortFilter {
+    /// Creates a filter that selects the ports that are listed by default.
+    pub fn new() -> PortFilter {
+        PortFilter {
+            port_type: Some(PortType::MIDI_GENERIC | PortType::SYNTH | PortType::APPLICATION),
+            capability: PortCap::empty(),
+            system: true,
+            no_export: true,
+            own_ports: true,
+            hardware_only: false,
+            name: None,
+        }
+    }
+
+    /// Creates a filter that selects all ports, regardless of their type.
+    pub fn all() -> PortFilter {
+        PortFilter {
+            port_type: None,
+            ..PortFilter::new()
+        }
+    }
+
+    /// Only select ports that have at least one of the given type flags.
+    pub fn port_type(mut self, port_type: PortType) -> PortFilter {
+        self.port_type = Some(port_type);
+        self
+    }
+
+    /// Select ports regardless of their type.
+    pub fn any_port_type(mut self) -> PortFilter {
+        self.port_type = None;
+        self
+    }
+
+    /// Only select ports that have all of the given capabilities, in addition
+    /// to the ones that are required for the direction (e.g. `READ | SUBS_READ`
+    /// for input ports).
+    pub fn capability(mut self, capability: PortCap) -> PortFilter {
+        self.capability = capability;
+        self
+    }
+
+    /// Whether to select the ports of the ALSA `System` client.
+    pub fn system(mut self, include: bool) -> PortFilter {
+        self.system = include;
+        self
+    }
+
+    /// Whether to select ports that have the `NO_EXPORT` capability,
+    /// i.e. ports that their owner does not want to be routed by others.
+    pub fn no_export(mut self, include: bool) -> PortFilter {
+        self.no_export = include;
+        self
+    }
+
+    /// Whether to select the ports of the ALSA client that is used
+    /// by this `MidiInput` or `MidiOutput` object.
+    pub fn own_ports(mut self, include: bool) -> PortFilter {
+        self.own_ports = include;
+        self
+    }
+
+    /// Whether to only select ports of type `HARDWARE`.
+    pub fn hardware_only(mut self, hardware_only: bool) -> PortFilter {
+        self.hardware_only = hardware_only;
+        self
+    }
+
+    /// Only select ports whose name matches the given pattern. Names are matched
+    /// in the format of `MidiInput::port_name`, and ports without a name are not selected.
+    pub fn name(mut self, matcher: PortMatcher) -> PortFilter {
+        self.name = Some(matcher);
+        self
+    }
+
+    /// Checks the port, where `name` returns its name in the format of `port_name`
+    /// and is only called if the filter has a name pattern.
+    pub(crate) fn accepts<N>(&self, port: &PortInfo, own_client: Option<i32>, name: N) -> bool
+    where
+        N: FnOnce() -> Option<String>,
+    {
+        self.accepts_port(
+            port.get_client(),
+            port.get_type(),
+            port.get_capability(),
+            own_client,
+            name,
+        )
+    }
+
+    fn accepts_port<N>(
+        &self,
+        client: i32,
+        port_type: PortType,
+        capability: PortCap,
+        own_client: Option<i32>,
+        name: N,
+    ) -> bool
+    where
+        N: FnOnce() -> Option<String>,
+    {
+        let type_matches = match self.port_type {
+            Some(t) => port_type.intersects(t),
+            None => true,
+        };
+        let name_matches = match self.name {
+            Some(ref matcher) => name().is_some_and(|name| matcher.matches(&name)),
+            None => true,
+        };
+        type_matches
+            && capability.contains(self.capability)
+            && (self.system || client != SYSTEM_CLIENT)
+            && (self.no_export || !capability.contains(PortCap::NO_EXPORT))
+            && (self.own_ports || Some(client) != own_client)
+            && (!self.hardware_only || port_type.contains(PortType::HARDWARE))
+            && name_matches
+    }
+}
+
+impl Default for PortFilter {
+    fn default() -> Self {
+        PortFilter::new()
+    }
+}
+
+/// Trait that is implemented by `MidiInput` and `MidiOutput` when
+/// using the ALSA backend, providing additional ways to list ports.
+pub trait MidiIOExt: MidiIO {
+    /// Get a collection of all MIDI input or output ports that are selected
+    /// by the given filter.
+    fn ports_filtered(&self, filter: &PortFilter) -> Option<Vec<Self::Port>>;
+
+    /// Get the number of MIDI input or output ports that are selected
+    /// by the given filter.
+    fn port_count_filtered(&self, filter: &PortFilter) -> Option<usize>;
+}
+
+/// Trait that is implemented by `MidiInput` when using the ALSA backend,
+/// giving access to the underlying sequencer client.
+pub trait MidiInputExt {
+    /// Creates a `MidiInput` object from an existing sequencer client, which
+    /// must have been opened for input (i.e. with `Direction::Capture` or
+    /// without a direction).
+    fn from_seq(seq: Seq) -> Self
+    where
+        Self: Sized;
+
+    /// Calls `f` with the sequencer client that is used by this `MidiInput` object.
+    ///
+    /// The client can be shared with the connections of this object (e.g. the sending
+    /// half of a duplex port), so it is locked while `f` runs. Events that it receives are
+    /// read by the input handler thread of a connection, so `f` must not read any events.
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Seq) -> R;
+}
+
+/// Trait that is implemented by `MidiOutput` when using the ALSA backend,
+/// giving access to the underlying sequencer client.
+pub trait MidiOutputExt {
+    /// Creates a `MidiOutput` object from an existing sequencer client, which
+    /// must have been opened for output (i.e. with `Direction::Playback` or
+    /// without a direction).
+    fn from_seq(seq: Seq) -> Self
+    where
+        Self: Sized;
+
+    /// Calls `f` with the sequencer client that is used by this `MidiOutput` object,
+    /// which is locked while `f` runs (see `MidiInputExt::with_seq`).
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Seq) -> R;
+}
+
+/// Trait that is implemented by `MidiInputPort` when using the ALSA backend.
+pub trait MidiInputPortExt {
+    /// Creates a port object that refers to the sequencer port with the given
+    /// address. Whether the port actually exists is only checked when connecting to it.
+    fn from_alsa_addr(addr: Addr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the address of the sequencer port.
+    fn alsa_addr(&self) -> Option<Addr>;
+}
+
+/// Trait that is implemented by `MidiOutputPort` when using the ALSA backend.
+pub trait MidiOutputPortExt {
+    /// Creates a port object that refers to the sequencer port with the given
+    /// address. Whether the port actually exists is only checked when connecting to it.
+    fn from_alsa_addr(addr: Addr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the address of the sequencer port.
+    fn alsa_addr(&self) -> Option<Addr>;
+}
+
+/// Trait that is implemented by `MidiInputConnection` and `ClientInputConnection`
+/// when using the ALSA backend.
+pub trait MidiInputConnectionExt {
+    /// Get the address of the sequencer port that was created for this connection.
+    fn alsa_port(&self) -> Option<Addr>;
+}
+
+/// Trait that is implemented by `MidiOutputConnection` and `ClientOutputConnection`
+/// when using the ALSA backend.
+pub trait MidiOutputConnectionExt {
+    /// Get the address of the sequencer port that was created for this connection.
+    fn alsa_port(&self) -> Option<Addr>;
+
+    /// Calls `f` with the sequencer client that is used by this connection,
+    /// which is locked while `f` runs (see `MidiInputExt::with_seq`).
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Seq) -> R;
+}
+
+/// Trait that is implemented by `MidiClient` when using the ALSA backend,
+/// giving access to the underlying sequencer client.
+pub trait MidiClientExt {
+    /// Calls `f` with the sequencer client that owns all ports of this `MidiClient`,
+    /// which is locked while `f` runs (see `MidiInputExt::with_seq`).
+    fn with_seq<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Seq) -> R;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIDI_IN: PortCap = PortCap::READ.union(PortCap::SUBS_READ);
+
+    fn accepts(filter: &PortFilter, client: i32, port_type: PortType, capability: PortCap) -> bool {
+        filter.accepts_port(client, port_type, capability, Some(128), || {
+            Some(format!("Client {}:Port {}:0", client, client))
+        })
+    }
+
+    #[test]
+    fn test_port_type() {
+        let filter = PortFilter::new();
+        assert!(accepts(&filter, 20, PortType::MIDI_GENERIC, MIDI_IN));
+        assert!(!accepts(&filter, 20, PortType::SPECIFIC, MIDI_IN));
+        assert!(accepts(&PortFilter::all(), 20, PortType::SPECIFIC, MIDI_IN));
+        let synths = PortFilter::new().port_type(PortType::SYNTH);
+        assert!(!accepts(&synths, 20, PortType::MIDI_GENERIC, MIDI_IN));
+        assert!(accepts(&synths, 20, PortType::SYNTH, MIDI_IN));
+        let hardware = PortFilter::all().hardware_only(true);
+        assert!(!accepts(&hardware, 20, PortType::MIDI_GENERIC, MIDI_IN));
+        assert!(accepts(
+            &hardware,
+            20,
+            PortType::MIDI_GENERIC | PortType::HARDWARE,
+            MIDI_IN
+        ));
+    }
+
+    #[test]
+    fn test_include_exclude() {
+        let all = PortFilter::all();
+        assert!(accepts(&all, SYSTEM_CLIENT, PortType::empty(), MIDI_IN));
+        assert!(!accepts(
+            &all.clone().system(false),
+            SYSTEM_CLIENT,
+            PortType::empty(),
+            MIDI_IN
+        ));
+
+        let no_export = MIDI_IN | PortCap::NO_EXPORT;
+        assert!(accepts(&all, 20, PortType::empty(), no_export));
+        assert!(!accepts(
+            &all.clone().no_export(false),
+            20,
+            PortType::empty(),
+            no_export
+        ));
+
+        assert!(accepts(&all, 128, PortType::empty(), MIDI_IN));
+        assert!(!accepts(
+            &all.clone().own_ports(false),
+            128,
+            PortType::empty(),
+            MIDI_IN
+        ));
+        assert!(accepts(
+            &all.clone().own_ports(false),
+            129,
+            PortType::empty(),
+            MIDI_IN
+        ));
+
+        let duplex = all.clone().capability(PortCap::READ | PortCap::WRITE);
+        assert!(!accepts(&duplex, 20, PortType::empty(), MIDI_IN));
+        assert!(accepts(
+            &duplex,
+            20,
+            PortType::empty(),
+            MIDI_IN | PortCap::WRITE
+        ));
+    }
+
+    #[test]
+    fn test_name() {
+        let filter = PortFilter::all().name(PortMatcher::Glob("Client 2?:*".to_string()));
+        assert!(accepts(&filter, 20, PortType::empty(), MIDI_IN));
+        assert!(!accepts(&filter, 130, PortType::empty(), MIDI_IN));
+        let substring = PortFilter::all().name("Port 14".into());
+        assert!(accepts(&substring, 14, PortType::empty(), MIDI_IN));
+        assert!(!accepts(&substring, 20, PortType::empty(), MIDI_IN));
+        // Ports whose name cannot be determined are not selected
+        assert!(!substring.accepts_port(14, PortType::empty(), MIDI_IN, None, || None));
+        // The name is only determined if the filter needs it
+        assert!(
+            PortFilter::all().accepts_port(14, PortType::empty(), MIDI_IN, None, || {
+                panic!("name should not be needed")
+            })
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_name_regex() {
+        let re = regex::Regex::new(r"^Client \d+:Port 2\d:").unwrap();
+        let filter = PortFilter::all().name(re.into());
+        assert!(accepts(&filter, 21, PortType::empty(), MIDI_IN));
+        assert!(!accepts(&filter, 130, PortType::empty(), MIDI_IN));
+    }
+}
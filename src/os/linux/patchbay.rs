@@ -0,0 +1,229 @@
+//! Routing of arbitrary ALSA sequencer ports, similar to what `aconnect` does.
+//!
+//! Subscriptions made through a `Patchbay` connect two ports of any clients
+//! directly in the kernel, so events are routed without passing through
+//! this process. They stay in place after the `Patchbay` has been dropped,
+//! until they are removed again or one of the ports disappears.
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+
+use alsa::seq::{ClientIter, PortIter, PortSubscribe, PortSubscribeIter, QuerySubsType};
+use alsa::Seq;
+
+pub use alsa::seq::Addr;
+
+use super::PortFilter;
+use crate::backend::alsa::helpers;
+use crate::errors::{InitError, PortInfoError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An error that can occur when creating or removing a subscription.
+pub enum PatchbayError {
+    /// One of the ports does not exist.
+    InvalidPort,
+    /// The subscription already exists, or one of the ports is
+    /// exclusively subscribed to another port.
+    Busy,
+    /// The subscription to be removed does not exist.
+    NotSubscribed,
+    /// Any other error, with a description of what failed.
+    Other(&'static str),
+}
+
+impl Error for PatchbayError {}
+
+impl fmt::Display for PatchbayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PatchbayError::InvalidPort => "invalid port".fmt(f),
+            PatchbayError::Busy => "port is already subscribed".fmt(f),
+            PatchbayError::NotSubscribed => "ports are not subscribed".fmt(f),
+            PatchbayError::Other(msg) => msg.fmt(f),
+        }
+    }
+}
+
+/// Options that are used when creating a subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SubscribeOptions {
+    exclusive: bool,
+    timestamp_queue: Option<i32>,
+    real_time: bool,
+}
+
+impl SubscribeOptions {
+    /// Creates options for a non-exclusive subscription without timestamping.
+    pub fn new() -> SubscribeOptions {
+        SubscribeOptions::default()
+    }
+
+    /// Whether the subscription is exclusive, i.e. no other subscriptions
+    /// to the ports can be made until it is removed.
+    pub fn exclusive(mut self, exclusive: bool) -> SubscribeOptions {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Let the sequencer timestamp delivered events with the time of the given queue,
+    /// either in real time (`real_time == true`) or in ticks.
+    pub fn timestamp(mut self, queue: i32, real_time: bool) -> SubscribeOptions {
+        self.timestamp_queue = Some(queue);
+        self.real_time = real_time;
+        self
+    }
+
+    /// Returns whether the subscription is exclusive.
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    /// Returns the queue that is used to timestamp delivered events, if any.
+    pub fn timestamp_queue(&self) -> Option<i32> {
+        self.timestamp_queue
+    }
+
+    /// Returns whether delivered events are timestamped in real time instead of ticks.
+    pub fn is_real_time(&self) -> bool {
+        self.real_time
+    }
+
+    fn from_port_subscribe(sub: &PortSubscribe) -> SubscribeOptions {
+        SubscribeOptions {
+            exclusive: sub.get_exclusive(),
+            timestamp_queue: if sub.get_time_update() {
+                Some(sub.get_queue())
+            } else {
+                None
+            },
+            real_time: sub.get_time_real(),
+        }
+    }
+}
+
+/// An existing subscription from a sender port to a destination port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subscription {
+    /// The port that events are read from.
+    pub sender: Addr,
+    /// The port that events are delivered to.
+    pub dest: Addr,
+    /// The options the subscription was made with.
+    pub options: SubscribeOptions,
+}
+
+impl Subscription {
+    fn from_port_subscribe(sub: &PortSubscribe) -> Subscription {
+        Subscription {
+            sender: sub.get_sender(),
+            dest: sub.get_dest(),
+            options: SubscribeOptions::from_port_subscribe(sub),
+        }
+    }
+}
+
+/// Lists, creates and removes subscriptions between any two ALSA sequencer ports.
+///
+/// A `Patchbay` opens its own sequencer client, which does not have any ports.
+pub struct Patchbay {
+    seq: Seq,
+}
+
+impl Patchbay {
+    /// Creates a new `Patchbay`, opening a sequencer client with the given name.
+    pub fn new(client_name: &str) -> Result<Self, InitError> {
+        let seq = Seq::open(None, None, true).map_err(|_| InitError)?;
+        let c_client_name = CString::new(client_name).map_err(|_| InitError)?;
+        seq.set_client_name(&c_client_name).map_err(|_| InitError)?;
+        Ok(Patchbay { seq })
+    }
+
+    /// Get the addresses of all ports that are selected by the given filter.
+    pub fn ports(&self, filter: &PortFilter) -> Vec<Addr> {
+        let own_client = self.seq.client_id().ok();
+        ClientIter::new(&self.seq)
+            .flat_map(|c| PortIter::new(&self.seq, c.get_client()))
+            .filter(|p| {
+                filter.accepts(p, own_client, || {
+                    helpers::get_port_name(&self.seq, p.addr()).ok()
+                })
+            })
+            .map(|p| p.addr())
+            .collect()
+    }
+
+    /// Get the name of the port with the given address, in the same format as
+    /// `MidiInput::port_name` and `MidiOutput::port_name`.
+    pub fn port_name(&self, port: Addr) -> Result<String, PortInfoError> {
+        helpers::get_port_name(&self.seq, port)
+    }
+
+    /// Get all subscriptions that deliver events read from the given port.
+    pub fn subscribers(&self, sender: Addr) -> Vec<Subscription> {
+        PortSubscribeIter::new(&self.seq, sender, QuerySubsType::READ)
+            .map(|sub| Subscription::from_port_subscribe(&sub))
+            .collect()
+    }
+
+    /// Get all subscriptions that deliver events to the given port.
+    pub fn senders(&self, dest: Addr) -> Vec<Subscription> {
+        PortSubscribeIter::new(&self.seq, dest, QuerySubsType::WRITE)
+            .map(|sub| Subscription::from_port_subscribe(&sub))
+            .collect()
+    }
+
+    /// Get all subscriptions between any two ports.
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.ports(&PortFilter::all())
+            .into_iter()
+            .flat_map(|port| self.subscribers(port))
+            .collect()
+    }
+
+    /// Subscribe the destination port to the sender port, so that all events
+    /// read from `sender` are delivered to `dest`.
+    pub fn subscribe(
+        &self,
+        sender: Addr,
+        dest: Addr,
+        options: &SubscribeOptions,
+    ) -> Result<(), PatchbayError> {
+        if self.seq.get_any_port_info(sender).is_err() || self.seq.get_any_port_info(dest).is_err()
+        {
+            return Err(PatchbayError::InvalidPort);
+        }
+
+        let sub = PortSubscribe::empty()
+            .map_err(|_| PatchbayError::Other("could not allocate ALSA subscription"))?;
+        sub.set_sender(sender);
+        sub.set_dest(dest);
+        sub.set_exclusive(options.exclusive);
+        if let Some(queue) = options.timestamp_queue {
+            sub.set_queue(queue);
+            sub.set_time_update(true);
+            sub.set_time_real(options.real_time);
+        }
+
+        self.seq.subscribe_port(&sub).map_err(|e| match e.errno() {
+            libc::EBUSY => PatchbayError::Busy,
+            libc::EINVAL | libc::ENOENT | libc::ENXIO => PatchbayError::InvalidPort,
+            _ => PatchbayError::Other("could not create ALSA subscription"),
+        })
+    }
+
+    /// Remove the subscription from the sender port to the destination port.
+    pub fn unsubscribe(&self, sender: Addr, dest: Addr) -> Result<(), PatchbayError> {
+        if self.seq.get_any_port_info(sender).is_err() || self.seq.get_any_port_info(dest).is_err()
+        {
+            return Err(PatchbayError::InvalidPort);
+        }
+
+        self.seq
+            .unsubscribe_port(sender, dest)
+            .map_err(|e| match e.errno() {
+                libc::ENOENT => PatchbayError::NotSubscribed,
+                _ => PatchbayError::Other("could not remove ALSA subscription"),
+            })
+    }
+}
@@ -0,0 +1,601 @@
+//! The MIDI 2.0 mode of the ALSA backend, which exchanges Universal MIDI Packets (UMP)
+//! with other sequencer clients.
+//!
+//! A `UmpInput` or `UmpOutput` opens a sequencer client in UMP mode, which requires
+//! Linux 6.5 and alsa-lib 1.2.10 or newer (see `is_supported`). Packets are passed as
+//! slices of 32-bit words, and the protocol of the client decides whether channel voice
+//! messages are MIDI 1.0 (message type 2) or MIDI 2.0 (message type 4) packets.
+//!
+//! The sequencer translates between the protocols automatically: events of legacy
+//! MIDI 1.0 clients (e.g. `MidiOutput` connections) arrive as packets of the client's
+//! protocol, and packets that are sent to legacy clients or to UMP clients of the other
+//! protocol are converted by the kernel.
+//!
+//! The timestamp of a received packet is the time at which the input handler thread read
+//! it, in microseconds since the connection was made. The sequencer does not schedule the
+//! events on a queue, so they do not carry a timestamp of their own.
+
+use std::ffi::CString;
+use std::mem;
+use std::sync::Arc;
+use std::thread::{Builder, JoinHandle};
+use std::time::Instant;
+
+pub use alsa::seq::Addr;
+use alsa::seq::PortCap;
+
+use crate::backend::alsa::ump::{self, UmpSeq};
+use crate::errors::*;
+
+pub use crate::ump::Protocol;
+
+fn midi_version(protocol: Protocol) -> i32 {
+    match protocol {
+        Protocol::Midi1 => ump::CLIENT_UMP_MIDI_1_0,
+        Protocol::Midi2 => ump::CLIENT_UMP_MIDI_2_0,
+    }
+}
+
+fn endpoint_protocol(protocol: Protocol) -> u32 {
+    match protocol {
+        Protocol::Midi1 => ump::EP_PROTO_MIDI1,
+        Protocol::Midi2 => ump::EP_PROTO_MIDI2,
+    }
+}
+
+/// Returns whether alsa-lib supports UMP clients. Opening a client can still fail
+/// if the kernel does not support them.
+pub fn is_supported() -> bool {
+    ump::lib().is_some()
+}
+
+/// A sequencer client that is a UMP endpoint, e.g. a USB MIDI 2.0 device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// The number of the sequencer client. Its port 0 represents the whole endpoint,
+    /// and the ports of its groups are numbered from 1.
+    pub client: i32,
+    /// The name of the endpoint.
+    pub name: String,
+    /// The protocol that the endpoint currently uses.
+    pub protocol: Option<Protocol>,
+    /// Whether the endpoint can use the MIDI 1.0 protocol.
+    pub supports_midi1: bool,
+    /// Whether the endpoint can use the MIDI 2.0 protocol.
+    pub supports_midi2: bool,
+}
+
+impl Endpoint {
+    fn from_info(client: i32, info: ump::EndpointInfo) -> Endpoint {
+        Endpoint {
+            client,
+            name: info.name,
+            protocol: if info.protocol & ump::EP_PROTO_MIDI2 != 0 {
+                Some(Protocol::Midi2)
+            } else if info.protocol & ump::EP_PROTO_MIDI1 != 0 {
+                Some(Protocol::Midi1)
+            } else {
+                None
+            },
+            supports_midi1: info.protocol_caps & ump::EP_PROTO_MIDI1 != 0,
+            supports_midi2: info.protocol_caps & ump::EP_PROTO_MIDI2 != 0,
+        }
+    }
+}
+
+/// The direction of a function block, as seen from the endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockDirection {
+    /// The block receives messages.
+    Input,
+    /// The block sends messages.
+    Output,
+    /// The block receives and sends messages.
+    Bidirectional,
+}
+
+/// A function block of a UMP endpoint, which is a range of its groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionBlock {
+    /// The number of the block.
+    pub id: u32,
+    /// The name of the block.
+    pub name: String,
+    /// Whether the block is currently in use.
+    pub active: bool,
+    /// The direction of the block.
+    pub direction: BlockDirection,
+    /// The first group of the block, counted from 0.
+    pub first_group: u8,
+    /// The number of groups of the block.
+    pub num_groups: u8,
+}
+
+impl FunctionBlock {
+    fn from_info(info: ump::BlockInfo) -> FunctionBlock {
+        FunctionBlock {
+            id: info.id,
+            name: info.name,
+            active: info.active,
+            direction: match info.direction {
+                ump::DIR_INPUT => BlockDirection::Input,
+                ump::DIR_OUTPUT => BlockDirection::Output,
+                _ => BlockDirection::Bidirectional,
+            },
+            first_group: info.first_group as u8,
+            num_groups: info.num_groups as u8,
+        }
+    }
+}
+
+fn endpoints(seq: &UmpSeq) -> Vec<Endpoint> {
+    seq.endpoints()
+        .into_iter()
+        .map(|(client, info)| Endpoint::from_info(client, info))
+        .collect()
+}
+
+fn function_blocks(seq: &UmpSeq, client: i32) -> Vec<FunctionBlock> {
+    seq.blocks(client)
+        .into_iter()
+        .map(FunctionBlock::from_info)
+        .collect()
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u32], &mut T) + Send>;
+
+type HandlerThread<T> = JoinHandle<(Callback<T>, T)>;
+
+/// The receiving and the sending half of a virtual endpoint.
+pub type EndpointConnection<T> = (UmpInputConnection<T>, UmpOutputConnection);
+
+/// An object for receiving Universal MIDI Packets, the counterpart
+/// of `MidiInput` for sequencer clients in UMP mode.
+pub struct UmpInput {
+    seq: Arc<UmpSeq>,
+    protocol: Protocol,
+}
+
+impl UmpInput {
+    /// Opens a sequencer client in UMP mode that receives packets of the given protocol.
+    pub fn new(client_name: &str, protocol: Protocol) -> Result<Self, InitError> {
+        Ok(UmpInput {
+            seq: Arc::new(UmpSeq::open(client_name, midi_version(protocol))?),
+            protocol,
+        })
+    }
+
+    /// Get the protocol of the packets that are received.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Get the addresses of all ports of other clients that packets can be received from,
+    /// including the ports of legacy clients.
+    pub fn ports(&self) -> Vec<Addr> {
+        self.seq.ports(PortCap::READ | PortCap::SUBS_READ)
+    }
+
+    /// Get the name of a port, in the same format as `MidiInput::port_name`.
+    pub fn port_name(&self, port: Addr) -> Result<String, PortInfoError> {
+        self.seq.port_name(port)
+    }
+
+    /// Get all clients that are UMP endpoints.
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        endpoints(&self.seq)
+    }
+
+    /// Get the function blocks of the UMP endpoint with the given client number.
+    pub fn function_blocks(&self, client: i32) -> Vec<FunctionBlock> {
+        function_blocks(&self.seq, client)
+    }
+
+    /// Connect to the given port and call `callback` with every packet that is received,
+    /// with the number of microseconds since the connection was made as its timestamp.
+    pub fn connect<F, T: Send + 'static>(
+        self,
+        port: Addr,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<UmpInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u32], &mut T) + Send + 'static,
+    {
+        let c_port_name = match CString::new(port_name) {
+            Ok(c_port_name) => c_port_name,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "port_name must not contain null bytes",
+                    self,
+                ))
+            }
+        };
+        let vport = match self
+            .seq
+            .create_port(&c_port_name, PortCap::WRITE | PortCap::SUBS_WRITE)
+        {
+            Ok(vport) => vport,
+            Err(()) => {
+                return Err(ConnectError::other(
+                    "could not create ALSA input port",
+                    self,
+                ))
+            }
+        };
+        if self.seq.connect_from(vport, port).is_err() {
+            self.seq.delete_port(vport);
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self));
+        }
+
+        self.start(Some(vport), Some(port), Box::new(callback), data)
+            .map_err(|err| {
+                let err = err.into_inner();
+                err.seq.disconnect_from(vport, port);
+                err.seq.delete_port(vport);
+                ConnectError::other("could not start ALSA input handler thread", err)
+            })
+    }
+
+    /// Create a port that other clients can connect to and call `callback`
+    /// with every packet that is received.
+    pub fn create_virtual<F, T: Send + 'static>(
+        self,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<UmpInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u32], &mut T) + Send + 'static,
+    {
+        let c_port_name = match CString::new(port_name) {
+            Ok(c_port_name) => c_port_name,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "port_name must not contain null bytes",
+                    self,
+                ))
+            }
+        };
+        let vport = match self
+            .seq
+            .create_port(&c_port_name, PortCap::WRITE | PortCap::SUBS_WRITE)
+        {
+            Ok(vport) => vport,
+            Err(()) => {
+                return Err(ConnectError::other(
+                    "could not create ALSA input port",
+                    self,
+                ))
+            }
+        };
+
+        self.start(Some(vport), None, Box::new(callback), data)
+            .map_err(|err| {
+                let err = err.into_inner();
+                err.seq.delete_port(vport);
+                ConnectError::other("could not start ALSA input handler thread", err)
+            })
+    }
+
+    /// Turn the client into a UMP endpoint with the given number of groups (1 to 16)
+    /// and one bidirectional function block that spans all of them.
+    ///
+    /// `callback` is called with the packets that are sent to the endpoint or any of its
+    /// groups, and the returned `UmpOutputConnection` sends packets from the endpoint,
+    /// which the sequencer delivers to the subscribers of the port of their group.
+    /// Both use the client of this `UmpInput`, and closing the output connection returns
+    /// a `UmpOutput` for the same client. The endpoint exists until the client is closed,
+    /// i.e. until the `UmpInput` and that `UmpOutput` have been dropped.
+    pub fn create_virtual_endpoint<F, T: Send + 'static>(
+        self,
+        name: &str,
+        num_groups: u8,
+        callback: F,
+        data: T,
+    ) -> Result<EndpointConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u32], &mut T) + Send + 'static,
+    {
+        if !(1..=16).contains(&num_groups) {
+            return Err(ConnectError::other(
+                "a UMP endpoint must have 1 to 16 groups",
+                self,
+            ));
+        }
+        let c_name = match CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => return Err(ConnectError::other("name must not contain null bytes", self)),
+        };
+        if self
+            .seq
+            .create_endpoint(&c_name, endpoint_protocol(self.protocol), num_groups as u32)
+            .is_err()
+        {
+            return Err(ConnectError::other(
+                "could not create ALSA UMP endpoint",
+                self,
+            ));
+        }
+
+        let midi_out = UmpOutput {
+            seq: self.seq.clone(),
+            protocol: self.protocol,
+        };
+        let conn_in = self.start(None, None, Box::new(callback), data)?;
+        let conn_out = UmpOutputConnection {
+            midi_out: Some(midi_out),
+            vport: ump::ENDPOINT_PORT,
+            dest: None,
+            endpoint: true,
+        };
+        Ok((conn_in, conn_out))
+    }
+
+    /// Starts the input handler thread for events that are delivered to `vport`,
+    /// or to any port of the client if it is `None`.
+    fn start<T: Send + 'static>(
+        self,
+        vport: Option<i32>,
+        source: Option<Addr>,
+        callback: Callback<T>,
+        data: T,
+    ) -> Result<UmpInputConnection<T>, ConnectError<Self>> {
+        let mut trigger_fds = [-1, -1];
+        if unsafe { libc::pipe(trigger_fds.as_mut_ptr()) } == -1 {
+            return Err(ConnectError::other(
+                "could not create communication pipe for ALSA handler",
+                self,
+            ));
+        }
+
+        let seq = self.seq.clone();
+        let trigger_rcv_fd = trigger_fds[0];
+        let threadbuilder = Builder::new().name("midir ALSA UMP input handler".to_string());
+        let thread = threadbuilder.spawn(move || {
+            let start = Instant::now();
+            let mut callback = callback;
+            let mut data = data;
+            seq.read_events(trigger_rcv_fd, |ev| {
+                if vport.is_some_and(|port| port != ev.dest_port()) {
+                    return;
+                }
+                if let Some(packet) = ev.packet() {
+                    let timestamp = if cfg!(feature = "avoid_timestamping") {
+                        0
+                    } else {
+                        start.elapsed().as_micros() as u64
+                    };
+                    callback(timestamp, packet, &mut data);
+                }
+            });
+            unsafe { libc::close(trigger_rcv_fd) };
+            (callback, data)
+        });
+
+        match thread {
+            Ok(thread) => Ok(UmpInputConnection {
+                midi_in: Some(self),
+                thread: Some(thread),
+                vport,
+                source,
+                trigger_send_fd: trigger_fds[1],
+            }),
+            Err(_) => {
+                unsafe {
+                    libc::close(trigger_fds[0]);
+                    libc::close(trigger_fds[1]);
+                }
+                Err(ConnectError::other(
+                    "could not start ALSA input handler thread",
+                    self,
+                ))
+            }
+        }
+    }
+}
+
+/// Represents an open connection of a `UmpInput`.
+pub struct UmpInputConnection<T: 'static> {
+    midi_in: Option<UmpInput>,
+    thread: Option<HandlerThread<T>>,
+    vport: Option<i32>, // `None` for an endpoint, which receives through all of its ports
+    source: Option<Addr>,
+    trigger_send_fd: i32,
+}
+
+impl<T> UmpInputConnection<T> {
+    /// Get the address of the port that receives the packets, which is
+    /// the endpoint port for the connection of a virtual endpoint.
+    pub fn port(&self) -> Addr {
+        let seq = &self.midi_in.as_ref().unwrap().seq;
+        Addr {
+            client: seq.client_id(),
+            port: self.vport.unwrap_or(ump::ENDPOINT_PORT),
+        }
+    }
+
+    /// Closes the connection and returns the `UmpInput` and the user data.
+    pub fn close(mut self) -> (UmpInput, T) {
+        let data = self.close_internal();
+        (self.midi_in.take().unwrap(), data)
+    }
+
+    fn close_internal(&mut self) -> T {
+        // Request the thread to stop.
+        let _res = unsafe {
+            libc::write(
+                self.trigger_send_fd,
+                &false as *const bool as *const _,
+                mem::size_of::<bool>() as libc::size_t,
+            )
+        };
+        let (_, data) = match self.thread.take().unwrap().join() {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e),
+        };
+        unsafe { libc::close(self.trigger_send_fd) };
+
+        let seq = &self.midi_in.as_ref().unwrap().seq;
+        if let Some(vport) = self.vport {
+            if let Some(source) = self.source {
+                seq.disconnect_from(vport, source);
+            }
+            seq.delete_port(vport);
+        }
+        data
+    }
+}
+
+impl<T> Drop for UmpInputConnection<T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been closed
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+/// An object for sending Universal MIDI Packets, the counterpart
+/// of `MidiOutput` for sequencer clients in UMP mode.
+pub struct UmpOutput {
+    seq: Arc<UmpSeq>,
+    protocol: Protocol,
+}
+
+impl UmpOutput {
+    /// Opens a sequencer client in UMP mode that sends packets of the given protocol.
+    pub fn new(client_name: &str, protocol: Protocol) -> Result<Self, InitError> {
+        Ok(UmpOutput {
+            seq: Arc::new(UmpSeq::open(client_name, midi_version(protocol))?),
+            protocol,
+        })
+    }
+
+    /// Get the protocol of the packets that are sent.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Get the addresses of all ports of other clients that packets can be sent to,
+    /// including the ports of legacy clients.
+    pub fn ports(&self) -> Vec<Addr> {
+        self.seq.ports(PortCap::WRITE | PortCap::SUBS_WRITE)
+    }
+
+    /// Get the name of a port, in the same format as `MidiOutput::port_name`.
+    pub fn port_name(&self, port: Addr) -> Result<String, PortInfoError> {
+        self.seq.port_name(port)
+    }
+
+    /// Get all clients that are UMP endpoints.
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        endpoints(&self.seq)
+    }
+
+    /// Get the function blocks of the UMP endpoint with the given client number.
+    pub fn function_blocks(&self, client: i32) -> Vec<FunctionBlock> {
+        function_blocks(&self.seq, client)
+    }
+
+    /// Connect to the given port, so that packets can be sent to it.
+    pub fn connect(
+        self,
+        port: Addr,
+        port_name: &str,
+    ) -> Result<UmpOutputConnection, ConnectError<Self>> {
+        let vport = match self.create_port(port_name) {
+            Ok(vport) => vport,
+            Err(msg) => return Err(ConnectError::other(msg, self)),
+        };
+        if self.seq.connect_to(vport, port).is_err() {
+            self.seq.delete_port(vport);
+            return Err(ConnectError::new(ConnectErrorKind::InvalidPort, self));
+        }
+        Ok(UmpOutputConnection {
+            midi_out: Some(self),
+            vport,
+            dest: Some(port),
+            endpoint: false,
+        })
+    }
+
+    /// Create a port that other clients can connect to, so that they
+    /// receive the packets that are sent.
+    pub fn create_virtual(
+        self,
+        port_name: &str,
+    ) -> Result<UmpOutputConnection, ConnectError<Self>> {
+        let vport = match self.create_port(port_name) {
+            Ok(vport) => vport,
+            Err(msg) => return Err(ConnectError::other(msg, self)),
+        };
+        Ok(UmpOutputConnection {
+            midi_out: Some(self),
+            vport,
+            dest: None,
+            endpoint: false,
+        })
+    }
+
+    fn create_port(&self, port_name: &str) -> Result<i32, &'static str> {
+        let c_port_name =
+            CString::new(port_name).map_err(|_| "port_name must not contain null bytes")?;
+        self.seq
+            .create_port(&c_port_name, PortCap::READ | PortCap::SUBS_READ)
+            .map_err(|()| "could not create ALSA output port")
+    }
+}
+
+/// Represents an open connection of a `UmpOutput`.
+pub struct UmpOutputConnection {
+    midi_out: Option<UmpOutput>,
+    vport: i32,
+    dest: Option<Addr>,
+    endpoint: bool, // whether it sends from the endpoint port, see `create_virtual_endpoint`
+}
+
+impl UmpOutputConnection {
+    fn seq(&self) -> &UmpSeq {
+        &self.midi_out.as_ref().unwrap().seq
+    }
+
+    /// Get the address of the port that the packets are sent from.
+    pub fn port(&self) -> Addr {
+        Addr {
+            client: self.seq().client_id(),
+            port: self.vport,
+        }
+    }
+
+    /// Send one or more complete packets, given as consecutive 32-bit words.
+    pub fn send(&mut self, packets: &[u32]) -> Result<(), SendError> {
+        self.seq().send_packets(self.vport, packets)
+    }
+
+    /// Closes the connection and returns the `UmpOutput`.
+    pub fn close(mut self) -> UmpOutput {
+        self.close_internal();
+        self.midi_out.take().unwrap()
+    }
+
+    fn close_internal(&mut self) {
+        // The ports of an endpoint exist until its client is closed
+        if self.endpoint {
+            return;
+        }
+        if let Some(dest) = self.dest {
+            self.seq().disconnect_to(self.vport, dest);
+        }
+        self.seq().delete_port(self.vport);
+    }
+}
+
+impl Drop for UmpOutputConnection {
+    fn drop(&mut self) {
+        if self.midi_out.is_some() {
+            self.close_internal();
+        }
+    }
+}
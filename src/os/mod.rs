@@ -1,2 +1,20 @@
 #[cfg(unix)]
 pub mod unix;
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+pub mod linux;
+
+#[cfg(all(feature = "jack", not(target_os = "windows")))]
+pub mod jack;
+
+#[cfg(feature = "dummy")]
+pub mod dummy;
+
+#[cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+pub mod rtpmidi;
+
+#[cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+pub mod ipmidi;
+
+#[cfg(all(unix, feature = "serial"))]
+pub mod serial;
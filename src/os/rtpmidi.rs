@@ -0,0 +1,74 @@
+//! Functionality that is specific to the RTP-MIDI backend, which exchanges MIDI with other
+//! computers through AppleMIDI network sessions (as used by macOS and iOS).
+//!
+//! Every session listens on two UDP ports: the control port, which is the address of the
+//! session, and the data port after it. A `MidiInputPort` or `MidiOutputPort` stands for a
+//! session; connecting to it creates a local session that invites the remote one. Creating
+//! a virtual port publishes a session that others can join (see
+//! `VirtualPortOptions::udp_port`), and what is sent through it reaches all participants.
+//!
+//! Sessions are not discovered automatically. The ports of the backend are the sessions
+//! that have been published by this process and those that have been added with
+//! `add_remote_session`; sessions at any other address can be used with
+//! `MidiInputPortExt::from_session_address`.
+//!
+//! The traits of this module are implemented whenever the RTP-MIDI backend is compiled in.
+//! Their `session_address` methods return `None` if the port or connection uses another
+//! backend (see `MidiInput::backend`).
+
+use std::net::SocketAddr;
+
+use crate::backend;
+
+/// The control port that is usually used by the first session of a host.
+pub const DEFAULT_PORT: u16 = 5004;
+
+/// Registers the remote session at the given address (of its control port) under a
+/// name, so that it is listed by `ports`. An existing entry for that address is replaced.
+pub fn add_remote_session(name: &str, address: SocketAddr) {
+    backend::rtpmidi::add_remote_session(name, address)
+}
+
+/// Removes a session that has been registered with `add_remote_session`.
+/// Returns whether it had been registered.
+pub fn remove_remote_session(address: SocketAddr) -> bool {
+    backend::rtpmidi::remove_remote_session(address)
+}
+
+/// Trait that is implemented by `MidiInputPort` when using the RTP-MIDI backend.
+pub trait MidiInputPortExt {
+    /// Creates a port object for the session at the given address (of its control
+    /// port). Whether the session exists is only checked when connecting to it.
+    fn from_session_address(name: &str, address: SocketAddr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the address of the control port of the session.
+    fn session_address(&self) -> Option<SocketAddr>;
+}
+
+/// Trait that is implemented by `MidiOutputPort` when using the RTP-MIDI backend.
+pub trait MidiOutputPortExt {
+    /// Creates a port object for the session at the given address (of its control
+    /// port). Whether the session exists is only checked when connecting to it.
+    fn from_session_address(name: &str, address: SocketAddr) -> Self
+    where
+        Self: Sized;
+
+    /// Get the address of the control port of the session.
+    fn session_address(&self) -> Option<SocketAddr>;
+}
+
+/// Trait that is implemented by `MidiInputConnection` when using the RTP-MIDI backend.
+pub trait MidiInputConnectionExt {
+    /// Get the local address of the control port of the connection's own session,
+    /// which is what other hosts have to invite in order to join a published session.
+    fn session_address(&self) -> Option<SocketAddr>;
+}
+
+/// Trait that is implemented by `MidiOutputConnection` when using the RTP-MIDI backend.
+pub trait MidiOutputConnectionExt {
+    /// Get the local address of the control port of the connection's own session,
+    /// which is what other hosts have to invite in order to join a published session.
+    fn session_address(&self) -> Option<SocketAddr>;
+}
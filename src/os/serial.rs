@@ -0,0 +1,29 @@
+//! Functionality that is specific to the serial backend, which talks MIDI over
+//! TTY devices such as UARTs or the USB CDC serial ports of microcontroller boards.
+//!
+//! The backend has no ports until they are configured with `add_port`. The configuration
+//! is shared by all `MidiInput` and `MidiOutput` objects of the process. Every port can be
+//! used for input and output at the same time.
+
+use std::path::Path;
+
+use crate::backend;
+
+/// The baud rate of the MIDI standard, used by UARTs that are wired to MIDI jacks.
+pub const MIDI_BAUD_RATE: u32 = 31250;
+
+/// The baud rate used by "Hairless MIDI" style serial bridges.
+pub const HAIRLESS_BAUD_RATE: u32 = 115200;
+
+/// Adds a port for the TTY device at `path` (e.g. `/dev/ttyACM0`), which is listed under
+/// `name` by `ports` and opened with the given baud rate. An existing port for the same
+/// path is replaced.
+pub fn add_port<P: AsRef<Path>>(name: &str, path: P, baud_rate: u32) {
+    backend::serial::add_port(name, path.as_ref().to_path_buf(), baud_rate)
+}
+
+/// Removes a port that has been added with `add_port`. Connections to the
+/// port are not affected. Returns whether the port had been added.
+pub fn remove_port<P: AsRef<Path>>(path: P) -> bool {
+    backend::serial::remove_port(path.as_ref().to_path_buf())
+}
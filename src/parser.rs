@@ -0,0 +1,214 @@
+use crate::Ignore;
+
+/// Returns whether a complete message should be dropped because of the ignore flags of an input.
+pub(crate) fn is_ignored(message: &[u8], flags: Ignore) -> bool {
+    match message.first() {
+        Some(0xF0) => flags.contains(Ignore::Sysex),
+        Some(0xF1) | Some(0xF8) | Some(0xF9) => flags.contains(Ignore::Time),
+        Some(0xFE) => flags.contains(Ignore::ActiveSense),
+        _ => false,
+    }
+}
+
+/// The size above which an unterminated SysEx message is dropped, so that a stream
+/// that never sends the end byte cannot make the buffer grow without limit.
+const MAX_SYSEX_LEN: usize = 1 << 20;
+
+/// Splits a stream of MIDI bytes, as it is received from a hardware port, into
+/// complete messages. Running status is expanded, so every message starts with a
+/// status byte, and real-time messages that are interleaved with other messages
+/// are reported on their own as soon as they are received.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct StreamParser {
+    message: Vec<u8>,
+    running_status: Option<u8>,
+    data_len: usize,
+    in_sysex: bool,
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+impl StreamParser {
+    pub fn new() -> StreamParser {
+        StreamParser {
+            message: Vec::new(),
+            running_status: None,
+            data_len: 0,
+            in_sysex: false,
+        }
+    }
+
+    /// Parses the given bytes and calls `callback` for every message that has been completed.
+    /// Incomplete messages are kept until the next call.
+    pub fn feed<F>(&mut self, bytes: &[u8], mut callback: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        for &byte in bytes {
+            self.push(byte, &mut callback);
+        }
+    }
+
+    fn push<F>(&mut self, byte: u8, callback: &mut F)
+    where
+        F: FnMut(&[u8]),
+    {
+        if byte >= 0xF8 {
+            // Real-time messages may appear anywhere, even inside of other messages
+            callback(&[byte]);
+            return;
+        }
+
+        if byte & 0x80 == 0 {
+            if self.in_sysex {
+                if self.message.len() < MAX_SYSEX_LEN {
+                    self.message.push(byte);
+                } else {
+                    // Drop the oversized message, the remaining data bytes are ignored
+                    self.message.clear();
+                    self.in_sysex = false;
+                }
+                return;
+            }
+            if self.message.is_empty() {
+                match self.running_status {
+                    Some(status) => self.start(status),
+                    None => return, // data byte without a status, drop it
+                }
+            }
+            self.message.push(byte);
+            if self.message.len() == 1 + self.data_len {
+                callback(&self.message);
+                self.message.clear();
+            }
+            return;
+        }
+
+        if byte == 0xF7 && self.in_sysex {
+            self.message.push(byte);
+            callback(&self.message);
+            self.message.clear();
+            self.in_sysex = false;
+            return;
+        }
+
+        // Any other status byte ends an unfinished message, which is dropped
+        self.message.clear();
+        self.in_sysex = false;
+        match byte {
+            0x80..=0xEF => {
+                self.running_status = Some(byte);
+                self.start(byte);
+            }
+            0xF0 => {
+                self.running_status = None;
+                self.in_sysex = true;
+                self.message.push(byte);
+            }
+            _ => {
+                // System common messages cancel running status
+                self.running_status = None;
+                match byte {
+                    0xF1..=0xF3 => self.start(byte),
+                    0xF6 => callback(&[byte]),
+                    _ => {} // undefined status or end of exclusive without a start
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, status: u8) {
+        self.message.push(status);
+        self.data_len = match status {
+            0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+            _ => 2,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut parser = StreamParser::new();
+        let mut messages = Vec::new();
+        parser.feed(bytes, |message| messages.push(message.to_vec()));
+        messages
+    }
+
+    #[test]
+    fn test_running_status() {
+        assert_eq!(
+            parse(&[0x90, 60, 100, 62, 100, 0xC0, 5, 6, 0x80, 60, 0]),
+            vec![
+                vec![0x90, 60, 100],
+                vec![0x90, 62, 100],
+                vec![0xC0, 5],
+                vec![0xC0, 6],
+                vec![0x80, 60, 0]
+            ]
+        );
+        // System common messages cancel running status
+        assert_eq!(
+            parse(&[0x90, 60, 100, 0xF3, 1, 62, 100]),
+            vec![vec![0x90, 60, 100], vec![0xF3, 1]]
+        );
+    }
+
+    #[test]
+    fn test_interleaved_messages() {
+        assert_eq!(
+            parse(&[0x90, 0xF8, 60, 0xFE, 100, 0xF0, 1, 0xF8, 2, 0xF7]),
+            vec![
+                vec![0xF8],
+                vec![0xFE],
+                vec![0x90, 60, 100],
+                vec![0xF8],
+                vec![0xF0, 1, 2, 0xF7]
+            ]
+        );
+        // Unfinished messages are dropped when another status byte arrives
+        assert_eq!(
+            parse(&[0xF0, 1, 2, 0x90, 60, 0xB0, 7, 127, 0xF6]),
+            vec![vec![0xB0, 7, 127], vec![0xF6]]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_sysex() {
+        let mut bytes = vec![0xF0];
+        bytes.resize(1 + MAX_SYSEX_LEN, 0x01);
+        bytes.extend([0xF7, 0xC0, 5]);
+        assert_eq!(parse(&bytes), vec![vec![0xC0, 5]]);
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let flags = Ignore::Time;
+        assert!(is_ignored(&[0xF8], flags));
+        assert!(is_ignored(&[0xF9], flags));
+        assert!(is_ignored(&[0xF1, 0x10], flags));
+        assert!(!is_ignored(&[0xFE], flags));
+        assert!(!is_ignored(&[0xF0, 0x7E, 0xF7], flags));
+        assert!(is_ignored(&[0xF0, 0x7E, 0xF7], Ignore::All));
+        assert!(!is_ignored(&[0x90, 60, 100], Ignore::All));
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let mut parser = StreamParser::new();
+        let mut messages = Vec::new();
+        for chunk in [
+            &[0xF0, 0x7E][..],
+            &[0x7F, 0x06],
+            &[0x01, 0xF7, 0xE0],
+            &[0, 64],
+        ] {
+            parser.feed(chunk, |message| messages.push(message.to_vec()));
+        }
+        assert_eq!(
+            messages,
+            vec![vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7], vec![0xE0, 0, 64]]
+        );
+    }
+}
@@ -0,0 +1,923 @@
+//! Reading and writing Standard MIDI Files (SMF) of format 0, 1 and 2.
+//!
+//! The MIDI messages of a track are stored in the same representation that is used for live
+//! messages: complete messages without running status, with system exclusive messages from
+//! `0xF0` to `0xF7`, so they can be passed to `MidiOutputConnection::send` directly and
+//! messages from a connection callback can be recorded as they are. A `TempoMap` converts
+//! between ticks and the microsecond timestamps of connection callbacks.
+#![deny(missing_docs)]
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The default tempo of a file without tempo events, in microseconds per quarter note.
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+/// An error that can occur when reading or writing a file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SmfError {
+    /// Reading from or writing to the underlying stream failed.
+    Io(io::Error),
+    /// The file, or the `Smf` that should be written, is invalid.
+    InvalidData(&'static str),
+}
+
+impl Error for SmfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SmfError::Io(ref err) => Some(err),
+            SmfError::InvalidData(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SmfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SmfError::Io(ref err) => write!(f, "I/O error: {}", err),
+            SmfError::InvalidData(msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for SmfError {
+    fn from(err: io::Error) -> Self {
+        SmfError::Io(err)
+    }
+}
+
+/// The format of a file, which decides how its tracks are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A single track (format 0).
+    SingleTrack,
+    /// Several tracks that are played at the same time (format 1). The tempo events of
+    /// all tracks apply to the whole file (they are usually in the first track).
+    Parallel,
+    /// Several independent tracks that are played one after the other (format 2),
+    /// each with its own tempo events.
+    Sequential,
+}
+
+/// The frame rate of a file with SMPTE timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fps {
+    /// 24 frames per second.
+    Fps24,
+    /// 25 frames per second.
+    Fps25,
+    /// 30 drop frame, i.e. 29.97 frames per second.
+    Fps30Drop,
+    /// 30 frames per second.
+    Fps30,
+}
+
+/// The unit of the delta times of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Ticks per quarter note, so the duration of a tick depends on the tempo.
+    Metrical(u16),
+    /// Ticks per SMPTE frame, so the duration of a tick does not depend on the tempo.
+    Timecode(Fps, u8),
+}
+
+impl Timing {
+    fn from_division(division: u16) -> Result<Timing, SmfError> {
+        if division & 0x8000 == 0 {
+            return Ok(Timing::Metrical(division));
+        }
+        let fps = match (division >> 8) as u8 as i8 {
+            -24 => Fps::Fps24,
+            -25 => Fps::Fps25,
+            -29 => Fps::Fps30Drop,
+            -30 => Fps::Fps30,
+            _ => return Err(SmfError::InvalidData("invalid SMPTE frame rate")),
+        };
+        Ok(Timing::Timecode(fps, division as u8))
+    }
+
+    fn division(self) -> u16 {
+        match self {
+            Timing::Metrical(ticks) => ticks,
+            Timing::Timecode(fps, ticks) => {
+                let fps: i8 = match fps {
+                    Fps::Fps24 => -24,
+                    Fps::Fps25 => -25,
+                    Fps::Fps30Drop => -29,
+                    Fps::Fps30 => -30,
+                };
+                (fps as u8 as u16) << 8 | ticks as u16
+            }
+        }
+    }
+}
+
+/// A meta event, which contains information that is not sent to MIDI devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetaEvent {
+    /// The number of the sequence (format 2) or of the file (formats 0 and 1).
+    SequenceNumber(u16),
+    /// Any text.
+    Text(Vec<u8>),
+    /// A copyright notice.
+    Copyright(Vec<u8>),
+    /// The name of the sequence (in the first track) or of the track.
+    TrackName(Vec<u8>),
+    /// The name of the instrument that is used in the track.
+    InstrumentName(Vec<u8>),
+    /// The lyrics of a syllable.
+    Lyric(Vec<u8>),
+    /// The name of a point in the sequence, e.g. a rehearsal letter.
+    Marker(Vec<u8>),
+    /// A description of something that happens on stage at this point.
+    CuePoint(Vec<u8>),
+    /// The name of the program (patch) that is used in the track.
+    ProgramName(Vec<u8>),
+    /// The name of the device that the track is played on.
+    DeviceName(Vec<u8>),
+    /// The channel that the following meta and system exclusive events are associated with.
+    ChannelPrefix(u8),
+    /// The port that the following events are played on.
+    Port(u8),
+    /// The end of the track, which must be its last event.
+    EndOfTrack,
+    /// A change of the tempo, in microseconds per quarter note.
+    Tempo(u32),
+    /// The SMPTE time at which the track starts.
+    SmpteOffset {
+        /// The hours, with the frame rate in the two most significant bits.
+        hours: u8,
+        /// The minutes.
+        minutes: u8,
+        /// The seconds.
+        seconds: u8,
+        /// The frames.
+        frames: u8,
+        /// The fractional frames, in 1/100 of a frame.
+        subframes: u8,
+    },
+    /// A change of the time signature.
+    TimeSignature {
+        /// The numerator.
+        numerator: u8,
+        /// The denominator as a power of two (e.g. 3 for eighth notes).
+        denominator: u8,
+        /// The number of MIDI clocks per metronome click.
+        clocks_per_click: u8,
+        /// The number of 32nd notes per quarter note (usually 8).
+        thirty_seconds_per_quarter: u8,
+    },
+    /// A change of the key signature.
+    KeySignature {
+        /// The number of sharps (positive) or flats (negative).
+        sharps: i8,
+        /// Whether the key is minor.
+        minor: bool,
+    },
+    /// Data for a specific sequencer.
+    SequencerSpecific(Vec<u8>),
+    /// A meta event of another type, or with an invalid length.
+    Unknown {
+        /// The type of the meta event.
+        kind: u8,
+        /// The data.
+        data: Vec<u8>,
+    },
+}
+
+impl MetaEvent {
+    fn parse(kind: u8, data: &[u8]) -> MetaEvent {
+        let text = || data.to_vec();
+        match (kind, data.len()) {
+            (0x00, 2) => MetaEvent::SequenceNumber(u16::from_be_bytes([data[0], data[1]])),
+            (0x01, _) => MetaEvent::Text(text()),
+            (0x02, _) => MetaEvent::Copyright(text()),
+            (0x03, _) => MetaEvent::TrackName(text()),
+            (0x04, _) => MetaEvent::InstrumentName(text()),
+            (0x05, _) => MetaEvent::Lyric(text()),
+            (0x06, _) => MetaEvent::Marker(text()),
+            (0x07, _) => MetaEvent::CuePoint(text()),
+            (0x08, _) => MetaEvent::ProgramName(text()),
+            (0x09, _) => MetaEvent::DeviceName(text()),
+            (0x20, 1) => MetaEvent::ChannelPrefix(data[0]),
+            (0x21, 1) => MetaEvent::Port(data[0]),
+            (0x2F, 0) => MetaEvent::EndOfTrack,
+            (0x51, 3) => MetaEvent::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]])),
+            (0x54, 5) => MetaEvent::SmpteOffset {
+                hours: data[0],
+                minutes: data[1],
+                seconds: data[2],
+                frames: data[3],
+                subframes: data[4],
+            },
+            (0x58, 4) => MetaEvent::TimeSignature {
+                numerator: data[0],
+                denominator: data[1],
+                clocks_per_click: data[2],
+                thirty_seconds_per_quarter: data[3],
+            },
+            (0x59, 2) => MetaEvent::KeySignature {
+                sharps: data[0] as i8,
+                minor: data[1] != 0,
+            },
+            (0x7F, _) => MetaEvent::SequencerSpecific(text()),
+            _ => MetaEvent::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    fn kind_and_data(&self) -> (u8, Vec<u8>) {
+        match *self {
+            MetaEvent::SequenceNumber(number) => (0x00, number.to_be_bytes().to_vec()),
+            MetaEvent::Text(ref text) => (0x01, text.clone()),
+            MetaEvent::Copyright(ref text) => (0x02, text.clone()),
+            MetaEvent::TrackName(ref text) => (0x03, text.clone()),
+            MetaEvent::InstrumentName(ref text) => (0x04, text.clone()),
+            MetaEvent::Lyric(ref text) => (0x05, text.clone()),
+            MetaEvent::Marker(ref text) => (0x06, text.clone()),
+            MetaEvent::CuePoint(ref text) => (0x07, text.clone()),
+            MetaEvent::ProgramName(ref text) => (0x08, text.clone()),
+            MetaEvent::DeviceName(ref text) => (0x09, text.clone()),
+            MetaEvent::ChannelPrefix(channel) => (0x20, vec![channel]),
+            MetaEvent::Port(port) => (0x21, vec![port]),
+            MetaEvent::EndOfTrack => (0x2F, Vec::new()),
+            MetaEvent::Tempo(tempo) => (0x51, tempo.to_be_bytes()[1..].to_vec()),
+            MetaEvent::SmpteOffset {
+                hours,
+                minutes,
+                seconds,
+                frames,
+                subframes,
+            } => (0x54, vec![hours, minutes, seconds, frames, subframes]),
+            MetaEvent::TimeSignature {
+                numerator,
+                denominator,
+                clocks_per_click,
+                thirty_seconds_per_quarter,
+            } => (
+                0x58,
+                vec![
+                    numerator,
+                    denominator,
+                    clocks_per_click,
+                    thirty_seconds_per_quarter,
+                ],
+            ),
+            MetaEvent::KeySignature { sharps, minor } => (0x59, vec![sharps as u8, minor as u8]),
+            MetaEvent::SequencerSpecific(ref data) => (0x7F, data.clone()),
+            MetaEvent::Unknown { kind, ref data } => (kind, data.clone()),
+        }
+    }
+}
+
+/// The content of an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// A complete MIDI message, in the representation of connection callbacks and
+    /// `MidiOutputConnection::send`.
+    ///
+    /// A system exclusive message that is split into several events starts with `0xF0`
+    /// but does not end with `0xF7`, and its other parts are `Escape` events. System common
+    /// and real-time messages cannot be stored as such and are written as `Escape` events.
+    Midi(Vec<u8>),
+    /// Bytes that are sent as they are (an `0xF7` event), e.g. the continuation of a split
+    /// system exclusive message or a real-time message.
+    Escape(Vec<u8>),
+    /// A meta event.
+    Meta(MetaEvent),
+}
+
+/// An event of a track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The number of ticks since the previous event of the track.
+    pub delta: u32,
+    /// The content of the event.
+    pub kind: EventKind,
+}
+
+/// The events of a track, which should end with `MetaEvent::EndOfTrack`.
+pub type Track = Vec<Event>;
+
+/// Returns the events of a track together with their absolute time in ticks.
+pub fn absolute_ticks(track: &[Event]) -> impl Iterator<Item = (u64, &Event)> {
+    track.iter().scan(0, |tick, event| {
+        *tick += event.delta as u64;
+        Some((*tick, event))
+    })
+}
+
+/// Builds a track from events with absolute times, e.g. while recording.
+#[derive(Debug, Clone, Default)]
+pub struct TrackBuilder {
+    events: Track,
+    tick: u64,
+}
+
+impl TrackBuilder {
+    /// Creates an empty track.
+    pub fn new() -> TrackBuilder {
+        TrackBuilder::default()
+    }
+
+    /// Appends an event at the given tick. An event that is earlier than the previous one
+    /// is moved to the time of the previous one.
+    pub fn push(&mut self, tick: u64, kind: EventKind) {
+        let tick = tick.max(self.tick);
+        // Delta times have at most 28 bits, so long pauses are filled with empty text events
+        while tick - self.tick > MAX_DELTA as u64 {
+            self.events.push(Event {
+                delta: MAX_DELTA,
+                kind: EventKind::Meta(MetaEvent::Text(Vec::new())),
+            });
+            self.tick += MAX_DELTA as u64;
+        }
+        self.events.push(Event {
+            delta: (tick - self.tick) as u32,
+            kind,
+        });
+        self.tick = tick;
+    }
+
+    /// Ends the track at the given tick (or at its last event, if that is later).
+    pub fn finish(mut self, tick: u64) -> Track {
+        self.push(tick, EventKind::Meta(MetaEvent::EndOfTrack));
+        self.events
+    }
+}
+
+const MAX_DELTA: u32 = 0x0FFF_FFFF;
+
+/// A Standard MIDI File.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Smf {
+    /// The format of the file.
+    pub format: Format,
+    /// The unit of the delta times.
+    pub timing: Timing,
+    /// The tracks.
+    pub tracks: Vec<Track>,
+}
+
+impl Smf {
+    /// Creates a file without tracks.
+    pub fn new(format: Format, timing: Timing) -> Smf {
+        Smf {
+            format,
+            timing,
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Parses a file. Chunks of unknown types are skipped.
+    pub fn parse(mut bytes: &[u8]) -> Result<Smf, SmfError> {
+        let (kind, header) = read_chunk(&mut bytes)?;
+        if kind != *b"MThd" || header.len() < 6 {
+            return Err(SmfError::InvalidData("missing file header"));
+        }
+        let format = match u16::from_be_bytes([header[0], header[1]]) {
+            0 => Format::SingleTrack,
+            1 => Format::Parallel,
+            2 => Format::Sequential,
+            _ => return Err(SmfError::InvalidData("unknown file format")),
+        };
+        let num_tracks = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let timing = Timing::from_division(u16::from_be_bytes([header[4], header[5]]))?;
+
+        let mut tracks = Vec::with_capacity(num_tracks);
+        while tracks.len() < num_tracks && !bytes.is_empty() {
+            let (kind, data) = read_chunk(&mut bytes)?;
+            if kind == *b"MTrk" {
+                tracks.push(parse_track(data)?);
+            }
+        }
+        if tracks.len() < num_tracks {
+            return Err(SmfError::InvalidData("missing track"));
+        }
+        Ok(Smf {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// Reads a file from `reader`.
+    pub fn read<R: Read>(mut reader: R) -> Result<Smf, SmfError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Smf::parse(&bytes)
+    }
+
+    /// Reads the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Smf, SmfError> {
+        Smf::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the file to `writer`, using running status for channel messages.
+    /// An end of track event is added to tracks that do not end with one.
+    /// Incomplete or malformed channel messages are rejected with `SmfError::InvalidData`.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), SmfError> {
+        if self.format == Format::SingleTrack && self.tracks.len() != 1 {
+            return Err(SmfError::InvalidData(
+                "a file of format 0 must have exactly one track",
+            ));
+        }
+        let num_tracks = u16::try_from(self.tracks.len())
+            .map_err(|_| SmfError::InvalidData("too many tracks"))?;
+        if matches!(self.timing, Timing::Metrical(ticks) if ticks >= 0x8000) {
+            return Err(SmfError::InvalidData(
+                "ticks per quarter note must be less than 0x8000",
+            ));
+        }
+        let format: u16 = match self.format {
+            Format::SingleTrack => 0,
+            Format::Parallel => 1,
+            Format::Sequential => 2,
+        };
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        writer.write_all(&format.to_be_bytes())?;
+        writer.write_all(&num_tracks.to_be_bytes())?;
+        writer.write_all(&self.timing.division().to_be_bytes())?;
+        for track in &self.tracks {
+            let data = write_track(track)?;
+            let len = u32::try_from(data.len())
+                .map_err(|_| SmfError::InvalidData("track is too long"))?;
+            writer.write_all(b"MTrk")?;
+            writer.write_all(&len.to_be_bytes())?;
+            writer.write_all(&data)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the file to a byte vector.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SmfError> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Writes the file to `path`, replacing an existing file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SmfError> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Returns the tempo map of the file, built from the tempo events of all tracks.
+    /// For files of format 2, use `TempoMap::new` with a single track instead.
+    pub fn tempo_map(&self) -> TempoMap {
+        TempoMap::new(self.timing, &self.tracks)
+    }
+
+    /// Returns the events of all tracks with their absolute time in ticks, ordered by time,
+    /// e.g. to play a file of format 1. Events at the same time are ordered by track.
+    pub fn merged(&self) -> Vec<(u64, &Event)> {
+        let mut events: Vec<_> = self
+            .tracks
+            .iter()
+            .flat_map(|track| absolute_ticks(track))
+            .collect();
+        events.sort_by_key(|&(tick, _)| tick); // stable, so the order of tracks is kept
+        events
+    }
+}
+
+fn read_chunk<'a>(bytes: &mut &'a [u8]) -> Result<([u8; 4], &'a [u8]), SmfError> {
+    if bytes.len() < 8 {
+        return Err(SmfError::InvalidData("incomplete chunk header"));
+    }
+    let kind = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    let len = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    let data = bytes
+        .get(8..8 + len)
+        .ok_or(SmfError::InvalidData("incomplete chunk"))?;
+    *bytes = &bytes[8 + len..];
+    Ok((kind, data))
+}
+
+/// Reads from the bytes of a track, keeping track of the position.
+struct TrackReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> TrackReader<'a> {
+    fn byte(&mut self) -> Result<u8, SmfError> {
+        let (&byte, rest) = self
+            .bytes
+            .split_first()
+            .ok_or(SmfError::InvalidData("incomplete event"))?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SmfError> {
+        if self.bytes.len() < len {
+            return Err(SmfError::InvalidData("incomplete event"));
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn variable_length(&mut self) -> Result<u32, SmfError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SmfError::InvalidData(
+            "variable-length quantity is too long",
+        ))
+    }
+}
+
+fn parse_track(bytes: &[u8]) -> Result<Track, SmfError> {
+    let mut reader = TrackReader { bytes };
+    let mut events = Vec::new();
+    let mut running_status = None;
+    while !reader.bytes.is_empty() {
+        let delta = reader.variable_length()?;
+        let mut status = reader.byte()?;
+        let mut first_data = None;
+        if status < 0x80 {
+            first_data = Some(status);
+            status = running_status.ok_or(SmfError::InvalidData("missing status byte"))?;
+        }
+        let kind = match status {
+            0xFF => {
+                running_status = None;
+                let kind = reader.byte()?;
+                let len = reader.variable_length()? as usize;
+                EventKind::Meta(MetaEvent::parse(kind, reader.bytes(len)?))
+            }
+            0xF0 | 0xF7 => {
+                running_status = None;
+                let len = reader.variable_length()? as usize;
+                let data = reader.bytes(len)?;
+                if status == 0xF0 {
+                    let mut message = Vec::with_capacity(len + 1);
+                    message.push(0xF0);
+                    message.extend_from_slice(data);
+                    EventKind::Midi(message)
+                } else {
+                    EventKind::Escape(data.to_vec())
+                }
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let mut message = vec![status];
+                let len = if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    1
+                } else {
+                    2
+                };
+                for _ in 0..len {
+                    let byte = match first_data.take() {
+                        Some(byte) => byte,
+                        None => reader.byte()?,
+                    };
+                    message.push(byte & 0x7F);
+                }
+                EventKind::Midi(message)
+            }
+            _ => return Err(SmfError::InvalidData("invalid status byte")),
+        };
+        let end = kind == EventKind::Meta(MetaEvent::EndOfTrack);
+        events.push(Event { delta, kind });
+        if end {
+            break; // ignore anything after the end of the track
+        }
+    }
+    Ok(events)
+}
+
+fn write_variable_length(data: &mut Vec<u8>, value: u32) {
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push(0x80 | (value >> shift) as u8 & 0x7F);
+        shift -= 7;
+    }
+    data.push(value as u8 & 0x7F);
+}
+
+fn write_track(track: &[Event]) -> Result<Vec<u8>, SmfError> {
+    let mut data = Vec::new();
+    let mut running_status = None;
+    for event in track {
+        if event.delta > MAX_DELTA {
+            return Err(SmfError::InvalidData("delta time is too large"));
+        }
+        write_variable_length(&mut data, event.delta);
+        match event.kind {
+            EventKind::Midi(ref message) => match message.first() {
+                None => return Err(SmfError::InvalidData("MIDI message must not be empty")),
+                Some(&status @ 0x80..=0xEF) => {
+                    let len = match status {
+                        0xC0..=0xDF => 2,
+                        _ => 3,
+                    };
+                    if message.len() != len || message[1..].iter().any(|&byte| byte >= 0x80) {
+                        return Err(SmfError::InvalidData("invalid channel message"));
+                    }
+                    if running_status != Some(status) {
+                        data.push(status);
+                        running_status = Some(status);
+                    }
+                    data.extend_from_slice(&message[1..]);
+                }
+                Some(0xF0) => {
+                    running_status = None;
+                    data.push(0xF0);
+                    write_variable_length(&mut data, message.len() as u32 - 1);
+                    data.extend_from_slice(&message[1..]);
+                }
+                Some(_) => {
+                    // System common and real-time messages can only be stored as escapes
+                    running_status = None;
+                    data.push(0xF7);
+                    write_variable_length(&mut data, message.len() as u32);
+                    data.extend_from_slice(message);
+                }
+            },
+            EventKind::Escape(ref bytes) => {
+                running_status = None;
+                data.push(0xF7);
+                write_variable_length(&mut data, bytes.len() as u32);
+                data.extend_from_slice(bytes);
+            }
+            EventKind::Meta(ref meta) => {
+                running_status = None;
+                let (kind, bytes) = meta.kind_and_data();
+                data.extend_from_slice(&[0xFF, kind]);
+                write_variable_length(&mut data, bytes.len() as u32);
+                data.extend_from_slice(&bytes);
+            }
+        }
+    }
+    if track.last().map(|event| &event.kind) != Some(&EventKind::Meta(MetaEvent::EndOfTrack)) {
+        data.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TempoChange {
+    tick: u64,
+    micros: u64,
+    tempo: u32,
+}
+
+/// Converts between ticks and microseconds, taking tempo changes into account.
+///
+/// Microseconds are counted from the start of the tracks, like the timestamps of
+/// connection callbacks are counted from an arbitrary point in time, so the timestamp
+/// of the first recorded message can be subtracted to record from tick 0.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    timing: Timing,
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    /// Builds the tempo map of the given tracks, which are played at the same time.
+    pub fn new(timing: Timing, tracks: &[Track]) -> TempoMap {
+        let mut tempos: Vec<(u64, u32)> = tracks
+            .iter()
+            .flat_map(|track| absolute_ticks(track))
+            .filter_map(|(tick, event)| match event.kind {
+                EventKind::Meta(MetaEvent::Tempo(tempo)) => Some((tick, tempo)),
+                _ => None,
+            })
+            .collect();
+        tempos.sort_by_key(|&(tick, _)| tick);
+
+        let mut map = TempoMap {
+            timing,
+            changes: vec![TempoChange {
+                tick: 0,
+                micros: 0,
+                tempo: DEFAULT_TEMPO,
+            }],
+        };
+        for (tick, tempo) in tempos {
+            let last = map.changes[map.changes.len() - 1];
+            let micros = last.micros + map.duration(tick - last.tick, last.tempo);
+            map.changes.push(TempoChange {
+                tick,
+                micros,
+                tempo,
+            });
+        }
+        map
+    }
+
+    /// The duration of a tick in microseconds, as a fraction.
+    fn micros_per_tick(&self, tempo: u32) -> (u128, u128) {
+        match self.timing {
+            Timing::Metrical(ticks_per_quarter) => {
+                (tempo as u128, ticks_per_quarter.max(1) as u128)
+            }
+            Timing::Timecode(fps, ticks_per_frame) => {
+                let (frames, seconds) = match fps {
+                    Fps::Fps24 => (24, 1),
+                    Fps::Fps25 => (25, 1),
+                    Fps::Fps30Drop => (30_000, 1001),
+                    Fps::Fps30 => (30, 1),
+                };
+                (1_000_000 * seconds, frames * ticks_per_frame.max(1) as u128)
+            }
+        }
+    }
+
+    /// The duration of the ticks in microseconds, rounded down.
+    fn duration(&self, ticks: u64, tempo: u32) -> u64 {
+        let (num, den) = self.micros_per_tick(tempo);
+        (ticks as u128 * num / den) as u64
+    }
+
+    /// The number of ticks in the duration, rounded down.
+    fn ticks(&self, micros: u64, tempo: u32) -> u64 {
+        let (num, den) = self.micros_per_tick(tempo);
+        (micros as u128 * den / num.max(1)) as u64
+    }
+
+    fn change_at_tick(&self, tick: u64) -> TempoChange {
+        let index = self.changes.partition_point(|change| change.tick <= tick);
+        self.changes[index.saturating_sub(1)]
+    }
+
+    /// Returns the tempo at the given tick, in microseconds per quarter note.
+    pub fn tempo_at(&self, tick: u64) -> u32 {
+        self.change_at_tick(tick).tempo
+    }
+
+    /// Converts a time in ticks to microseconds.
+    pub fn ticks_to_micros(&self, tick: u64) -> u64 {
+        let change = self.change_at_tick(tick);
+        change.micros + self.duration(tick - change.tick, change.tempo)
+    }
+
+    /// Converts a time in microseconds to ticks, rounded down.
+    pub fn micros_to_ticks(&self, micros: u64) -> u64 {
+        let index = self
+            .changes
+            .partition_point(|change| change.micros <= micros);
+        let change = self.changes[index.saturating_sub(1)];
+        change.tick + self.ticks(micros - change.micros, change.tempo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn midi(delta: u32, message: &[u8]) -> Event {
+        Event {
+            delta,
+            kind: EventKind::Midi(message.to_vec()),
+        }
+    }
+
+    fn meta(delta: u32, meta: MetaEvent) -> Event {
+        Event {
+            delta,
+            kind: EventKind::Meta(meta),
+        }
+    }
+
+    const FILE: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 33, // track header
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // tempo
+        0x00, 0x90, 60, 100, // note on
+        0x00, 64, 100, // running status
+        0x81, 0x40, 0x80, 60, 0, // note off after 192 ticks
+        0x00, 0xF0, 0x03, 0x43, 0x12, 0xF7, // sysex
+        0x00, 0xF7, 0x01, 0xF8, // escape
+        0x00, 0xFF, 0x2F, 0x00, // end of track
+    ];
+
+    #[test]
+    fn test_read() {
+        let smf = Smf::parse(FILE).unwrap();
+        assert_eq!(smf.format, Format::SingleTrack);
+        assert_eq!(smf.timing, Timing::Metrical(96));
+        assert_eq!(
+            smf.tracks,
+            [vec![
+                meta(0, MetaEvent::Tempo(500_000)),
+                midi(0, &[0x90, 60, 100]),
+                midi(0, &[0x90, 64, 100]),
+                midi(192, &[0x80, 60, 0]),
+                midi(0, &[0xF0, 0x43, 0x12, 0xF7]),
+                Event {
+                    delta: 0,
+                    kind: EventKind::Escape(vec![0xF8]),
+                },
+                meta(0, MetaEvent::EndOfTrack),
+            ]]
+        );
+        assert!(Smf::parse(&FILE[..30]).is_err());
+    }
+
+    #[test]
+    fn test_write() {
+        let smf = Smf::parse(FILE).unwrap();
+        assert_eq!(smf.to_bytes().unwrap(), FILE);
+
+        // The end of the track is added, and real-time messages are written as escapes
+        let mut smf = Smf::new(Format::Sequential, Timing::Timecode(Fps::Fps30Drop, 80));
+        smf.tracks
+            .push(vec![midi(0x0FFF_FFFF, &[0xFA]), midi(0, &[0xC0, 5])]);
+        let bytes = smf.to_bytes().unwrap();
+        assert_eq!(&bytes[8..14], [0, 2, 0, 1, 0xE3, 80]);
+        assert_eq!(
+            &bytes[22..],
+            [0xFF, 0xFF, 0xFF, 0x7F, 0xF7, 0x01, 0xFA, 0x00, 0xC0, 5, 0x00, 0xFF, 0x2F, 0x00]
+        );
+        let read = Smf::parse(&bytes).unwrap();
+        assert_eq!(read.timing, smf.timing);
+        assert_eq!(read.tracks[0].len(), 3);
+
+        smf.format = Format::SingleTrack;
+        smf.tracks.push(Vec::new());
+        assert!(smf.to_bytes().is_err());
+
+        // Channel messages must have the right length and only data bytes after the status
+        let mut smf = Smf::new(Format::SingleTrack, Timing::Metrical(96));
+        for message in [&[0x90, 60][..], &[0xC0, 5, 6], &[0x90, 60, 0x80]] {
+            smf.tracks = vec![vec![midi(0, message)]];
+            assert!(matches!(smf.to_bytes(), Err(SmfError::InvalidData(_))));
+        }
+        // The top bit of the division would turn it into a timecode
+        smf.tracks = vec![Vec::new()];
+        smf.timing = Timing::Metrical(0x8000);
+        assert!(matches!(smf.to_bytes(), Err(SmfError::InvalidData(_))));
+        smf.timing = Timing::Metrical(0x7FFF);
+        assert!(smf.to_bytes().is_ok());
+    }
+
+    #[test]
+    fn test_tempo_map() {
+        let tempo_track = vec![
+            meta(0, MetaEvent::Tempo(1_000_000)),
+            meta(96, MetaEvent::Tempo(250_000)),
+        ];
+        let map = TempoMap::new(Timing::Metrical(96), &[tempo_track, Vec::new()]);
+        assert_eq!(map.ticks_to_micros(48), 500_000);
+        assert_eq!(map.ticks_to_micros(96), 1_000_000);
+        assert_eq!(map.ticks_to_micros(192), 1_250_000);
+        assert_eq!(map.micros_to_ticks(1_250_000), 192);
+        assert_eq!(map.micros_to_ticks(500_000), 48);
+        assert_eq!(map.tempo_at(95), 1_000_000);
+
+        let map = TempoMap::new(Timing::Metrical(480), &[]);
+        assert_eq!(map.ticks_to_micros(480), DEFAULT_TEMPO as u64);
+
+        let map = TempoMap::new(Timing::Timecode(Fps::Fps25, 40), &[]);
+        assert_eq!(map.ticks_to_micros(1000), 1_000_000);
+        assert_eq!(map.micros_to_ticks(1_000_000), 1000);
+        let map = TempoMap::new(Timing::Timecode(Fps::Fps30Drop, 1), &[]);
+        assert_eq!(map.ticks_to_micros(30_000), 1_001_000_000);
+    }
+
+    #[test]
+    fn test_record() {
+        let map = TempoMap::new(Timing::Metrical(96), &[]);
+        let mut builder = TrackBuilder::new();
+        let start = 1_000_000;
+        for (timestamp, message) in [(start, [0x90, 60, 100]), (start + 500_000, [0x80, 60, 0])] {
+            let tick = map.micros_to_ticks(timestamp - start);
+            builder.push(tick, EventKind::Midi(message.to_vec()));
+        }
+        let track = builder.finish(0);
+        assert_eq!(
+            track,
+            [
+                midi(0, &[0x90, 60, 100]),
+                midi(96, &[0x80, 60, 0]),
+                meta(0, MetaEvent::EndOfTrack)
+            ]
+        );
+        let smf = Smf {
+            format: Format::Parallel,
+            timing: Timing::Metrical(96),
+            tracks: vec![vec![meta(0, MetaEvent::EndOfTrack)], track],
+        };
+        let merged: Vec<_> = smf.merged().into_iter().map(|(tick, _)| tick).collect();
+        assert_eq!(merged, [0, 0, 96, 96]);
+    }
+}
@@ -0,0 +1,243 @@
+//! MIDI over arbitrary byte streams, such as TCP or Unix sockets, pipes or the
+//! standard input and output of a child process.
+//!
+//! A `StreamInput` reads from any `Read` implementation on a thread of its own and splits
+//! the bytes into messages like a hardware port (expanding running status and reporting
+//! interleaved real-time messages on their own). A `StreamOutputConnection` writes messages
+//! to any `Write` implementation.
+#![deny(missing_docs)]
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{Builder, JoinHandle};
+use std::time::Instant;
+
+use crate::errors::*;
+use crate::parser::{is_ignored, StreamParser};
+use crate::Ignore;
+
+/// An object for receiving MIDI messages from a byte stream, the
+/// counterpart of `MidiInput` for streams.
+#[derive(Debug)]
+pub struct StreamInput {
+    ignore_flags: Ignore,
+}
+
+impl Default for StreamInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamInput {
+    /// Creates a new `StreamInput` that does not ignore any messages.
+    pub fn new() -> Self {
+        StreamInput {
+            ignore_flags: Ignore::None,
+        }
+    }
+
+    /// Set flags to decide what kind of messages should be ignored (i.e., filtered out)
+    /// by this `StreamInput`. By default, no messages are ignored.
+    pub fn ignore(&mut self, flags: Ignore) {
+        self.ignore_flags = flags;
+    }
+
+    /// Starts reading from `reader` on a new thread and calls `callback` for every message,
+    /// with the time of the read that completed it as its timestamp.
+    ///
+    /// `name` is only used to name the thread. The thread runs until the stream ends or
+    /// fails, or until the connection is closed. Read errors of the kinds `WouldBlock`,
+    /// `TimedOut` and `Interrupted` are not treated as failures, so a reader with a read timeout
+    /// (e.g. a `TcpStream` after `set_read_timeout`) can be used to make closing or dropping
+    /// the connection take effect without waiting for more data.
+    pub fn connect<R, F, T>(
+        self,
+        reader: R,
+        name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<StreamInputConnection<R, T>, ConnectError<StreamInput>>
+    where
+        R: Read + Send + 'static,
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+        T: Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handler_data = HandlerData {
+            ignore_flags: self.ignore_flags,
+            reader,
+            stop: stop.clone(),
+            callback: Box::new(callback),
+        };
+
+        let threadbuilder = Builder::new();
+        let name = format!("midir stream input handler (stream '{}')", name);
+        let threadbuilder = threadbuilder.name(name);
+        let thread = match threadbuilder.spawn(move || {
+            let mut d = data;
+            let h = handle_input(handler_data, &mut d);
+            (h, d) // return both the handler data and the user data
+        }) {
+            Ok(handle) => handle,
+            Err(_) => {
+                return Err(ConnectError::other(
+                    "could not start stream input handler thread",
+                    self,
+                ));
+            }
+        };
+
+        Ok(StreamInputConnection {
+            thread: Some(thread),
+            stop,
+        })
+    }
+}
+
+type Callback<T> = Box<dyn FnMut(u64, &[u8], &mut T) + Send>;
+
+struct HandlerData<R, T: 'static> {
+    ignore_flags: Ignore,
+    reader: R,
+    stop: Arc<AtomicBool>,
+    callback: Callback<T>,
+}
+
+type HandlerThread<R, T> = JoinHandle<(HandlerData<R, T>, T)>;
+
+/// Represents an open connection to a byte stream that messages are received from.
+///
+/// Dropping the connection closes it just like `close`, so it also blocks until the current
+/// read of the input thread returns. With a reader that blocks without a timeout, check
+/// `is_finished` first or make sure that the stream ends before the connection is dropped.
+pub struct StreamInputConnection<R, T: 'static> {
+    thread: Option<HandlerThread<R, T>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl<R, T> StreamInputConnection<R, T> {
+    /// Check whether the stream has ended or failed, so that no more messages
+    /// will be received and closing the connection will not block.
+    pub fn is_finished(&self) -> bool {
+        match self.thread {
+            Some(ref thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Closes the connection and returns the `StreamInput`, the reader and the user data.
+    ///
+    /// This blocks until the current read of the input thread returns.
+    pub fn close(mut self) -> (StreamInput, R, T) {
+        let (handler_data, user_data) = self.close_internal();
+        (
+            StreamInput {
+                ignore_flags: handler_data.ignore_flags,
+            },
+            handler_data.reader,
+            user_data,
+        )
+    }
+
+    /// This must only be called if the handler thread has not yet been shut down
+    fn close_internal(&mut self) -> (HandlerData<R, T>, T) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        let thread = self.thread.take().unwrap();
+        match thread.join() {
+            Ok(data) => data,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<&'static str>() {
+                    panic!("Error when joining stream thread: {}", e);
+                } else {
+                    panic!("Unknown error when joining stream thread: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+impl<R, T> Drop for StreamInputConnection<R, T> {
+    fn drop(&mut self) {
+        // Use `self.thread` as a flag whether the connection has already been closed
+        if self.thread.is_some() {
+            self.close_internal();
+        }
+    }
+}
+
+/// Reads from the stream until it ends or a request to stop is received.
+fn handle_input<R: Read, T>(mut data: HandlerData<R, T>, user_data: &mut T) -> HandlerData<R, T> {
+    let start = Instant::now();
+    let mut parser = StreamParser::new();
+    let mut buffer = [0; 1024];
+    let ignore_flags = data.ignore_flags;
+    let callback = &mut data.callback;
+
+    while !data.stop.load(Ordering::SeqCst) {
+        let count = match data.reader.read(&mut buffer) {
+            Ok(0) => break, // end of stream
+            Ok(count) => count,
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(_) => break,
+        };
+        // Don't deliver messages that have been read after a request to stop
+        if data.stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let timestamp = if cfg!(feature = "avoid_timestamping") {
+            0
+        } else {
+            start.elapsed().as_micros() as u64
+        };
+        parser.feed(&buffer[..count], |message| {
+            if !is_ignored(message, ignore_flags) {
+                callback(timestamp, message, user_data);
+            }
+        });
+    }
+
+    data
+}
+
+/// Represents an open connection to a byte stream that messages are sent to,
+/// the counterpart of `MidiOutputConnection` for streams.
+#[derive(Debug)]
+pub struct StreamOutputConnection<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> StreamOutputConnection<W> {
+    /// Creates a connection that sends messages to `writer`.
+    pub fn new(writer: W) -> Self {
+        StreamOutputConnection { writer }
+    }
+
+    /// Closes the connection and returns the writer.
+    pub fn close(self) -> W {
+        self.writer
+    }
+
+    /// Send a message to the stream. The bytes are written exactly as given, so messages
+    /// may use running status, and the writer is flushed after every message.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if message.is_empty() {
+            return Err(SendError::InvalidData(
+                "message to be sent must not be empty",
+            ));
+        }
+        self.writer
+            .write_all(message)
+            .and_then(|_| self.writer.flush())
+            .map_err(|_| SendError::Other("could not write to the stream"))
+    }
+}
@@ -0,0 +1,1660 @@
+//! Universal MIDI Packets (UMP), the message format of MIDI 2.0, and their translation
+//! from and to the MIDI 1.0 byte messages that are used by `MidiInputConnection`
+//! and `MidiOutputConnection`.
+//!
+//! A `Packet` holds the 32-bit words of a single packet, which can be decoded into a typed
+//! `Message` and encoded again with `Message::to_packet`. An `Upscaler` translates MIDI 1.0
+//! byte messages into packets of either protocol, and a `Downscaler` translates packets back
+//! into byte messages, both following the default translation of the UMP specification.
+#![deny(missing_docs)]
+
+/// The protocol of the channel voice messages in a stream of packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// MIDI 1.0 channel voice messages in packets of message type 2.
+    Midi1,
+    /// MIDI 2.0 channel voice messages in packets of message type 4.
+    Midi2,
+}
+
+/// Returns the number of 32-bit words of the packet that starts with the given word,
+/// which only depends on its message type (the 4 most significant bits).
+pub fn packet_len(first_word: u32) -> usize {
+    match first_word >> 28 {
+        0x0 | 0x1 | 0x2 | 0x6 | 0x7 => 1,
+        0x3 | 0x4 | 0x8 | 0x9 | 0xA => 2,
+        0xB | 0xC => 3,
+        _ => 4,
+    }
+}
+
+/// Splits consecutive 32-bit words into packets. An incomplete packet at the end is ignored.
+pub fn packets(mut words: &[u32]) -> impl Iterator<Item = Packet> + '_ {
+    std::iter::from_fn(move || {
+        let len = packet_len(*words.first()?);
+        let packet = Packet::new(words.get(..len)?)?;
+        words = &words[len..];
+        Some(packet)
+    })
+}
+
+/// Scales a value up to a higher resolution, using the min-center-max scaling of the
+/// MIDI 2.0 specification: the minimum, the center and the maximum of the source range
+/// are mapped to the minimum, the center and the maximum of the destination range.
+pub fn scale_up(value: u32, src_bits: u32, dst_bits: u32) -> u32 {
+    let scale_bits = dst_bits - src_bits;
+    let mut shifted = value << scale_bits;
+    let center = 1 << (src_bits - 1);
+    if value <= center {
+        return shifted;
+    }
+    // Fill the lower bits by repeating the bits below the most significant one
+    let repeat_bits = src_bits - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    if scale_bits > repeat_bits {
+        repeat <<= scale_bits - repeat_bits;
+    } else {
+        repeat >>= repeat_bits - scale_bits;
+    }
+    while repeat != 0 {
+        shifted |= repeat;
+        repeat >>= repeat_bits;
+    }
+    shifted
+}
+
+/// Scales a value down to a lower resolution, the inverse of `scale_up`.
+pub fn scale_down(value: u32, src_bits: u32, dst_bits: u32) -> u32 {
+    value >> (src_bits - dst_bits)
+}
+
+/// The 32-bit words of a single Universal MIDI Packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Packet {
+    words: [u32; 4],
+    len: u8,
+}
+
+impl Packet {
+    /// Creates a packet from its words. Returns `None` if the number of words
+    /// does not match the message type of the first word.
+    pub fn new(words: &[u32]) -> Option<Packet> {
+        let len = packet_len(*words.first()?);
+        if words.len() != len {
+            return None;
+        }
+        let mut packet = Packet {
+            words: [0; 4],
+            len: len as u8,
+        };
+        packet.words[..len].copy_from_slice(words);
+        Some(packet)
+    }
+
+    fn from_array(words: [u32; 4]) -> Packet {
+        Packet {
+            words,
+            len: packet_len(words[0]) as u8,
+        }
+    }
+
+    /// Get the words of the packet.
+    pub fn words(&self) -> &[u32] {
+        &self.words[..self.len as usize]
+    }
+
+    /// Get the message type of the packet.
+    pub fn message_type(&self) -> u8 {
+        (self.words[0] >> 28) as u8
+    }
+
+    /// Get the group of the packet, or `None` for the message types without a group
+    /// (utility messages and UMP stream messages).
+    pub fn group(&self) -> Option<u8> {
+        match self.message_type() {
+            0x0 | 0xF => None,
+            _ => Some(((self.words[0] >> 24) & 0xF) as u8),
+        }
+    }
+
+    /// Decodes the message of the packet.
+    pub fn message(&self) -> Message {
+        Message::decode(self)
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        (self.words[index / 4] >> (24 - 8 * (index % 4))) as u8
+    }
+}
+
+impl From<Message> for Packet {
+    fn from(message: Message) -> Packet {
+        message.to_packet()
+    }
+}
+
+/// The position of a packet in a message that is split into several packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk {
+    /// The whole message is contained in the packet.
+    Complete,
+    /// The packet starts the message.
+    Start,
+    /// The packet continues the message.
+    Continue,
+    /// The packet ends the message.
+    End,
+}
+
+impl Chunk {
+    fn from_bits(bits: u32) -> Chunk {
+        match bits & 0x3 {
+            0 => Chunk::Complete,
+            1 => Chunk::Start,
+            2 => Chunk::Continue,
+            _ => Chunk::End,
+        }
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            Chunk::Complete => 0,
+            Chunk::Start => 1,
+            Chunk::Continue => 2,
+            Chunk::End => 3,
+        }
+    }
+}
+
+/// A utility message (message type 0), which does not belong to a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utility {
+    /// No operation.
+    NoOp,
+    /// A jitter reduction clock, in units of 1/31250 seconds.
+    JrClock(u16),
+    /// A jitter reduction timestamp of the following message, in units of 1/31250 seconds.
+    JrTimestamp(u16),
+    /// The number of delta clockstamp ticks per quarter note.
+    DeltaClockstampTpq(u16),
+    /// The number of ticks since the previous message (20 bits).
+    DeltaClockstamp(u32),
+}
+
+/// A system common or system real-time message (message type 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemMessage {
+    /// A MIDI time code quarter frame.
+    TimeCode(u8),
+    /// The song position pointer, in MIDI beats (14 bits).
+    SongPosition(u16),
+    /// The selection of a song.
+    SongSelect(u8),
+    /// A request to tune analog oscillators.
+    TuneRequest,
+    /// A timing clock, sent 24 times per quarter note.
+    TimingClock,
+    /// Start the current sequence.
+    Start,
+    /// Continue the current sequence.
+    Continue,
+    /// Stop the current sequence.
+    Stop,
+    /// Active sensing.
+    ActiveSensing,
+    /// Reset all receivers to their power-up state.
+    Reset,
+}
+
+impl SystemMessage {
+    fn from_bytes(bytes: &[u8]) -> Option<SystemMessage> {
+        let data = |i: usize| bytes.get(i).map(|b| b & 0x7F);
+        Some(match *bytes.first()? {
+            0xF1 => SystemMessage::TimeCode(data(1)?),
+            0xF2 => SystemMessage::SongPosition(data(1)? as u16 | (data(2)? as u16) << 7),
+            0xF3 => SystemMessage::SongSelect(data(1)?),
+            0xF6 => SystemMessage::TuneRequest,
+            0xF8 => SystemMessage::TimingClock,
+            0xFA => SystemMessage::Start,
+            0xFB => SystemMessage::Continue,
+            0xFC => SystemMessage::Stop,
+            0xFE => SystemMessage::ActiveSensing,
+            0xFF => SystemMessage::Reset,
+            _ => return None,
+        })
+    }
+
+    /// Returns the MIDI 1.0 byte message.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            SystemMessage::TimeCode(value) => vec![0xF1, value & 0x7F],
+            SystemMessage::SongPosition(position) => {
+                vec![
+                    0xF2,
+                    (position & 0x7F) as u8,
+                    ((position >> 7) & 0x7F) as u8,
+                ]
+            }
+            SystemMessage::SongSelect(song) => vec![0xF3, song & 0x7F],
+            SystemMessage::TuneRequest => vec![0xF6],
+            SystemMessage::TimingClock => vec![0xF8],
+            SystemMessage::Start => vec![0xFA],
+            SystemMessage::Continue => vec![0xFB],
+            SystemMessage::Stop => vec![0xFC],
+            SystemMessage::ActiveSensing => vec![0xFE],
+            SystemMessage::Reset => vec![0xFF],
+        }
+    }
+}
+
+/// A MIDI 1.0 channel voice message (message type 2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Midi1Message {
+    /// Note off, with a release velocity.
+    NoteOff {
+        /// The note number.
+        note: u8,
+        /// The release velocity.
+        velocity: u8,
+    },
+    /// Note on. A velocity of 0 means note off.
+    NoteOn {
+        /// The note number.
+        note: u8,
+        /// The velocity.
+        velocity: u8,
+    },
+    /// Polyphonic key pressure.
+    PolyPressure {
+        /// The note number.
+        note: u8,
+        /// The pressure.
+        pressure: u8,
+    },
+    /// Control change.
+    ControlChange {
+        /// The controller number.
+        controller: u8,
+        /// The value.
+        value: u8,
+    },
+    /// Program change.
+    ProgramChange {
+        /// The program number.
+        program: u8,
+    },
+    /// Channel pressure.
+    ChannelPressure {
+        /// The pressure.
+        pressure: u8,
+    },
+    /// Pitch bend (14 bits, centered at 0x2000).
+    PitchBend {
+        /// The pitch bend value.
+        value: u16,
+    },
+}
+
+impl Midi1Message {
+    /// Parses a MIDI 1.0 byte message, returning the message and its channel.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(u8, Midi1Message)> {
+        let status = *bytes.first()?;
+        let data = |i: usize| bytes.get(i).map(|b| b & 0x7F);
+        let message = match status & 0xF0 {
+            0x80 => Midi1Message::NoteOff {
+                note: data(1)?,
+                velocity: data(2)?,
+            },
+            0x90 => Midi1Message::NoteOn {
+                note: data(1)?,
+                velocity: data(2)?,
+            },
+            0xA0 => Midi1Message::PolyPressure {
+                note: data(1)?,
+                pressure: data(2)?,
+            },
+            0xB0 => Midi1Message::ControlChange {
+                controller: data(1)?,
+                value: data(2)?,
+            },
+            0xC0 => Midi1Message::ProgramChange { program: data(1)? },
+            0xD0 => Midi1Message::ChannelPressure { pressure: data(1)? },
+            0xE0 => Midi1Message::PitchBend {
+                value: data(1)? as u16 | (data(2)? as u16) << 7,
+            },
+            _ => return None,
+        };
+        Some((status & 0x0F, message))
+    }
+
+    /// Returns the MIDI 1.0 byte message for the given channel.
+    pub fn to_bytes(&self, channel: u8) -> Vec<u8> {
+        let channel = channel & 0x0F;
+        match *self {
+            Midi1Message::NoteOff { note, velocity } => vec![0x80 | channel, note, velocity],
+            Midi1Message::NoteOn { note, velocity } => vec![0x90 | channel, note, velocity],
+            Midi1Message::PolyPressure { note, pressure } => vec![0xA0 | channel, note, pressure],
+            Midi1Message::ControlChange { controller, value } => {
+                vec![0xB0 | channel, controller, value]
+            }
+            Midi1Message::ProgramChange { program } => vec![0xC0 | channel, program],
+            Midi1Message::ChannelPressure { pressure } => vec![0xD0 | channel, pressure],
+            Midi1Message::PitchBend { value } => vec![
+                0xE0 | channel,
+                (value & 0x7F) as u8,
+                ((value >> 7) & 0x7F) as u8,
+            ],
+        }
+    }
+}
+
+/// A MIDI 2.0 channel voice message (message type 4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Midi2Message {
+    /// Note off, with a release velocity and an attribute.
+    NoteOff {
+        /// The note number.
+        note: u8,
+        /// The release velocity.
+        velocity: u16,
+        /// The type of the attribute (0 if there is none).
+        attribute_type: u8,
+        /// The attribute data.
+        attribute: u16,
+    },
+    /// Note on, with an attribute. In contrast to MIDI 1.0, a velocity of 0 does not
+    /// mean note off.
+    NoteOn {
+        /// The note number.
+        note: u8,
+        /// The velocity.
+        velocity: u16,
+        /// The type of the attribute (0 if there is none).
+        attribute_type: u8,
+        /// The attribute data.
+        attribute: u16,
+    },
+    /// Polyphonic key pressure.
+    PolyPressure {
+        /// The note number.
+        note: u8,
+        /// The pressure.
+        pressure: u32,
+    },
+    /// A registered per-note controller.
+    RegisteredPerNoteController {
+        /// The note number.
+        note: u8,
+        /// The controller number.
+        index: u8,
+        /// The value.
+        value: u32,
+    },
+    /// An assignable per-note controller.
+    AssignablePerNoteController {
+        /// The note number.
+        note: u8,
+        /// The controller number.
+        index: u8,
+        /// The value.
+        value: u32,
+    },
+    /// Per-note management.
+    PerNoteManagement {
+        /// The note number.
+        note: u8,
+        /// Detach the per-note controllers from previously received notes.
+        detach: bool,
+        /// Reset the per-note controllers to their default values.
+        reset: bool,
+    },
+    /// Control change.
+    ControlChange {
+        /// The controller number.
+        controller: u8,
+        /// The value.
+        value: u32,
+    },
+    /// A registered controller (RPN).
+    RegisteredController {
+        /// The bank of the controller (the RPN MSB).
+        bank: u8,
+        /// The controller number in the bank (the RPN LSB).
+        index: u8,
+        /// The value.
+        value: u32,
+    },
+    /// An assignable controller (NRPN).
+    AssignableController {
+        /// The bank of the controller (the NRPN MSB).
+        bank: u8,
+        /// The controller number in the bank (the NRPN LSB).
+        index: u8,
+        /// The value.
+        value: u32,
+    },
+    /// A relative change of a registered controller.
+    RelativeRegisteredController {
+        /// The bank of the controller.
+        bank: u8,
+        /// The controller number in the bank.
+        index: u8,
+        /// The change of the value.
+        value: i32,
+    },
+    /// A relative change of an assignable controller.
+    RelativeAssignableController {
+        /// The bank of the controller.
+        bank: u8,
+        /// The controller number in the bank.
+        index: u8,
+        /// The change of the value.
+        value: i32,
+    },
+    /// Program change, optionally with a bank select.
+    ProgramChange {
+        /// The program number.
+        program: u8,
+        /// The bank MSB and LSB, if the bank is selected as well.
+        bank: Option<(u8, u8)>,
+    },
+    /// Channel pressure.
+    ChannelPressure {
+        /// The pressure.
+        pressure: u32,
+    },
+    /// Pitch bend (centered at 0x80000000).
+    PitchBend {
+        /// The pitch bend value.
+        value: u32,
+    },
+    /// Per-note pitch bend (centered at 0x80000000).
+    PerNotePitchBend {
+        /// The note number.
+        note: u8,
+        /// The pitch bend value.
+        value: u32,
+    },
+}
+
+/// The header of a mixed data set (message type 5).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MixedDataSetHeader {
+    /// The group.
+    pub group: u8,
+    /// The identifier of the mixed data set.
+    pub id: u8,
+    /// The number of valid bytes in this chunk.
+    pub valid_bytes: u16,
+    /// The number of chunks of the mixed data set.
+    pub num_chunks: u16,
+    /// The number of this chunk.
+    pub chunk: u16,
+    /// The manufacturer identifier.
+    pub manufacturer: u16,
+    /// The device identifier.
+    pub device: u16,
+    /// The first sub-identifier.
+    pub sub_id1: u16,
+    /// The second sub-identifier.
+    pub sub_id2: u16,
+}
+
+/// The destination of a flex data message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlexAddress {
+    /// The message is addressed to a channel of the group.
+    Channel(u8),
+    /// The message is addressed to the whole group.
+    Group,
+}
+
+/// A flex data message (message type 0xD), e.g. a tempo, time signature or text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlexData {
+    /// The group.
+    pub group: u8,
+    /// The position of the packet in a message that spans several packets (e.g. long texts).
+    pub format: Chunk,
+    /// The destination of the message.
+    pub address: FlexAddress,
+    /// The bank of the status (0 for setup and performance, 1 for metadata text,
+    /// 2 for performance text).
+    pub status_bank: u8,
+    /// The status, which decides the meaning of the data.
+    pub status: u8,
+    /// The data.
+    pub data: [u32; 3],
+}
+
+impl FlexData {
+    /// Creates a "set tempo" message with the given number of 10 nanosecond units per
+    /// quarter note.
+    pub fn set_tempo(group: u8, tempo: u32) -> FlexData {
+        FlexData {
+            group,
+            format: Chunk::Complete,
+            address: FlexAddress::Group,
+            status_bank: 0,
+            status: 0,
+            data: [tempo, 0, 0],
+        }
+    }
+
+    /// Returns the number of 10 nanosecond units per quarter note if this is
+    /// a "set tempo" message.
+    pub fn tempo(&self) -> Option<u32> {
+        if self.status_bank == 0 && self.status == 0 {
+            Some(self.data[0])
+        } else {
+            None
+        }
+    }
+}
+
+/// A UMP stream message (message type 0xF), which is used to discover and configure
+/// endpoints and does not belong to a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMessage {
+    /// A request for information about the endpoint.
+    EndpointDiscovery {
+        /// The major version of UMP that the sender supports.
+        ump_version_major: u8,
+        /// The minor version of UMP that the sender supports.
+        ump_version_minor: u8,
+        /// A bitmap of the requested notifications.
+        filter: u8,
+    },
+    /// A request to change the protocol of the stream.
+    StreamConfigRequest {
+        /// The requested protocol.
+        protocol: Protocol,
+        /// Whether jitter reduction timestamps should be received.
+        rx_jr: bool,
+        /// Whether jitter reduction timestamps should be sent.
+        tx_jr: bool,
+    },
+    /// The notification of the current protocol of the stream.
+    StreamConfigNotification {
+        /// The current protocol.
+        protocol: Protocol,
+        /// Whether jitter reduction timestamps are received.
+        rx_jr: bool,
+        /// Whether jitter reduction timestamps are sent.
+        tx_jr: bool,
+    },
+    /// A request for information about function blocks.
+    FunctionBlockDiscovery {
+        /// The number of the block, or 0xFF for all blocks.
+        block: u8,
+        /// A bitmap of the requested notifications.
+        filter: u8,
+    },
+    /// The start of a clip of messages.
+    StartOfClip,
+    /// The end of a clip of messages.
+    EndOfClip,
+    /// Any other stream message, e.g. the notifications about the endpoint.
+    Other {
+        /// The position of the packet in a message that spans several packets.
+        format: Chunk,
+        /// The status (10 bits).
+        status: u16,
+        /// The data, i.e. the 16 least significant bits of the first word and
+        /// the other three words.
+        data: [u32; 4],
+    },
+}
+
+/// The typed content of a Universal MIDI Packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Message {
+    /// A utility message (message type 0).
+    Utility(Utility),
+    /// A system message (message type 1).
+    System {
+        /// The group.
+        group: u8,
+        /// The message.
+        message: SystemMessage,
+    },
+    /// A MIDI 1.0 channel voice message (message type 2).
+    Midi1 {
+        /// The group.
+        group: u8,
+        /// The channel.
+        channel: u8,
+        /// The message.
+        message: Midi1Message,
+    },
+    /// A chunk of a 7-bit system exclusive message (message type 3), without the
+    /// `0xF0` and `0xF7` bytes.
+    SysEx7 {
+        /// The group.
+        group: u8,
+        /// The position of the chunk in the message.
+        status: Chunk,
+        /// The data of the chunk, at most 6 bytes (further bytes are ignored by `to_packet`).
+        data: Vec<u8>,
+    },
+    /// A MIDI 2.0 channel voice message (message type 4).
+    Midi2 {
+        /// The group.
+        group: u8,
+        /// The channel.
+        channel: u8,
+        /// The message.
+        message: Midi2Message,
+    },
+    /// A chunk of an 8-bit system exclusive message (message type 5).
+    SysEx8 {
+        /// The group.
+        group: u8,
+        /// The position of the chunk in the message.
+        status: Chunk,
+        /// The identifier of the stream that the message belongs to.
+        stream_id: u8,
+        /// The data of the chunk, at most 13 bytes (further bytes are ignored by `to_packet`).
+        data: Vec<u8>,
+    },
+    /// The header of a mixed data set (message type 5).
+    MixedDataSetHeader(MixedDataSetHeader),
+    /// The payload of a mixed data set (message type 5).
+    MixedDataSetPayload {
+        /// The group.
+        group: u8,
+        /// The identifier of the mixed data set.
+        id: u8,
+        /// The payload.
+        data: [u8; 14],
+    },
+    /// A flex data message (message type 0xD).
+    FlexData(FlexData),
+    /// A UMP stream message (message type 0xF).
+    Stream(StreamMessage),
+    /// A packet of a reserved message type, or with a status that is not defined.
+    Unknown(Packet),
+}
+
+impl Message {
+    fn decode(packet: &Packet) -> Message {
+        let w = packet.words;
+        let group = ((w[0] >> 24) & 0xF) as u8;
+        let status = ((w[0] >> 20) & 0xF) as u8;
+        let channel = ((w[0] >> 16) & 0xF) as u8;
+        let b3 = packet.byte(2);
+        let b4 = packet.byte(3);
+        let unknown = Message::Unknown(*packet);
+        match packet.message_type() {
+            0x0 => Message::Utility(match status {
+                0x0 => Utility::NoOp,
+                0x1 => Utility::JrClock(w[0] as u16),
+                0x2 => Utility::JrTimestamp(w[0] as u16),
+                0x3 => Utility::DeltaClockstampTpq(w[0] as u16),
+                0x4 => Utility::DeltaClockstamp(w[0] & 0xFFFFF),
+                _ => return unknown,
+            }),
+            0x1 => match SystemMessage::from_bytes(&[packet.byte(1), b3, b4]) {
+                Some(message) => Message::System { group, message },
+                None => unknown,
+            },
+            0x2 => match Midi1Message::from_bytes(&[packet.byte(1), b3, b4]) {
+                Some((channel, message)) => Message::Midi1 {
+                    group,
+                    channel,
+                    message,
+                },
+                None => unknown,
+            },
+            0x3 => {
+                let len = (channel as usize).min(6);
+                Message::SysEx7 {
+                    group,
+                    status: Chunk::from_bits(status as u32),
+                    data: (0..len).map(|i| packet.byte(2 + i) & 0x7F).collect(),
+                }
+            }
+            0x4 => {
+                let message = match Midi2Message::decode(status, b3, b4, w[1]) {
+                    Some(message) => message,
+                    None => return unknown,
+                };
+                Message::Midi2 {
+                    group,
+                    channel,
+                    message,
+                }
+            }
+            0x5 => match status {
+                0x0..=0x3 => {
+                    // The number of bytes includes the stream identifier
+                    let len = (channel as usize).clamp(1, 14) - 1;
+                    Message::SysEx8 {
+                        group,
+                        status: Chunk::from_bits(status as u32),
+                        stream_id: b3,
+                        data: (0..len).map(|i| packet.byte(3 + i)).collect(),
+                    }
+                }
+                0x8 => Message::MixedDataSetHeader(MixedDataSetHeader {
+                    group,
+                    id: channel,
+                    valid_bytes: w[0] as u16,
+                    num_chunks: (w[1] >> 16) as u16,
+                    chunk: w[1] as u16,
+                    manufacturer: (w[2] >> 16) as u16,
+                    device: w[2] as u16,
+                    sub_id1: (w[3] >> 16) as u16,
+                    sub_id2: w[3] as u16,
+                }),
+                0x9 => {
+                    let mut data = [0; 14];
+                    for (i, byte) in data.iter_mut().enumerate() {
+                        *byte = packet.byte(2 + i);
+                    }
+                    Message::MixedDataSetPayload {
+                        group,
+                        id: channel,
+                        data,
+                    }
+                }
+                _ => unknown,
+            },
+            0xD => Message::FlexData(FlexData {
+                group,
+                format: Chunk::from_bits(w[0] >> 22),
+                address: match (w[0] >> 20) & 0x3 {
+                    0 => FlexAddress::Channel(channel),
+                    1 => FlexAddress::Group,
+                    _ => return unknown,
+                },
+                status_bank: b3,
+                status: b4,
+                data: [w[1], w[2], w[3]],
+            }),
+            0xF => Message::Stream(StreamMessage::decode(&w)),
+            _ => unknown,
+        }
+    }
+
+    /// Encodes the message into a packet.
+    pub fn to_packet(&self) -> Packet {
+        fn header(mt: u32, group: u8, status: u32, b2: u32) -> u32 {
+            mt << 28 | (group as u32 & 0xF) << 24 | (status & 0xF) << 20 | (b2 & 0xF) << 16
+        }
+        fn bytes(first: u32, data: &[u8], start: usize, count: usize) -> [u32; 4] {
+            let mut words = [first, 0, 0, 0];
+            for (i, &byte) in data.iter().take(count).enumerate() {
+                let pos = start + i;
+                words[pos / 4] |= (byte as u32) << (24 - 8 * (pos % 4));
+            }
+            words
+        }
+
+        let words = match *self {
+            Message::Utility(utility) => {
+                let (status, data) = match utility {
+                    Utility::NoOp => (0x0, 0),
+                    Utility::JrClock(time) => (0x1, time as u32),
+                    Utility::JrTimestamp(time) => (0x2, time as u32),
+                    Utility::DeltaClockstampTpq(ticks) => (0x3, ticks as u32),
+                    Utility::DeltaClockstamp(ticks) => (0x4, ticks & 0xFFFFF),
+                };
+                [status << 20 | data, 0, 0, 0]
+            }
+            Message::System { group, message } => {
+                let message = message.to_bytes();
+                bytes(0x1 << 28 | (group as u32 & 0xF) << 24, &message, 1, 3)
+            }
+            Message::Midi1 {
+                group,
+                channel,
+                message,
+            } => {
+                let message = message.to_bytes(channel);
+                bytes(0x2 << 28 | (group as u32 & 0xF) << 24, &message, 1, 3)
+            }
+            Message::SysEx7 {
+                group,
+                status,
+                ref data,
+            } => {
+                let len = data.len().min(6);
+                bytes(header(0x3, group, status.bits(), len as u32), data, 2, 6)
+            }
+            Message::Midi2 {
+                group,
+                channel,
+                message,
+            } => message.encode(header(0x4, group, 0, channel as u32)),
+            Message::SysEx8 {
+                group,
+                status,
+                stream_id,
+                ref data,
+            } => {
+                let len = data.len().min(13);
+                let first =
+                    header(0x5, group, status.bits(), len as u32 + 1) | (stream_id as u32) << 8;
+                bytes(first, data, 3, 13)
+            }
+            Message::MixedDataSetHeader(ref h) => [
+                header(0x5, h.group, 0x8, h.id as u32) | h.valid_bytes as u32,
+                (h.num_chunks as u32) << 16 | h.chunk as u32,
+                (h.manufacturer as u32) << 16 | h.device as u32,
+                (h.sub_id1 as u32) << 16 | h.sub_id2 as u32,
+            ],
+            Message::MixedDataSetPayload {
+                group,
+                id,
+                ref data,
+            } => bytes(header(0x5, group, 0x9, id as u32), data, 2, 14),
+            Message::FlexData(ref flex) => {
+                let (address, channel) = match flex.address {
+                    FlexAddress::Channel(channel) => (0, channel as u32),
+                    FlexAddress::Group => (1, 0),
+                };
+                [
+                    0xD << 28
+                        | (flex.group as u32 & 0xF) << 24
+                        | flex.format.bits() << 22
+                        | address << 20
+                        | (channel & 0xF) << 16
+                        | (flex.status_bank as u32) << 8
+                        | flex.status as u32,
+                    flex.data[0],
+                    flex.data[1],
+                    flex.data[2],
+                ]
+            }
+            Message::Stream(ref message) => message.encode(),
+            Message::Unknown(packet) => return packet,
+        };
+        Packet::from_array(words)
+    }
+}
+
+impl Midi2Message {
+    fn decode(status: u8, b3: u8, b4: u8, data: u32) -> Option<Midi2Message> {
+        let note = b3 & 0x7F;
+        Some(match status {
+            0x0 => Midi2Message::RegisteredPerNoteController {
+                note,
+                index: b4,
+                value: data,
+            },
+            0x1 => Midi2Message::AssignablePerNoteController {
+                note,
+                index: b4,
+                value: data,
+            },
+            0x2 => Midi2Message::RegisteredController {
+                bank: b3 & 0x7F,
+                index: b4 & 0x7F,
+                value: data,
+            },
+            0x3 => Midi2Message::AssignableController {
+                bank: b3 & 0x7F,
+                index: b4 & 0x7F,
+                value: data,
+            },
+            0x4 => Midi2Message::RelativeRegisteredController {
+                bank: b3 & 0x7F,
+                index: b4 & 0x7F,
+                value: data as i32,
+            },
+            0x5 => Midi2Message::RelativeAssignableController {
+                bank: b3 & 0x7F,
+                index: b4 & 0x7F,
+                value: data as i32,
+            },
+            0x6 => Midi2Message::PerNotePitchBend { note, value: data },
+            0x8 => Midi2Message::NoteOff {
+                note,
+                velocity: (data >> 16) as u16,
+                attribute_type: b4,
+                attribute: data as u16,
+            },
+            0x9 => Midi2Message::NoteOn {
+                note,
+                velocity: (data >> 16) as u16,
+                attribute_type: b4,
+                attribute: data as u16,
+            },
+            0xA => Midi2Message::PolyPressure {
+                note,
+                pressure: data,
+            },
+            0xB => Midi2Message::ControlChange {
+                controller: b3 & 0x7F,
+                value: data,
+            },
+            0xC => Midi2Message::ProgramChange {
+                program: (data >> 24) as u8 & 0x7F,
+                bank: if b4 & 0x01 != 0 {
+                    Some(((data >> 8) as u8 & 0x7F, data as u8 & 0x7F))
+                } else {
+                    None
+                },
+            },
+            0xD => Midi2Message::ChannelPressure { pressure: data },
+            0xE => Midi2Message::PitchBend { value: data },
+            0xF => Midi2Message::PerNoteManagement {
+                note,
+                detach: b4 & 0x02 != 0,
+                reset: b4 & 0x01 != 0,
+            },
+            _ => return None,
+        })
+    }
+
+    fn encode(&self, header: u32) -> [u32; 4] {
+        let (status, b3, b4, data) = match *self {
+            Midi2Message::RegisteredPerNoteController { note, index, value } => {
+                (0x0, note, index, value)
+            }
+            Midi2Message::AssignablePerNoteController { note, index, value } => {
+                (0x1, note, index, value)
+            }
+            Midi2Message::RegisteredController { bank, index, value } => (0x2, bank, index, value),
+            Midi2Message::AssignableController { bank, index, value } => (0x3, bank, index, value),
+            Midi2Message::RelativeRegisteredController { bank, index, value } => {
+                (0x4, bank, index, value as u32)
+            }
+            Midi2Message::RelativeAssignableController { bank, index, value } => {
+                (0x5, bank, index, value as u32)
+            }
+            Midi2Message::PerNotePitchBend { note, value } => (0x6, note, 0, value),
+            Midi2Message::NoteOff {
+                note,
+                velocity,
+                attribute_type,
+                attribute,
+            } => (
+                0x8,
+                note,
+                attribute_type,
+                (velocity as u32) << 16 | attribute as u32,
+            ),
+            Midi2Message::NoteOn {
+                note,
+                velocity,
+                attribute_type,
+                attribute,
+            } => (
+                0x9,
+                note,
+                attribute_type,
+                (velocity as u32) << 16 | attribute as u32,
+            ),
+            Midi2Message::PolyPressure { note, pressure } => (0xA, note, 0, pressure),
+            Midi2Message::ControlChange { controller, value } => (0xB, controller, 0, value),
+            Midi2Message::ProgramChange { program, bank } => match bank {
+                Some((msb, lsb)) => (
+                    0xC,
+                    0,
+                    0x01,
+                    (program as u32) << 24 | (msb as u32) << 8 | lsb as u32,
+                ),
+                None => (0xC, 0, 0, (program as u32) << 24),
+            },
+            Midi2Message::ChannelPressure { pressure } => (0xD, 0, 0, pressure),
+            Midi2Message::PitchBend { value } => (0xE, 0, 0, value),
+            Midi2Message::PerNoteManagement {
+                note,
+                detach,
+                reset,
+            } => (0xF, note, (detach as u8) << 1 | reset as u8, 0),
+        };
+        [
+            header | status << 20 | (b3 as u32 & 0x7F) << 8 | b4 as u32,
+            data,
+            0,
+            0,
+        ]
+    }
+}
+
+impl StreamMessage {
+    fn decode(w: &[u32; 4]) -> StreamMessage {
+        let format = Chunk::from_bits(w[0] >> 26);
+        let status = ((w[0] >> 16) & 0x3FF) as u16;
+        let protocol = match (w[0] >> 8) & 0xFF {
+            0x01 => Some(Protocol::Midi1),
+            0x02 => Some(Protocol::Midi2),
+            _ => None,
+        };
+        match (status, protocol) {
+            (0x000, _) => StreamMessage::EndpointDiscovery {
+                ump_version_major: (w[0] >> 8) as u8,
+                ump_version_minor: w[0] as u8,
+                filter: w[1] as u8,
+            },
+            (0x005, Some(protocol)) => StreamMessage::StreamConfigRequest {
+                protocol,
+                rx_jr: w[0] & 0x2 != 0,
+                tx_jr: w[0] & 0x1 != 0,
+            },
+            (0x006, Some(protocol)) => StreamMessage::StreamConfigNotification {
+                protocol,
+                rx_jr: w[0] & 0x2 != 0,
+                tx_jr: w[0] & 0x1 != 0,
+            },
+            (0x010, _) => StreamMessage::FunctionBlockDiscovery {
+                block: (w[0] >> 8) as u8,
+                filter: w[0] as u8,
+            },
+            (0x020, _) => StreamMessage::StartOfClip,
+            (0x021, _) => StreamMessage::EndOfClip,
+            _ => StreamMessage::Other {
+                format,
+                status,
+                data: [w[0] & 0xFFFF, w[1], w[2], w[3]],
+            },
+        }
+    }
+
+    fn encode(&self) -> [u32; 4] {
+        fn first(format: Chunk, status: u16, data: u32) -> u32 {
+            0xF << 28 | format.bits() << 26 | (status as u32 & 0x3FF) << 16 | (data & 0xFFFF)
+        }
+        fn config(protocol: Protocol, rx_jr: bool, tx_jr: bool) -> u32 {
+            let protocol = match protocol {
+                Protocol::Midi1 => 0x01,
+                Protocol::Midi2 => 0x02,
+            };
+            protocol << 8 | (rx_jr as u32) << 1 | tx_jr as u32
+        }
+        match *self {
+            StreamMessage::EndpointDiscovery {
+                ump_version_major,
+                ump_version_minor,
+                filter,
+            } => [
+                first(
+                    Chunk::Complete,
+                    0x000,
+                    (ump_version_major as u32) << 8 | ump_version_minor as u32,
+                ),
+                filter as u32,
+                0,
+                0,
+            ],
+            StreamMessage::StreamConfigRequest {
+                protocol,
+                rx_jr,
+                tx_jr,
+            } => [
+                first(Chunk::Complete, 0x005, config(protocol, rx_jr, tx_jr)),
+                0,
+                0,
+                0,
+            ],
+            StreamMessage::StreamConfigNotification {
+                protocol,
+                rx_jr,
+                tx_jr,
+            } => [
+                first(Chunk::Complete, 0x006, config(protocol, rx_jr, tx_jr)),
+                0,
+                0,
+                0,
+            ],
+            StreamMessage::FunctionBlockDiscovery { block, filter } => [
+                first(Chunk::Complete, 0x010, (block as u32) << 8 | filter as u32),
+                0,
+                0,
+                0,
+            ],
+            StreamMessage::StartOfClip => [first(Chunk::Complete, 0x020, 0), 0, 0, 0],
+            StreamMessage::EndOfClip => [first(Chunk::Complete, 0x021, 0), 0, 0, 0],
+            StreamMessage::Other {
+                format,
+                status,
+                data,
+            } => [first(format, status, data[0]), data[1], data[2], data[3]],
+        }
+    }
+}
+
+/// The controller that the data entry controllers of a channel are applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Parameter {
+    None,
+    Registered,
+    Assignable,
+}
+
+/// The state of the bank select and parameter number controllers of a channel.
+#[derive(Debug, Clone, Copy)]
+struct ChannelState {
+    bank_msb: Option<u8>,
+    bank_lsb: Option<u8>,
+    parameter: Parameter,
+    rpn: (u8, u8),
+    nrpn: (u8, u8),
+    data_msb: Option<u8>,
+}
+
+impl ChannelState {
+    const fn new() -> ChannelState {
+        ChannelState {
+            bank_msb: None,
+            bank_lsb: None,
+            parameter: Parameter::None,
+            rpn: (0x7F, 0x7F),
+            nrpn: (0x7F, 0x7F),
+            data_msb: None,
+        }
+    }
+}
+
+/// Translates MIDI 1.0 byte messages into packets.
+///
+/// With the MIDI 1.0 protocol, channel voice messages become packets of message type 2.
+/// With the MIDI 2.0 protocol, they are translated into MIDI 2.0 channel voice messages
+/// as described by the UMP specification: values are scaled up, the bank select controllers
+/// are combined with the following program change, and the parameter number and data entry
+/// controllers are combined into registered and assignable controller messages. System
+/// messages and system exclusive messages are the same for both protocols.
+#[derive(Debug, Clone)]
+pub struct Upscaler {
+    group: u8,
+    protocol: Protocol,
+    channels: [ChannelState; 16],
+}
+
+impl Upscaler {
+    /// Creates a translator that produces packets of the given group and protocol.
+    pub fn new(group: u8, protocol: Protocol) -> Upscaler {
+        Upscaler {
+            group: group & 0xF,
+            protocol,
+            channels: [ChannelState::new(); 16],
+        }
+    }
+
+    /// Translates a complete MIDI 1.0 message and passes the resulting packets to `f`.
+    /// Some messages (e.g. bank select with the MIDI 2.0 protocol) do not produce a packet
+    /// on their own, and invalid messages are ignored.
+    pub fn convert<F: FnMut(Packet)>(&mut self, message: &[u8], mut f: F) {
+        let group = self.group;
+        match message.first() {
+            Some(0xF0) => {
+                // System exclusive messages are split into chunks of 6 bytes
+                let end = if message.last() == Some(&0xF7) {
+                    message.len() - 1
+                } else {
+                    message.len()
+                };
+                let data = &message[1..end.max(1)];
+                let count = data.len().div_ceil(6).max(1);
+                for (i, chunk) in data
+                    .chunks(6)
+                    .chain(data.is_empty().then_some(&[][..]))
+                    .enumerate()
+                {
+                    let status = match (i, count) {
+                        (_, 1) => Chunk::Complete,
+                        (0, _) => Chunk::Start,
+                        (i, count) if i == count - 1 => Chunk::End,
+                        _ => Chunk::Continue,
+                    };
+                    f(Message::SysEx7 {
+                        group,
+                        status,
+                        data: chunk.to_vec(),
+                    }
+                    .to_packet());
+                }
+            }
+            Some(0xF1..=0xFF) => {
+                if let Some(message) = SystemMessage::from_bytes(message) {
+                    f(Message::System { group, message }.to_packet());
+                }
+            }
+            _ => {
+                if let Some((channel, message)) = Midi1Message::from_bytes(message) {
+                    match self.protocol {
+                        Protocol::Midi1 => f(Message::Midi1 {
+                            group,
+                            channel,
+                            message,
+                        }
+                        .to_packet()),
+                        Protocol::Midi2 => {
+                            if let Some(message) = self.upscale(channel, message) {
+                                f(Message::Midi2 {
+                                    group,
+                                    channel,
+                                    message,
+                                }
+                                .to_packet());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn upscale(&mut self, channel: u8, message: Midi1Message) -> Option<Midi2Message> {
+        let state = &mut self.channels[channel as usize];
+        Some(match message {
+            Midi1Message::NoteOff { note, velocity } => Midi2Message::NoteOff {
+                note,
+                velocity: scale_up(velocity as u32, 7, 16) as u16,
+                attribute_type: 0,
+                attribute: 0,
+            },
+            // A note on with a velocity of 0 is a note off in MIDI 1.0 only
+            Midi1Message::NoteOn { note, velocity: 0 } => Midi2Message::NoteOff {
+                note,
+                velocity: 0,
+                attribute_type: 0,
+                attribute: 0,
+            },
+            Midi1Message::NoteOn { note, velocity } => Midi2Message::NoteOn {
+                note,
+                velocity: scale_up(velocity as u32, 7, 16) as u16,
+                attribute_type: 0,
+                attribute: 0,
+            },
+            Midi1Message::PolyPressure { note, pressure } => Midi2Message::PolyPressure {
+                note,
+                pressure: scale_up(pressure as u32, 7, 32),
+            },
+            Midi1Message::ControlChange { controller, value } => match controller {
+                0 => {
+                    state.bank_msb = Some(value);
+                    return None;
+                }
+                32 => {
+                    state.bank_lsb = Some(value);
+                    return None;
+                }
+                98..=101 => {
+                    let (parameter, number) = if controller >= 100 {
+                        (Parameter::Registered, &mut state.rpn)
+                    } else {
+                        (Parameter::Assignable, &mut state.nrpn)
+                    };
+                    if controller % 2 == 1 {
+                        number.0 = value;
+                    } else {
+                        number.1 = value;
+                    }
+                    // The null parameter number deselects the parameter
+                    state.parameter = if *number == (0x7F, 0x7F) {
+                        Parameter::None
+                    } else {
+                        parameter
+                    };
+                    state.data_msb = None;
+                    return None;
+                }
+                6 | 38 if state.parameter != Parameter::None => {
+                    let (msb, lsb) = if controller == 6 {
+                        state.data_msb = Some(value);
+                        (value, 0)
+                    } else {
+                        (state.data_msb?, value)
+                    };
+                    let value = scale_up((msb as u32) << 7 | lsb as u32, 14, 32);
+                    match state.parameter {
+                        Parameter::Registered => Midi2Message::RegisteredController {
+                            bank: state.rpn.0,
+                            index: state.rpn.1,
+                            value,
+                        },
+                        _ => Midi2Message::AssignableController {
+                            bank: state.nrpn.0,
+                            index: state.nrpn.1,
+                            value,
+                        },
+                    }
+                }
+                _ => Midi2Message::ControlChange {
+                    controller,
+                    value: scale_up(value as u32, 7, 32),
+                },
+            },
+            Midi1Message::ProgramChange { program } => Midi2Message::ProgramChange {
+                program,
+                bank: state.bank_msb.map(|msb| (msb, state.bank_lsb.unwrap_or(0))),
+            },
+            Midi1Message::ChannelPressure { pressure } => Midi2Message::ChannelPressure {
+                pressure: scale_up(pressure as u32, 7, 32),
+            },
+            Midi1Message::PitchBend { value } => Midi2Message::PitchBend {
+                value: scale_up(value as u32, 14, 32),
+            },
+        })
+    }
+}
+
+/// Translates packets into MIDI 1.0 byte messages, e.g. to send them with
+/// `MidiOutputConnection::send`.
+///
+/// System messages and MIDI 1.0 channel voice messages are translated directly, and the
+/// chunks of 7-bit system exclusive messages are collected (separately for every group)
+/// until the message is complete. MIDI 2.0 channel voice messages are scaled down as
+/// described by the UMP specification, so registered and assignable controllers become
+/// parameter number and data entry controllers and a program change with a bank becomes
+/// bank select controllers followed by the program change. Other messages (e.g. per-note
+/// controllers) have no MIDI 1.0 equivalent and are dropped.
+#[derive(Debug, Clone)]
+pub struct Downscaler {
+    sysex: [Vec<u8>; 16],
+}
+
+impl Default for Downscaler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downscaler {
+    /// Creates a translator for packets of all groups.
+    pub fn new() -> Downscaler {
+        Downscaler {
+            sysex: Default::default(),
+        }
+    }
+
+    /// Translates a packet and passes the resulting complete messages to `f`.
+    pub fn convert<F: FnMut(&[u8])>(&mut self, packet: &Packet, mut f: F) {
+        match packet.message() {
+            Message::System { message, .. } => f(&message.to_bytes()),
+            Message::Midi1 {
+                channel, message, ..
+            } => f(&message.to_bytes(channel)),
+            Message::SysEx7 {
+                group,
+                status,
+                data,
+            } => {
+                let buffer = &mut self.sysex[group as usize];
+                if matches!(status, Chunk::Complete | Chunk::Start) {
+                    buffer.clear();
+                    buffer.push(0xF0);
+                } else if buffer.is_empty() {
+                    return; // the start of the message is missing
+                }
+                buffer.extend_from_slice(&data);
+                if matches!(status, Chunk::Complete | Chunk::End) {
+                    buffer.push(0xF7);
+                    f(buffer);
+                    buffer.clear();
+                }
+            }
+            Message::Midi2 {
+                channel, message, ..
+            } => {
+                for message in downscale(message) {
+                    f(&message.to_bytes(channel));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn downscale(message: Midi2Message) -> Vec<Midi1Message> {
+    let cc = |controller: u8, value: u8| Midi1Message::ControlChange { controller, value };
+    let parameter = |msb_cc: u8, bank: u8, index: u8, value: u32| {
+        let value = scale_down(value, 32, 14);
+        vec![
+            cc(msb_cc, bank),
+            cc(msb_cc - 1, index),
+            cc(6, (value >> 7) as u8),
+            cc(38, (value & 0x7F) as u8),
+        ]
+    };
+    match message {
+        Midi2Message::NoteOff { note, velocity, .. } => vec![Midi1Message::NoteOff {
+            note,
+            velocity: scale_down(velocity as u32, 16, 7) as u8,
+        }],
+        Midi2Message::NoteOn { note, velocity, .. } => vec![Midi1Message::NoteOn {
+            note,
+            // A velocity of 0 would turn the note off
+            velocity: (scale_down(velocity as u32, 16, 7) as u8).max(1),
+        }],
+        Midi2Message::PolyPressure { note, pressure } => vec![Midi1Message::PolyPressure {
+            note,
+            pressure: scale_down(pressure, 32, 7) as u8,
+        }],
+        Midi2Message::ControlChange { controller, value } => {
+            vec![cc(controller, scale_down(value, 32, 7) as u8)]
+        }
+        Midi2Message::RegisteredController { bank, index, value } => {
+            parameter(101, bank, index, value)
+        }
+        Midi2Message::AssignableController { bank, index, value } => {
+            parameter(99, bank, index, value)
+        }
+        Midi2Message::ProgramChange { program, bank } => {
+            let mut messages = match bank {
+                Some((msb, lsb)) => vec![cc(0, msb), cc(32, lsb)],
+                None => Vec::new(),
+            };
+            messages.push(Midi1Message::ProgramChange { program });
+            messages
+        }
+        Midi2Message::ChannelPressure { pressure } => vec![Midi1Message::ChannelPressure {
+            pressure: scale_down(pressure, 32, 7) as u8,
+        }],
+        Midi2Message::PitchBend { value } => vec![Midi1Message::PitchBend {
+            value: scale_down(value, 32, 14) as u16,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upscale(protocol: Protocol, messages: &[&[u8]]) -> Vec<Vec<u32>> {
+        let mut upscaler = Upscaler::new(1, protocol);
+        let mut packets = Vec::new();
+        for message in messages {
+            upscaler.convert(message, |packet| packets.push(packet.words().to_vec()));
+        }
+        packets
+    }
+
+    fn downscale(words: &[u32]) -> Vec<Vec<u8>> {
+        let mut downscaler = Downscaler::new();
+        let mut messages = Vec::new();
+        for packet in packets(words) {
+            downscaler.convert(&packet, |message| messages.push(message.to_vec()));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_scaling() {
+        assert_eq!(scale_up(0, 7, 16), 0);
+        assert_eq!(scale_up(0x40, 7, 16), 0x8000);
+        assert_eq!(scale_up(0x7F, 7, 16), 0xFFFF);
+        assert_eq!(scale_up(0x7F, 7, 32), 0xFFFF_FFFF);
+        assert_eq!(scale_up(0x2000, 14, 32), 0x8000_0000);
+        assert_eq!(scale_up(0x3FFF, 14, 32), 0xFFFF_FFFF);
+        for value in 0..128 {
+            assert_eq!(scale_down(scale_up(value, 7, 32), 32, 7), value);
+        }
+    }
+
+    #[test]
+    fn test_packets() {
+        let words = [0x2090_3C64, 0x4090_3C00, 0xFFFF_0000, 0xF000_0101, 0, 0];
+        let packets: Vec<_> = packets(&words).collect();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].group(), Some(0));
+        assert_eq!(packets[1].words(), &words[1..3]);
+        assert_eq!(Packet::new(&[0x4090_3C00]), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let messages = [
+            Message::Utility(Utility::JrTimestamp(0x1234)),
+            Message::System {
+                group: 2,
+                message: SystemMessage::SongPosition(0x1234),
+            },
+            Message::Midi1 {
+                group: 3,
+                channel: 4,
+                message: Midi1Message::PitchBend { value: 0x2001 },
+            },
+            Message::SysEx7 {
+                group: 0,
+                status: Chunk::Start,
+                data: vec![0x7E, 0x7F, 0x06, 0x01, 0x02],
+            },
+            Message::Midi2 {
+                group: 5,
+                channel: 6,
+                message: Midi2Message::NoteOn {
+                    note: 60,
+                    velocity: 0xC000,
+                    attribute_type: 3,
+                    attribute: 0x1234,
+                },
+            },
+            Message::Midi2 {
+                group: 5,
+                channel: 6,
+                message: Midi2Message::ProgramChange {
+                    program: 10,
+                    bank: Some((1, 2)),
+                },
+            },
+            Message::Midi2 {
+                group: 0,
+                channel: 0,
+                message: Midi2Message::PerNoteManagement {
+                    note: 61,
+                    detach: true,
+                    reset: false,
+                },
+            },
+            Message::SysEx8 {
+                group: 7,
+                status: Chunk::Complete,
+                stream_id: 9,
+                data: (0..13).collect(),
+            },
+            Message::FlexData(FlexData::set_tempo(1, 50_000_000)),
+            Message::Stream(StreamMessage::StreamConfigRequest {
+                protocol: Protocol::Midi2,
+                rx_jr: false,
+                tx_jr: true,
+            }),
+        ];
+        for message in messages {
+            assert_eq!(message.to_packet().message(), message);
+        }
+        let packet = Message::Midi1 {
+            group: 3,
+            channel: 4,
+            message: Midi1Message::NoteOn {
+                note: 60,
+                velocity: 100,
+            },
+        }
+        .to_packet();
+        assert_eq!(packet.words(), [0x2394_3C64]);
+    }
+
+    #[test]
+    fn test_upscale() {
+        assert_eq!(
+            upscale(Protocol::Midi1, &[&[0x90, 60, 100], &[0xF8]]),
+            [vec![0x2190_3C64], vec![0x11F8_0000]]
+        );
+        assert_eq!(
+            upscale(Protocol::Midi2, &[&[0x90, 60, 0x40], &[0x91, 60, 0]]),
+            [
+                vec![0x4190_3C00, 0x8000_0000],
+                vec![0x4181_3C00, 0x0000_0000]
+            ]
+        );
+        // Bank select is sent together with the program change
+        assert_eq!(
+            upscale(
+                Protocol::Midi2,
+                &[&[0xB0, 0, 1], &[0xB0, 32, 2], &[0xC0, 5]]
+            ),
+            [vec![0x41C0_0001, 0x0500_0102]]
+        );
+        // RPN 0 (pitch bend sensitivity) of 2 semitones
+        assert_eq!(
+            upscale(
+                Protocol::Midi2,
+                &[
+                    &[0xB0, 101, 0],
+                    &[0xB0, 100, 0],
+                    &[0xB0, 6, 2],
+                    &[0xB0, 38, 0]
+                ]
+            ),
+            [
+                vec![0x4120_0000, 0x0400_0000],
+                vec![0x4120_0000, 0x0400_0000]
+            ]
+        );
+        assert_eq!(
+            upscale(Protocol::Midi2, &[&[0xB0, 7, 0x7F]]),
+            [vec![0x41B0_0700, 0xFFFF_FFFF]]
+        );
+        // System exclusive messages are split into chunks
+        assert_eq!(
+            upscale(
+                Protocol::Midi2,
+                &[&[0xF0, 1, 2, 3, 4, 5, 6, 7, 0xF7], &[0xF0, 0xF7]]
+            ),
+            [
+                vec![0x3116_0102, 0x0304_0506],
+                vec![0x3131_0700, 0x0000_0000],
+                vec![0x3100_0000, 0x0000_0000]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_downscale() {
+        assert_eq!(
+            downscale(&[0x4090_3C00, 0x0000_0000, 0x40E0_0000, 0x8000_0000]),
+            [vec![0x90, 60, 1], vec![0xE0, 0x00, 0x40]]
+        );
+        assert_eq!(
+            downscale(&[0x40C0_0001, 0x0500_0102, 0x4020_0000, 0x0400_0000]),
+            [
+                vec![0xB0, 0, 1],
+                vec![0xB0, 32, 2],
+                vec![0xC0, 5],
+                vec![0xB0, 101, 0],
+                vec![0xB0, 100, 0],
+                vec![0xB0, 6, 2],
+                vec![0xB0, 38, 0]
+            ]
+        );
+        // Per-note messages have no MIDI 1.0 equivalent
+        assert!(downscale(&[0x4060_3C00, 0x8000_0000]).is_empty());
+        assert_eq!(
+            downscale(&[
+                0x3016_0102,
+                0x0304_0506,
+                0x10F8_0000,
+                0x3031_0700,
+                0x0000_0000
+            ]),
+            [vec![0xF8], vec![0xF0, 1, 2, 3, 4, 5, 6, 7, 0xF7]]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Virtual ports, which other applications can connect to.
+//!
+//! Virtual ports are supported by the ALSA, JACK, PipeWire, CoreMIDI, RTP-MIDI and dummy
+//! backends. The traits of this module are implemented on every platform, so code using them does not need to be
+//! conditionally compiled: on other backends, creating a virtual port fails at runtime,
+//! and `MidiInput::supports_virtual_ports` can be used to check for support beforehand.
+//!
+//! As `virtual` is a reserved keyword, this module must be referred to as `midir::r#virtual`.
+
+use crate::{
+    Backend, ConnectError, InitError, MidiDuplexConnection, MidiInputConnection,
+    MidiOutputConnection, Unsupported,
+};
+
+pub(crate) fn supports_virtual_ports(backend: Backend) -> Result<(), Unsupported> {
+    match backend {
+        Backend::Alsa
+        | Backend::Jack
+        | Backend::PipeWire
+        | Backend::CoreMidi
+        | Backend::Dummy
+        | Backend::RtpMidi => Ok(()),
+        _ => Err(Unsupported),
+    }
+}
+
+/// The kind of client that a virtual port is presented as, so that
+/// other applications (DAWs, patchbays) can categorize it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtualPortKind {
+    /// A port of an application, e.g. a sequencer or a MIDI effect.
+    Application,
+    /// A port of a (software) synthesizer.
+    Synth,
+    /// A port that behaves like a hardware MIDI port.
+    Hardware,
+}
+
+/// Options that can be specified when creating a virtual port with
+/// `create_virtual_with_options`.
+///
+/// Not every backend supports every option; options that a backend does not
+/// support are ignored. Currently, the kind, visibility, exclusivity, channel count
+/// and port number are only used by ALSA, while the pretty name is only used by
+/// JACK and PipeWire (as the node description), the aliases only by JACK and
+/// the UDP port only by RTP-MIDI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualPortOptions {
+    pub(crate) kind: VirtualPortKind,
+    pub(crate) no_export: bool,
+    pub(crate) exclusive: bool,
+    pub(crate) midi_channels: u32,
+    pub(crate) port_number: Option<u8>,
+    pub(crate) pretty_name: Option<String>,
+    pub(crate) aliases: Vec<String>,
+    pub(crate) udp_port: Option<u16>,
+}
+
+impl VirtualPortOptions {
+    /// Creates the options that are used by `create_virtual`: an application
+    /// port that can be routed by others and has 16 MIDI channels.
+    pub fn new() -> VirtualPortOptions {
+        VirtualPortOptions {
+            kind: VirtualPortKind::Application,
+            no_export: false,
+            exclusive: false,
+            midi_channels: 16,
+            port_number: None,
+            pretty_name: None,
+            aliases: Vec::new(),
+            udp_port: None,
+        }
+    }
+
+    /// Set the kind of client that the port is presented as.
+    pub fn kind(mut self, kind: VirtualPortKind) -> VirtualPortOptions {
+        self.kind = kind;
+        self
+    }
+
+    /// Whether the port should be hidden from patchbays, so that it can only be
+    /// routed by this application (ALSA's `NO_EXPORT` capability).
+    pub fn no_export(mut self, no_export: bool) -> VirtualPortOptions {
+        self.no_export = no_export;
+        self
+    }
+
+    /// Whether the port should only keep a single connection in each direction.
+    ///
+    /// This is best-effort rather than a capability of the port: other applications can
+    /// still connect, and any further connection is removed again shortly after it has
+    /// been made (and reported as subscribed and unsubscribed to a subscription callback),
+    /// so a few messages may pass through it in the meantime. On ALSA, the connections of
+    /// an output port created with `MidiOutput` have to be removed by a separate client,
+    /// which is not possible for ports hidden with `no_export`; creating such a port with
+    /// both options fails.
+    pub fn exclusive(mut self, exclusive: bool) -> VirtualPortOptions {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Set the number of MIDI channels that the port handles.
+    pub fn midi_channels(mut self, channels: u32) -> VirtualPortOptions {
+        self.midi_channels = channels;
+        self
+    }
+
+    /// Request a specific port number. Creating the port fails
+    /// if this number is already used by another port of the client.
+    pub fn port_number(mut self, port: u8) -> VirtualPortOptions {
+        self.port_number = Some(port);
+        self
+    }
+
+    /// Set a human-readable name that is shown instead of the port name
+    /// by applications that support it.
+    pub fn pretty_name(mut self, name: &str) -> VirtualPortOptions {
+        self.pretty_name = Some(name.to_string());
+        self
+    }
+
+    /// Add an alias under which the port can also be found.
+    pub fn alias(mut self, alias: &str) -> VirtualPortOptions {
+        self.aliases.push(alias.to_string());
+        self
+    }
+
+    /// Set the UDP port on which an RTP-MIDI session is published. This is the control
+    /// port, the data port is the one after it. By default, a free pair of ports is chosen.
+    pub fn udp_port(mut self, port: u16) -> VirtualPortOptions {
+        self.udp_port = Some(port);
+        self
+    }
+}
+
+impl Default for VirtualPortOptions {
+    fn default() -> Self {
+        VirtualPortOptions::new()
+    }
+}
+
+/// Trait that is implemented by `MidiInput`. Creating a virtual port
+/// fails on backends that do not support them (currently ALSA rawmidi, WinMM,
+/// WinRT, Web MIDI, ipMIDI and serial).
+pub trait VirtualInput<T: Send>
+where
+    Self: Sized,
+{
+    /// Creates a virtual input port. Once it has been created,
+    /// other applications can connect to this port and send MIDI
+    /// messages which will be received by this port.
+    fn create_virtual<F>(
+        self,
+        port_name: &str,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static,
+    {
+        self.create_virtual_with_options(port_name, &VirtualPortOptions::new(), callback, data)
+    }
+
+    /// Creates a virtual input port like `create_virtual`, using the
+    /// given options to configure the port.
+    fn create_virtual_with_options<F>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiInputConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static;
+}
+
+/// Trait that is implemented by `MidiOutput`. Creating a virtual port
+/// fails on backends that do not support them (currently ALSA rawmidi, WinMM,
+/// WinRT, Web MIDI, ipMIDI and serial).
+pub trait VirtualOutput
+where
+    Self: Sized,
+{
+    /// Creates a virtual output port. Once it has been created,
+    /// other applications can connect to this port and will
+    /// receive MIDI messages that are sent to this port.
+    fn create_virtual(self, port_name: &str) -> Result<MidiOutputConnection, ConnectError<Self>> {
+        self.create_virtual_with_options(port_name, &VirtualPortOptions::new())
+    }
+
+    /// Creates a virtual output port like `create_virtual`, using the
+    /// given options to configure the port.
+    fn create_virtual_with_options(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+    ) -> Result<MidiOutputConnection, ConnectError<Self>>;
+}
+
+/// Trait that is implemented by `MidiInput`, for virtual ports which can both receive
+/// and send messages. Creating such a port fails on backends that do not support virtual ports.
+pub trait VirtualDuplex<T: Send>
+where
+    Self: Sized,
+{
+    /// Creates a single virtual port that other applications can both send
+    /// MIDI messages to and receive MIDI messages from. Incoming messages are
+    /// passed to `callback`, messages sent through the returned output
+    /// connection are delivered to everyone who is connected to the port.
+    ///
+    /// On ALSA, this creates one port on the client of this `MidiInput`, which
+    /// is removed once both connections have been closed. On JACK, a pair of
+    /// ports named `<port_name> in` and `<port_name> out` is registered on the
+    /// same client; both are removed when the input connection is closed.
+    /// On CoreMIDI, a virtual destination and a virtual source of the same name
+    /// are created. On RTP-MIDI, one session is published, which ends once both
+    /// connections have been closed.
+    ///
+    /// Closing the output connection returns a `MidiOutput` object. On ALSA, it uses
+    /// the client of this `MidiInput`. On JACK and CoreMIDI, a separate client with
+    /// the same name is opened for it when the port is created; on CoreMIDI, this
+    /// client also owns the virtual source, so it outlives the input connection.
+    fn create_virtual_duplex<F>(
+        self,
+        port_name: &str,
+        options: &VirtualPortOptions,
+        callback: F,
+        data: T,
+    ) -> Result<MidiDuplexConnection<T>, ConnectError<Self>>
+    where
+        F: FnMut(u64, &[u8], &mut T) + Send + 'static;
+}
+
+/// A port that is connected to the port of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscriber {
+    id: String,
+    name: String,
+}
+
+impl Subscriber {
+    #[cfg(any(
+        all(target_os = "linux", feature = "alsa"),
+        all(feature = "jack", not(target_os = "windows")),
+        feature = "dummy"
+    ))]
+    pub(crate) fn new(id: String, name: String) -> Subscriber {
+        Subscriber { id, name }
+    }
+
+    /// Get a unique identifier of the connected port, in the same
+    /// format as `MidiInputPort::id` and `MidiOutputPort::id`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the name of the connected port, in the same format as `port_name`.
+    /// If the port has already disappeared when it is reported as unsubscribed,
+    /// its identifier is used instead.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A change of the ports that are connected to the port of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionEvent {
+    /// A port has been connected.
+    Subscribed(Subscriber),
+    /// A port has been disconnected.
+    Unsubscribed(Subscriber),
+}
+
+/// Trait that is implemented by `MidiInputConnection` and `MidiOutputConnection`
+/// on the ALSA, JACK and dummy backends, to keep track of the ports that are connected
+/// to a connection's own port (usually one created with `create_virtual`).
+///
+/// On the dummy backend, connections do not have ports of their own, so each
+/// subscriber stands for a connection (named after its client and the `port_name`
+/// it has been made with), and only virtual ports have subscribers.
+pub trait VirtualSubscribers {
+    /// Get the ports that are currently connected to the port of this connection.
+    fn subscribers(&self) -> Vec<Subscriber>;
+
+    /// Returns whether any port is currently connected to the port of this connection.
+    fn has_subscribers(&self) -> bool {
+        !self.subscribers().is_empty()
+    }
+
+    /// Register a callback that is called whenever a port is connected to or
+    /// disconnected from the port of this connection, replacing any previously
+    /// registered callback.
+    ///
+    /// The callback is called from a background thread: on ALSA, the input handler
+    /// thread for input connections, or a thread with its own (hidden) sequencer
+    /// client for output connections, and on JACK the notification thread of the client.
+    /// On the dummy backend, it is called on the thread that makes or closes the connection.
+    fn on_subscription_change<F>(&mut self, callback: F) -> Result<(), InitError>
+    where
+        F: FnMut(SubscriptionEvent) + Send + 'static;
+}
@@ -0,0 +1,278 @@
+//! Tests of the in-process `dummy` backend. They share one graph of ports,
+//! so each test uses its own port names instead of relying on port counts.
+#![cfg(feature = "dummy")]
+
+use std::sync::mpsc::channel;
+
+use midir::os::dummy::{disconnect_port, MidiInputConnectionExt};
+use midir::r#virtual::{
+    SubscriptionEvent, VirtualDuplex, VirtualInput, VirtualOutput, VirtualPortOptions,
+    VirtualSubscribers,
+};
+use midir::{
+    available_backends, Backend, ConnectErrorKind, Ignore, MidiDevice, MidiIO, MidiInput,
+    MidiOutput, SendError,
+};
+
+fn dummy_in(client_name: &str) -> MidiInput {
+    MidiInput::with_backend(Backend::Dummy, client_name).unwrap()
+}
+
+fn dummy_out(client_name: &str) -> MidiOutput {
+    MidiOutput::with_backend(Backend::Dummy, client_name).unwrap()
+}
+
+fn find_port<T: MidiIO>(io: &T, name: &str) -> T::Port {
+    io.ports()
+        .into_iter()
+        .find(|p| io.port_name(p).unwrap().ends_with(name))
+        .unwrap()
+}
+
+#[test]
+fn ignore_and_timestamps() {
+    let mut midi_in = dummy_in("Dummy Ignore Input");
+    midi_in.ignore(Ignore::Time);
+    let midi_out = dummy_out("Dummy Ignore Output");
+
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .create_virtual(
+            "dummy-ignore",
+            move |stamp, message, _| sender.send((stamp, message.to_vec())).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    let port = find_port(&midi_out, "dummy-ignore");
+    assert_eq!(
+        midi_out.port_name(&port).unwrap(),
+        "Dummy Ignore Input:dummy-ignore"
+    );
+    let mut conn_out = midi_out.connect(&port, "dummy-ignore").unwrap();
+
+    conn_in.set_fixed_timestamp(Some(1234));
+    conn_out.send(&[0xF8]).unwrap();
+    conn_out.send(&[0x90, 60, 1]).unwrap();
+
+    // Messages are delivered synchronously, and the clock message has been ignored
+    assert_eq!(receiver.try_recv(), Ok((1234, vec![0x90, 60, 1])));
+    assert!(receiver.try_recv().is_err());
+
+    assert_eq!(
+        conn_out.send(&[60, 1]),
+        Err(SendError::InvalidData(
+            "message must start with a status byte"
+        ))
+    );
+}
+
+#[test]
+fn duplex_and_disconnect() {
+    let midi_in = dummy_in("Dummy Duplex");
+    let observer_in = dummy_in("Dummy Observer");
+    let observer_out = dummy_out("Dummy Observer");
+
+    let (conn_in, mut conn_out) = midi_in
+        .create_virtual_duplex(
+            "dummy-duplex",
+            &VirtualPortOptions::new(),
+            |_, _, count: &mut usize| *count += 1,
+            0,
+        )
+        .unwrap();
+
+    let in_port = find_port(&observer_in, "dummy-duplex");
+    let out_port = find_port(&observer_out, "dummy-duplex");
+    assert_eq!(in_port.id(), out_port.id());
+
+    let (sender, receiver) = channel();
+    let observer = observer_in
+        .connect(
+            &in_port,
+            "dummy-observer",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    let mut observer_conn = observer_out.connect(&out_port, "dummy-observer").unwrap();
+
+    conn_out.send(&[0x90, 60, 1]).unwrap();
+    assert_eq!(receiver.try_recv(), Ok(vec![0x90, 60, 1]));
+    observer_conn.send(&[0x80, 60, 0]).unwrap();
+
+    // Unplugging the port stops all communication through it
+    assert!(disconnect_port(&in_port.id()));
+    assert!(!disconnect_port(&in_port.id()));
+    assert!(conn_out.send(&[0x90, 60, 1]).is_err());
+    assert!(observer_conn.send(&[0x80, 60, 0]).is_err());
+    assert!(receiver.try_recv().is_err());
+
+    observer.close();
+    conn_out.close();
+    assert_eq!(conn_in.close().1, 1);
+}
+
+#[test]
+fn device_open_duplex() {
+    let midi_in = dummy_in("Dummy Device");
+    let (_device_in, mut device_out) = midi_in
+        .create_virtual_duplex("dummy-device", &VirtualPortOptions::new(), |_, _, _| {}, ())
+        .unwrap();
+    let other = dummy_out("Dummy Other Device")
+        .create_virtual("dummy-other")
+        .unwrap();
+
+    let midi_in = dummy_in("Dummy Device User");
+    let midi_out = dummy_out("Dummy Device User");
+    let devices = MidiDevice::list(&midi_in, &midi_out);
+    let device = devices.iter().find(|d| d.name() == "Dummy Device").unwrap();
+    let foreign = devices
+        .iter()
+        .find(|d| d.name() == "Dummy Other Device")
+        .unwrap();
+    assert!(device.is_duplex());
+    assert!(!foreign.is_duplex());
+
+    // Ports of another device are rejected
+    let err = match device.open_duplex(
+        midi_in,
+        midi_out,
+        &foreign.inputs()[0],
+        &device.outputs()[0],
+        "dummy-device-user",
+        |_, _, _| {},
+        (),
+    ) {
+        Err(err) => err,
+        Ok(_) => panic!("ports of another device must be rejected"),
+    };
+    assert_eq!(
+        err.kind(),
+        ConnectErrorKind::Other("input port does not belong to this device")
+    );
+    let (midi_in, midi_out) = err.into_inner();
+
+    let (sender, receiver) = channel();
+    let (conn_in, conn_out) = device
+        .open_duplex(
+            midi_in,
+            midi_out,
+            &device.inputs()[0],
+            &device.outputs()[0],
+            "dummy-device-user",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    device_out.send(&[0x90, 60, 1]).unwrap();
+    assert_eq!(receiver.try_recv(), Ok(vec![0x90, 60, 1]));
+
+    conn_in.close();
+    conn_out.close();
+    other.close();
+}
+
+#[test]
+fn virtual_output_subscribers() {
+    let midi_in = dummy_in("Dummy Subscriber");
+    let midi_out = dummy_out("Dummy Source");
+
+    let mut conn_out = midi_out.create_virtual("dummy-source").unwrap();
+    let (sender, receiver) = channel();
+    conn_out
+        .on_subscription_change(move |event| sender.send(event).unwrap())
+        .unwrap();
+    // Without subscribers, messages are dropped
+    assert!(!conn_out.has_subscribers());
+    conn_out.send(&[0x90, 60, 1]).unwrap();
+
+    let port = find_port(&midi_in, "dummy-source");
+    let conn_in = midi_in
+        .connect(
+            &port,
+            "dummy-subscriber",
+            |_, _, count: &mut usize| *count += 1,
+            0,
+        )
+        .unwrap();
+    let subscriber = match receiver.try_recv() {
+        Ok(SubscriptionEvent::Subscribed(subscriber)) => subscriber,
+        event => panic!("unexpected event: {:?}", event),
+    };
+    assert_eq!(subscriber.name(), "Dummy Subscriber:dummy-subscriber");
+    assert_eq!(conn_out.subscribers(), [subscriber.clone()]);
+    // The connection of the subscriber does not have a port of its own
+    assert!(conn_in.subscribers().is_empty());
+
+    conn_out.send(&[0x90, 60, 1]).unwrap();
+    conn_out.send(&[0x80, 60, 0]).unwrap();
+
+    let (midi_in, count) = conn_in.close();
+    assert_eq!(count, 2);
+    assert_eq!(
+        receiver.try_recv(),
+        Ok(SubscriptionEvent::Unsubscribed(subscriber))
+    );
+    assert!(!conn_out.has_subscribers());
+
+    conn_out.close();
+    assert!(midi_in
+        .ports()
+        .iter()
+        .all(|p| !midi_in.port_name(p).unwrap().ends_with("dummy-source")));
+}
+
+#[test]
+fn virtual_input_subscribers() {
+    let midi_in = dummy_in("Dummy Sink");
+    let midi_out = dummy_out("Dummy Writer");
+
+    let mut conn_in = midi_in
+        .create_virtual("dummy-sink", |_, _, _| {}, ())
+        .unwrap();
+    let (sender, receiver) = channel();
+    conn_in
+        .on_subscription_change(move |event| sender.send(event).unwrap())
+        .unwrap();
+
+    let port = find_port(&midi_out, "dummy-sink");
+    let conn_out = midi_out.connect(&port, "dummy-writer").unwrap();
+    let subscriber = match receiver.try_recv() {
+        Ok(SubscriptionEvent::Subscribed(subscriber)) => subscriber,
+        event => panic!("unexpected event: {:?}", event),
+    };
+    assert_eq!(subscriber.name(), "Dummy Writer:dummy-writer");
+    assert_eq!(conn_in.subscribers(), [subscriber.clone()]);
+
+    conn_out.close();
+    assert_eq!(
+        receiver.try_recv(),
+        Ok(SubscriptionEvent::Unsubscribed(subscriber))
+    );
+    assert!(!conn_in.has_subscribers());
+    conn_in.close();
+}
+
+#[test]
+fn backend_selection() {
+    // The dummy backend must be selected explicitly if any other backend is available
+    assert_eq!(available_backends().last(), Some(&Backend::Dummy));
+    assert_eq!(Backend::from_name("DUMMY"), Some(Backend::Dummy));
+    assert!(!Backend::WebMidi.is_available());
+    assert!(MidiOutput::with_backend(Backend::WebMidi, "Dummy Backend").is_err());
+
+    let midi_in = MidiInput::with_backend(Backend::Dummy, "Dummy Backend").unwrap();
+    assert_eq!(midi_in.backend(), Backend::Dummy);
+    assert!(midi_in.supports_virtual_ports().is_ok());
+
+    // The other tests choose their backend explicitly, so they are not affected by this
+    std::env::set_var("MIDIR_BACKEND", "Dummy");
+    let midi_in = MidiInput::new("Dummy Env").unwrap();
+    assert_eq!(midi_in.backend(), Backend::Dummy);
+    let midi_out = MidiOutput::new("Dummy Env").unwrap();
+    assert_eq!(midi_out.backend(), Backend::Dummy);
+    std::env::set_var("MIDIR_BACKEND", "no-such-backend");
+    assert!(MidiInput::new("Dummy Env").is_err());
+    std::env::remove_var("MIDIR_BACKEND");
+}
@@ -0,0 +1,78 @@
+//! These tests send ipMIDI datagrams to a multicast group on the loopback interface,
+//! which the system must allow (it does by default on Linux, macOS and Windows).
+#![cfg(all(feature = "ipmidi", not(target_arch = "wasm32")))]
+
+use std::net::Ipv4Addr;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use midir::os::ipmidi;
+use midir::{Backend, Ignore, MidiInput, MidiOutput};
+
+#[test]
+fn loopback() {
+    ipmidi::set_interface(Ipv4Addr::LOCALHOST);
+    ipmidi::add_port("midir-test-ipmidi", ipmidi::DEFAULT_GROUP, 21990);
+
+    let mut midi_in = MidiInput::with_backend(Backend::IpMidi, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).unwrap() == "midir-test-ipmidi")
+        .expect("configured port not found");
+    assert_eq!(port.id(), "225.0.0.37:21990");
+
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .connect(
+            &port,
+            "midir-test",
+            move |stamp, message, _| sender.send((stamp, message.to_vec())).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    let midi_out = MidiOutput::with_backend(Backend::IpMidi, "midir-test").unwrap();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).unwrap() == "midir-test-ipmidi")
+        .expect("configured port not found");
+    let mut conn_out = midi_out.connect(&port, "midir-test").unwrap();
+
+    // Running status and interleaved real-time messages are split into messages
+    conn_out.send(&[0x90, 60, 100, 62, 0xF8, 100]).unwrap();
+    conn_out
+        .send(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])
+        .unwrap();
+    let mut messages = Vec::new();
+    for _ in 0..4 {
+        messages.push(receiver.recv_timeout(Duration::from_secs(1)).unwrap());
+    }
+    assert_eq!(messages[0].1, [0x90, 60, 100]);
+    assert_eq!(messages[1].1, [0xF8]);
+    assert_eq!(messages[2].1, [0x90, 62, 100]);
+    assert_eq!(messages[3].1, [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+    // Messages of one datagram share the arrival time
+    assert_eq!(messages[0].0, messages[2].0);
+    assert!(messages[3].0 >= messages[2].0);
+
+    // A message that is split across datagrams is not broken up by another sender
+    let midi_out = MidiOutput::with_backend(Backend::IpMidi, "midir-test").unwrap();
+    let mut other_out = midi_out.connect(&port, "midir-test").unwrap();
+    conn_out.send(&[0x90, 60]).unwrap();
+    other_out.send(&[0xB0, 7, 100]).unwrap();
+    conn_out.send(&[100]).unwrap();
+    let first = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    let second = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(first.1, [0xB0, 7, 100]);
+    assert_eq!(second.1, [0x90, 60, 100]);
+    other_out.close();
+
+    conn_out.close();
+    let (midi_in, _) = conn_in.close();
+    assert_eq!(midi_in.backend(), Backend::IpMidi);
+    assert!(ipmidi::remove_port(ipmidi::DEFAULT_GROUP, 21990));
+    assert_eq!(midi_in.port_count(), 0);
+}
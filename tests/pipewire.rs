@@ -0,0 +1,89 @@
+//! These tests need a running PipeWire daemon, e.g. one that has been started locally with
+//! `pipewire &` (and `wireplumber &`, which is not required). Run them with
+//! `cargo test --features pipewire --test pipewire -- --ignored`.
+#![cfg(all(target_os = "linux", feature = "pipewire"))]
+
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use midir::r#virtual::{VirtualInput, VirtualOutput};
+use midir::{Backend, Ignore, MidiInput, MidiOutput};
+
+#[test]
+#[ignore]
+fn virtual_output_to_input() {
+    let mut midi_in = MidiInput::with_backend(Backend::PipeWire, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    let midi_out = MidiOutput::with_backend(Backend::PipeWire, "midir-test").unwrap();
+
+    let mut conn_out = midi_out.create_virtual("midir-test-output").unwrap();
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| {
+            midi_in
+                .port_name(port)
+                .unwrap()
+                .contains("midir-test-output")
+        })
+        .expect("virtual output port not found");
+
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .connect(
+            &port,
+            "midir-test-reader",
+            move |stamp, message, _| sender.send((stamp, message.to_vec())).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    conn_out.send(&[0x90, 60, 100]).unwrap();
+    conn_out
+        .send(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])
+        .unwrap();
+    let (first_stamp, message) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message, [0x90, 60, 100]);
+    let (second_stamp, message) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message, [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+    assert!(second_stamp >= first_stamp);
+
+    let midi_in = conn_in.close().0;
+    conn_out.close();
+    assert_eq!(midi_in.backend(), Backend::PipeWire);
+}
+
+#[test]
+#[ignore]
+fn virtual_input_from_output() {
+    let midi_in = MidiInput::with_backend(Backend::PipeWire, "midir-test").unwrap();
+    let midi_out = MidiOutput::with_backend(Backend::PipeWire, "midir-test").unwrap();
+
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .create_virtual(
+            "midir-test-input",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| {
+            midi_out
+                .port_name(port)
+                .unwrap()
+                .contains("midir-test-input")
+        })
+        .expect("virtual input port not found");
+
+    let mut conn_out = midi_out.connect(&port, "midir-test-writer").unwrap();
+    conn_out.send(&[0xC0, 5]).unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(1)).unwrap(),
+        [0xC0, 5]
+    );
+    conn_out.close();
+    conn_in.close();
+}
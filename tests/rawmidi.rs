@@ -0,0 +1,77 @@
+//! These tests exchange messages between the ALSA rawmidi and sequencer backends through the
+//! ports of the `snd-virmidi` kernel module, which must be loaded (`sudo modprobe snd-virmidi`).
+//! Run them with `cargo test --test rawmidi -- --ignored`.
+#![cfg(all(target_os = "linux", feature = "alsa"))]
+
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use midir::{Backend, Ignore, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+fn connect_input(backend: Backend) -> (MidiInputConnection<()>, Receiver<Vec<u8>>) {
+    let mut midi_in = MidiInput::with_backend(backend, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).unwrap().contains("VirMIDI"))
+        .expect("no VirMIDI input port found, is snd-virmidi loaded?");
+    let (sender, receiver) = channel();
+    let conn = midi_in
+        .connect(
+            &port,
+            "midir-test",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    (conn, receiver)
+}
+
+fn connect_output(backend: Backend) -> MidiOutputConnection {
+    let midi_out = MidiOutput::with_backend(backend, "midir-test").unwrap();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).unwrap().contains("VirMIDI"))
+        .expect("no VirMIDI output port found, is snd-virmidi loaded?");
+    midi_out.connect(&port, "midir-test").unwrap()
+}
+
+fn receive(receiver: &Receiver<Vec<u8>>) -> Vec<u8> {
+    receiver.recv_timeout(Duration::from_secs(1)).unwrap()
+}
+
+#[test]
+#[ignore]
+fn rawmidi_to_sequencer() {
+    let (_conn_in, receiver) = connect_input(Backend::Alsa);
+    let mut conn_out = connect_output(Backend::AlsaRaw);
+
+    // Running status is sent as it is and expanded by the receiving side
+    conn_out.send(&[0x90, 60, 100, 62, 100]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 60, 100]);
+    assert_eq!(receive(&receiver), [0x90, 62, 100]);
+
+    conn_out
+        .send(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])
+        .unwrap();
+    assert_eq!(receive(&receiver), [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+}
+
+#[test]
+#[ignore]
+fn sequencer_to_rawmidi() {
+    let (conn_in, receiver) = connect_input(Backend::AlsaRaw);
+    let mut conn_out = connect_output(Backend::Alsa);
+
+    conn_out.send(&[0x90, 60, 100]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 60, 100]);
+    conn_out.send(&[0x90, 62, 100]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 62, 100]);
+    conn_out.send(&[0xC0, 5]).unwrap();
+    assert_eq!(receive(&receiver), [0xC0, 5]);
+
+    let (midi_in, _) = conn_in.close();
+    assert_eq!(midi_in.backend(), Backend::AlsaRaw);
+}
@@ -0,0 +1,156 @@
+//! These tests run RTP-MIDI sessions over the loopback interface, with both
+//! sides of each session living in this process.
+#![cfg(all(feature = "rtpmidi", not(target_arch = "wasm32")))]
+
+use std::net::UdpSocket;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use midir::os::rtpmidi::{MidiInputConnectionExt, MidiOutputPortExt};
+use midir::r#virtual::{VirtualDuplex, VirtualInput, VirtualOutput, VirtualPortOptions};
+use midir::{Backend, Ignore, MidiInput, MidiOutput, MidiOutputPort};
+
+fn midi_in() -> MidiInput {
+    let mut midi_in = MidiInput::with_backend(Backend::RtpMidi, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    midi_in
+}
+
+fn midi_out() -> MidiOutput {
+    MidiOutput::with_backend(Backend::RtpMidi, "midir-test").unwrap()
+}
+
+fn receive(receiver: &Receiver<Vec<u8>>) -> Vec<u8> {
+    receiver.recv_timeout(Duration::from_secs(1)).unwrap()
+}
+
+#[test]
+fn published_input() {
+    let (sender, receiver) = channel();
+    let conn_in = midi_in()
+        .create_virtual(
+            "midir-test-published-input",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    let midi_out = midi_out();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).unwrap() == "midir-test-published-input")
+        .expect("published session not found");
+    assert_eq!(port.session_address(), conn_in.session_address());
+    let mut conn_out = midi_out.connect(&port, "midir-test-sender").unwrap();
+
+    conn_out.send(&[0x90, 60, 100]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 60, 100]);
+    conn_out.send(&[0xC0, 5]).unwrap();
+    assert_eq!(receive(&receiver), [0xC0, 5]);
+
+    // Long SysEx messages are split over several packets
+    let mut sysex = vec![0xF0];
+    sysex.extend((0..3000).map(|i| (i % 128) as u8));
+    sysex.push(0xF7);
+    conn_out.send(&sysex).unwrap();
+    assert_eq!(receive(&receiver), sysex);
+
+    conn_out.close();
+    let (midi_in, _) = conn_in.close();
+    assert_eq!(midi_in.backend(), Backend::RtpMidi);
+}
+
+#[test]
+fn published_output() {
+    let midi_out = midi_out();
+    let options = VirtualPortOptions::new();
+    let mut conn_out = midi_out
+        .create_virtual_with_options("midir-test-published-output", &options)
+        .unwrap();
+
+    let midi_in = midi_in();
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).unwrap() == "midir-test-published-output")
+        .expect("published session not found");
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .connect(
+            &port,
+            "midir-test-receiver",
+            move |stamp, message, _| sender.send((stamp, message.to_vec())).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    conn_out.send(&[0xB0, 7, 80]).unwrap();
+    conn_out.send(&[0xF8]).unwrap();
+    let (first_stamp, message) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message, [0xB0, 7, 80]);
+    let (second_stamp, message) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message, [0xF8]);
+    assert!(second_stamp >= first_stamp);
+
+    conn_in.close();
+    conn_out.close();
+}
+
+#[test]
+fn published_duplex() {
+    let (sender, receiver) = channel();
+    let (conn_in, mut conn_out) = midi_in()
+        .create_virtual_duplex(
+            "midir-test-published-duplex",
+            &VirtualPortOptions::new(),
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    // Join the session once for sending and once for receiving
+    let address = conn_in.session_address().unwrap();
+    let port = MidiOutputPort::from_session_address("duplex", address);
+    let mut remote_out = midi_out().connect(&port, "midir-test-remote-out").unwrap();
+    let remote_in = midi_in();
+    let in_port = remote_in
+        .ports()
+        .into_iter()
+        .find(|port| remote_in.port_name(port).unwrap() == "midir-test-published-duplex")
+        .unwrap();
+    let (remote_sender, remote_receiver) = channel();
+    let remote_in = remote_in
+        .connect(
+            &in_port,
+            "midir-test-remote-in",
+            move |_, message, _| remote_sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    remote_out.send(&[0x90, 64, 1]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 64, 1]);
+    conn_out.send(&[0x80, 64, 0]).unwrap();
+    assert_eq!(receive(&remote_receiver), [0x80, 64, 0]);
+
+    // The session keeps running for the output connection
+    conn_in.close();
+    conn_out.send(&[0x90, 65, 1]).unwrap();
+    assert_eq!(receive(&remote_receiver), [0x90, 65, 1]);
+
+    conn_out.close();
+    remote_in.close();
+    remote_out.close();
+}
+
+#[test]
+fn connect_without_session() {
+    // Nothing answers the invitation once the socket has been closed
+    let address = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let port = MidiOutputPort::from_session_address("nobody", address);
+    assert!(midi_out().connect(&port, "midir-test-nobody").is_err());
+}
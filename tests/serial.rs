@@ -0,0 +1,99 @@
+//! These tests connect to the slave side of a pseudo-terminal pair and play
+//! the device on the master side.
+#![cfg(all(unix, feature = "serial"))]
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use midir::os::serial;
+use midir::{Backend, Ignore, MidiInput, MidiOutput};
+
+/// Opens a pseudo-terminal pair and returns the master and the path of the slave.
+fn open_pty() -> (File, String) {
+    let mut master = -1;
+    let mut slave = -1;
+    let mut name = [0 as libc::c_char; 128];
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            name.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    assert_eq!(result, 0, "could not open a pseudo-terminal");
+    // The slave is opened again by the backend
+    unsafe { libc::close(slave) };
+    let path = unsafe { CStr::from_ptr(name.as_ptr()) };
+    let master = unsafe { File::from_raw_fd(master) };
+    (master, path.to_string_lossy().into_owned())
+}
+
+#[test]
+fn pseudo_terminal() {
+    let (mut master, path) = open_pty();
+    serial::add_port("midir-test-serial", &path, serial::MIDI_BAUD_RATE);
+
+    let mut midi_in = MidiInput::with_backend(Backend::Serial, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).unwrap() == "midir-test-serial")
+        .expect("configured port not found");
+    assert_eq!(port.id(), path);
+    let (sender, receiver) = channel();
+    let conn_in = midi_in
+        .connect(
+            &port,
+            "midir-test",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    let midi_out = MidiOutput::with_backend(Backend::Serial, "midir-test").unwrap();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).unwrap() == "midir-test-serial")
+        .expect("configured port not found");
+    let mut conn_out = midi_out.connect(&port, "midir-test").unwrap();
+
+    // Running status, interleaved real-time messages and SysEx from the device
+    master
+        .write_all(&[
+            0x90, 60, 100, 62, 0xF8, 100, 0xF0, 0x7D, 0x01, 0xF8, 0x02, 0xF7,
+        ])
+        .unwrap();
+    let expected: [&[u8]; 5] = [
+        &[0x90, 60, 100],
+        &[0xF8],
+        &[0x90, 62, 100],
+        &[0xF8],
+        &[0xF0, 0x7D, 0x01, 0x02, 0xF7],
+    ];
+    for message in expected {
+        let received = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received, message);
+    }
+
+    // Messages to the device arrive exactly as they are sent
+    conn_out.send(&[0xB0, 7, 80]).unwrap();
+    conn_out.send(&[0xF0, 0x7D, 0x03, 0xF7]).unwrap();
+    let mut buffer = [0; 7];
+    master.read_exact(&mut buffer).unwrap();
+    assert_eq!(buffer, [0xB0, 7, 80, 0xF0, 0x7D, 0x03, 0xF7]);
+
+    conn_out.close();
+    let (midi_in, _) = conn_in.close();
+    assert_eq!(midi_in.backend(), Backend::Serial);
+    assert!(serial::remove_port(&path));
+    assert_eq!(midi_in.port_count(), 0);
+}
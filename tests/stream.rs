@@ -0,0 +1,98 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::io::Cursor;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, TryRecvError};
+use std::time::Duration;
+
+use midir::stream::{StreamInput, StreamOutputConnection};
+use midir::{Ignore, SendError};
+
+#[test]
+fn tcp_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let writer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (reader, _) = listener.accept().unwrap();
+    // Lets closing the connection take effect while no data arrives
+    reader
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    let (sender, receiver) = channel();
+    let conn_in = StreamInput::new()
+        .connect(
+            reader,
+            "midir-test",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    let mut conn_out = StreamOutputConnection::new(writer);
+
+    conn_out.send(&[0x90, 60, 100]).unwrap();
+    // Running status is expanded
+    conn_out.send(&[62, 100]).unwrap();
+    conn_out.send(&[0xF0, 0x7D, 0x01, 0xF7]).unwrap();
+    assert!(matches!(conn_out.send(&[]), Err(SendError::InvalidData(_))));
+    let expected: [&[u8]; 3] = [
+        &[0x90, 60, 100],
+        &[0x90, 62, 100],
+        &[0xF0, 0x7D, 0x01, 0xF7],
+    ];
+    for message in expected {
+        let received = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received, message);
+    }
+
+    assert!(!conn_in.is_finished());
+    let (_, reader, _) = conn_in.close();
+    assert_eq!(
+        reader.peer_addr().unwrap(),
+        conn_out.close().local_addr().unwrap()
+    );
+}
+
+#[test]
+fn end_of_stream() {
+    let bytes = vec![0xF8, 0x90, 60, 100, 0xFE, 0x80, 60, 0, 0xF0, 0x01];
+    let mut input = StreamInput::new();
+    input.ignore(Ignore::TimeAndActiveSense);
+    let conn_in = input
+        .connect(
+            Cursor::new(bytes),
+            "midir-test",
+            |_, message, received: &mut Vec<Vec<u8>>| received.push(message.to_vec()),
+            Vec::new(),
+        )
+        .unwrap();
+
+    while !conn_in.is_finished() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let (_, _, received) = conn_in.close();
+    // The unfinished SysEx message at the end of the stream is dropped
+    assert_eq!(received, [vec![0x90, 60, 100], vec![0x80, 60, 0]]);
+}
+
+#[test]
+fn drop_stops_thread() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _writer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (reader, _) = listener.accept().unwrap();
+    reader
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    let (sender, receiver) = channel::<Vec<u8>>();
+    let conn_in = StreamInput::new()
+        .connect(
+            reader,
+            "midir-test",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    // Dropping waits for the thread, so the callback (and with it the sender) is gone
+    drop(conn_in);
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+}
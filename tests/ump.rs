@@ -0,0 +1,152 @@
+//! These tests need a sequencer with UMP support (Linux 6.5 and alsa-lib 1.2.10 or newer),
+//! and `through_dummy` also needs the `snd-seq-dummy` kernel module (`sudo modprobe snd-seq-dummy`).
+//! Run them with `cargo test --test ump -- --ignored`.
+#![cfg(all(target_os = "linux", feature = "alsa"))]
+
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use midir::os::linux::ump::{
+    Addr, BlockDirection, Protocol, UmpInput, UmpInputConnection, UmpOutput,
+};
+use midir::os::linux::MidiInputPortExt;
+use midir::{Backend, Ignore, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput};
+
+fn ump_input(protocol: Protocol) -> (UmpInputConnection<()>, Receiver<Vec<u32>>) {
+    let midi_in = UmpInput::new("midir-test", protocol).unwrap();
+    let (sender, receiver) = channel();
+    let conn = midi_in
+        .create_virtual(
+            "midir-test",
+            move |_, packet, _| sender.send(packet.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    (conn, receiver)
+}
+
+fn legacy_input(port: Addr) -> (MidiInputConnection<()>, Receiver<Vec<u8>>) {
+    let mut midi_in = MidiInput::with_backend(Backend::Alsa, "midir-test").unwrap();
+    midi_in.ignore(Ignore::None);
+    let (sender, receiver) = channel();
+    let conn = midi_in
+        .connect(
+            &MidiInputPort::from_alsa_addr(port),
+            "midir-test",
+            move |_, message, _| sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    (conn, receiver)
+}
+
+fn receive<T>(receiver: &Receiver<T>) -> T {
+    receiver.recv_timeout(Duration::from_secs(1)).unwrap()
+}
+
+#[test]
+#[ignore]
+fn legacy_to_ump() {
+    let (conn_in, receiver) = ump_input(Protocol::Midi1);
+
+    let midi_out = MidiOutput::with_backend(Backend::Alsa, "midir-test").unwrap();
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| port.id() == format!("{}:{}", conn_in.port().client, conn_in.port().port))
+        .expect("UMP port not found");
+    let mut conn_out = midi_out.connect(&port, "midir-test").unwrap();
+
+    conn_out.send(&[0x90, 60, 100]).unwrap();
+    assert_eq!(receive(&receiver), [0x2090_3C64]);
+    conn_out.send(&[0xB0, 7, 80]).unwrap();
+    assert_eq!(receive(&receiver), [0x20B0_0750]);
+}
+
+#[test]
+#[ignore]
+fn ump_to_legacy() {
+    let midi_out = UmpOutput::new("midir-test", Protocol::Midi2).unwrap();
+    let mut conn_out = midi_out.create_virtual("midir-test").unwrap();
+    let (_conn_in, receiver) = legacy_input(conn_out.port());
+
+    // The velocity of a MIDI 2.0 note on is scaled down to 7 bits
+    conn_out.send(&[0x4090_3C00, 0xFFFF_0000]).unwrap();
+    assert_eq!(receive(&receiver), [0x90, 60, 127]);
+    // Several packets can be sent at once
+    conn_out.send(&[0x2080_3C00, 0x20B0_0750]).unwrap();
+    assert_eq!(receive(&receiver), [0x80, 60, 0]);
+    assert_eq!(receive(&receiver), [0xB0, 7, 80]);
+    assert!(conn_out.send(&[0x4090_3C00]).is_err());
+}
+
+#[test]
+#[ignore]
+fn virtual_endpoint() {
+    let midi_in = UmpInput::new("midir-test-endpoint", Protocol::Midi2).unwrap();
+    let (sender, receiver) = channel();
+    let (conn_in, mut conn_out) = midi_in
+        .create_virtual_endpoint(
+            "midir-test-endpoint",
+            2,
+            move |_, packet, _| sender.send(packet.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    let client = conn_in.port().client;
+
+    let peer_in = UmpInput::new("midir-test", Protocol::Midi2).unwrap();
+    let endpoint = peer_in
+        .endpoints()
+        .into_iter()
+        .find(|endpoint| endpoint.client == client)
+        .expect("endpoint not found");
+    assert_eq!(endpoint.name, "midir-test-endpoint");
+    assert_eq!(endpoint.protocol, Some(Protocol::Midi2));
+    let blocks = peer_in.function_blocks(client);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].direction, BlockDirection::Bidirectional);
+    assert_eq!((blocks[0].first_group, blocks[0].num_groups), (0, 2));
+
+    // Packets of the second group are delivered to the subscribers of the second group port
+    let (sender, peer_receiver) = channel();
+    let _peer = peer_in
+        .connect(
+            Addr { client, port: 2 },
+            "midir-test",
+            move |_, packet, _| sender.send(packet.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+    conn_out.send(&[0x4190_3C00, 0x8000_0000]).unwrap();
+    assert_eq!(receive(&peer_receiver), [0x4190_3C00, 0x8000_0000]);
+
+    let peer_out = UmpOutput::new("midir-test", Protocol::Midi2).unwrap();
+    let mut peer_conn = peer_out
+        .connect(Addr { client, port: 1 }, "midir-test")
+        .unwrap();
+    peer_conn.send(&[0x40B0_0700, 0x8000_0000]).unwrap();
+    assert_eq!(receive(&receiver), [0x40B0_0700, 0x8000_0000]);
+
+    // Both halves use the client of the endpoint
+    assert_eq!(conn_out.port(), Addr { client, port: 0 });
+    let midi_out = conn_out.close();
+    assert_eq!(midi_out.protocol(), Protocol::Midi2);
+    drop(conn_in);
+}
+
+#[test]
+#[ignore]
+fn through_dummy() {
+    let midi_out = UmpOutput::new("midir-test", Protocol::Midi2).unwrap();
+    let through = midi_out
+        .ports()
+        .into_iter()
+        .find(|&port| midi_out.port_name(port).unwrap().contains("Midi Through"))
+        .expect("no Midi Through port found, is snd-seq-dummy loaded?");
+    let (_conn_in, receiver) = legacy_input(through);
+    let mut conn_out = midi_out.connect(through, "midir-test").unwrap();
+
+    conn_out.send(&[0x40E0_0000, 0x8000_0000]).unwrap();
+    assert_eq!(receive(&receiver), [0xE0, 0x00, 0x40]);
+}
@@ -1,10 +1,20 @@
 //! This file contains automated tests, but they require virtual ports and therefore can't work on Windows or Web MIDI ...
 #![cfg(not(any(windows, target_arch = "wasm32")))]
+// The serial and ipMIDI backends have no virtual ports, so one of the others is needed
+#![cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    all(target_os = "linux", feature = "alsa"),
+    all(target_os = "linux", feature = "pipewire"),
+    feature = "jack",
+    feature = "dummy",
+    feature = "rtpmidi"
+))]
 
 use std::thread::sleep;
 use std::time::Duration;
 
-use midir::os::unix::{VirtualInput, VirtualOutput};
+use midir::r#virtual::{VirtualInput, VirtualOutput};
 use midir::{Ignore, MidiInput, MidiOutput, MidiOutputPort};
 
 #[test]
@@ -12,6 +22,7 @@ fn end_to_end() {
     let mut midi_in = MidiInput::new("My Test Input").unwrap();
     midi_in.ignore(Ignore::None);
     let midi_out = MidiOutput::new("My Test Output").unwrap();
+    assert!(midi_in.supports_virtual_ports().is_ok());
 
     let previous_count = midi_out.port_count();
 
@@ -88,3 +99,257 @@ fn end_to_end() {
     conn_out.close();
     assert_eq!(midi_in.port_count(), previous_count);
 }
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "alsa", not(feature = "jack")))]
+fn duplex() {
+    use midir::os::unix::{VirtualDuplex, VirtualPortOptions};
+
+    let midi_in = MidiInput::new("My Test Duplex").unwrap();
+    let observer_in = MidiInput::new("My Test Observer").unwrap();
+    let observer_out = MidiOutput::new("My Test Observer").unwrap();
+
+    let previous_in_count = observer_in.port_count();
+    let previous_out_count = observer_out.port_count();
+
+    println!("Creating virtual duplex port ...");
+    let (conn_in, mut conn_out) = midi_in
+        .create_virtual_duplex(
+            "midir-test",
+            &VirtualPortOptions::new(),
+            |stamp, message, _| {
+                println!("{}: {:?} (len = {})", stamp, message, message.len());
+            },
+            (),
+        )
+        .unwrap();
+
+    // On ALSA, the same port can be both read from and written to
+    assert_eq!(observer_in.port_count(), previous_in_count + 1);
+    assert_eq!(observer_out.port_count(), previous_out_count + 1);
+
+    conn_out.send(&[144, 60, 1]).unwrap();
+    sleep(Duration::from_millis(50));
+
+    println!("Closing virtual input ...");
+    conn_in.close();
+    assert_eq!(observer_in.port_count(), previous_in_count + 1);
+    conn_out.send(&[144, 60, 0]).unwrap();
+
+    println!("Closing virtual output ...");
+    let midi_out = conn_out.close();
+    assert_eq!(observer_in.port_count(), previous_in_count);
+    assert_eq!(observer_out.port_count(), previous_out_count);
+    // The returned object uses the client of the input
+    assert_eq!(midi_out.port_count(), previous_out_count);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "alsa", not(feature = "jack")))]
+fn shared_client() {
+    use midir::os::unix::VirtualPortOptions;
+    use midir::MidiClient;
+    use std::sync::mpsc::channel;
+
+    let client = MidiClient::new("My Test Client").unwrap();
+    let sender = MidiOutput::new("My Test Sender").unwrap();
+    let observer = MidiInput::new("My Test Observer").unwrap();
+    let previous_count = observer.port_count();
+
+    println!("Creating virtual ports ...");
+    let mut conn_out = client
+        .create_virtual_output("midir-test-out", &VirtualPortOptions::new())
+        .unwrap();
+    let (in_sender, in_receiver) = channel();
+    let conn_in = client
+        .create_virtual_input(
+            "midir-test-in",
+            &VirtualPortOptions::new(),
+            move |stamp, message, _| {
+                println!("{}: {:?} (len = {})", stamp, message, message.len());
+                in_sender.send(message.to_vec()).unwrap();
+            },
+            (),
+        )
+        .unwrap();
+    assert_eq!(observer.port_count(), previous_count + 1);
+
+    // Connect the virtual output to an input port of the same client
+    let port = client
+        .input_ports()
+        .into_iter()
+        .find(|p| {
+            client
+                .input_port_name(p)
+                .unwrap()
+                .contains("midir-test-out")
+        })
+        .unwrap();
+    let (loop_sender, loop_receiver) = channel();
+    let conn_loop = client
+        .connect_input(
+            &port,
+            "midir-test-loop",
+            move |_, message, _| loop_sender.send(message.to_vec()).unwrap(),
+            (),
+        )
+        .unwrap();
+
+    // Send to the virtual input from another client
+    let port = sender
+        .ports()
+        .into_iter()
+        .find(|p| sender.port_name(p).unwrap().contains("midir-test-in"))
+        .unwrap();
+    let mut conn_sender = sender.connect(&port, "midir-test-sender").unwrap();
+
+    conn_out.send(&[144, 60, 1]).unwrap();
+    conn_sender.send(&[144, 61, 1]).unwrap();
+    sleep(Duration::from_millis(50));
+
+    // Each input port of the client only receives the messages sent to it
+    assert_eq!(
+        loop_receiver.try_iter().collect::<Vec<_>>(),
+        [vec![144, 60, 1]]
+    );
+    assert_eq!(
+        in_receiver.try_iter().collect::<Vec<_>>(),
+        [vec![144, 61, 1]]
+    );
+
+    conn_sender.close();
+    conn_loop.close();
+    conn_in.close();
+    conn_out.close();
+    assert_eq!(observer.port_count(), previous_count);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "alsa", not(feature = "jack")))]
+fn close_from_callback() {
+    use midir::os::unix::VirtualPortOptions;
+    use midir::{ClientInputConnection, MidiClient};
+    use std::sync::mpsc::channel;
+
+    let client = MidiClient::new("My Test Close Client").unwrap();
+    let sender = MidiOutput::new("My Test Sender").unwrap();
+
+    let other = client
+        .create_virtual_input(
+            "midir-test-other",
+            &VirtualPortOptions::new(),
+            |_, _, _| {},
+            (),
+        )
+        .unwrap();
+    let (closed_sender, closed_receiver) = channel();
+    let conn_in = client
+        .create_virtual_input(
+            "midir-test-close",
+            &VirtualPortOptions::new(),
+            move |_, _, other: &mut Option<ClientInputConnection<()>>| {
+                // Closing a port of the same client must not block the dispatch thread
+                if let Some(other) = other.take() {
+                    other.close();
+                    closed_sender.send(()).unwrap();
+                }
+            },
+            Some(other),
+        )
+        .unwrap();
+
+    let port = sender
+        .ports()
+        .into_iter()
+        .find(|p| sender.port_name(p).unwrap().contains("midir-test-close"))
+        .unwrap();
+    let mut conn_sender = sender.connect(&port, "midir-test-sender").unwrap();
+    conn_sender.send(&[144, 60, 1]).unwrap();
+    closed_receiver
+        .recv_timeout(Duration::from_millis(500))
+        .unwrap();
+
+    let sender = conn_sender.close();
+    assert!(sender
+        .ports()
+        .iter()
+        .all(|p| !sender.port_name(p).unwrap().contains("midir-test-other")));
+    assert!(conn_in.close().is_none());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "alsa", not(feature = "jack")))]
+fn subscribers() {
+    use midir::os::unix::{SubscriptionEvent, VirtualSubscribers};
+    use std::sync::mpsc::channel;
+
+    let midi_in = MidiInput::new("My Test Subscriber").unwrap();
+    let midi_out = MidiOutput::new("My Test Subscriptions").unwrap();
+
+    println!("Creating virtual output port ...");
+    let mut conn_out = midi_out.create_virtual("midir-test").unwrap();
+    assert!(!conn_out.has_subscribers());
+
+    let (sender, receiver) = channel();
+    conn_out
+        .on_subscription_change(move |event| sender.send(event).unwrap())
+        .unwrap();
+
+    let new_port = midi_in.ports().pop().unwrap();
+    let conn_in = midi_in
+        .connect(&new_port, "midir-test", |_, _, _| {}, ())
+        .unwrap();
+    match receiver.recv_timeout(Duration::from_millis(500)).unwrap() {
+        SubscriptionEvent::Subscribed(_) => {}
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert_eq!(conn_out.subscribers().len(), 1);
+
+    conn_in.close();
+    match receiver.recv_timeout(Duration::from_millis(500)).unwrap() {
+        SubscriptionEvent::Unsubscribed(_) => {}
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(!conn_out.has_subscribers());
+    conn_out.close();
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "alsa", not(feature = "jack")))]
+fn exclusive() {
+    use midir::os::unix::{VirtualPortOptions, VirtualSubscribers};
+
+    let midi_in = MidiInput::new("My Test Exclusive").unwrap();
+    let first = MidiOutput::new("My Test Sender").unwrap();
+    let second = MidiOutput::new("My Test Sender").unwrap();
+
+    println!("Creating exclusive virtual input port ...");
+    let conn_in = midi_in
+        .create_virtual_with_options(
+            "midir-test",
+            &VirtualPortOptions::new().exclusive(true),
+            |_, _, _| {},
+            (),
+        )
+        .unwrap();
+
+    let new_port = first.ports().pop().unwrap();
+    let conn_first = first.connect(&new_port, "midir-test").unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(conn_in.subscribers().len(), 1);
+
+    // The second connection is removed again by the input handler thread
+    let conn_second = second.connect(&new_port, "midir-test").unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(conn_in.subscribers().len(), 1);
+
+    conn_second.close();
+    let first = conn_first.close();
+    conn_in.close();
+
+    // Exclusivity cannot be enforced for hidden output ports
+    let options = VirtualPortOptions::new().exclusive(true).no_export(true);
+    assert!(first
+        .create_virtual_with_options("midir-test", &options)
+        .is_err());
+}